@@ -7,9 +7,45 @@ pub struct Args {
     #[clap(long, short = 'l', default_value = "info")]
     pub log_level: log::LevelFilter,
 
+    /// Environment variable passed to the program (KEY=VALUE), may be repeated
+    #[clap(long = "env", short = 'e')]
+    pub env: Vec<String>,
+
+    /// Address to load position-independent executables at (default 0x555555554000)
+    #[clap(long, value_parser = parse_address)]
+    pub load_base: Option<u64>,
+
+    /// Randomize the load base and AT_RANDOM bytes using the given seed
+    #[clap(long)]
+    pub random_seed: Option<u64>,
+
+    /// Directory used as the root directory of the program, to load the dynamic linker and shared libraries from
+    #[clap(long)]
+    pub sysroot: Option<std::path::PathBuf>,
+
+    /// Dynamic linker for dynamically linked programs [interpreter|builtin]
+    #[clap(long, default_value_t)]
+    pub linker: crate::program::DynamicLinker,
+
+    /// Processor reported to the program by cpuid [skylake-server|haswell|nehalem]
+    #[clap(long, default_value = "skylake-server")]
+    pub cpu_model: crate::cpu::cpuid::CpuModel,
+
     /// Input binary file path
     #[clap(index = 1)]
     pub binary_path: std::path::PathBuf,
+
+    /// Arguments passed to the program
+    #[clap(index = 2, trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal address
+fn parse_address(s: &str) -> Result<u64, std::num::ParseIntError> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
 }
 
 impl Args {
@@ -0,0 +1,67 @@
+use super::error::Error;
+use super::flags;
+use super::Cpu;
+
+use iced_x86::{Instruction, Register};
+
+impl Cpu {
+    /// Base address of a segment. In 64-bit mode only fs and gs have a base,
+    /// the other segments are flat.
+    pub fn segment_base(&self, segment: Register) -> u64 {
+        match segment {
+            Register::FS => self.registers.fs_base,
+            Register::GS => self.registers.gs_base,
+            _ => 0,
+        }
+    }
+
+    /// Linear address of the memory operand of an instruction: the segment base plus the
+    /// effective address `base + index * scale + displacement`, computed in the address size
+    pub fn memory_address(&self, instruction: &Instruction) -> Result<u64, Error> {
+        let effective_address = self.effective_address(instruction)?;
+        Ok(self.segment_base(instruction.memory_segment()).wrapping_add(effective_address))
+    }
+
+    /// Offset of the memory operand of an instruction within its segment
+    pub fn effective_address(&self, instruction: &Instruction) -> Result<u64, Error> {
+        let index = match instruction.memory_index() {
+            Register::None => 0,
+            register => self.get_register_u64(register)?,
+        };
+        self.indexed_address(instruction, index)
+    }
+
+    /// Linear address of an element of a memory operand indexed by a vector register (the gathers), `index` being the
+    /// sign-extended element of the index register
+    pub fn vector_element_address(&self, instruction: &Instruction, index: u64) -> Result<u64, Error> {
+        let effective_address = self.indexed_address(instruction, index)?;
+        Ok(self.segment_base(instruction.memory_segment()).wrapping_add(effective_address))
+    }
+
+    /// Effective address of the memory operand of an instruction with the value of its index register
+    fn indexed_address(&self, instruction: &Instruction, index: u64) -> Result<u64, Error> {
+        let base = match instruction.memory_base() {
+            // the decoder already resolves rip-relative displacements to absolute addresses
+            Register::None | Register::RIP | Register::EIP => 0,
+            register => self.get_register_u64(register)?,
+        };
+        let index = index.wrapping_mul(instruction.memory_index_scale() as u64);
+
+        let address = base.wrapping_add(index).wrapping_add(instruction.memory_displacement64());
+        Ok(address & flags::mask(address_size(instruction)))
+    }
+}
+
+/// Address size in bytes of the memory operand, given by the registers used to address it (an address-size
+/// prefix selects 32-bit registers in 64-bit mode, and eip instead of rip). Without registers the decoder
+/// reports the address size as the displacement size, which is 4 for `[disp32]` with an address-size prefix.
+fn address_size(instruction: &Instruction) -> usize {
+    match (instruction.memory_base(), instruction.memory_index()) {
+        (Register::None, index) if index.is_gpr() => index.size(),
+        (Register::None, _) => match instruction.memory_displ_size() {
+            size @ (2 | 4 | 8) => size as usize,
+            _ => 8,
+        },
+        (base, _) => base.size(),
+    }
+}
@@ -0,0 +1,265 @@
+use super::error::Error;
+use super::flags;
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic, OpKind, Register};
+
+impl Cpu {
+    /// Executes an instruction of the integer ALU group: add, adc, sub, sbb, cmp, and, or, xor, test, inc, dec, neg and not
+    pub fn execute_arithmetic(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let size = self.operand_size(instruction, 0);
+        let a = self.read_operand(instruction, 0)?;
+        let rflags = self.registers.rflags;
+
+        // inc and dec keep CF, not keeps all flags
+        let (result, new_flags) = match instruction.mnemonic() {
+            Mnemonic::Add => flags::add(a, self.read_operand(instruction, 1)?, false, size),
+            Mnemonic::Adc => flags::add(a, self.read_operand(instruction, 1)?, self.flag(flags::CF), size),
+            Mnemonic::Sub | Mnemonic::Cmp => flags::sub(a, self.read_operand(instruction, 1)?, false, size),
+            Mnemonic::Sbb => flags::sub(a, self.read_operand(instruction, 1)?, self.flag(flags::CF), size),
+            Mnemonic::And | Mnemonic::Test => {
+                let result = a & self.read_operand(instruction, 1)?;
+                (result, flags::logic(result, size))
+            }
+            Mnemonic::Or => {
+                let result = a | self.read_operand(instruction, 1)?;
+                (result, flags::logic(result, size))
+            }
+            Mnemonic::Xor => {
+                let result = a ^ self.read_operand(instruction, 1)?;
+                (result, flags::logic(result, size))
+            }
+            Mnemonic::Inc => flags::inc(a, size, rflags),
+            Mnemonic::Dec => flags::dec(a, size, rflags),
+            Mnemonic::Neg => flags::neg(a, size),
+            Mnemonic::Not => (!a & flags::mask(size), rflags),
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        };
+
+        self.update_flags(new_flags, flags::STATUS);
+
+        // cmp and test only set the flags
+        match instruction.mnemonic() {
+            Mnemonic::Cmp | Mnemonic::Test => Ok(()),
+            _ => self.write_operand(instruction, 0, result),
+        }
+    }
+
+    /// The lock prefix is only allowed on the read-modify-write instructions below with a memory destination,
+    /// anything else raises #UD. Locked instructions are atomic by construction, as there is only one emulated processor.
+    pub fn check_lock_prefix(&self, instruction: &Instruction) -> Result<(), Error> {
+        if !instruction.has_lock_prefix() {
+            return Ok(());
+        }
+
+        let lockable = matches!(
+            instruction.mnemonic(),
+            Mnemonic::Add | Mnemonic::Adc | Mnemonic::And | Mnemonic::Btc | Mnemonic::Btr | Mnemonic::Bts | Mnemonic::Cmpxchg
+                | Mnemonic::Cmpxchg8b | Mnemonic::Cmpxchg16b | Mnemonic::Dec | Mnemonic::Inc | Mnemonic::Neg | Mnemonic::Not
+                | Mnemonic::Or | Mnemonic::Sbb | Mnemonic::Sub | Mnemonic::Xadd | Mnemonic::Xchg | Mnemonic::Xor
+        );
+        match lockable && instruction.op0_kind() == OpKind::Memory {
+            true => Ok(()),
+            false => Err(Error::InvalidOpcode(instruction.ip())),
+        }
+    }
+
+    /// Executes mul, imul, div and idiv. The double-width operand is AX for byte operands and rDX:rAX otherwise.
+    /// The flags left undefined follow current Intel processors: multiplication sets SF and PF from the low half
+    /// of the product and clears ZF and AF, division leaves all flags unchanged.
+    pub fn execute_multiply_divide(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let size = self.operand_size(instruction, 0);
+        let bits = size as u32 * 8;
+
+        match (instruction.mnemonic(), instruction.op_count()) {
+            (Mnemonic::Mul, 1) => {
+                let product = self.get_register_u64(accumulator(size))? as u128 * self.read_operand(instruction, 0)? as u128;
+                self.write_double(size, product)?;
+                self.set_multiply_flags(product as u64, product >> bits != 0, size);
+            }
+
+            (Mnemonic::Imul, 1) => {
+                let a = flags::sign_extend(self.get_register_u64(accumulator(size))?, size) as i64 as i128;
+                let b = flags::sign_extend(self.read_operand(instruction, 0)?, size) as i64 as i128;
+                let product = a * b;
+                self.write_double(size, product as u128)?;
+                self.set_signed_multiply_flags(product, size);
+            }
+
+            // two operand form multiplies the destination, three operand form the source with an immediate
+            (Mnemonic::Imul, count) => {
+                let (a, b) = match count {
+                    2 => (self.read_operand(instruction, 0)?, self.read_operand(instruction, 1)?),
+                    _ => (self.read_operand(instruction, 1)?, self.read_operand(instruction, 2)?),
+                };
+                let product = flags::sign_extend(a, size) as i64 as i128 * flags::sign_extend(b, size) as i64 as i128;
+                self.write_operand(instruction, 0, product as u64)?;
+                self.set_signed_multiply_flags(product, size);
+            }
+
+            (Mnemonic::Div, _) => {
+                let dividend = self.read_double(size)?;
+                let divisor = self.read_operand(instruction, 0)? as u128;
+                if divisor == 0 {
+                    return Err(Error::DivideFault(instruction.ip()));
+                }
+                let quotient = dividend / divisor;
+                if quotient > flags::mask(size) as u128 {
+                    return Err(Error::DivideFault(instruction.ip()));
+                }
+                self.write_quotient_remainder(size, quotient as u64, (dividend % divisor) as u64)?;
+            }
+
+            (Mnemonic::Idiv, _) => {
+                // sign-extend the double-width dividend from its top bit
+                let shift = 128 - 2 * bits;
+                let dividend = ((self.read_double(size)? << shift) as i128) >> shift;
+                let divisor = flags::sign_extend(self.read_operand(instruction, 0)?, size) as i64 as i128;
+                let quotient = dividend.checked_div(divisor).ok_or(Error::DivideFault(instruction.ip()))?;
+                let min = -(1i128 << (bits - 1));
+                let max = (1i128 << (bits - 1)) - 1;
+                if quotient < min || quotient > max {
+                    return Err(Error::DivideFault(instruction.ip()));
+                }
+                // the remainder has the sign of the dividend
+                self.write_quotient_remainder(size, quotient as u64, (dividend % divisor) as u64)?;
+            }
+
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        }
+
+        Ok(())
+    }
+
+    /// CF and OF are set by signed multiplication if the product does not fit into the operand size
+    fn set_signed_multiply_flags(&mut self, product: i128, size: usize) {
+        let truncated = flags::sign_extend(product as u64, size) as i64 as i128;
+        self.set_multiply_flags(product as u64, truncated != product, size);
+    }
+
+    /// Sets CF and OF on overflow, SF and PF from the low half of the product and clears ZF and AF
+    fn set_multiply_flags(&mut self, low: u64, overflow: bool, size: usize) {
+        let overflow = if overflow { flags::CF | flags::OF } else { 0 };
+        self.update_flags((flags::logic(low, size) & (flags::SF | flags::PF)) | overflow, flags::STATUS);
+    }
+
+    /// Reads the double-width operand: AX for byte operands, rDX:rAX otherwise
+    fn read_double(&self, size: usize) -> Result<u128, Error> {
+        match size {
+            1 => Ok(self.get_register_u64(Register::AX)? as u128),
+            size => {
+                let low = self.get_register_u64(accumulator(size))? as u128;
+                let high = self.get_register_u64(data_register(size))? as u128;
+                Ok((high << (size * 8)) | low)
+            }
+        }
+    }
+
+    /// Writes the double-width result of a multiplication
+    fn write_double(&mut self, size: usize, value: u128) -> Result<(), Error> {
+        match size {
+            1 => self.set_register(Register::AX, value as u64),
+            size => {
+                self.set_register(accumulator(size), value as u64)?;
+                self.set_register(data_register(size), (value >> (size * 8)) as u64)
+            }
+        }
+    }
+
+    /// Writes the quotient and remainder of a division: AL and AH for byte operands, rAX and rDX otherwise
+    fn write_quotient_remainder(&mut self, size: usize, quotient: u64, remainder: u64) -> Result<(), Error> {
+        match size {
+            1 => {
+                self.set_register(Register::AL, quotient)?;
+                self.set_register(Register::AH, remainder)
+            }
+            size => {
+                self.set_register(accumulator(size), quotient)?;
+                self.set_register(data_register(size), remainder)
+            }
+        }
+    }
+}
+
+/// Accumulator register (AL, AX, EAX or RAX) of an operand size
+pub fn accumulator(size: usize) -> Register {
+    match size {
+        1 => Register::AL,
+        2 => Register::AX,
+        4 => Register::EAX,
+        _ => Register::RAX,
+    }
+}
+
+/// Data register (DL, DX, EDX or RDX) of an operand size, holding the upper half of double-width operands
+pub fn data_register(size: usize) -> Register {
+    match size {
+        1 => Register::DL,
+        2 => Register::DX,
+        4 => Register::EDX,
+        _ => Register::RDX,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::flags::CF;
+    use crate::cpu::testing::{cpu_with_data, cpu_with_flags, DATA};
+
+    use iced_x86::{Code, MemoryOperand};
+
+    // the flags themselves are covered by the tests of flags::add and flags::sub
+    #[test]
+    fn adc_and_sbb_read_the_carry_flag_and_write_only_the_destination() {
+        for carry in [0, 1] {
+            let mut cpu = cpu_with_flags(if carry == 1 { CF } else { 0 });
+            cpu.registers.rax = 0x1234_5678_9abc_def0;
+            cpu.registers.rbx = 0x10;
+            cpu.execute_instruction(Instruction::with2(Code::Adc_rm8_r8, Register::AL, Register::BL).unwrap()).unwrap();
+            assert_eq!(cpu.registers.rax, 0x1234_5678_9abc_de00 + carry);
+            assert!(cpu.flag(CF));
+
+            let mut cpu = cpu_with_flags(if carry == 1 { CF } else { 0 });
+            cpu.registers.rax = 0x1234_5678_9abc_def0;
+            cpu.registers.rbx = 0x10;
+            cpu.execute_instruction(Instruction::with2(Code::Sbb_rm16_r16, Register::AX, Register::BX).unwrap()).unwrap();
+            assert_eq!(cpu.registers.rax, 0x1234_5678_9abc_dee0 - carry);
+            assert!(!cpu.flag(CF));
+
+            // 32-bit destinations are zero-extended
+            let mut cpu = cpu_with_flags(if carry == 1 { CF } else { 0 });
+            cpu.registers.rax = 0x1234_5678_9abc_def0;
+            cpu.registers.rbx = 0x10;
+            cpu.execute_instruction(Instruction::with2(Code::Adc_rm32_r32, Register::EAX, Register::EBX).unwrap()).unwrap();
+            assert_eq!(cpu.registers.rax, 0x9abc_df00 + carry);
+        }
+    }
+
+    #[test]
+    fn lock_prefix_requires_a_lockable_instruction_with_a_memory_destination() {
+        let mut cpu = cpu_with_data(&[0]);
+        cpu.registers.rax = 0x2a;
+
+        let locked = |code, op0: MemoryOperand| {
+            let mut instruction = Instruction::with2(code, op0, Register::RAX).unwrap();
+            instruction.set_has_lock_prefix(true);
+            instruction
+        };
+        cpu.execute_instruction(locked(Code::Add_rm64_r64, MemoryOperand::with_base(Register::RBX))).unwrap();
+        assert_eq!(cpu.memory.read_uint(DATA, 8).unwrap(), 0x2a);
+
+        let mut add_register = Instruction::with2(Code::Add_rm64_r64, Register::RCX, Register::RAX).unwrap();
+        add_register.set_has_lock_prefix(true);
+        for instruction in [
+            add_register,
+            locked(Code::Cmp_rm64_r64, MemoryOperand::with_base(Register::RBX)),
+            locked(Code::Test_rm64_r64, MemoryOperand::with_base(Register::RBX)),
+            locked(Code::Mov_rm64_r64, MemoryOperand::with_base(Register::RBX)),
+            locked(Code::Bt_rm64_r64, MemoryOperand::with_base(Register::RBX)),
+        ] {
+            assert!(matches!(cpu.execute_instruction(instruction), Err(Error::InvalidOpcode(_))), "{:?}", instruction.code());
+        }
+        assert_eq!(cpu.memory.read_uint(DATA, 8).unwrap(), 0x2a);
+    }
+}
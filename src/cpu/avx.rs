@@ -0,0 +1,535 @@
+use super::error::Error;
+use super::flags::{AF, CF, OF, PF, SF, ZF};
+use super::sse::{Vector, LANES};
+use super::sse_float::fused_operation;
+use super::sse_integer::{self, element, signed};
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic, OpKind};
+
+impl Cpu {
+    /// Executes a VEX-encoded instruction: those of AVX, AVX2, FMA and F16C without a legacy form here, the others
+    /// by their SSE implementation on each 128-bit lane
+    pub fn execute_avx(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        self.check_features(instruction)?;
+        let mnemonic = instruction.mnemonic();
+        match mnemonic {
+            // the lanes above XMM registers of the registers the VEX encoding reaches, or these registers, zeroed
+            Mnemonic::Vzeroupper => self.registers.sse.zmm.iter_mut().take(16).for_each(|register| register[1..].fill(0)),
+            Mnemonic::Vzeroall => self.registers.sse.zmm[..16].fill([0; LANES]),
+
+            // the lowest element of the source, or the 64, 128 or 256 bits of memory, in every element of the destination
+            Mnemonic::Vbroadcastss | Mnemonic::Vbroadcastsd | Mnemonic::Vbroadcastf128 | Mnemonic::Vbroadcasti128 | Mnemonic::Vpbroadcastb
+            | Mnemonic::Vpbroadcastw | Mnemonic::Vpbroadcastd | Mnemonic::Vpbroadcastq | Mnemonic::Vbroadcastf32x2 | Mnemonic::Vbroadcasti32x2
+            | Mnemonic::Vbroadcastf32x4 | Mnemonic::Vbroadcastf64x2 | Mnemonic::Vbroadcasti32x4 | Mnemonic::Vbroadcasti64x2
+            | Mnemonic::Vbroadcastf32x8 | Mnemonic::Vbroadcastf64x4 | Mnemonic::Vbroadcasti32x8 | Mnemonic::Vbroadcasti64x4 => {
+                let source = self.read_vector_operand(instruction, 1)?;
+                let value = match element_bits(mnemonic) {
+                    256 => [source[0], source[1], source[0], source[1]],
+                    128 => [source[0]; LANES],
+                    bits => elements(bits, |_| element(source[0], bits, 0)),
+                };
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // each lane of the destination is a lane of either source selected by 2 bits of the immediate, or zeroed
+            // when the following bit is set
+            Mnemonic::Vperm2f128 | Mnemonic::Vperm2i128 => {
+                let (a, b) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let (sources, immediate) = ([a[0], a[1], b[0], b[1]], instruction.immediate8());
+                let mut value = [0; LANES];
+                for (lane, value) in value.iter_mut().enumerate().take(2) {
+                    let control = immediate >> (4 * lane);
+                    if control & 8 == 0 {
+                        *value = sources[control as usize & 3];
+                    }
+                }
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // the quadwords of each 256 bits of the source selected by the immediate, or the elements by the indexes of
+            // the first source
+            Mnemonic::Vpermq | Mnemonic::Vpermpd if instruction.op_kind(2) == OpKind::Immediate8 => {
+                let (source, immediate) = (self.read_vector_operand(instruction, 1)?, instruction.immediate8() as u32);
+                let value = elements(64, |index| vector_element(&source, 64, (index & !3) + (immediate >> (2 * (index & 3)) & 3)));
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+            Mnemonic::Vpermd | Mnemonic::Vpermps | Mnemonic::Vpermq | Mnemonic::Vpermpd | Mnemonic::Vpermw => {
+                let (indexes, table) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let bits = element_bits(mnemonic);
+                let count = element_count(instruction, bits);
+                let value = elements(bits, |index| vector_element(&table, bits, vector_element(&indexes, bits, index) as u32 & (count - 1)));
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // the elements selected within each lane by the immediate or by the control elements of the second source
+            Mnemonic::Vpermilps | Mnemonic::Vpermilpd => {
+                let source = self.read_vector_operand(instruction, 1)?;
+                let control = match instruction.op_kind(2) {
+                    OpKind::Immediate8 => None,
+                    _ => Some(self.read_vector_operand(instruction, 2)?),
+                };
+                let immediate = instruction.immediate8() as u32;
+                let bits = element_bits(mnemonic);
+                let value = elements(bits, |index| {
+                    let selector = match (mnemonic, control) {
+                        (Mnemonic::Vpermilps, None) => immediate >> (2 * (index % 4)) & 3,
+                        (Mnemonic::Vpermilps, Some(control)) => vector_element(&control, 32, index) as u32 & 3,
+                        (_, None) => immediate >> (index % 8) & 1,
+                        (_, Some(control)) => (vector_element(&control, 64, index) as u32 >> 1) & 1,
+                    };
+                    vector_element(&source, bits, index - index % (128 / bits) + selector)
+                });
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // the lane selected by the immediate replaced or extracted
+            Mnemonic::Vinsertf128 | Mnemonic::Vinserti128 => {
+                let mut value = self.read_vector_operand(instruction, 1)?;
+                value[instruction.immediate8() as usize & 1] = self.read_xmm_operand(instruction, 2)?;
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+            Mnemonic::Vextractf128 | Mnemonic::Vextracti128 => {
+                let value = self.read_vector_operand(instruction, 1)?[instruction.immediate8() as usize & 1];
+                self.write_xmm_operand(instruction, 0, value)?;
+            }
+
+            Mnemonic::Vmaskmovps | Mnemonic::Vmaskmovpd | Mnemonic::Vpmaskmovd | Mnemonic::Vpmaskmovq => self.masked_move(instruction)?,
+            Mnemonic::Vgatherdps | Mnemonic::Vgatherdpd | Mnemonic::Vgatherqps | Mnemonic::Vgatherqpd | Mnemonic::Vpgatherdd
+            | Mnemonic::Vpgatherdq | Mnemonic::Vpgatherqd | Mnemonic::Vpgatherqq => self.gather(instruction)?,
+
+            // each element shifted by the count in the same element of the second source, the logical shifts by the
+            // width or more give zero and the arithmetic ones the sign
+            Mnemonic::Vpsllvw | Mnemonic::Vpsllvd | Mnemonic::Vpsllvq | Mnemonic::Vpsrlvw | Mnemonic::Vpsrlvd | Mnemonic::Vpsrlvq
+            | Mnemonic::Vpsravw | Mnemonic::Vpsravd | Mnemonic::Vpsravq => {
+                let (source, counts) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let bits = element_bits(mnemonic);
+                let value = elements(bits, |index| {
+                    let (element, count) = (vector_element(&source, bits, index), vector_element(&counts, bits, index));
+                    match mnemonic {
+                        Mnemonic::Vpsravw | Mnemonic::Vpsravd | Mnemonic::Vpsravq => (signed(element, bits) >> count.min(bits as u64 - 1)) as u64,
+                        _ if count >= bits as u64 => 0,
+                        Mnemonic::Vpsllvw | Mnemonic::Vpsllvd | Mnemonic::Vpsllvq => element << count,
+                        _ => element >> count,
+                    }
+                });
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // the doublewords of the second source selected by the bits of the immediate, those of the first for the others
+            Mnemonic::Vpblendd => {
+                let (a, b) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let immediate = instruction.immediate8();
+                let value = elements(32, |index| vector_element(if immediate >> (index % 8) & 1 != 0 { &b } else { &a }, 32, index));
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // ptest on the sign bits of the elements only
+            Mnemonic::Vtestps | Mnemonic::Vtestpd => {
+                let (a, b) = (self.read_vector_operand(instruction, 0)?, self.read_vector_operand(instruction, 1)?);
+                let bits = element_bits(mnemonic);
+                let signs = sse_integer::vector(bits, |_| 1 << (bits - 1));
+                let (and, and_not) = (0..LANES).fold((0, 0), |(and, and_not), lane| (and | (a[lane] & b[lane] & signs), and_not | (!a[lane] & b[lane] & signs)));
+                let zero = |value: u128, flag: u64| if value == 0 { flag } else { 0 };
+                self.update_flags(zero(and, ZF) | zero(and_not, CF), ZF | CF | OF | SF | AF | PF);
+            }
+
+            Mnemonic::Vfmadd132ps | Mnemonic::Vfmadd132pd | Mnemonic::Vfmadd132ss | Mnemonic::Vfmadd132sd | Mnemonic::Vfmadd213ps
+            | Mnemonic::Vfmadd213pd | Mnemonic::Vfmadd213ss | Mnemonic::Vfmadd213sd | Mnemonic::Vfmadd231ps
+            | Mnemonic::Vfmadd231pd | Mnemonic::Vfmadd231ss | Mnemonic::Vfmadd231sd | Mnemonic::Vfmsub132ps
+            | Mnemonic::Vfmsub132pd | Mnemonic::Vfmsub132ss | Mnemonic::Vfmsub132sd | Mnemonic::Vfmsub213ps
+            | Mnemonic::Vfmsub213pd | Mnemonic::Vfmsub213ss | Mnemonic::Vfmsub213sd | Mnemonic::Vfmsub231ps
+            | Mnemonic::Vfmsub231pd | Mnemonic::Vfmsub231ss | Mnemonic::Vfmsub231sd | Mnemonic::Vfnmadd132ps
+            | Mnemonic::Vfnmadd132pd | Mnemonic::Vfnmadd132ss | Mnemonic::Vfnmadd132sd | Mnemonic::Vfnmadd213ps
+            | Mnemonic::Vfnmadd213pd | Mnemonic::Vfnmadd213ss | Mnemonic::Vfnmadd213sd | Mnemonic::Vfnmadd231ps
+            | Mnemonic::Vfnmadd231pd | Mnemonic::Vfnmadd231ss | Mnemonic::Vfnmadd231sd | Mnemonic::Vfnmsub132ps
+            | Mnemonic::Vfnmsub132pd | Mnemonic::Vfnmsub132ss | Mnemonic::Vfnmsub132sd | Mnemonic::Vfnmsub213ps
+            | Mnemonic::Vfnmsub213pd | Mnemonic::Vfnmsub213ss | Mnemonic::Vfnmsub213sd | Mnemonic::Vfnmsub231ps
+            | Mnemonic::Vfnmsub231pd | Mnemonic::Vfnmsub231ss | Mnemonic::Vfnmsub231sd | Mnemonic::Vfmaddsub132ps
+            | Mnemonic::Vfmaddsub132pd | Mnemonic::Vfmaddsub213ps | Mnemonic::Vfmaddsub213pd | Mnemonic::Vfmaddsub231ps
+            | Mnemonic::Vfmaddsub231pd | Mnemonic::Vfmsubadd132ps | Mnemonic::Vfmsubadd132pd | Mnemonic::Vfmsubadd213ps
+            | Mnemonic::Vfmsubadd213pd | Mnemonic::Vfmsubadd231ps | Mnemonic::Vfmsubadd231pd => {
+                // the elements the write mask excludes are computed on ones, which raise no exception
+                let (d, s1, s2) = (self.read_vector_operand(instruction, 0)?, self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let [d, s1, s2] = [d, s1, s2].map(|operand| self.mask_float_source(instruction, operand));
+                let (denormals_are_zero, mut context) = (self.registers.sse.denormals_are_zero(), self.vector_context(instruction));
+                let mut value = [0; LANES];
+                for (lane, value) in value.iter_mut().enumerate().take(instruction.op0_register().size() / 16) {
+                    *value = fused_operation(mnemonic, d[lane], s1[lane], s2[lane], denormals_are_zero, &mut context);
+                }
+                self.report_sse(instruction, &context)?;
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            _ => return self.execute_sse(instruction),
+        }
+        Ok(())
+    }
+
+    /// vmaskmovps, vmaskmovpd, vpmaskmovd and vpmaskmovq: the elements whose sign bit is set in the mask are loaded,
+    /// the others zeroed, or stored, the others left in memory. Masked elements are not accessed and cannot fault.
+    fn masked_move(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let bits = element_bits(instruction.mnemonic());
+        let mask = self.read_vector_operand(instruction, 1)?;
+        let address = self.memory_address(instruction)?;
+        let store = instruction.op0_kind() == OpKind::Memory;
+        let count = element_count(instruction, bits);
+        let selected = (0..count).filter(|&index| vector_element(&mask, bits, index) >> (bits - 1) != 0);
+        let element_address = |index: u32| address.wrapping_add((bits / 8 * index) as u64);
+
+        match store {
+            true => {
+                let data = self.read_vector_operand(instruction, 2)?;
+                for index in selected {
+                    self.memory.write_uint(element_address(index), bits as usize / 8, vector_element(&data, bits, index))?;
+                }
+                Ok(())
+            }
+            false => {
+                let mut value = [0; LANES];
+                for index in selected {
+                    set_element(&mut value, bits, index, self.memory.read_uint(element_address(index), bits as usize / 8)?);
+                }
+                self.write_vector_operand(instruction, 0, value)
+            }
+        }
+    }
+
+    /// The gathers: the elements whose sign bit is set in the mask (the third operand) are loaded from the addresses
+    /// indexed by the elements of the vector index register, clearing their mask element. A fault leaves the elements
+    /// loaded before it in the destination and their mask elements cleared, otherwise the whole mask is zeroed.
+    fn gather(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let (destination, index_register, mask_register) = (instruction.op0_register(), instruction.memory_index(), instruction.op2_register());
+        let numbers = [destination.number(), index_register.number(), mask_register.number()];
+        if numbers[0] == numbers[1] || numbers[0] == numbers[2] || numbers[1] == numbers[2] {
+            return Err(Error::InvalidOpcode(instruction.ip()));
+        }
+
+        let (data_bits, index_bits) = match instruction.mnemonic() {
+            Mnemonic::Vgatherdps | Mnemonic::Vpgatherdd => (32, 32),
+            Mnemonic::Vgatherdpd | Mnemonic::Vpgatherdq => (64, 32),
+            Mnemonic::Vgatherqps | Mnemonic::Vpgatherqd => (32, 64),
+            _ => (64, 64),
+        };
+        let destination_count = destination.size() as u32 * 8 / data_bits;
+        let count = destination_count.min(index_register.size() as u32 * 8 / index_bits);
+        let indexes = self.registers.sse.zmm[index_register.number()];
+        let mut value = self.read_vector_operand(instruction, 0)?;
+        let mut mask = self.read_vector_operand(instruction, 2)?;
+
+        for index in 0..count {
+            if vector_element(&mask, data_bits, index) >> (data_bits - 1) == 0 {
+                continue;
+            }
+            let element = signed(vector_element(&indexes, index_bits, index), index_bits) as u64;
+            let loaded = self.vector_element_address(instruction, element).and_then(|address| Ok(self.memory.read_uint(address, data_bits as usize / 8)?));
+            match loaded {
+                Ok(loaded) => {
+                    set_element(&mut value, data_bits, index, loaded);
+                    set_element(&mut mask, data_bits, index, 0);
+                }
+                Err(e) => {
+                    self.write_vector_register(instruction, destination, value);
+                    self.write_vector_register(instruction, mask_register, mask);
+                    return Err(e);
+                }
+            }
+        }
+        for index in count..destination_count {
+            set_element(&mut value, data_bits, index, 0);
+        }
+        self.write_vector_register(instruction, destination, value);
+        self.write_vector_register(instruction, mask_register, [0; LANES]);
+        Ok(())
+    }
+}
+
+/// Mnemonic of the SSE instruction executed for a VEX-encoded one, the mnemonic itself for the others
+pub fn legacy_mnemonic(mnemonic: Mnemonic) -> Mnemonic {
+    match mnemonic {
+        Mnemonic::Vmovaps => Mnemonic::Movaps,
+        Mnemonic::Vmovapd => Mnemonic::Movapd,
+        Mnemonic::Vmovdqa | Mnemonic::Vmovdqa32 | Mnemonic::Vmovdqa64 => Mnemonic::Movdqa,
+        Mnemonic::Vmovups => Mnemonic::Movups,
+        Mnemonic::Vmovupd => Mnemonic::Movupd,
+        Mnemonic::Vmovdqu | Mnemonic::Vmovdqu8 | Mnemonic::Vmovdqu16 | Mnemonic::Vmovdqu32 | Mnemonic::Vmovdqu64 => Mnemonic::Movdqu,
+        Mnemonic::Vmovntps => Mnemonic::Movntps,
+        Mnemonic::Vmovntpd => Mnemonic::Movntpd,
+        Mnemonic::Vmovntdq => Mnemonic::Movntdq,
+        Mnemonic::Vmovss => Mnemonic::Movss,
+        Mnemonic::Vmovsd => Mnemonic::Movsd,
+        Mnemonic::Vmovd => Mnemonic::Movd,
+        Mnemonic::Vmovq => Mnemonic::Movq,
+        Mnemonic::Vmovlps => Mnemonic::Movlps,
+        Mnemonic::Vmovlpd => Mnemonic::Movlpd,
+        Mnemonic::Vmovhps => Mnemonic::Movhps,
+        Mnemonic::Vmovhpd => Mnemonic::Movhpd,
+        Mnemonic::Vmovhlps => Mnemonic::Movhlps,
+        Mnemonic::Vmovlhps => Mnemonic::Movlhps,
+        Mnemonic::Vmovmskps => Mnemonic::Movmskps,
+        Mnemonic::Vmovmskpd => Mnemonic::Movmskpd,
+        Mnemonic::Vpmovmskb => Mnemonic::Pmovmskb,
+        Mnemonic::Vpextrw => Mnemonic::Pextrw,
+        Mnemonic::Vpinsrw => Mnemonic::Pinsrw,
+        Mnemonic::Vmaskmovdqu => Mnemonic::Maskmovdqu,
+        Mnemonic::Vlddqu => Mnemonic::Lddqu,
+        Mnemonic::Vmovntdqa => Mnemonic::Movntdqa,
+        Mnemonic::Vmovddup => Mnemonic::Movddup,
+        Mnemonic::Vmovshdup => Mnemonic::Movshdup,
+        Mnemonic::Vmovsldup => Mnemonic::Movsldup,
+        Mnemonic::Vpextrb => Mnemonic::Pextrb,
+        Mnemonic::Vpextrd => Mnemonic::Pextrd,
+        Mnemonic::Vpextrq => Mnemonic::Pextrq,
+        Mnemonic::Vextractps => Mnemonic::Extractps,
+        Mnemonic::Vpinsrb => Mnemonic::Pinsrb,
+        Mnemonic::Vpinsrd => Mnemonic::Pinsrd,
+        Mnemonic::Vpinsrq => Mnemonic::Pinsrq,
+        Mnemonic::Vinsertps => Mnemonic::Insertps,
+        Mnemonic::Vldmxcsr => Mnemonic::Ldmxcsr,
+        Mnemonic::Vstmxcsr => Mnemonic::Stmxcsr,
+        Mnemonic::Vaddps => Mnemonic::Addps,
+        Mnemonic::Vaddpd => Mnemonic::Addpd,
+        Mnemonic::Vaddss => Mnemonic::Addss,
+        Mnemonic::Vaddsd => Mnemonic::Addsd,
+        Mnemonic::Vsubps => Mnemonic::Subps,
+        Mnemonic::Vsubpd => Mnemonic::Subpd,
+        Mnemonic::Vsubss => Mnemonic::Subss,
+        Mnemonic::Vsubsd => Mnemonic::Subsd,
+        Mnemonic::Vmulps => Mnemonic::Mulps,
+        Mnemonic::Vmulpd => Mnemonic::Mulpd,
+        Mnemonic::Vmulss => Mnemonic::Mulss,
+        Mnemonic::Vmulsd => Mnemonic::Mulsd,
+        Mnemonic::Vdivps => Mnemonic::Divps,
+        Mnemonic::Vdivpd => Mnemonic::Divpd,
+        Mnemonic::Vdivss => Mnemonic::Divss,
+        Mnemonic::Vdivsd => Mnemonic::Divsd,
+        Mnemonic::Vminps => Mnemonic::Minps,
+        Mnemonic::Vminpd => Mnemonic::Minpd,
+        Mnemonic::Vminss => Mnemonic::Minss,
+        Mnemonic::Vminsd => Mnemonic::Minsd,
+        Mnemonic::Vmaxps => Mnemonic::Maxps,
+        Mnemonic::Vmaxpd => Mnemonic::Maxpd,
+        Mnemonic::Vmaxss => Mnemonic::Maxss,
+        Mnemonic::Vmaxsd => Mnemonic::Maxsd,
+        Mnemonic::Vsqrtps => Mnemonic::Sqrtps,
+        Mnemonic::Vsqrtpd => Mnemonic::Sqrtpd,
+        Mnemonic::Vsqrtss => Mnemonic::Sqrtss,
+        Mnemonic::Vsqrtsd => Mnemonic::Sqrtsd,
+        Mnemonic::Vrcpps => Mnemonic::Rcpps,
+        Mnemonic::Vrcpss => Mnemonic::Rcpss,
+        Mnemonic::Vrsqrtps => Mnemonic::Rsqrtps,
+        Mnemonic::Vrsqrtss => Mnemonic::Rsqrtss,
+        Mnemonic::Vcmpps => Mnemonic::Cmpps,
+        Mnemonic::Vcmppd => Mnemonic::Cmppd,
+        Mnemonic::Vcmpss => Mnemonic::Cmpss,
+        Mnemonic::Vcmpsd => Mnemonic::Cmpsd,
+        Mnemonic::Vcomiss => Mnemonic::Comiss,
+        Mnemonic::Vcomisd => Mnemonic::Comisd,
+        Mnemonic::Vucomiss => Mnemonic::Ucomiss,
+        Mnemonic::Vucomisd => Mnemonic::Ucomisd,
+        Mnemonic::Vcvtsi2ss => Mnemonic::Cvtsi2ss,
+        Mnemonic::Vcvtsi2sd => Mnemonic::Cvtsi2sd,
+        Mnemonic::Vcvtss2si => Mnemonic::Cvtss2si,
+        Mnemonic::Vcvtsd2si => Mnemonic::Cvtsd2si,
+        Mnemonic::Vcvttss2si => Mnemonic::Cvttss2si,
+        Mnemonic::Vcvttsd2si => Mnemonic::Cvttsd2si,
+        Mnemonic::Vcvtss2sd => Mnemonic::Cvtss2sd,
+        Mnemonic::Vcvtsd2ss => Mnemonic::Cvtsd2ss,
+        Mnemonic::Vcvtps2pd => Mnemonic::Cvtps2pd,
+        Mnemonic::Vcvtpd2ps => Mnemonic::Cvtpd2ps,
+        Mnemonic::Vcvtdq2ps => Mnemonic::Cvtdq2ps,
+        Mnemonic::Vcvtps2dq => Mnemonic::Cvtps2dq,
+        Mnemonic::Vcvttps2dq => Mnemonic::Cvttps2dq,
+        Mnemonic::Vcvtdq2pd => Mnemonic::Cvtdq2pd,
+        Mnemonic::Vcvtpd2dq => Mnemonic::Cvtpd2dq,
+        Mnemonic::Vcvttpd2dq => Mnemonic::Cvttpd2dq,
+        Mnemonic::Vaddsubps => Mnemonic::Addsubps,
+        Mnemonic::Vaddsubpd => Mnemonic::Addsubpd,
+        Mnemonic::Vhaddps => Mnemonic::Haddps,
+        Mnemonic::Vhaddpd => Mnemonic::Haddpd,
+        Mnemonic::Vhsubps => Mnemonic::Hsubps,
+        Mnemonic::Vhsubpd => Mnemonic::Hsubpd,
+        Mnemonic::Vdpps => Mnemonic::Dpps,
+        Mnemonic::Vdppd => Mnemonic::Dppd,
+        Mnemonic::Vroundps => Mnemonic::Roundps,
+        Mnemonic::Vroundpd => Mnemonic::Roundpd,
+        Mnemonic::Vroundss => Mnemonic::Roundss,
+        Mnemonic::Vroundsd => Mnemonic::Roundsd,
+        Mnemonic::Vpcmpestri => Mnemonic::Pcmpestri,
+        Mnemonic::Vpcmpestri64 => Mnemonic::Pcmpestri64,
+        Mnemonic::Vpcmpestrm => Mnemonic::Pcmpestrm,
+        Mnemonic::Vpcmpestrm64 => Mnemonic::Pcmpestrm64,
+        Mnemonic::Vpcmpistri => Mnemonic::Pcmpistri,
+        Mnemonic::Vpcmpistrm => Mnemonic::Pcmpistrm,
+        Mnemonic::Vandps => Mnemonic::Andps,
+        Mnemonic::Vandpd => Mnemonic::Andpd,
+        Mnemonic::Vandnps => Mnemonic::Andnps,
+        Mnemonic::Vandnpd => Mnemonic::Andnpd,
+        Mnemonic::Vorps => Mnemonic::Orps,
+        Mnemonic::Vorpd => Mnemonic::Orpd,
+        Mnemonic::Vxorps => Mnemonic::Xorps,
+        Mnemonic::Vxorpd => Mnemonic::Xorpd,
+        Mnemonic::Vpand | Mnemonic::Vpandd | Mnemonic::Vpandq => Mnemonic::Pand,
+        Mnemonic::Vpandn | Mnemonic::Vpandnd | Mnemonic::Vpandnq => Mnemonic::Pandn,
+        Mnemonic::Vpor | Mnemonic::Vpord | Mnemonic::Vporq => Mnemonic::Por,
+        Mnemonic::Vpxor | Mnemonic::Vpxord | Mnemonic::Vpxorq => Mnemonic::Pxor,
+        Mnemonic::Vpaddb => Mnemonic::Paddb,
+        Mnemonic::Vpaddw => Mnemonic::Paddw,
+        Mnemonic::Vpaddd => Mnemonic::Paddd,
+        Mnemonic::Vpaddq => Mnemonic::Paddq,
+        Mnemonic::Vpsubb => Mnemonic::Psubb,
+        Mnemonic::Vpsubw => Mnemonic::Psubw,
+        Mnemonic::Vpsubd => Mnemonic::Psubd,
+        Mnemonic::Vpsubq => Mnemonic::Psubq,
+        Mnemonic::Vpaddsb => Mnemonic::Paddsb,
+        Mnemonic::Vpaddsw => Mnemonic::Paddsw,
+        Mnemonic::Vpsubsb => Mnemonic::Psubsb,
+        Mnemonic::Vpsubsw => Mnemonic::Psubsw,
+        Mnemonic::Vpaddusb => Mnemonic::Paddusb,
+        Mnemonic::Vpaddusw => Mnemonic::Paddusw,
+        Mnemonic::Vpsubusb => Mnemonic::Psubusb,
+        Mnemonic::Vpsubusw => Mnemonic::Psubusw,
+        Mnemonic::Vpmullw => Mnemonic::Pmullw,
+        Mnemonic::Vpmulhw => Mnemonic::Pmulhw,
+        Mnemonic::Vpmulhuw => Mnemonic::Pmulhuw,
+        Mnemonic::Vpmuludq => Mnemonic::Pmuludq,
+        Mnemonic::Vpmaddwd => Mnemonic::Pmaddwd,
+        Mnemonic::Vpsadbw => Mnemonic::Psadbw,
+        Mnemonic::Vpavgb => Mnemonic::Pavgb,
+        Mnemonic::Vpavgw => Mnemonic::Pavgw,
+        Mnemonic::Vpminub => Mnemonic::Pminub,
+        Mnemonic::Vpmaxub => Mnemonic::Pmaxub,
+        Mnemonic::Vpminsw => Mnemonic::Pminsw,
+        Mnemonic::Vpmaxsw => Mnemonic::Pmaxsw,
+        Mnemonic::Vpcmpeqb => Mnemonic::Pcmpeqb,
+        Mnemonic::Vpcmpeqw => Mnemonic::Pcmpeqw,
+        Mnemonic::Vpcmpeqd => Mnemonic::Pcmpeqd,
+        Mnemonic::Vpcmpgtb => Mnemonic::Pcmpgtb,
+        Mnemonic::Vpcmpgtw => Mnemonic::Pcmpgtw,
+        Mnemonic::Vpcmpgtd => Mnemonic::Pcmpgtd,
+        Mnemonic::Vpsllw => Mnemonic::Psllw,
+        Mnemonic::Vpslld => Mnemonic::Pslld,
+        Mnemonic::Vpsllq => Mnemonic::Psllq,
+        Mnemonic::Vpsrlw => Mnemonic::Psrlw,
+        Mnemonic::Vpsrld => Mnemonic::Psrld,
+        Mnemonic::Vpsrlq => Mnemonic::Psrlq,
+        Mnemonic::Vpsraw => Mnemonic::Psraw,
+        Mnemonic::Vpsrad => Mnemonic::Psrad,
+        Mnemonic::Vpslldq => Mnemonic::Pslldq,
+        Mnemonic::Vpsrldq => Mnemonic::Psrldq,
+        Mnemonic::Vpacksswb => Mnemonic::Packsswb,
+        Mnemonic::Vpackssdw => Mnemonic::Packssdw,
+        Mnemonic::Vpackuswb => Mnemonic::Packuswb,
+        Mnemonic::Vpunpcklbw => Mnemonic::Punpcklbw,
+        Mnemonic::Vpunpcklwd => Mnemonic::Punpcklwd,
+        Mnemonic::Vpunpckldq => Mnemonic::Punpckldq,
+        Mnemonic::Vpunpcklqdq => Mnemonic::Punpcklqdq,
+        Mnemonic::Vpunpckhbw => Mnemonic::Punpckhbw,
+        Mnemonic::Vpunpckhwd => Mnemonic::Punpckhwd,
+        Mnemonic::Vpunpckhdq => Mnemonic::Punpckhdq,
+        Mnemonic::Vpunpckhqdq => Mnemonic::Punpckhqdq,
+        Mnemonic::Vunpcklps => Mnemonic::Unpcklps,
+        Mnemonic::Vunpcklpd => Mnemonic::Unpcklpd,
+        Mnemonic::Vunpckhps => Mnemonic::Unpckhps,
+        Mnemonic::Vunpckhpd => Mnemonic::Unpckhpd,
+        Mnemonic::Vpshufd => Mnemonic::Pshufd,
+        Mnemonic::Vpshuflw => Mnemonic::Pshuflw,
+        Mnemonic::Vpshufhw => Mnemonic::Pshufhw,
+        Mnemonic::Vshufps => Mnemonic::Shufps,
+        Mnemonic::Vshufpd => Mnemonic::Shufpd,
+        Mnemonic::Vpshufb => Mnemonic::Pshufb,
+        Mnemonic::Vpalignr => Mnemonic::Palignr,
+        Mnemonic::Vpabsb => Mnemonic::Pabsb,
+        Mnemonic::Vpabsw => Mnemonic::Pabsw,
+        Mnemonic::Vpabsd => Mnemonic::Pabsd,
+        Mnemonic::Vpsignb => Mnemonic::Psignb,
+        Mnemonic::Vpsignw => Mnemonic::Psignw,
+        Mnemonic::Vpsignd => Mnemonic::Psignd,
+        Mnemonic::Vphaddw => Mnemonic::Phaddw,
+        Mnemonic::Vphaddd => Mnemonic::Phaddd,
+        Mnemonic::Vphaddsw => Mnemonic::Phaddsw,
+        Mnemonic::Vphsubw => Mnemonic::Phsubw,
+        Mnemonic::Vphsubd => Mnemonic::Phsubd,
+        Mnemonic::Vphsubsw => Mnemonic::Phsubsw,
+        Mnemonic::Vpmaddubsw => Mnemonic::Pmaddubsw,
+        Mnemonic::Vpmulhrsw => Mnemonic::Pmulhrsw,
+        Mnemonic::Vblendps => Mnemonic::Blendps,
+        Mnemonic::Vblendpd => Mnemonic::Blendpd,
+        Mnemonic::Vblendvps => Mnemonic::Blendvps,
+        Mnemonic::Vblendvpd => Mnemonic::Blendvpd,
+        Mnemonic::Vpblendvb => Mnemonic::Pblendvb,
+        Mnemonic::Vpblendw => Mnemonic::Pblendw,
+        Mnemonic::Vmpsadbw => Mnemonic::Mpsadbw,
+        Mnemonic::Vpackusdw => Mnemonic::Packusdw,
+        Mnemonic::Vpcmpeqq => Mnemonic::Pcmpeqq,
+        Mnemonic::Vpcmpgtq => Mnemonic::Pcmpgtq,
+        Mnemonic::Vphminposuw => Mnemonic::Phminposuw,
+        Mnemonic::Vpminsb => Mnemonic::Pminsb,
+        Mnemonic::Vpminsd => Mnemonic::Pminsd,
+        Mnemonic::Vpminuw => Mnemonic::Pminuw,
+        Mnemonic::Vpminud => Mnemonic::Pminud,
+        Mnemonic::Vpmaxsb => Mnemonic::Pmaxsb,
+        Mnemonic::Vpmaxsd => Mnemonic::Pmaxsd,
+        Mnemonic::Vpmaxuw => Mnemonic::Pmaxuw,
+        Mnemonic::Vpmaxud => Mnemonic::Pmaxud,
+        Mnemonic::Vpmovsxbw => Mnemonic::Pmovsxbw,
+        Mnemonic::Vpmovsxbd => Mnemonic::Pmovsxbd,
+        Mnemonic::Vpmovsxbq => Mnemonic::Pmovsxbq,
+        Mnemonic::Vpmovsxwd => Mnemonic::Pmovsxwd,
+        Mnemonic::Vpmovsxwq => Mnemonic::Pmovsxwq,
+        Mnemonic::Vpmovsxdq => Mnemonic::Pmovsxdq,
+        Mnemonic::Vpmovzxbw => Mnemonic::Pmovzxbw,
+        Mnemonic::Vpmovzxbd => Mnemonic::Pmovzxbd,
+        Mnemonic::Vpmovzxbq => Mnemonic::Pmovzxbq,
+        Mnemonic::Vpmovzxwd => Mnemonic::Pmovzxwd,
+        Mnemonic::Vpmovzxwq => Mnemonic::Pmovzxwq,
+        Mnemonic::Vpmovzxdq => Mnemonic::Pmovzxdq,
+        Mnemonic::Vpmuldq => Mnemonic::Pmuldq,
+        Mnemonic::Vpmulld => Mnemonic::Pmulld,
+        Mnemonic::Vptest => Mnemonic::Ptest,
+        _ => mnemonic,
+    }
+}
+
+/// Size of the elements of an instruction operating on the same elements throughout
+pub fn element_bits(mnemonic: Mnemonic) -> u32 {
+    match mnemonic {
+        Mnemonic::Vpbroadcastb => 8,
+        Mnemonic::Vpbroadcastw | Mnemonic::Vpermw | Mnemonic::Vpsllvw | Mnemonic::Vpsrlvw | Mnemonic::Vpsravw => 16,
+        Mnemonic::Vbroadcastsd | Mnemonic::Vpbroadcastq | Mnemonic::Vpermilpd | Mnemonic::Vpsllvq | Mnemonic::Vpsrlvq | Mnemonic::Vpsravq
+        | Mnemonic::Vtestpd | Mnemonic::Vmaskmovpd | Mnemonic::Vpmaskmovq | Mnemonic::Vpermq | Mnemonic::Vpermpd | Mnemonic::Vbroadcastf32x2
+        | Mnemonic::Vbroadcasti32x2 => 64,
+        Mnemonic::Vbroadcastf128 | Mnemonic::Vbroadcasti128 | Mnemonic::Vbroadcastf32x4 | Mnemonic::Vbroadcastf64x2 | Mnemonic::Vbroadcasti32x4
+        | Mnemonic::Vbroadcasti64x2 => 128,
+        Mnemonic::Vbroadcastf32x8 | Mnemonic::Vbroadcastf64x4 | Mnemonic::Vbroadcasti32x8 | Mnemonic::Vbroadcasti64x4 => 256,
+        _ => 32,
+    }
+}
+
+/// Number of `bits`-bit elements of the destination register, or of the memory operand a store writes
+pub fn element_count(instruction: &Instruction, bits: u32) -> u32 {
+    let size = match instruction.op0_kind() {
+        OpKind::Register => instruction.op0_register().size(),
+        _ => instruction.memory_size().size(),
+    };
+    size as u32 * 8 / bits
+}
+
+/// The `bits`-bit element `index` of a vector, counting across its lanes
+pub fn vector_element(vector: &Vector, bits: u32, index: u32) -> u64 {
+    let per_lane = 128 / bits;
+    element(vector[(index / per_lane) as usize], bits, index % per_lane)
+}
+
+/// Replaces the `bits`-bit element `index` of a vector
+pub fn set_element(vector: &mut Vector, bits: u32, index: u32, value: u64) {
+    let (per_lane, mask) = (128 / bits, u128::MAX >> (128 - bits));
+    let (lane, shift) = ((index / per_lane) as usize, bits * (index % per_lane));
+    vector[lane] = (vector[lane] & !(mask << shift)) | (value as u128 & mask) << shift;
+}
+
+/// The vector of the `bits`-bit elements computed for each index across all lanes
+pub fn elements(bits: u32, mut element: impl FnMut(u32) -> u64) -> Vector {
+    let per_lane = 128 / bits;
+    std::array::from_fn(|lane| sse_integer::vector(bits, |index| element(lane as u32 * per_lane + index)))
+}
@@ -0,0 +1,559 @@
+use super::avx::{element_bits, elements, set_element, vector_element};
+use super::error::Error;
+use super::flags::{AF, CF, OF, PF, SF, ZF};
+use super::sse::{vector_from_bytes, vector_length, vector_to_bytes, Vector, LANES};
+use super::sse_integer::signed;
+use super::Cpu;
+
+use iced_x86::{Instruction, MemorySize, Mnemonic, OpCodeOperandKind, OpKind, Register};
+
+/// Saturation of the elements of a down conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Saturation {
+    /// the low bits of the elements are kept
+    Truncate,
+    Signed,
+    Unsigned,
+}
+
+impl Cpu {
+    /// Executes an EVEX-encoded instruction: those of AVX-512 without a VEX form here, the others by their AVX or SSE
+    /// implementation, the write mask applying to their result when they write it
+    pub fn execute_avx512(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        self.check_features(instruction)?;
+        let mnemonic = instruction.mnemonic();
+        match mnemonic {
+            Mnemonic::Vcvtss2usi | Mnemonic::Vcvtsd2usi | Mnemonic::Vcvttss2usi | Mnemonic::Vcvttsd2usi | Mnemonic::Vcvtusi2ss
+            | Mnemonic::Vcvtusi2sd | Mnemonic::Vcvtps2udq | Mnemonic::Vcvttps2udq | Mnemonic::Vcvtpd2udq | Mnemonic::Vcvttpd2udq
+            | Mnemonic::Vcvtudq2ps | Mnemonic::Vcvtudq2pd | Mnemonic::Vcvtps2qq | Mnemonic::Vcvttps2qq | Mnemonic::Vcvtps2uqq
+            | Mnemonic::Vcvttps2uqq | Mnemonic::Vcvtpd2qq | Mnemonic::Vcvttpd2qq | Mnemonic::Vcvtpd2uqq | Mnemonic::Vcvttpd2uqq
+            | Mnemonic::Vcvtqq2ps | Mnemonic::Vcvtqq2pd | Mnemonic::Vcvtuqq2ps | Mnemonic::Vcvtuqq2pd | Mnemonic::Vrcp14ps
+            | Mnemonic::Vrcp14pd | Mnemonic::Vrcp14ss | Mnemonic::Vrcp14sd | Mnemonic::Vrsqrt14ps | Mnemonic::Vrsqrt14pd
+            | Mnemonic::Vrsqrt14ss | Mnemonic::Vrsqrt14sd | Mnemonic::Vgetexpps | Mnemonic::Vgetexppd | Mnemonic::Vgetexpss
+            | Mnemonic::Vgetexpsd | Mnemonic::Vgetmantps | Mnemonic::Vgetmantpd | Mnemonic::Vgetmantss | Mnemonic::Vgetmantsd
+            | Mnemonic::Vrndscaleps | Mnemonic::Vrndscalepd | Mnemonic::Vrndscaless | Mnemonic::Vrndscalesd | Mnemonic::Vreduceps
+            | Mnemonic::Vreducepd | Mnemonic::Vreducess | Mnemonic::Vreducesd | Mnemonic::Vrangeps | Mnemonic::Vrangepd
+            | Mnemonic::Vrangess | Mnemonic::Vrangesd | Mnemonic::Vscalefps | Mnemonic::Vscalefpd | Mnemonic::Vscalefss
+            | Mnemonic::Vscalefsd | Mnemonic::Vfixupimmps | Mnemonic::Vfixupimmpd | Mnemonic::Vfixupimmss | Mnemonic::Vfixupimmsd
+            | Mnemonic::Vfpclassps | Mnemonic::Vfpclasspd | Mnemonic::Vfpclassss | Mnemonic::Vfpclasssd => return self.sse_float(instruction, mnemonic),
+
+            // the elements of the second source where the write mask is set, those of the first or zeros elsewhere
+            Mnemonic::Vblendmps | Mnemonic::Vblendmpd | Mnemonic::Vpblendmb | Mnemonic::Vpblendmw | Mnemonic::Vpblendmd
+            | Mnemonic::Vpblendmq => {
+                let (a, b) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let (bits, count) = mask_layout(instruction);
+                let (mask, zeroing) = (self.write_mask(instruction), instruction.zeroing_masking());
+                let value = elements(bits, |index| match selected(mask, index) {
+                    true => vector_element(&b, bits, index),
+                    false if zeroing || index >= count => 0,
+                    false => vector_element(&a, bits, index),
+                });
+                self.write_vector_register(instruction, instruction.op0_register(), value);
+            }
+
+            // each bit is the bit of the immediate indexed by the bits of the destination and the two sources
+            Mnemonic::Vpternlogd | Mnemonic::Vpternlogq => {
+                let [d, a, b] = [self.read_vector_operand(instruction, 0)?, self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?];
+                let immediate = instruction.immediate8();
+                let value = std::array::from_fn(|lane| {
+                    (0..8).filter(|index| immediate >> index & 1 != 0).fold(0, |value, index| {
+                        let bit = |operand: u128, set: bool| if set { operand } else { !operand };
+                        value | (bit(d[lane], index & 4 != 0) & bit(a[lane], index & 2 != 0) & bit(b[lane], index & 1 != 0))
+                    })
+                });
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // the elements of a table of two registers selected by the indexes, which vpermi2 takes from the
+            // destination and overwrites and vpermt2 from the first source, overwriting the low half of the table
+            Mnemonic::Vpermi2w | Mnemonic::Vpermi2d | Mnemonic::Vpermi2q | Mnemonic::Vpermi2ps | Mnemonic::Vpermi2pd | Mnemonic::Vpermt2w
+            | Mnemonic::Vpermt2d | Mnemonic::Vpermt2q | Mnemonic::Vpermt2ps | Mnemonic::Vpermt2pd => {
+                let [first, second, high] = [self.read_vector_operand(instruction, 0)?, self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?];
+                let (indexes, low) = match mnemonic {
+                    Mnemonic::Vpermi2w | Mnemonic::Vpermi2d | Mnemonic::Vpermi2q | Mnemonic::Vpermi2ps | Mnemonic::Vpermi2pd => (first, second),
+                    _ => (second, first),
+                };
+                let bits = instruction.memory_size().element_size() as u32 * 8;
+                let count = vector_length(instruction) as u32 * 8 / bits;
+                let value = elements(bits, |index| {
+                    let selector = vector_element(&indexes, bits, index) as u32;
+                    vector_element(if selector & count != 0 { &high } else { &low }, bits, selector & (count - 1))
+                });
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // the elements of the concatenation of the first source above the second, from the element given by the
+            // immediate
+            Mnemonic::Valignd | Mnemonic::Valignq => {
+                let (a, b) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let bits = if mnemonic == Mnemonic::Valignd { 32 } else { 64 };
+                let count = vector_length(instruction) as u32 * 8 / bits;
+                let shift = instruction.immediate8() as u32 & (count - 1);
+                let value = elements(bits, |index| match index + shift {
+                    index if index < count => vector_element(&b, bits, index),
+                    index => vector_element(&a, bits, index - count),
+                });
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // the low half of the lanes selected from the first source by fields of the immediate, the high half from the
+            // second
+            Mnemonic::Vshuff32x4 | Mnemonic::Vshuff64x2 | Mnemonic::Vshufi32x4 | Mnemonic::Vshufi64x2 => {
+                let (a, b) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let lanes = vector_length(instruction) / 16;
+                let (immediate, field) = (instruction.immediate8() as usize, lanes / 2);
+                let mut value = [0; LANES];
+                for (lane, value) in value.iter_mut().enumerate().take(lanes) {
+                    let source = if lane < lanes / 2 { &a } else { &b };
+                    *value = source[immediate >> (field * lane) & (lanes - 1)];
+                }
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // the 128 or 256 bits selected by the immediate replaced or extracted
+            Mnemonic::Vinsertf32x4 | Mnemonic::Vinsertf64x2 | Mnemonic::Vinserti32x4 | Mnemonic::Vinserti64x2 => {
+                let (mut value, source) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                value[instruction.immediate8() as usize & (vector_length(instruction) / 16 - 1)] = source[0];
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+            Mnemonic::Vinsertf32x8 | Mnemonic::Vinsertf64x4 | Mnemonic::Vinserti32x8 | Mnemonic::Vinserti64x4 => {
+                let (mut value, source) = (self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let half = 2 * (instruction.immediate8() as usize & 1);
+                value[half..half + 2].copy_from_slice(&source[..2]);
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+            Mnemonic::Vextractf32x4 | Mnemonic::Vextractf64x2 | Mnemonic::Vextracti32x4 | Mnemonic::Vextracti64x2 => {
+                let source = self.read_vector_operand(instruction, 1)?;
+                let lane = instruction.immediate8() as usize & (instruction.op1_register().size() / 16 - 1);
+                self.write_xmm_operand(instruction, 0, source[lane])?;
+            }
+            Mnemonic::Vextractf32x8 | Mnemonic::Vextractf64x4 | Mnemonic::Vextracti32x8 | Mnemonic::Vextracti64x4 => {
+                let source = self.read_vector_operand(instruction, 1)?;
+                let half = 2 * (instruction.immediate8() as usize & 1);
+                self.write_vector_operand(instruction, 0, [source[half], source[half + 1], 0, 0])?;
+            }
+
+            Mnemonic::Vpcompressd | Mnemonic::Vpcompressq | Mnemonic::Vcompressps | Mnemonic::Vcompresspd => self.compress(instruction)?,
+            Mnemonic::Vpexpandd | Mnemonic::Vpexpandq | Mnemonic::Vexpandps | Mnemonic::Vexpandpd => self.expand(instruction)?,
+            Mnemonic::Vgatherdps | Mnemonic::Vgatherdpd | Mnemonic::Vgatherqps | Mnemonic::Vgatherqpd | Mnemonic::Vpgatherdd
+            | Mnemonic::Vpgatherdq | Mnemonic::Vpgatherqd | Mnemonic::Vpgatherqq => self.masked_gather(instruction)?,
+            Mnemonic::Vscatterdps | Mnemonic::Vscatterdpd | Mnemonic::Vscatterqps | Mnemonic::Vscatterqpd | Mnemonic::Vpscatterdd
+            | Mnemonic::Vpscatterdq | Mnemonic::Vpscatterqd | Mnemonic::Vpscatterqq => self.scatter(instruction)?,
+
+            // the elements of the source narrowed by keeping their low bits or with signed or unsigned saturation
+            Mnemonic::Vpmovwb | Mnemonic::Vpmovswb | Mnemonic::Vpmovuswb | Mnemonic::Vpmovdb | Mnemonic::Vpmovsdb | Mnemonic::Vpmovusdb
+            | Mnemonic::Vpmovdw | Mnemonic::Vpmovsdw | Mnemonic::Vpmovusdw | Mnemonic::Vpmovqb | Mnemonic::Vpmovsqb | Mnemonic::Vpmovusqb
+            | Mnemonic::Vpmovqw | Mnemonic::Vpmovsqw | Mnemonic::Vpmovusqw | Mnemonic::Vpmovqd | Mnemonic::Vpmovsqd | Mnemonic::Vpmovusqd => {
+                let (from, to, saturation) = match mnemonic {
+                    Mnemonic::Vpmovwb => (16, 8, Saturation::Truncate),
+                    Mnemonic::Vpmovswb => (16, 8, Saturation::Signed),
+                    Mnemonic::Vpmovuswb => (16, 8, Saturation::Unsigned),
+                    Mnemonic::Vpmovdb => (32, 8, Saturation::Truncate),
+                    Mnemonic::Vpmovsdb => (32, 8, Saturation::Signed),
+                    Mnemonic::Vpmovusdb => (32, 8, Saturation::Unsigned),
+                    Mnemonic::Vpmovdw => (32, 16, Saturation::Truncate),
+                    Mnemonic::Vpmovsdw => (32, 16, Saturation::Signed),
+                    Mnemonic::Vpmovusdw => (32, 16, Saturation::Unsigned),
+                    Mnemonic::Vpmovqb => (64, 8, Saturation::Truncate),
+                    Mnemonic::Vpmovsqb => (64, 8, Saturation::Signed),
+                    Mnemonic::Vpmovusqb => (64, 8, Saturation::Unsigned),
+                    Mnemonic::Vpmovqw => (64, 16, Saturation::Truncate),
+                    Mnemonic::Vpmovsqw => (64, 16, Saturation::Signed),
+                    Mnemonic::Vpmovusqw => (64, 16, Saturation::Unsigned),
+                    Mnemonic::Vpmovqd => (64, 32, Saturation::Truncate),
+                    Mnemonic::Vpmovsqd => (64, 32, Saturation::Signed),
+                    _ => (64, 32, Saturation::Unsigned),
+                };
+                let source = self.read_vector_operand(instruction, 1)?;
+                let count = instruction.op1_register().size() as u32 * 8 / from;
+                let value = elements(to, |index| match index < count {
+                    true => narrow(vector_element(&source, from, index), from, to, saturation),
+                    false => 0,
+                });
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+
+            // each element all ones or zeros by a bit of the opmask register, or the sign bits of the elements in one
+            Mnemonic::Vpmovm2b | Mnemonic::Vpmovm2w | Mnemonic::Vpmovm2d | Mnemonic::Vpmovm2q => {
+                let source = self.registers.sse.k[instruction.op1_register().number()];
+                let bits = mask_layout(instruction).0;
+                let count = instruction.op0_register().size() as u32 * 8 / bits;
+                let value = elements(bits, |index| if index < count && selected(source, index) { u64::MAX } else { 0 });
+                self.write_vector_register(instruction, instruction.op0_register(), value);
+            }
+            Mnemonic::Vpmovb2m | Mnemonic::Vpmovw2m | Mnemonic::Vpmovd2m | Mnemonic::Vpmovq2m => {
+                let source = self.read_vector_operand(instruction, 1)?;
+                let bits = match mnemonic {
+                    Mnemonic::Vpmovb2m => 8,
+                    Mnemonic::Vpmovw2m => 16,
+                    Mnemonic::Vpmovd2m => 32,
+                    _ => 64,
+                };
+                let count = instruction.op1_register().size() as u32 * 8 / bits;
+                let mask = (0..count).fold(0, |mask, index| mask | (vector_element(&source, bits, index) >> (bits - 1)) << index);
+                self.registers.sse.k[instruction.op0_register().number()] = mask;
+            }
+
+            // AVX512CD: the bits of the lower elements equal to each element, the leading zeros are counted with the
+            // integer instructions
+            Mnemonic::Vpconflictd | Mnemonic::Vpconflictq => {
+                let source = self.read_vector_operand(instruction, 1)?;
+                let bits = if mnemonic == Mnemonic::Vpconflictd { 32 } else { 64 };
+                let value = elements(bits, |index| {
+                    let element = vector_element(&source, bits, index);
+                    (0..index).filter(|&lower| vector_element(&source, bits, lower) == element).fold(0, |value, lower| value | 1 << lower)
+                });
+                self.write_vector_operand(instruction, 0, value)?;
+            }
+            // the low byte or word of an opmask register in every quadword or doubleword
+            Mnemonic::Vpbroadcastmb2q | Mnemonic::Vpbroadcastmw2d => {
+                let source = self.registers.sse.k[instruction.op1_register().number()];
+                let value = match mnemonic {
+                    Mnemonic::Vpbroadcastmb2q => elements(64, |_| source & 0xff),
+                    _ => elements(32, |_| source & 0xffff),
+                };
+                self.write_vector_register(instruction, instruction.op0_register(), value);
+            }
+
+            _ => return self.execute_avx(instruction),
+        }
+        Ok(())
+    }
+
+    /// Executes the instructions on the opmask registers, which are VEX-encoded. The suffix of the mnemonic is the size
+    /// of the operands, the bits above it are zeroed.
+    pub fn execute_opmask(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        self.check_features(instruction)?;
+        let mnemonic = instruction.mnemonic();
+        let bits = opmask_bits(mnemonic);
+        let width = u64::MAX >> (64 - bits);
+        let k = |operand: u32| self.registers.sse.k[instruction.op_register(operand).number()] & width;
+        let value = match mnemonic {
+            Mnemonic::Kmovb | Mnemonic::Kmovw | Mnemonic::Kmovd | Mnemonic::Kmovq => {
+                let value = match instruction.op1_kind() {
+                    OpKind::Register if instruction.op1_register().is_k() => k(1),
+                    OpKind::Register => self.get_register_u64(instruction.op1_register())? & width,
+                    _ => self.memory.read_uint(self.memory_address(instruction)?, bits as usize / 8)?,
+                };
+                match instruction.op0_kind() {
+                    OpKind::Register if instruction.op0_register().is_k() => value,
+                    OpKind::Register => return self.set_register(instruction.op0_register(), value),
+                    _ => return Ok(self.memory.write_uint(self.memory_address(instruction)?, bits as usize / 8, value)?),
+                }
+            }
+            Mnemonic::Knotb | Mnemonic::Knotw | Mnemonic::Knotd | Mnemonic::Knotq => !k(1),
+            Mnemonic::Kandb | Mnemonic::Kandw | Mnemonic::Kandd | Mnemonic::Kandq => k(1) & k(2),
+            Mnemonic::Kandnb | Mnemonic::Kandnw | Mnemonic::Kandnd | Mnemonic::Kandnq => !k(1) & k(2),
+            Mnemonic::Korb | Mnemonic::Korw | Mnemonic::Kord | Mnemonic::Korq => k(1) | k(2),
+            Mnemonic::Kxorb | Mnemonic::Kxorw | Mnemonic::Kxord | Mnemonic::Kxorq => k(1) ^ k(2),
+            Mnemonic::Kxnorb | Mnemonic::Kxnorw | Mnemonic::Kxnord | Mnemonic::Kxnorq => !(k(1) ^ k(2)),
+            Mnemonic::Kaddb | Mnemonic::Kaddw | Mnemonic::Kaddd | Mnemonic::Kaddq => k(1).wrapping_add(k(2)),
+            Mnemonic::Kshiftlb | Mnemonic::Kshiftlw | Mnemonic::Kshiftld | Mnemonic::Kshiftlq => k(1).checked_shl(instruction.immediate8() as u32).unwrap_or(0),
+            Mnemonic::Kshiftrb | Mnemonic::Kshiftrw | Mnemonic::Kshiftrd | Mnemonic::Kshiftrq => k(1).checked_shr(instruction.immediate8() as u32).unwrap_or(0),
+            // the low halves of the sources, the first in the high half of the destination
+            Mnemonic::Kunpckbw | Mnemonic::Kunpckwd | Mnemonic::Kunpckdq => {
+                let half = width >> (bits / 2);
+                (k(1) & half) << (bits / 2) | (k(2) & half)
+            }
+            // kortest sets ZF when the OR of the registers is zero and CF when it is all ones, ktest ZF when their AND is
+            // zero and CF when the AND of the complement of the first with the second is zero
+            _ => {
+                let (zero, carry) = match mnemonic {
+                    Mnemonic::Kortestb | Mnemonic::Kortestw | Mnemonic::Kortestd | Mnemonic::Kortestq => (k(0) | k(1) == 0, k(0) | k(1) == width),
+                    _ => (k(0) & k(1) == 0, !k(0) & k(1) & width == 0),
+                };
+                let flag = |condition: bool, flag: u64| if condition { flag } else { 0 };
+                self.update_flags(flag(zero, ZF) | flag(carry, CF), ZF | CF | OF | SF | AF | PF);
+                return Ok(());
+            }
+        };
+        self.registers.sse.k[instruction.op0_register().number()] = value & width;
+        Ok(())
+    }
+
+    /// The write mask of an EVEX instruction, all ones without one
+    pub fn write_mask(&self, instruction: &Instruction) -> u64 {
+        match instruction.op_mask() {
+            Register::None => u64::MAX,
+            register => self.registers.sse.k[register.number()],
+        }
+    }
+
+    /// The result of an instruction for a vector register, with the elements the write mask excludes zeroed or, when
+    /// merging, those of the register
+    pub fn merge_masked(&self, instruction: &Instruction, register: Register, mut value: Vector) -> Vector {
+        if instruction.op_mask() == Register::None {
+            return value;
+        }
+        let ((bits, count), mask) = (mask_layout(instruction), self.write_mask(instruction));
+        let old = self.registers.sse.zmm[register.number()];
+        for index in (0..count).filter(|&index| !selected(mask, index)) {
+            let element = if instruction.zeroing_masking() { 0 } else { vector_element(&old, bits, index) };
+            set_element(&mut value, bits, index, element);
+        }
+        value
+    }
+
+    /// Writes the result of a comparison to an opmask register: the sign bits of its elements, cleared where the write
+    /// mask is
+    pub fn write_opmask_result(&mut self, instruction: &Instruction, register: Register, value: Vector) {
+        let ((bits, count), mask) = (mask_layout(instruction), self.write_mask(instruction));
+        let result = (0..count).fold(0, |result, index| result | (vector_element(&value, bits, index) >> (bits - 1)) << index);
+        self.registers.sse.k[register.number()] = result & mask;
+    }
+
+    /// Reads the elements of a memory operand the write mask selects, after reading the whole operand faulted: the
+    /// others are zeros and are not accessed
+    pub fn read_masked_memory(&self, instruction: &Instruction, address: u64) -> Result<Vector, Error> {
+        let memory_size = instruction.memory_size();
+        let (size, mask) = (memory_size.element_size(), self.write_mask(instruction));
+        let count = mask_layout(instruction).1.min((memory_size.size() / size) as u32);
+        let mut value = [0; LANES];
+        for index in (0..count).filter(|&index| selected(mask, index)) {
+            let element = self.memory.read_uint(address.wrapping_add(size as u64 * index as u64), size)?;
+            set_element(&mut value, 8 * size as u32, index, element);
+        }
+        Ok(value)
+    }
+
+    /// Writes the elements of a memory operand the write mask selects, the others are not accessed
+    pub fn write_masked_memory(&mut self, instruction: &Instruction, address: u64, value: Vector) -> Result<(), Error> {
+        let ((bits, count), mask) = (mask_layout(instruction), self.write_mask(instruction));
+        for index in (0..count).filter(|&index| selected(mask, index)) {
+            self.memory.write_uint(address.wrapping_add((bits / 8 * index) as u64), bits as usize / 8, vector_element(&value, bits, index))?;
+        }
+        Ok(())
+    }
+
+    /// A floating-point source whose elements the write mask excludes are replaced by ones, or zeros for an integer
+    /// source, so that computing them raises no exception
+    pub fn mask_float_source(&self, instruction: &Instruction, mut source: Vector) -> Vector {
+        if instruction.op_mask() == Register::None {
+            return source;
+        }
+        let element = match instruction.mnemonic() {
+            Mnemonic::Vcvtps2ph => MemorySize::Float32,
+            _ => instruction.memory_size().element_type(),
+        };
+        let (bits, neutral) = match element {
+            MemorySize::Float16 => (16, 0x3c00),
+            MemorySize::Float32 => (32, 0x3f80_0000),
+            MemorySize::Float64 => (64, 0x3ff0_0000_0000_0000),
+            element => (8 * element.size() as u32, 0),
+        };
+        let ((_, count), mask) = (mask_layout(instruction), self.write_mask(instruction));
+        for index in (0..count).filter(|&index| !selected(mask, index)) {
+            set_element(&mut source, bits, index, neutral);
+        }
+        source
+    }
+
+    /// vpcompress and vcompress: the elements the write mask selects stored contiguously from the lowest element of
+    /// the destination, the others kept or zeroed in a register and not accessed in memory
+    fn compress(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let (bits, count) = mask_layout(instruction);
+        let (source, mask) = (self.read_vector_operand(instruction, 1)?, self.write_mask(instruction));
+        let mut value = [0; LANES];
+        let mut packed = 0;
+        for index in (0..count).filter(|&index| selected(mask, index)) {
+            set_element(&mut value, bits, packed, vector_element(&source, bits, index));
+            packed += 1;
+        }
+        match instruction.op0_kind() {
+            OpKind::Register => {
+                let (register, zeroing) = (instruction.op0_register(), instruction.zeroing_masking());
+                let old = self.registers.sse.zmm[register.number()];
+                for index in packed..count {
+                    set_element(&mut value, bits, index, if zeroing { 0 } else { vector_element(&old, bits, index) });
+                }
+                self.write_vector_register(instruction, register, value);
+                Ok(())
+            }
+            _ => {
+                let address = self.memory_address(instruction)?;
+                Ok(self.memory.write(address, &vector_to_bytes(value)[..(bits / 8 * packed) as usize])?)
+            }
+        }
+    }
+
+    /// vpexpand and vexpand: the contiguous elements from the lowest of the source in the elements the write mask
+    /// selects. Only as many elements as it selects are read from memory.
+    fn expand(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let (bits, count) = mask_layout(instruction);
+        let mask = self.write_mask(instruction);
+        let source = match instruction.op1_kind() {
+            OpKind::Memory => {
+                let mut bytes = [0; 16 * LANES];
+                let active = (0..count).filter(|&index| selected(mask, index)).count();
+                self.memory.read(self.memory_address(instruction)?, &mut bytes[..bits as usize / 8 * active])?;
+                vector_from_bytes(&bytes)
+            }
+            _ => self.read_vector_operand(instruction, 1)?,
+        };
+        let mut value = [0; LANES];
+        for (packed, index) in (0..count).filter(|&index| selected(mask, index)).enumerate() {
+            set_element(&mut value, bits, index, vector_element(&source, bits, packed as u32));
+        }
+        self.write_vector_operand(instruction, 0, value)
+    }
+
+    /// The gathers of AVX-512, whose mask is the write mask: the elements it selects are loaded from the addresses
+    /// indexed by the elements of the vector index register, clearing their bit. A fault leaves the elements loaded
+    /// before it in the destination and their bits cleared, otherwise the whole opmask register is zeroed.
+    fn masked_gather(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let (destination, index_register, mask_register) = (instruction.op0_register(), instruction.memory_index(), instruction.op_mask());
+        if destination.number() == index_register.number() || mask_register == Register::None {
+            return Err(Error::InvalidOpcode(instruction.ip()));
+        }
+        let (data_bits, index_bits) = vsib_element_bits(instruction.mnemonic());
+        let count = (destination.size() as u32 * 8 / data_bits).min(index_register.size() as u32 * 8 / index_bits);
+        let indexes = self.registers.sse.zmm[index_register.number()];
+        let mut value = self.read_vector_operand(instruction, 0)?;
+
+        for index in 0..count {
+            if !selected(self.registers.sse.k[mask_register.number()], index) {
+                continue;
+            }
+            let element = signed(vector_element(&indexes, index_bits, index), index_bits) as u64;
+            let loaded = self.vector_element_address(instruction, element).and_then(|address| Ok(self.memory.read_uint(address, data_bits as usize / 8)?));
+            match loaded {
+                Ok(loaded) => {
+                    set_element(&mut value, data_bits, index, loaded);
+                    self.registers.sse.k[mask_register.number()] &= !(1 << index);
+                }
+                Err(e) => {
+                    self.write_vector_register(instruction, destination, value);
+                    return Err(e);
+                }
+            }
+        }
+        self.write_vector_register(instruction, destination, value);
+        self.registers.sse.k[mask_register.number()] = 0;
+        Ok(())
+    }
+
+    /// The scatters: the elements of the source the write mask selects are stored to the addresses indexed by the
+    /// elements of the vector index register, from the lowest, clearing their bit. A fault leaves the elements stored
+    /// before it in memory and their bits cleared, otherwise the whole opmask register is zeroed.
+    fn scatter(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let (source, index_register, mask_register) = (instruction.op1_register(), instruction.memory_index(), instruction.op_mask());
+        if mask_register == Register::None {
+            return Err(Error::InvalidOpcode(instruction.ip()));
+        }
+        let (data_bits, index_bits) = vsib_element_bits(instruction.mnemonic());
+        let count = (source.size() as u32 * 8 / data_bits).min(index_register.size() as u32 * 8 / index_bits);
+        let (indexes, data) = (self.registers.sse.zmm[index_register.number()], self.read_vector_operand(instruction, 1)?);
+
+        for index in 0..count {
+            if !selected(self.registers.sse.k[mask_register.number()], index) {
+                continue;
+            }
+            let element = signed(vector_element(&indexes, index_bits, index), index_bits) as u64;
+            let address = self.vector_element_address(instruction, element)?;
+            self.memory.write_uint(address, data_bits as usize / 8, vector_element(&data, data_bits, index))?;
+            self.registers.sse.k[mask_register.number()] &= !(1 << index);
+        }
+        self.registers.sse.k[mask_register.number()] = 0;
+        Ok(())
+    }
+}
+
+/// Whether the write mask selects the element `index`
+fn selected(mask: u64, index: u32) -> bool {
+    index < 64 && mask >> index & 1 != 0
+}
+
+/// Size of the operands of an opmask instruction
+fn opmask_bits(mnemonic: Mnemonic) -> u32 {
+    match mnemonic {
+        Mnemonic::Kaddb | Mnemonic::Kandb | Mnemonic::Kandnb | Mnemonic::Kmovb | Mnemonic::Knotb | Mnemonic::Korb | Mnemonic::Kortestb
+        | Mnemonic::Kshiftlb | Mnemonic::Kshiftrb | Mnemonic::Ktestb | Mnemonic::Kxnorb | Mnemonic::Kxorb => 8,
+        Mnemonic::Kaddd | Mnemonic::Kandd | Mnemonic::Kandnd | Mnemonic::Kmovd | Mnemonic::Knotd | Mnemonic::Kord | Mnemonic::Kortestd
+        | Mnemonic::Kshiftld | Mnemonic::Kshiftrd | Mnemonic::Ktestd | Mnemonic::Kxnord | Mnemonic::Kxord | Mnemonic::Kunpckwd => 32,
+        Mnemonic::Kaddq | Mnemonic::Kandq | Mnemonic::Kandnq | Mnemonic::Kmovq | Mnemonic::Knotq | Mnemonic::Korq | Mnemonic::Kortestq
+        | Mnemonic::Kshiftlq | Mnemonic::Kshiftrq | Mnemonic::Ktestq | Mnemonic::Kxnorq | Mnemonic::Kxorq | Mnemonic::Kunpckdq => 64,
+        _ => 16,
+    }
+}
+
+/// Sizes of the data and index elements of a gather or scatter
+fn vsib_element_bits(mnemonic: Mnemonic) -> (u32, u32) {
+    match mnemonic {
+        Mnemonic::Vgatherdps | Mnemonic::Vpgatherdd | Mnemonic::Vscatterdps | Mnemonic::Vpscatterdd => (32, 32),
+        Mnemonic::Vgatherdpd | Mnemonic::Vpgatherdq | Mnemonic::Vscatterdpd | Mnemonic::Vpscatterdq => (64, 32),
+        Mnemonic::Vgatherqps | Mnemonic::Vpgatherqd | Mnemonic::Vscatterqps | Mnemonic::Vpscatterqd => (32, 64),
+        _ => (64, 64),
+    }
+}
+
+/// A `from`-bit element narrowed to `to` bits
+fn narrow(element: u64, from: u32, to: u32, saturation: Saturation) -> u64 {
+    let limit = u64::MAX >> (64 - to);
+    match saturation {
+        Saturation::Truncate => element & limit,
+        Saturation::Signed => signed(element, from).clamp(-(1 << (to - 1)), (1 << (to - 1)) - 1) as u64 & limit,
+        Saturation::Unsigned => element.min(limit),
+    }
+}
+
+/// Size in bits and number of the elements of the destination of an EVEX instruction which the write mask applies to.
+/// The scalar instructions have a single element, those writing an opmask register or narrowing their elements have as
+/// many as their source.
+pub fn mask_layout(instruction: &Instruction) -> (u32, u32) {
+    let (mnemonic, memory_size) = (instruction.mnemonic(), instruction.memory_size());
+    let bits = match destination_element_bits(mnemonic) {
+        Some(bits) => bits,
+        None if memory_size.element_size() != 0 => 8 * memory_size.element_size() as u32,
+        None => element_bits(mnemonic),
+    };
+    let scalar = !instruction.is_broadcast()
+        && !memory_size.is_packed()
+        && !matches!(
+            mnemonic,
+            Mnemonic::Vbroadcastss | Mnemonic::Vbroadcastsd | Mnemonic::Vpbroadcastb | Mnemonic::Vpbroadcastw | Mnemonic::Vpbroadcastd
+                | Mnemonic::Vpbroadcastq | Mnemonic::Vmovddup | Mnemonic::Vpbroadcastmb2q | Mnemonic::Vpbroadcastmw2d
+        );
+    let count = match instruction.op0_kind() {
+        _ if scalar => 1,
+        OpKind::Memory => 8 * memory_size.size() as u32 / bits,
+        _ if matches!(
+            instruction.op_code().op_kind(0),
+            OpCodeOperandKind::xmm_or_mem | OpCodeOperandKind::ymm_or_mem | OpCodeOperandKind::zmm_or_mem
+        ) && memory_size.is_packed() =>
+        {
+            8 * memory_size.size() as u32 / bits
+        }
+        _ if instruction.op0_register().is_k() => 8 * vector_length(instruction) as u32 / bits,
+        _ if matches!(
+            mnemonic,
+            Mnemonic::Vcvtpd2ps | Mnemonic::Vcvtpd2dq | Mnemonic::Vcvttpd2dq | Mnemonic::Vcvtpd2udq | Mnemonic::Vcvttpd2udq
+                | Mnemonic::Vcvtqq2ps | Mnemonic::Vcvtuqq2ps
+        ) =>
+        {
+            vector_length(instruction) as u32 / 8
+        }
+        _ => 8 * instruction.op0_register().size() as u32 / bits,
+    };
+    (bits, count)
+}
+
+/// Size of the destination elements of the instructions whose elements differ in size from those of their source
+fn destination_element_bits(mnemonic: Mnemonic) -> Option<u32> {
+    Some(match mnemonic {
+        Mnemonic::Vpmovwb | Mnemonic::Vpmovswb | Mnemonic::Vpmovuswb | Mnemonic::Vpmovdb | Mnemonic::Vpmovsdb | Mnemonic::Vpmovusdb
+        | Mnemonic::Vpmovqb | Mnemonic::Vpmovsqb | Mnemonic::Vpmovusqb | Mnemonic::Vpacksswb | Mnemonic::Vpackuswb | Mnemonic::Vpmovm2b
+        | Mnemonic::Vpbroadcastb => 8,
+        Mnemonic::Vpmovdw | Mnemonic::Vpmovsdw | Mnemonic::Vpmovusdw | Mnemonic::Vpmovqw | Mnemonic::Vpmovsqw | Mnemonic::Vpmovusqw
+        | Mnemonic::Vpackssdw | Mnemonic::Vpackusdw | Mnemonic::Vpmaddubsw | Mnemonic::Vdbpsadbw | Mnemonic::Vcvtps2ph | Mnemonic::Vpmovsxbw
+        | Mnemonic::Vpmovzxbw | Mnemonic::Vpmovm2w | Mnemonic::Vpbroadcastw => 16,
+        Mnemonic::Vpmovqd | Mnemonic::Vpmovsqd | Mnemonic::Vpmovusqd | Mnemonic::Vcvtpd2ps | Mnemonic::Vcvtpd2dq | Mnemonic::Vcvttpd2dq
+        | Mnemonic::Vcvtpd2udq | Mnemonic::Vcvttpd2udq | Mnemonic::Vcvtqq2ps | Mnemonic::Vcvtuqq2ps | Mnemonic::Vcvtph2ps
+        | Mnemonic::Vcvtsd2ss | Mnemonic::Vpmaddwd | Mnemonic::Vpmovsxbd | Mnemonic::Vpmovsxwd | Mnemonic::Vpmovzxbd | Mnemonic::Vpmovzxwd
+        | Mnemonic::Vpmovm2d | Mnemonic::Vpbroadcastd | Mnemonic::Vpbroadcastmw2d => 32,
+        Mnemonic::Vcvtps2pd | Mnemonic::Vcvtdq2pd | Mnemonic::Vcvtudq2pd | Mnemonic::Vcvtps2qq | Mnemonic::Vcvttps2qq | Mnemonic::Vcvtps2uqq
+        | Mnemonic::Vcvttps2uqq | Mnemonic::Vcvtss2sd | Mnemonic::Vpmovsxbq | Mnemonic::Vpmovsxwq | Mnemonic::Vpmovsxdq
+        | Mnemonic::Vpmovzxbq | Mnemonic::Vpmovzxwq | Mnemonic::Vpmovzxdq | Mnemonic::Vpmovm2q | Mnemonic::Vpbroadcastq
+        | Mnemonic::Vpbroadcastmb2q | Mnemonic::Vpmuldq | Mnemonic::Vpmuludq => 64,
+        _ => return None,
+    })
+}
@@ -0,0 +1,412 @@
+use super::error::Error;
+use super::flags::{self, CF, OF, PF, SF, STATUS, ZF};
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic, OpKind};
+
+// Flags the SDM leaves undefined follow current Intel processors: shifts clear AF, multi-bit shifts and
+// rotates compute OF as for their first single-bit step (rol and ror by an immediate keep it), bit tests
+// keep the other flags, and bit scans, counts and BMI instructions clear them.
+
+/// Reflected polynomial of CRC-32C
+const CRC32C_POLYNOMIAL: u32 = 0x82f6_3b78;
+
+impl Cpu {
+    /// Executes shl/sal, shr, sar, rol, ror, rcl, rcr, shld and shrd
+    pub fn execute_shift(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let size = self.operand_size(instruction, 0);
+        let bits = size as u32 * 8;
+        let a = self.read_operand(instruction, 0)?;
+
+        let count_operand = match instruction.mnemonic() {
+            Mnemonic::Shld | Mnemonic::Shrd => 2,
+            _ => 1,
+        };
+        // the count is masked to 5 bits, or 6 bits for 64-bit operands
+        let count_mask = if size == 8 { 0x3f } else { 0x1f };
+        let count = (self.read_operand(instruction, count_operand)? & count_mask) as u32;
+        // flags are not affected by a zero count, but 32-bit destinations are still zero-extended
+        if count == 0 {
+            return self.write_operand(instruction, 0, a);
+        }
+
+        let rflags = self.registers.rflags;
+        let carry = rflags & CF != 0;
+        let sign = |value: u64| value & flags::sign_bit(size) != 0;
+        // OF of a left shift or rotate by one: the two top bits differ
+        let rotate_flags = match instruction.op_kind(count_operand) {
+            OpKind::Register => CF | OF,
+            _ if count == 1 => CF | OF,
+            _ => CF,
+        };
+        let first_step_overflow = sign(a) != sign(a << 1);
+
+        let (result, new_flags, mask) = match instruction.mnemonic() {
+            Mnemonic::Shl | Mnemonic::Sal => {
+                let result = shift_left(a, count) & flags::mask(size);
+                let cf = count <= bits && (a >> (bits - count)) & 1 != 0;
+                (result, flags::logic(result, size) | flag(CF, cf) | flag(OF, first_step_overflow), STATUS)
+            }
+            Mnemonic::Shr => {
+                let result = shift_right(a, count);
+                let cf = (shift_right(a, count - 1)) & 1 != 0;
+                (result, flags::logic(result, size) | flag(CF, cf) | flag(OF, sign(a)), STATUS)
+            }
+            Mnemonic::Sar => {
+                let signed = flags::sign_extend(a, size) as i64;
+                let result = (signed >> count.min(63)) as u64 & flags::mask(size);
+                let cf = (signed >> (count - 1).min(63)) & 1 != 0;
+                (result, flags::logic(result, size) | flag(CF, cf), STATUS)
+            }
+            Mnemonic::Rol => {
+                let count = count % bits;
+                let result = (shift_left(a, count) | shift_right(a, bits - count)) & flags::mask(size);
+                (result, flag(CF, result & 1 != 0) | flag(OF, first_step_overflow), rotate_flags)
+            }
+            Mnemonic::Ror => {
+                let count = count % bits;
+                let result = (shift_right(a, count) | shift_left(a, bits - count)) & flags::mask(size);
+                // the lowest bit is rotated into the sign bit by the first step
+                (result, flag(CF, sign(result)) | flag(OF, sign(a) != (a & 1 != 0)), rotate_flags)
+            }
+            Mnemonic::Rcl | Mnemonic::Rcr => {
+                // rotate the bits + 1 wide value of CF and the operand
+                let width = bits + 1;
+                let count = count % width;
+                let value = ((carry as u128) << bits) | a as u128;
+                let wide_mask = (1u128 << width) - 1;
+                let rotated = if instruction.mnemonic() == Mnemonic::Rcl {
+                    ((value << count) | (value >> (width - count))) & wide_mask
+                } else {
+                    ((value >> count) | (value << (width - count))) & wide_mask
+                };
+                let result = rotated as u64 & flags::mask(size);
+                let cf = (rotated >> bits) & 1 != 0;
+                let of = if instruction.mnemonic() == Mnemonic::Rcl {
+                    first_step_overflow
+                } else {
+                    sign(a) != carry
+                };
+                // a count which is a multiple of the width rotates nothing and leaves the flags unchanged
+                (result, flag(CF, cf) | flag(OF, of), if count == 0 { 0 } else { CF | OF })
+            }
+            Mnemonic::Shld | Mnemonic::Shrd => {
+                let b = self.read_operand(instruction, 1)?;
+                let (a_wide, b_wide) = (a as u128, b as u128);
+                // shift the concatenation of the destination and source, counts above the operand
+                // size (only possible for 16-bit operands) shift in the destination again
+                let (value, width) = match (instruction.mnemonic(), size) {
+                    (_, 2) => ((a_wide << 32) | (b_wide << 16) | a_wide, 48),
+                    (Mnemonic::Shld, _) => ((a_wide << bits) | b_wide, 2 * bits),
+                    _ => ((b_wide << bits) | a_wide, 2 * bits),
+                };
+                let (result, cf) = if instruction.mnemonic() == Mnemonic::Shld {
+                    let shifted = value << count;
+                    ((shifted >> (width - bits)) as u64 & flags::mask(size), (value >> (width - count)) & 1 != 0)
+                } else {
+                    ((value >> count) as u64 & flags::mask(size), (value >> (count - 1)) & 1 != 0)
+                };
+                // the first step shifts in the top bit of the source for shld, the lowest for shrd
+                let of = match instruction.mnemonic() {
+                    Mnemonic::Shld => first_step_overflow,
+                    _ => sign(a) != (b & 1 != 0),
+                };
+                (result, flags::logic(result, size) | flag(CF, cf) | flag(OF, of), STATUS)
+            }
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        };
+
+        self.update_flags(new_flags, mask);
+        self.write_operand(instruction, 0, result)
+    }
+
+    /// Executes bt, bts, btr, btc, bsf, bsr, bswap, popcnt, lzcnt, tzcnt, crc32 and the BMI1/BMI2 instructions
+    pub fn execute_bit_manipulation(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let size = self.operand_size(instruction, 0);
+        let bits = size as u32 * 8;
+        let mask = flags::mask(size);
+
+        match instruction.mnemonic() {
+            Mnemonic::Bt | Mnemonic::Bts | Mnemonic::Btr | Mnemonic::Btc => return self.bit_test(instruction),
+
+            Mnemonic::Bsf | Mnemonic::Bsr => {
+                let source = self.read_operand(instruction, 1)?;
+                // a zero source keeps the destination, like current processors do
+                if source == 0 {
+                    self.update_flags(ZF | PF, STATUS);
+                    return Ok(());
+                }
+                let index = match instruction.mnemonic() {
+                    Mnemonic::Bsf => source.trailing_zeros(),
+                    _ => 63 - source.leading_zeros(),
+                } as u64;
+                self.update_flags(flags::logic(index, size) & PF, STATUS);
+                self.write_operand(instruction, 0, index)?;
+            }
+
+            Mnemonic::Bswap => {
+                let value = self.read_operand(instruction, 0)?;
+                // bswap of a 16-bit register is undefined, and clears it on current processors
+                let result = match size {
+                    4 => (value as u32).swap_bytes() as u64,
+                    8 => value.swap_bytes(),
+                    _ => 0,
+                };
+                self.write_operand(instruction, 0, result)?;
+            }
+
+            Mnemonic::Popcnt => {
+                let source = self.read_operand(instruction, 1)?;
+                self.update_flags(flag(ZF, source == 0), STATUS);
+                self.write_operand(instruction, 0, source.count_ones() as u64)?;
+            }
+
+            // accumulates the bytes of the source into the CRC-32C in the low doubleword of the destination
+            Mnemonic::Crc32 => {
+                let source = self.read_operand(instruction, 1)?;
+                let bytes = source.to_le_bytes();
+                let crc = bytes[..self.operand_size(instruction, 1)].iter().fold(self.read_operand(instruction, 0)? as u32, |crc, &byte| crc32c(crc, byte));
+                self.write_operand(instruction, 0, crc as u64)?;
+            }
+
+            Mnemonic::Lzcnt | Mnemonic::Tzcnt => {
+                let source = self.read_operand(instruction, 1)?;
+                let count = match (instruction.mnemonic(), source) {
+                    (_, 0) => bits,
+                    (Mnemonic::Lzcnt, source) => source.leading_zeros() - (64 - bits),
+                    (_, source) => source.trailing_zeros(),
+                };
+                self.update_flags(flag(CF, source == 0) | flag(ZF, count == 0), STATUS);
+                self.write_operand(instruction, 0, count as u64)?;
+            }
+
+            Mnemonic::Andn => {
+                let result = !self.read_operand(instruction, 1)? & self.read_operand(instruction, 2)? & mask;
+                self.update_flags(flags::logic(result, size) & (ZF | SF), STATUS);
+                self.write_operand(instruction, 0, result)?;
+            }
+
+            Mnemonic::Bextr => {
+                let source = self.read_operand(instruction, 1)?;
+                let control = self.read_operand(instruction, 2)?;
+                let start = (control & 0xff) as u32;
+                let length = ((control >> 8) & 0xff) as u32;
+                let result = shift_right(source, start) & low_bits(length);
+                self.update_flags(flag(ZF, result == 0), STATUS);
+                self.write_operand(instruction, 0, result)?;
+            }
+
+            Mnemonic::Bzhi => {
+                let source = self.read_operand(instruction, 1)?;
+                let index = (self.read_operand(instruction, 2)? & 0xff) as u32;
+                let result = source & low_bits(index) & mask;
+                self.update_flags((flags::logic(result, size) & (ZF | SF)) | flag(CF, index > bits - 1), STATUS);
+                self.write_operand(instruction, 0, result)?;
+            }
+
+            Mnemonic::Blsi | Mnemonic::Blsr | Mnemonic::Blsmsk => {
+                let source = self.read_operand(instruction, 1)?;
+                let result = match instruction.mnemonic() {
+                    Mnemonic::Blsi => source.wrapping_neg() & source,
+                    Mnemonic::Blsr => source.wrapping_sub(1) & source,
+                    _ => source.wrapping_sub(1) ^ source,
+                } & mask;
+                let cf = match instruction.mnemonic() {
+                    Mnemonic::Blsi => source != 0,
+                    _ => source == 0,
+                };
+                // blsmsk always clears ZF, as its result can not be zero
+                self.update_flags((flags::logic(result, size) & (ZF | SF)) | flag(CF, cf), STATUS);
+                self.write_operand(instruction, 0, result)?;
+            }
+
+            Mnemonic::Pdep | Mnemonic::Pext => {
+                let source = self.read_operand(instruction, 1)?;
+                let selector = self.read_operand(instruction, 2)?;
+                let result = match instruction.mnemonic() {
+                    Mnemonic::Pdep => deposit_bits(source, selector),
+                    _ => extract_bits(source, selector),
+                };
+                self.write_operand(instruction, 0, result)?;
+            }
+
+            Mnemonic::Shlx | Mnemonic::Shrx | Mnemonic::Sarx => {
+                let source = self.read_operand(instruction, 1)?;
+                let count = (self.read_operand(instruction, 2)? & (bits as u64 - 1)) as u32;
+                let result = match instruction.mnemonic() {
+                    Mnemonic::Shlx => source << count,
+                    Mnemonic::Shrx => source >> count,
+                    _ => (flags::sign_extend(source, size) as i64 >> count) as u64,
+                } & mask;
+                self.write_operand(instruction, 0, result)?;
+            }
+
+            Mnemonic::Rorx => {
+                let source = self.read_operand(instruction, 1)?;
+                let count = (self.read_operand(instruction, 2)? & (bits as u64 - 1)) as u32;
+                let result = (shift_right(source, count) | shift_left(source, bits - count)) & mask;
+                self.write_operand(instruction, 0, result)?;
+            }
+
+            Mnemonic::Mulx => {
+                // unsigned multiplication of rDX by the source, without affecting flags
+                let multiplier = self.get_register_u64(super::arithmetic::data_register(size))?;
+                let product = multiplier as u128 * self.read_operand(instruction, 2)? as u128;
+                // the high half wins if both destinations are the same register
+                self.write_operand(instruction, 1, product as u64)?;
+                self.write_operand(instruction, 0, (product >> bits) as u64)?;
+            }
+
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        }
+
+        Ok(())
+    }
+
+    /// bt, bts, btr and btc: copies the selected bit to CF, then sets, clears or complements it.
+    /// A register bit offset addresses a bit string in memory relative to the memory operand.
+    fn bit_test(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let size = self.operand_size(instruction, 0);
+        let bits = size as u64 * 8;
+        let offset = self.read_operand(instruction, 1)?;
+
+        let (address, bit) = match (instruction.op0_kind(), instruction.op1_kind()) {
+            (OpKind::Memory, OpKind::Register) => {
+                let offset = flags::sign_extend(offset, size) as i64;
+                let displacement = offset.div_euclid(bits as i64) * size as i64;
+                let address = self.memory_address(instruction)?.wrapping_add_signed(displacement);
+                (Some(address), offset.rem_euclid(bits as i64) as u64)
+            }
+            _ => (None, offset % bits),
+        };
+
+        let value = match address {
+            Some(address) => self.memory.read_uint(address, size)?,
+            None => self.read_operand(instruction, 0)?,
+        };
+        self.set_flag(CF, (value >> bit) & 1 != 0);
+
+        let result = match instruction.mnemonic() {
+            Mnemonic::Bts => value | (1 << bit),
+            Mnemonic::Btr => value & !(1 << bit),
+            Mnemonic::Btc => value ^ (1 << bit),
+            _ => return Ok(()),
+        };
+        match address {
+            Some(address) => Ok(self.memory.write_uint(address, size, result)?),
+            None => self.write_operand(instruction, 0, result),
+        }
+    }
+}
+
+fn flag(flag: u64, set: bool) -> u64 {
+    if set { flag } else { 0 }
+}
+
+/// Shifts left, giving zero for shifts of 64 bits or more
+fn shift_left(value: u64, count: u32) -> u64 {
+    value.checked_shl(count).unwrap_or_default()
+}
+
+/// Shifts right, giving zero for shifts of 64 bits or more
+fn shift_right(value: u64, count: u32) -> u64 {
+    value.checked_shr(count).unwrap_or_default()
+}
+
+/// Updates a CRC-32C (Castagnoli polynomial, bit-reflected) with a byte
+fn crc32c(crc: u32, byte: u8) -> u32 {
+    (0..8).fold(crc ^ byte as u32, |crc, _| (crc >> 1) ^ (CRC32C_POLYNOMIAL & (crc & 1).wrapping_neg()))
+}
+
+/// Mask of the lowest `count` bits
+fn low_bits(count: u32) -> u64 {
+    shift_left(1, count).wrapping_sub(1)
+}
+
+/// Deposits the low bits of `source` at the set bits of `selector` (pdep)
+fn deposit_bits(source: u64, selector: u64) -> u64 {
+    let mut result = 0;
+    let mut source_bit = 0;
+    for bit in 0..64 {
+        if selector & (1 << bit) != 0 {
+            result |= ((source >> source_bit) & 1) << bit;
+            source_bit += 1;
+        }
+    }
+    result
+}
+
+/// Gathers the bits of `source` at the set bits of `selector` into the low bits (pext)
+fn extract_bits(source: u64, selector: u64) -> u64 {
+    let mut result = 0;
+    let mut result_bit = 0;
+    for bit in 0..64 {
+        if selector & (1 << bit) != 0 {
+            result |= ((source >> bit) & 1) << result_bit;
+            result_bit += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::flags::RESERVED;
+    use crate::cpu::testing::cpu_with_flags;
+
+    use iced_x86::{Code, Register};
+
+    // the count is masked to 5 bits (6 for 64-bit operands) before rcl and rcr take it modulo 9 or 17
+    // for byte and word operands, a masked count of 0 leaves the flags unchanged
+    #[test]
+    fn shift_and_rotate_counts_are_masked() {
+        let cases = [
+            (Code::Shl_rm8_CL, Register::AL, 0x81, 9, false, 0x0, false, true),
+            (Code::Shl_rm8_CL, Register::AL, 0x81, 33, false, 0x2, true, true),
+            (Code::Shl_rm16_CL, Register::AX, 0x8001, 17, false, 0x0, false, true),
+            (Code::Shl_rm32_CL, Register::EAX, 0x80000001, 33, false, 0x2, true, true),
+            (Code::Shl_rm32_CL, Register::EAX, 0x1, 32, true, 0x1, true, false),
+            (Code::Shl_rm64_CL, Register::RAX, 0x8000000000000001, 65, false, 0x2, true, true),
+            (Code::Shl_rm64_CL, Register::RAX, 0x1, 64, true, 0x1, true, false),
+            (Code::Shr_rm8_CL, Register::AL, 0x81, 9, false, 0x0, false, true),
+            (Code::Shr_rm32_CL, Register::EAX, 0x80000001, 33, false, 0x40000000, true, true),
+            (Code::Shr_rm64_CL, Register::RAX, 0x8000000000000001, 127, false, 0x1, false, true),
+            (Code::Sar_rm8_CL, Register::AL, 0x80, 40, false, 0xff, true, false),
+            (Code::Sar_rm16_CL, Register::AX, 0x8000, 31, false, 0xffff, true, false),
+            (Code::Rol_rm8_CL, Register::AL, 0x81, 8, false, 0x81, true, true),
+            (Code::Rol_rm8_CL, Register::AL, 0x81, 41, false, 0x3, true, true),
+            (Code::Rol_rm16_CL, Register::AX, 0x8001, 16, false, 0x8001, true, true),
+            (Code::Ror_rm32_CL, Register::EAX, 0x80000001, 63, false, 0x3, false, false),
+            (Code::Ror_rm64_CL, Register::RAX, 0x3, 67, false, 0x6000000000000000, false, true),
+            (Code::Rol_rm64_CL, Register::RAX, 0x8000000000000000, 64, true, 0x8000000000000000, true, false),
+            (Code::Rcl_rm8_CL, Register::AL, 0x81, 9, false, 0x81, false, false),
+            (Code::Rcl_rm8_CL, Register::AL, 0x81, 10, true, 0x3, true, true),
+            (Code::Rcl_rm8_CL, Register::AL, 0x81, 31, false, 0x14, false, true),
+            (Code::Rcr_rm16_CL, Register::AX, 0x8001, 17, true, 0x8001, true, false),
+            (Code::Rcr_rm16_CL, Register::AX, 0x8001, 18, false, 0x4000, true, true),
+            (Code::Rcl_rm32_CL, Register::EAX, 0x80000001, 33, false, 0x2, true, true),
+            (Code::Rcr_rm64_CL, Register::RAX, 0x1, 65, false, 0x0, true, false),
+            (Code::Rcr_rm32_CL, Register::EAX, 0x1, 32, true, 0x1, true, false),
+        ];
+        for &(code, destination, value, count, carry, result, cf, of) in &cases {
+            let mut cpu = cpu_with_flags(if carry { CF } else { 0 });
+            cpu.registers.rax = value;
+            cpu.registers.rcx = count;
+            let instruction = Instruction::with2(code, destination, Register::CL).unwrap();
+            cpu.execute_instruction(instruction).unwrap();
+            assert_eq!(cpu.get_register_u64(destination).unwrap(), result, "{:?} 0x{:x}, {}", code, value, count);
+            assert_eq!((cpu.flag(CF), cpu.flag(OF)), (cf, of), "{:?} 0x{:x}, {}", code, value, count);
+        }
+    }
+
+    #[test]
+    fn masked_zero_count_still_zero_extends_32_bit_destinations() {
+        let mut cpu = cpu_with_flags(CF);
+        cpu.registers.rax = 0xffff_ffff_8000_0001;
+        cpu.registers.rcx = 32;
+        cpu.execute_instruction(Instruction::with2(Code::Shl_rm32_CL, Register::EAX, Register::CL).unwrap()).unwrap();
+        assert_eq!(cpu.registers.rax, 0x8000_0001);
+        assert_eq!(cpu.registers.rflags, RESERVED | CF);
+    }
+}
@@ -0,0 +1,144 @@
+use super::error::Error;
+use super::flags;
+use super::Cpu;
+
+use iced_x86::{Code, Instruction, Mnemonic, OpKind, Register};
+
+impl Cpu {
+    /// Executes a near branch: jmp, jcc, call, ret, loop, jrcxz, and the stack frame instructions enter and leave.
+    /// `rip` already points to the next instruction.
+    pub fn execute_control_flow(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        match instruction.mnemonic() {
+            Mnemonic::Jmp => {
+                self.registers.rip = self.branch_target(instruction)?;
+            }
+
+            Mnemonic::Call => {
+                let target = self.branch_target(instruction)?;
+                let size = instruction.stack_pointer_increment().unsigned_abs() as usize;
+                self.push_stack_value(self.registers.rip, size)?;
+                self.registers.rip = target;
+            }
+
+            Mnemonic::Ret => {
+                // `ret imm16` releases imm16 bytes of arguments after popping the return address
+                let release = match instruction.op_count() {
+                    0 => 0,
+                    _ => instruction.immediate16() as u64,
+                };
+                let size = (instruction.stack_pointer_increment() as u64 - release) as usize;
+                self.registers.rip = self.pop_stack_value(size)?;
+                self.registers.rsp = self.registers.rsp.wrapping_add(release);
+            }
+
+            Mnemonic::Loop | Mnemonic::Loope | Mnemonic::Loopne => {
+                let counter = counter_register(instruction.code());
+                let count = self.get_register_u64(counter)?.wrapping_sub(1) & flags::mask(counter.size());
+                self.set_register(counter, count)?;
+
+                let taken = count != 0 && match instruction.mnemonic() {
+                    Mnemonic::Loope => self.flag(flags::ZF),
+                    Mnemonic::Loopne => !self.flag(flags::ZF),
+                    _ => true,
+                };
+                if taken {
+                    self.registers.rip = instruction.near_branch_target();
+                }
+            }
+
+            Mnemonic::Jrcxz | Mnemonic::Jecxz | Mnemonic::Jcxz => {
+                if self.get_register_u64(counter_register(instruction.code()))? == 0 {
+                    self.registers.rip = instruction.near_branch_target();
+                }
+            }
+
+            Mnemonic::Enter => self.enter(instruction)?,
+
+            Mnemonic::Leave => {
+                let size = match instruction.code() {
+                    Code::Leavew => 2,
+                    Code::Leaved => 4,
+                    _ => 8,
+                };
+                self.registers.rsp = self.registers.rbp;
+                let rbp = self.pop_stack_value(size)?;
+                self.set_register(frame_pointer(size), rbp)?;
+            }
+
+            _ if instruction.is_jcc_short_or_near() => {
+                if flags::condition(instruction.condition_code(), self.registers.rflags) {
+                    self.registers.rip = instruction.near_branch_target();
+                }
+            }
+
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        }
+
+        Ok(())
+    }
+
+    /// Target of a near jmp or call: relative, or indirect through a register or memory. Far indirect branches
+    /// through a `m16:16`, `m16:32` or `m16:64` pointer also have a memory operand but load a new code segment,
+    /// which is not supported.
+    fn branch_target(&self, instruction: &Instruction) -> Result<u64, Error> {
+        if instruction.is_call_far_indirect() || instruction.is_jmp_far_indirect() {
+            return Err(Error::UnimplementedInstruction(*instruction));
+        }
+
+        match instruction.op0_kind() {
+            OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => Ok(instruction.near_branch_target()),
+            OpKind::Register | OpKind::Memory => self.read_operand(instruction, 0),
+            _ => Err(Error::UnimplementedInstruction(*instruction)),
+        }
+    }
+
+    /// `enter size, level`: creates a stack frame of `size` bytes, copying `level - 1` frame pointers of the enclosing frames
+    fn enter(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let size = match instruction.code() {
+            Code::Enterw_imm16_imm8 => 2,
+            Code::Enterd_imm16_imm8 => 4,
+            _ => 8,
+        };
+        let frame_size = instruction.immediate16() as u64;
+        let level = instruction.immediate8_2nd() % 32;
+        let rbp = frame_pointer(size);
+
+        self.push_stack_value(self.get_register_u64(rbp)?, size)?;
+        let frame_pointer = self.registers.rsp;
+
+        if level > 0 {
+            let mut enclosing = self.get_register_u64(rbp)?;
+            for _ in 1..level {
+                enclosing = enclosing.wrapping_sub(size as u64);
+                let value = self.memory.read_uint(enclosing, size)?;
+                self.push_stack_value(value, size)?;
+            }
+            self.push_stack_value(frame_pointer, size)?;
+        }
+
+        self.set_register(rbp, frame_pointer)?;
+        self.registers.rsp = self.registers.rsp.wrapping_sub(frame_size);
+        Ok(())
+    }
+}
+
+/// Counter register of loop and jrcxz, selected by the address size
+fn counter_register(code: Code) -> Register {
+    match code {
+        Code::Loop_rel8_16_CX | Code::Loop_rel8_32_CX | Code::Loope_rel8_16_CX | Code::Loope_rel8_32_CX
+        | Code::Loopne_rel8_16_CX | Code::Loopne_rel8_32_CX | Code::Jcxz_rel8_16 | Code::Jcxz_rel8_32 => Register::CX,
+        Code::Loop_rel8_16_ECX | Code::Loop_rel8_32_ECX | Code::Loop_rel8_64_ECX | Code::Loope_rel8_16_ECX
+        | Code::Loope_rel8_32_ECX | Code::Loope_rel8_64_ECX | Code::Loopne_rel8_16_ECX | Code::Loopne_rel8_32_ECX
+        | Code::Loopne_rel8_64_ECX | Code::Jecxz_rel8_16 | Code::Jecxz_rel8_32 | Code::Jecxz_rel8_64 => Register::ECX,
+        _ => Register::RCX,
+    }
+}
+
+/// Frame pointer register matching the stack operand size
+fn frame_pointer(size: usize) -> Register {
+    match size {
+        2 => Register::BP,
+        4 => Register::EBP,
+        _ => Register::RBP,
+    }
+}
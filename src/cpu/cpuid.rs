@@ -0,0 +1,438 @@
+use super::error::Error;
+use super::Cpu;
+
+use iced_x86::{CpuidFeature, Instruction, Register};
+
+/// Register of a cpuid leaf holding feature flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FeatureWord {
+    /// leaf 1 edx
+    Basic1Edx,
+    /// leaf 1 ecx
+    Basic1Ecx,
+    /// leaf 7 subleaf 0 ebx
+    Extended7Ebx,
+    /// leaf 7 subleaf 0 ecx
+    Extended7Ecx,
+    /// leaf 7 subleaf 0 edx
+    Extended7Edx,
+    /// leaf 0x80000001 ecx
+    Amd1Ecx,
+    /// leaf 0x80000001 edx
+    Amd1Edx,
+}
+
+const FEATURE_WORDS: usize = 7;
+
+/// A processor feature, reported as a bit in one of the cpuid feature leaves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Feature {
+    word: FeatureWord,
+    bit: u32,
+}
+
+impl Feature {
+    const fn new(word: FeatureWord, bit: u32) -> Self {
+        Self { word, bit }
+    }
+}
+
+pub const FPU: Feature = Feature::new(FeatureWord::Basic1Edx, 0);
+pub const TSC: Feature = Feature::new(FeatureWord::Basic1Edx, 4);
+pub const CX8: Feature = Feature::new(FeatureWord::Basic1Edx, 8);
+pub const CMOV: Feature = Feature::new(FeatureWord::Basic1Edx, 15);
+pub const CLFSH: Feature = Feature::new(FeatureWord::Basic1Edx, 19);
+pub const MMX: Feature = Feature::new(FeatureWord::Basic1Edx, 23);
+pub const FXSR: Feature = Feature::new(FeatureWord::Basic1Edx, 24);
+pub const SSE: Feature = Feature::new(FeatureWord::Basic1Edx, 25);
+pub const SSE2: Feature = Feature::new(FeatureWord::Basic1Edx, 26);
+
+pub const SSE3: Feature = Feature::new(FeatureWord::Basic1Ecx, 0);
+pub const PCLMULQDQ: Feature = Feature::new(FeatureWord::Basic1Ecx, 1);
+pub const SSSE3: Feature = Feature::new(FeatureWord::Basic1Ecx, 9);
+pub const FMA: Feature = Feature::new(FeatureWord::Basic1Ecx, 12);
+pub const CX16: Feature = Feature::new(FeatureWord::Basic1Ecx, 13);
+pub const SSE4_1: Feature = Feature::new(FeatureWord::Basic1Ecx, 19);
+pub const SSE4_2: Feature = Feature::new(FeatureWord::Basic1Ecx, 20);
+pub const MOVBE: Feature = Feature::new(FeatureWord::Basic1Ecx, 22);
+pub const POPCNT: Feature = Feature::new(FeatureWord::Basic1Ecx, 23);
+pub const XSAVE: Feature = Feature::new(FeatureWord::Basic1Ecx, 26);
+pub const OSXSAVE: Feature = Feature::new(FeatureWord::Basic1Ecx, 27);
+pub const AVX: Feature = Feature::new(FeatureWord::Basic1Ecx, 28);
+pub const F16C: Feature = Feature::new(FeatureWord::Basic1Ecx, 29);
+pub const RDRAND: Feature = Feature::new(FeatureWord::Basic1Ecx, 30);
+
+pub const BMI1: Feature = Feature::new(FeatureWord::Extended7Ebx, 3);
+pub const AVX2: Feature = Feature::new(FeatureWord::Extended7Ebx, 5);
+pub const BMI2: Feature = Feature::new(FeatureWord::Extended7Ebx, 8);
+pub const ERMS: Feature = Feature::new(FeatureWord::Extended7Ebx, 9);
+pub const AVX512F: Feature = Feature::new(FeatureWord::Extended7Ebx, 16);
+pub const AVX512DQ: Feature = Feature::new(FeatureWord::Extended7Ebx, 17);
+pub const RDSEED: Feature = Feature::new(FeatureWord::Extended7Ebx, 18);
+pub const ADX: Feature = Feature::new(FeatureWord::Extended7Ebx, 19);
+pub const AVX512CD: Feature = Feature::new(FeatureWord::Extended7Ebx, 28);
+pub const AVX512BW: Feature = Feature::new(FeatureWord::Extended7Ebx, 30);
+pub const AVX512VL: Feature = Feature::new(FeatureWord::Extended7Ebx, 31);
+
+pub const LAHF_LM: Feature = Feature::new(FeatureWord::Amd1Ecx, 0);
+pub const ABM: Feature = Feature::new(FeatureWord::Amd1Ecx, 5);
+
+pub const SYSCALL: Feature = Feature::new(FeatureWord::Amd1Edx, 11);
+pub const NX: Feature = Feature::new(FeatureWord::Amd1Edx, 20);
+pub const RDTSCP: Feature = Feature::new(FeatureWord::Amd1Edx, 27);
+pub const LM: Feature = Feature::new(FeatureWord::Amd1Edx, 29);
+
+/// Set of processor features
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Features([u32; FEATURE_WORDS]);
+
+impl Features {
+    pub const fn new(features: &[Feature]) -> Self {
+        let mut words = [0; FEATURE_WORDS];
+        let mut i = 0;
+        while i < features.len() {
+            words[features[i].word as usize] |= 1 << features[i].bit;
+            i += 1;
+        }
+        Self(words)
+    }
+
+    /// Features present in both sets
+    pub fn intersection(&self, other: &Features) -> Features {
+        let mut words = self.0;
+        for (word, other) in words.iter_mut().zip(other.0) {
+            *word &= other;
+        }
+        Self(words)
+    }
+
+    pub fn contains(&self, feature: Feature) -> bool {
+        self.word(feature.word) & (1 << feature.bit) != 0
+    }
+
+    fn word(&self, word: FeatureWord) -> u32 {
+        self.0[word as usize]
+    }
+}
+
+/// Features the emulator executes, only these are reported by cpuid whatever the CPU model lists
+pub const IMPLEMENTED: Features = Features::new(&[
+    FPU, CX8, CMOV, FXSR, SSE, SSE2, SSE3, SSSE3, FMA, SSE4_1, SSE4_2, CX16, MOVBE, POPCNT, XSAVE, OSXSAVE, AVX, F16C, BMI1, AVX2,
+    BMI2, ERMS, AVX512F, AVX512DQ, AVX512CD, AVX512BW, AVX512VL, LAHF_LM, ABM, SYSCALL, NX, LM,
+]);
+
+/// Feature an instruction requires, for the features whose instructions are rejected when the CPU model lacks them
+fn required_feature(feature: CpuidFeature) -> Option<Feature> {
+    match feature {
+        CpuidFeature::AVX => Some(AVX),
+        CpuidFeature::AVX2 => Some(AVX2),
+        CpuidFeature::FMA => Some(FMA),
+        CpuidFeature::F16C => Some(F16C),
+        CpuidFeature::AVX512F => Some(AVX512F),
+        CpuidFeature::AVX512DQ => Some(AVX512DQ),
+        CpuidFeature::AVX512CD => Some(AVX512CD),
+        CpuidFeature::AVX512BW => Some(AVX512BW),
+        CpuidFeature::AVX512VL => Some(AVX512VL),
+        CpuidFeature::MOVBE => Some(MOVBE),
+        CpuidFeature::XSAVE => Some(XSAVE),
+        _ => None,
+    }
+}
+
+/// State components of XCR0 and the xsave area
+pub const XSTATE_X87: u64 = 1 << 0;
+pub const XSTATE_SSE: u64 = 1 << 1;
+pub const XSTATE_AVX: u64 = 1 << 2;
+pub const XSTATE_OPMASK: u64 = 1 << 5;
+pub const XSTATE_ZMM_HI256: u64 = 1 << 6;
+pub const XSTATE_HI16_ZMM: u64 = 1 << 7;
+/// Components of the AVX-512 state: the opmask registers, the upper halves of ZMM0 to ZMM15 and ZMM16 to ZMM31
+pub const XSTATE_AVX512: u64 = XSTATE_OPMASK | XSTATE_ZMM_HI256 | XSTATE_HI16_ZMM;
+
+/// Kind of a cache, as reported by cpuid leaf 4
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Data = 1,
+    Instruction = 2,
+    Unified = 3,
+}
+
+/// A cache level described by cpuid leaf 4
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cache {
+    pub level: u32,
+    pub kind: CacheKind,
+    /// size in bytes
+    pub size: u32,
+    pub ways: u32,
+    /// cache line size in bytes
+    pub line_size: u32,
+}
+
+impl Cache {
+    const fn new(level: u32, kind: CacheKind, size: u32, ways: u32) -> Self {
+        Self { level, kind, size, ways, line_size: 64 }
+    }
+
+    fn sets(&self) -> u32 {
+        self.size / (self.ways * self.line_size)
+    }
+}
+
+/// Processor identification reported by cpuid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuModel {
+    /// vendor identification string of 12 bytes
+    pub vendor: String,
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+    /// processor brand string of up to 48 bytes
+    pub brand: String,
+    /// features of the modelled processor, reported if they are also [`IMPLEMENTED`]
+    pub features: Features,
+    pub caches: Vec<Cache>,
+}
+
+impl CpuModel {
+    /// Intel Xeon Scalable processor (Skylake-SP), the most recent of the profiles
+    pub fn skylake_server() -> Self {
+        Self {
+            vendor: "GenuineIntel".to_string(),
+            family: 6,
+            model: 0x55,
+            stepping: 4,
+            brand: "Intel(R) Xeon(R) Gold 6148 CPU @ 2.40GHz".to_string(),
+            features: Features::new(&[
+                FPU, TSC, CX8, CMOV, CLFSH, MMX, FXSR, SSE, SSE2, SSE3, PCLMULQDQ, SSSE3, FMA, CX16, SSE4_1, SSE4_2, MOVBE,
+                POPCNT, XSAVE, OSXSAVE, AVX, F16C, RDRAND, BMI1, AVX2, BMI2, ERMS, AVX512F, AVX512DQ, RDSEED, ADX, AVX512CD,
+                AVX512BW, AVX512VL, LAHF_LM, ABM, SYSCALL, NX, RDTSCP, LM,
+            ]),
+            caches: vec![
+                Cache::new(1, CacheKind::Data, 32 * 1024, 8),
+                Cache::new(1, CacheKind::Instruction, 32 * 1024, 8),
+                Cache::new(2, CacheKind::Unified, 1024 * 1024, 16),
+                Cache::new(3, CacheKind::Unified, 28160 * 1024, 11),
+            ],
+        }
+    }
+
+    /// Intel Core processor of the 4th generation (Haswell), with AVX2 but no AVX-512
+    pub fn haswell() -> Self {
+        Self {
+            vendor: "GenuineIntel".to_string(),
+            family: 6,
+            model: 0x3c,
+            stepping: 3,
+            brand: "Intel(R) Core(TM) i7-4770 CPU @ 3.40GHz".to_string(),
+            features: Features::new(&[
+                FPU, TSC, CX8, CMOV, CLFSH, MMX, FXSR, SSE, SSE2, SSE3, PCLMULQDQ, SSSE3, FMA, CX16, SSE4_1, SSE4_2, MOVBE,
+                POPCNT, XSAVE, OSXSAVE, AVX, F16C, RDRAND, BMI1, AVX2, BMI2, ERMS, LAHF_LM, ABM, SYSCALL, NX, RDTSCP, LM,
+            ]),
+            caches: vec![
+                Cache::new(1, CacheKind::Data, 32 * 1024, 8),
+                Cache::new(1, CacheKind::Instruction, 32 * 1024, 8),
+                Cache::new(2, CacheKind::Unified, 256 * 1024, 8),
+                Cache::new(3, CacheKind::Unified, 8 * 1024 * 1024, 16),
+            ],
+        }
+    }
+
+    /// Intel Core processor of the 1st generation (Nehalem), the first with SSE4.2
+    pub fn nehalem() -> Self {
+        Self {
+            vendor: "GenuineIntel".to_string(),
+            family: 6,
+            model: 0x1a,
+            stepping: 5,
+            brand: "Intel(R) Core(TM) i7 CPU 920 @ 2.67GHz".to_string(),
+            features: Features::new(&[
+                FPU, TSC, CX8, CMOV, CLFSH, MMX, FXSR, SSE, SSE2, SSE3, SSSE3, CX16, SSE4_1, SSE4_2, POPCNT, LAHF_LM, SYSCALL,
+                NX, RDTSCP, LM,
+            ]),
+            caches: vec![
+                Cache::new(1, CacheKind::Data, 32 * 1024, 8),
+                Cache::new(1, CacheKind::Instruction, 32 * 1024, 4),
+                Cache::new(2, CacheKind::Unified, 256 * 1024, 8),
+                Cache::new(3, CacheKind::Unified, 8 * 1024 * 1024, 16),
+            ],
+        }
+    }
+
+    /// Features reported by cpuid and executed
+    pub fn supported(&self) -> Features {
+        self.features.intersection(&IMPLEMENTED)
+    }
+
+    /// State components enabled in XCR0: those of the x87 FPU and SSE, of AVX when it is supported and of AVX-512
+    /// when AVX512F is
+    pub fn xcr0(&self) -> u64 {
+        let supported = self.supported();
+        match (supported.contains(AVX), supported.contains(AVX512F)) {
+            (true, true) => XSTATE_X87 | XSTATE_SSE | XSTATE_AVX | XSTATE_AVX512,
+            (true, false) => XSTATE_X87 | XSTATE_SSE | XSTATE_AVX,
+            _ => XSTATE_X87 | XSTATE_SSE,
+        }
+    }
+
+    /// Values of eax, ebx, ecx and edx returned by cpuid for a leaf and subleaf. Leaves which are
+    /// not implemented return zeros.
+    pub fn cpuid(&self, leaf: u32, subleaf: u32) -> [u32; 4] {
+        let features = self.supported();
+        match leaf {
+            0 => {
+                let vendor = self.vendor_words();
+                [MAX_BASIC_LEAF, vendor[0], vendor[2], vendor[1]]
+            }
+            1 => {
+                let signature = self.signature();
+                // clflush line size in quadwords and a single logical processor
+                let ebx = (8 << 8) | (1 << 16);
+                [signature, ebx, features.word(FeatureWord::Basic1Ecx), features.word(FeatureWord::Basic1Edx)]
+            }
+            // the cache and TLB descriptor 0xff refers to leaf 4
+            2 => [0x00ff_0001, 0, 0, 0],
+            4 => match self.caches.get(subleaf as usize) {
+                Some(cache) => {
+                    let eax = cache.kind as u32 | (cache.level << 5) | (1 << 8);
+                    let ebx = (cache.line_size - 1) | ((cache.ways - 1) << 22);
+                    [eax, ebx, cache.sets() - 1, 0]
+                }
+                None => [0; 4],
+            },
+            7 if subleaf == 0 => [
+                0,
+                features.word(FeatureWord::Extended7Ebx),
+                features.word(FeatureWord::Extended7Ecx),
+                features.word(FeatureWord::Extended7Edx),
+            ],
+            // the xsave area: the legacy region and header of 576 bytes, then the upper halves of the YMM registers and
+            // the AVX-512 state, each component at its offset in the standard format
+            0xd if features.contains(XSAVE) => {
+                let xcr0 = self.xcr0();
+                let size = XSAVE_COMPONENTS.iter()
+                    .filter(|&&(component, ..)| xcr0 & (1 << component) != 0)
+                    .map(|&(_, offset, size)| offset + size)
+                    .max()
+                    .unwrap_or(XSAVE_AVX_OFFSET);
+                match subleaf {
+                    0 => [xcr0 as u32, size, size, 0],
+                    _ => match XSAVE_COMPONENTS.iter().find(|&&(component, ..)| component == subleaf && xcr0 & (1 << component) != 0) {
+                        Some(&(_, offset, size)) => [size, offset, 0, 0],
+                        None => [0; 4],
+                    },
+                }
+            }
+            0x8000_0000 => [MAX_EXTENDED_LEAF, 0, 0, 0],
+            0x8000_0001 => [self.signature(), 0, features.word(FeatureWord::Amd1Ecx), features.word(FeatureWord::Amd1Edx)],
+            0x8000_0002..=0x8000_0004 => {
+                let brand = self.brand_words();
+                let offset = (leaf - 0x8000_0002) as usize * 4;
+                [brand[offset], brand[offset + 1], brand[offset + 2], brand[offset + 3]]
+            }
+            // L2 cache size in KiB and line size
+            0x8000_0006 => {
+                let ecx = self.caches.iter()
+                    .find(|cache| cache.level == 2)
+                    .map(|cache| ((cache.size / 1024) << 16) | cache.line_size)
+                    .unwrap_or_default();
+                [0, 0, ecx, 0]
+            }
+            // 48 bits of linear and 39 bits of physical address space
+            0x8000_0008 => [(48 << 8) | 39, 0, 0, 0],
+            _ => [0; 4],
+        }
+    }
+
+    /// Family, model and stepping in the leaf 1 eax format
+    fn signature(&self) -> u32 {
+        let (family, extended_family) = match self.family {
+            family if family < 15 => (family, 0),
+            family => (15, family - 15),
+        };
+        (self.stepping & 0xf) | ((self.model & 0xf) << 4) | (family << 8) | ((self.model >> 4) << 16) | (extended_family << 20)
+    }
+
+    fn vendor_words(&self) -> [u32; 3] {
+        let mut bytes = [0; 12];
+        let vendor = self.vendor.as_bytes();
+        let len = vendor.len().min(bytes.len());
+        bytes[..len].copy_from_slice(&vendor[..len]);
+        std::array::from_fn(|i| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
+    }
+
+    /// Brand string padded with zero bytes, the last byte is always zero
+    fn brand_words(&self) -> [u32; 12] {
+        let mut bytes = [0; 48];
+        let brand = self.brand.as_bytes();
+        let len = brand.len().min(bytes.len() - 1);
+        bytes[..len].copy_from_slice(&brand[..len]);
+        std::array::from_fn(|i| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
+    }
+}
+
+impl Default for CpuModel {
+    fn default() -> Self {
+        Self::skylake_server()
+    }
+}
+
+impl std::str::FromStr for CpuModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skylake-server" => Ok(Self::skylake_server()),
+            "haswell" => Ok(Self::haswell()),
+            "nehalem" => Ok(Self::nehalem()),
+            _ => Err(format!("unknown CPU model {:?}, expected skylake-server, haswell or nehalem", s)),
+        }
+    }
+}
+
+/// Highest basic leaf answered by cpuid
+const MAX_BASIC_LEAF: u32 = 0xd;
+
+/// Offset and size in the xsave area of the upper halves of the YMM registers
+pub const XSAVE_AVX_OFFSET: u32 = 576;
+pub const XSAVE_AVX_SIZE: u32 = 256;
+/// Offsets and sizes in the xsave area of the opmask registers, the upper halves of ZMM0 to ZMM15 and ZMM16 to ZMM31
+pub const XSAVE_OPMASK_OFFSET: u32 = 1088;
+pub const XSAVE_OPMASK_SIZE: u32 = 64;
+pub const XSAVE_ZMM_HI256_OFFSET: u32 = 1152;
+pub const XSAVE_ZMM_HI256_SIZE: u32 = 512;
+pub const XSAVE_HI16_ZMM_OFFSET: u32 = 1664;
+pub const XSAVE_HI16_ZMM_SIZE: u32 = 1024;
+
+/// The state components after the legacy region with their offset and size, as cpuid reports them in leaf 0xd
+const XSAVE_COMPONENTS: [(u32, u32, u32); 4] = [
+    (2, XSAVE_AVX_OFFSET, XSAVE_AVX_SIZE),
+    (5, XSAVE_OPMASK_OFFSET, XSAVE_OPMASK_SIZE),
+    (6, XSAVE_ZMM_HI256_OFFSET, XSAVE_ZMM_HI256_SIZE),
+    (7, XSAVE_HI16_ZMM_OFFSET, XSAVE_HI16_ZMM_SIZE),
+];
+
+/// Highest extended leaf answered by cpuid
+const MAX_EXTENDED_LEAF: u32 = 0x8000_0008;
+
+impl Cpu {
+    /// Executes cpuid: eax selects the leaf and ecx the subleaf
+    pub fn execute_cpuid(&mut self) -> Result<(), Error> {
+        let leaf = self.get_register_u64(Register::EAX)? as u32;
+        let subleaf = self.get_register_u64(Register::ECX)? as u32;
+        let [eax, ebx, ecx, edx] = self.model.cpuid(leaf, subleaf);
+        self.set_register(Register::EAX, eax as u64)?;
+        self.set_register(Register::EBX, ebx as u64)?;
+        self.set_register(Register::ECX, ecx as u64)?;
+        self.set_register(Register::EDX, edx as u64)
+    }
+
+    /// Rejects an instruction of a feature the CPU model does not support as an invalid opcode (#UD)
+    pub fn check_features(&self, instruction: &Instruction) -> Result<(), Error> {
+        let supported = self.model.supported();
+        match instruction.cpuid_features().iter().filter_map(|&feature| required_feature(feature)).all(|feature| supported.contains(feature)) {
+            true => Ok(()),
+            false => Err(Error::InvalidOpcode(instruction.ip())),
+        }
+    }
+}
@@ -1,12 +1,34 @@
 use iced_x86::{Instruction, Register};
 
+use crate::mem::Access;
+
+/// Guest page fault (#PF), raised on an access to an unmapped page or one violating its protection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageFault {
+    /// faulting linear address (as reported in CR2)
+    pub address: u64,
+    pub access: Access,
+    /// whether the page was mapped (a protection violation) or not
+    pub present: bool,
+    /// address of the faulting instruction
+    pub rip: u64,
+}
+
 #[derive(Debug)]
 pub enum Error {
+    Memory(crate::mem::error::Error),
+    PageFault(PageFault),
+    InvalidOpcode(u64),
+    /// divide error (#DE) at rip, on division by zero or a quotient too large for the destination
+    DivideFault(u64),
+    /// general protection fault (#GP) at rip
+    GeneralProtectionFault(u64),
+    /// x87 floating-point error (#MF) at rip, delivering an unmasked exception pending since an earlier instruction
+    FloatingPointFault(u64),
+    /// SIMD floating-point exception (#XM) at rip
+    SimdFloatingPointFault(u64),
     UnimplementedRegister(Register),
-    UnimplementedRegisterSize(usize),
     UnimplementedInstruction(Instruction),
-    StackOverflowRead,
-    StackOverflowWrite,
 }
 
 impl std::error::Error for Error {}
@@ -14,11 +36,33 @@ impl std::error::Error for Error {}
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::Memory(e) => write!(f, "memory error: {}", e),
+            Self::PageFault(fault) => write!(
+                f,
+                "page fault: {} of {} address 0x{:x} at rip 0x{:x}",
+                fault.access,
+                if fault.present { "protected" } else { "unmapped" },
+                fault.address,
+                fault.rip,
+            ),
+            Self::InvalidOpcode(rip) => write!(f, "invalid opcode at rip 0x{:x}", rip),
+            Self::DivideFault(rip) => write!(f, "divide error at rip 0x{:x}", rip),
+            Self::GeneralProtectionFault(rip) => write!(f, "general protection fault at rip 0x{:x}", rip),
+            Self::FloatingPointFault(rip) => write!(f, "x87 floating-point error at rip 0x{:x}", rip),
+            Self::SimdFloatingPointFault(rip) => write!(f, "SIMD floating-point exception at rip 0x{:x}", rip),
             Self::UnimplementedRegister(register) => write!(f, "register {:?} is not implemented", register),
-            Self::UnimplementedRegisterSize(size) => write!(f, "register with size {} is not implemented", size),
             Self::UnimplementedInstruction(instruction) => write!(f, "opcode {:?} is not implemented (in instruction {})", instruction.code(), instruction),
-            Self::StackOverflowRead => write!(f, "attempted to read a value larger than the stack from the stack"),
-            Self::StackOverflowWrite => write!(f, "attempted to write a value larger than the stack to the stack"),
+        }
+    }
+}
+
+impl From<crate::mem::error::Error> for Error {
+    /// Guest accesses become page faults, with `rip` filled in by [`crate::cpu::Cpu::execute_instruction`]
+    fn from(e: crate::mem::error::Error) -> Self {
+        match e {
+            crate::mem::error::Error::Unmapped { address, access } => Self::PageFault(PageFault { address, access, present: false, rip: 0 }),
+            crate::mem::error::Error::ProtectionViolation { address, access } => Self::PageFault(PageFault { address, access, present: true, rip: 0 }),
+            e => Self::Memory(e),
         }
     }
 }
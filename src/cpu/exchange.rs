@@ -0,0 +1,87 @@
+use super::arithmetic::accumulator;
+use super::error::Error;
+use super::flags::{self, ZF};
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic, OpKind, Register};
+
+impl Cpu {
+    /// Executes xchg, xadd, cmpxchg, cmpxchg8b and cmpxchg16b
+    pub fn execute_exchange(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let size = self.operand_size(instruction, 0);
+
+        match instruction.mnemonic() {
+            Mnemonic::Xchg => {
+                let a = self.read_operand(instruction, 0)?;
+                let b = self.read_operand(instruction, 1)?;
+                self.write_operand(instruction, 0, b)?;
+                self.write_operand(instruction, 1, a)?;
+            }
+
+            // the source receives the old destination, written first so that xadd of a register with itself yields the sum
+            Mnemonic::Xadd => {
+                let a = self.read_operand(instruction, 0)?;
+                let b = self.read_operand(instruction, 1)?;
+                let (sum, new_flags) = flags::add(a, b, false, size);
+                self.write_operand(instruction, 1, a)?;
+                self.write_operand(instruction, 0, sum)?;
+                self.update_flags(new_flags, flags::STATUS);
+            }
+
+            // the accumulator is only written on failure, a memory destination is always written like on current processors
+            Mnemonic::Cmpxchg => {
+                let expected = self.get_register_u64(accumulator(size))?;
+                let current = self.read_operand(instruction, 0)?;
+                let (_, new_flags) = flags::sub(expected, current, false, size);
+                self.update_flags(new_flags, flags::STATUS);
+                if expected == current {
+                    let value = self.read_operand(instruction, 1)?;
+                    self.write_operand(instruction, 0, value)?;
+                } else {
+                    if instruction.op0_kind() == OpKind::Memory {
+                        self.write_operand(instruction, 0, current)?;
+                    }
+                    self.set_register(accumulator(size), current)?;
+                }
+            }
+
+            Mnemonic::Cmpxchg8b => {
+                let address = self.memory_address(instruction)?;
+                let current = self.memory.read_uint(address, 8)?;
+                let expected = (self.get_register_u64(Register::EDX)? << 32) | self.get_register_u64(Register::EAX)?;
+                let value = match current == expected {
+                    true => (self.get_register_u64(Register::ECX)? << 32) | self.get_register_u64(Register::EBX)?,
+                    false => current,
+                };
+                self.memory.write_uint(address, 8, value)?;
+                self.set_flag(ZF, current == expected);
+                if current != expected {
+                    self.set_register(Register::EAX, current)?;
+                    self.set_register(Register::EDX, current >> 32)?;
+                }
+            }
+
+            Mnemonic::Cmpxchg16b => {
+                let address = self.memory_address(instruction)?;
+                if address % 16 != 0 {
+                    return Err(Error::GeneralProtectionFault(instruction.ip()));
+                }
+                let current = [self.memory.read_uint(address, 8)?, self.memory.read_uint(address + 8, 8)?];
+                let expected = [self.registers.rax, self.registers.rdx];
+                let value = match current == expected {
+                    true => [self.registers.rbx, self.registers.rcx],
+                    false => current,
+                };
+                self.memory.write_uint(address, 8, value[0])?;
+                self.memory.write_uint(address + 8, 8, value[1])?;
+                self.set_flag(ZF, current == expected);
+                self.registers.rax = current[0];
+                self.registers.rdx = current[1];
+            }
+
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use super::error::Error;
+use super::Cpu;
+
+use crate::mem::{Access, PAGE_SIZE};
+
+use iced_x86::{Decoder, DecoderError, DecoderOptions, Instruction};
+
+/// Longest valid x86 instruction in bytes
+pub const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+impl Cpu {
+    /// Fetches and decodes the instruction at `rip` from guest memory
+    pub fn fetch(&mut self) -> Result<Instruction, Error> {
+        let rip = self.registers.rip;
+
+        self.invalidate_modified_code();
+        if let Some(instruction) = self.decode_cache.get(&rip) {
+            return Ok(*instruction);
+        }
+
+        let mut bytes = [0u8; MAX_INSTRUCTION_LENGTH];
+        let len = self.memory.fetch(rip, &mut bytes);
+        if len == 0 {
+            return Err(self.fetch_fault(rip));
+        }
+
+        let mut decoder = Decoder::with_ip(self.bitness, &bytes[..len], rip, DecoderOptions::NONE);
+        let instruction = decoder.decode();
+        if instruction.is_invalid() {
+            return match decoder.last_error() {
+                // the instruction continues into memory which is not executable
+                DecoderError::NoMoreBytes if len < MAX_INSTRUCTION_LENGTH => Err(self.fetch_fault(rip.wrapping_add(len as u64))),
+                _ => Err(Error::InvalidOpcode(rip)),
+            };
+        }
+
+        self.decode_cache.insert(rip, instruction);
+        Ok(instruction)
+    }
+
+    fn fetch_fault(&mut self, address: u64) -> Error {
+        let e = match self.memory.check_access(address, 1, Access::Execute) {
+            Err(e) => e.into(),
+            Ok(_) => Error::InvalidOpcode(self.registers.rip),
+        };
+        self.fault_at(e, self.registers.rip)
+    }
+
+    /// Drops decoded instructions overlapping executable pages which were modified since the last fetch
+    fn invalidate_modified_code(&mut self) {
+        for page_base in self.memory.take_modified_code_pages() {
+            // instructions starting on the previous page may extend into this one
+            let start = page_base.saturating_sub(MAX_INSTRUCTION_LENGTH as u64 - 1);
+            let end = page_base.saturating_add(PAGE_SIZE);
+            let stale: Vec<u64> = self.decode_cache.range(start..end).map(|(&rip, _)| rip).collect();
+            for rip in stale {
+                self.decode_cache.remove(&rip);
+            }
+        }
+    }
+}
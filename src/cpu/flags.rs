@@ -0,0 +1,350 @@
+use super::error::Error;
+use super::Cpu;
+
+use iced_x86::{Code, ConditionCode, Instruction, Mnemonic, Register};
+
+// RFLAGS bits
+pub const CF: u64 = 1 << 0;
+pub const PF: u64 = 1 << 2;
+pub const AF: u64 = 1 << 4;
+pub const ZF: u64 = 1 << 6;
+pub const SF: u64 = 1 << 7;
+pub const TF: u64 = 1 << 8;
+pub const IF: u64 = 1 << 9;
+pub const DF: u64 = 1 << 10;
+pub const OF: u64 = 1 << 11;
+pub const NT: u64 = 1 << 14;
+pub const RF: u64 = 1 << 16;
+pub const VM: u64 = 1 << 17;
+pub const AC: u64 = 1 << 18;
+pub const ID: u64 = 1 << 21;
+
+/// Bit 1 of RFLAGS is reserved and always set
+pub const RESERVED: u64 = 1 << 1;
+
+/// Flags written by the arithmetic instructions
+pub const STATUS: u64 = CF | PF | AF | ZF | SF | OF;
+
+/// Flags popf can change at privilege level 3, IF and IOPL are kept
+const POPF_WRITABLE: u64 = STATUS | TF | DF | NT | AC | ID;
+
+/// Flags loaded from AH by sahf
+const SAHF_WRITABLE: u64 = CF | PF | AF | ZF | SF;
+
+/// Mask selecting the low `size` bytes of a value
+pub fn mask(size: usize) -> u64 {
+    match size {
+        8 => u64::MAX,
+        size => (1 << (size * 8)) - 1,
+    }
+}
+
+/// Most significant bit of a value of `size` bytes
+pub fn sign_bit(size: usize) -> u64 {
+    1 << (size * 8 - 1)
+}
+
+/// Sign-extends the low `size` bytes of a value to 64 bits
+pub fn sign_extend(value: u64, size: usize) -> u64 {
+    let shift = 64 - size as u32 * 8;
+    (((value << shift) as i64) >> shift) as u64
+}
+
+/// PF, ZF and SF of a result of `size` bytes
+fn result_flags(result: u64, size: usize) -> u64 {
+    let mut flags = 0;
+    // PF is set if the low byte has an even number of set bits
+    if (result as u8).count_ones().is_multiple_of(2) {
+        flags |= PF;
+    }
+    if result & mask(size) == 0 {
+        flags |= ZF;
+    }
+    if result & sign_bit(size) != 0 {
+        flags |= SF;
+    }
+    flags
+}
+
+/// Result and status flags of `a + b + carry` (add, adc)
+pub fn add(a: u64, b: u64, carry: bool, size: usize) -> (u64, u64) {
+    let (a, b) = (a & mask(size), b & mask(size));
+    let wide = a as u128 + b as u128 + carry as u128;
+    let result = wide as u64 & mask(size);
+
+    let mut flags = result_flags(result, size);
+    if wide > mask(size) as u128 {
+        flags |= CF;
+    }
+    if (a ^ b ^ result) & 0x10 != 0 {
+        flags |= AF;
+    }
+    // overflow if both operands have the same sign, which differs from the sign of the result
+    if (a ^ result) & (b ^ result) & sign_bit(size) != 0 {
+        flags |= OF;
+    }
+    (result, flags)
+}
+
+/// Result and status flags of `a - b - borrow` (sub, sbb, cmp)
+pub fn sub(a: u64, b: u64, borrow: bool, size: usize) -> (u64, u64) {
+    let (a, b) = (a & mask(size), b & mask(size));
+    let result = a.wrapping_sub(b).wrapping_sub(borrow as u64) & mask(size);
+
+    let mut flags = result_flags(result, size);
+    if (a as u128) < b as u128 + borrow as u128 {
+        flags |= CF;
+    }
+    if (a ^ b ^ result) & 0x10 != 0 {
+        flags |= AF;
+    }
+    // overflow if the operands have different signs and the result has the sign of the subtrahend
+    if (a ^ b) & (a ^ result) & sign_bit(size) != 0 {
+        flags |= OF;
+    }
+    (result, flags)
+}
+
+/// Status flags of the result of a logical operation (and, or, xor, test): CF and OF are
+/// cleared, AF is undefined and cleared like on current Intel and AMD processors
+pub fn logic(result: u64, size: usize) -> u64 {
+    result_flags(result & mask(size), size)
+}
+
+/// Result and status flags of `a + 1`, CF is not affected
+pub fn inc(a: u64, size: usize, flags: u64) -> (u64, u64) {
+    let (result, new_flags) = add(a, 1, false, size);
+    (result, (new_flags & !CF) | (flags & CF))
+}
+
+/// Result and status flags of `a - 1`, CF is not affected
+pub fn dec(a: u64, size: usize, flags: u64) -> (u64, u64) {
+    let (result, new_flags) = sub(a, 1, false, size);
+    (result, (new_flags & !CF) | (flags & CF))
+}
+
+/// Result and status flags of `0 - a`, CF is set unless the operand is zero
+pub fn neg(a: u64, size: usize) -> (u64, u64) {
+    sub(0, a, false, size)
+}
+
+/// Evaluates a condition code (of jcc, setcc, cmovcc) against the flags
+pub fn condition(condition_code: ConditionCode, flags: u64) -> bool {
+    let set = |flag: u64| flags & flag != 0;
+    match condition_code {
+        ConditionCode::None => true,
+        ConditionCode::o => set(OF),
+        ConditionCode::no => !set(OF),
+        ConditionCode::b => set(CF),
+        ConditionCode::ae => !set(CF),
+        ConditionCode::e => set(ZF),
+        ConditionCode::ne => !set(ZF),
+        ConditionCode::be => set(CF) || set(ZF),
+        ConditionCode::a => !set(CF) && !set(ZF),
+        ConditionCode::s => set(SF),
+        ConditionCode::ns => !set(SF),
+        ConditionCode::p => set(PF),
+        ConditionCode::np => !set(PF),
+        ConditionCode::l => set(SF) != set(OF),
+        ConditionCode::ge => set(SF) == set(OF),
+        ConditionCode::le => set(ZF) || set(SF) != set(OF),
+        ConditionCode::g => !set(ZF) && set(SF) == set(OF),
+    }
+}
+
+/// Whether an instruction is a conditional move (cmovcc)
+pub fn is_cmovcc(instruction: &Instruction) -> bool {
+    matches!(
+        instruction.mnemonic(),
+        Mnemonic::Cmovo | Mnemonic::Cmovno | Mnemonic::Cmovb | Mnemonic::Cmovae | Mnemonic::Cmove | Mnemonic::Cmovne | Mnemonic::Cmovbe | Mnemonic::Cmova
+            | Mnemonic::Cmovs | Mnemonic::Cmovns | Mnemonic::Cmovp | Mnemonic::Cmovnp | Mnemonic::Cmovl | Mnemonic::Cmovge | Mnemonic::Cmovle | Mnemonic::Cmovg
+    )
+}
+
+/// Whether an instruction sets a byte on a condition (setcc)
+pub fn is_setcc(instruction: &Instruction) -> bool {
+    matches!(
+        instruction.mnemonic(),
+        Mnemonic::Seto | Mnemonic::Setno | Mnemonic::Setb | Mnemonic::Setae | Mnemonic::Sete | Mnemonic::Setne | Mnemonic::Setbe | Mnemonic::Seta
+            | Mnemonic::Sets | Mnemonic::Setns | Mnemonic::Setp | Mnemonic::Setnp | Mnemonic::Setl | Mnemonic::Setge | Mnemonic::Setle | Mnemonic::Setg
+    )
+}
+
+impl Cpu {
+    pub fn flag(&self, flag: u64) -> bool {
+        self.registers.rflags & flag != 0
+    }
+
+    pub fn set_flag(&mut self, flag: u64, value: bool) {
+        if value {
+            self.registers.rflags |= flag;
+        } else {
+            self.registers.rflags &= !flag;
+        }
+    }
+
+    /// Replaces the flags selected by `mask` with those in `flags`
+    pub fn update_flags(&mut self, flags: u64, mask: u64) {
+        self.registers.rflags = (self.registers.rflags & !mask) | (flags & mask) | RESERVED;
+    }
+
+    /// Executes the instructions that read or write the flags directly: cmovcc, setcc, lahf, sahf,
+    /// pushf, popf, clc, stc, cmc, cld and std
+    pub fn execute_flag_instruction(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        if is_cmovcc(instruction) {
+            // the source is read and a 32-bit destination is zero-extended even if the condition is false
+            let source = self.read_operand(instruction, 1)?;
+            let value = match condition(instruction.condition_code(), self.registers.rflags) {
+                true => source,
+                false => self.read_operand(instruction, 0)?,
+            };
+            return self.write_operand(instruction, 0, value);
+        }
+        if is_setcc(instruction) {
+            let value = condition(instruction.condition_code(), self.registers.rflags);
+            return self.write_operand(instruction, 0, value as u64);
+        }
+
+        match instruction.mnemonic() {
+            // AH = SF:ZF:0:AF:0:PF:1:CF
+            Mnemonic::Lahf => self.set_register(Register::AH, self.registers.rflags & 0xff)?,
+            Mnemonic::Sahf => {
+                let value = self.get_register_u64(Register::AH)?;
+                self.update_flags(value, SAHF_WRITABLE);
+            }
+            // VM and RF are never pushed
+            Mnemonic::Pushf | Mnemonic::Pushfq => {
+                let size = match instruction.code() {
+                    Code::Pushfw => 2,
+                    _ => 8,
+                };
+                self.push_stack_value(self.registers.rflags & !(VM | RF), size)?;
+            }
+            Mnemonic::Popf | Mnemonic::Popfq => {
+                let (size, writable) = match instruction.code() {
+                    Code::Popfw => (2, POPF_WRITABLE & 0xffff),
+                    _ => (8, POPF_WRITABLE),
+                };
+                let value = self.pop_stack_value(size)?;
+                self.update_flags(value, writable);
+            }
+            Mnemonic::Clc => self.set_flag(CF, false),
+            Mnemonic::Stc => self.set_flag(CF, true),
+            Mnemonic::Cmc => self.set_flag(CF, !self.flag(CF)),
+            Mnemonic::Cld => self.set_flag(DF, false),
+            Mnemonic::Std => self.set_flag(DF, true),
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_flags_at_operand_size_boundaries() {
+        let cases: &[(u64, u64, bool, usize, u64, u64)] = &[
+            (0x7f, 0x1, false, 1, 0x80, AF | SF | OF),
+            (0xff, 0x1, false, 1, 0x0, CF | PF | AF | ZF),
+            (0x80, 0x80, false, 1, 0x0, CF | PF | ZF | OF),
+            (0xf, 0x1, false, 1, 0x10, AF),
+            (0x1, 0x2, false, 1, 0x3, PF),
+            (0x7f, 0x0, true, 1, 0x80, AF | SF | OF),
+            (0xff, 0xff, true, 1, 0xff, CF | PF | AF | SF),
+            (0xff, 0x0, true, 1, 0x0, CF | PF | AF | ZF),
+            (0x7e, 0x1, true, 1, 0x80, AF | SF | OF),
+            (0x7fff, 0x1, false, 2, 0x8000, PF | AF | SF | OF),
+            (0xffff, 0x1, false, 2, 0x0, CF | PF | AF | ZF),
+            (0x8000, 0x8000, false, 2, 0x0, CF | PF | ZF | OF),
+            (0xf, 0x1, false, 2, 0x10, AF),
+            (0x1, 0x2, false, 2, 0x3, PF),
+            (0x7fff, 0x0, true, 2, 0x8000, PF | AF | SF | OF),
+            (0xffff, 0xffff, true, 2, 0xffff, CF | PF | AF | SF),
+            (0xffff, 0x0, true, 2, 0x0, CF | PF | AF | ZF),
+            (0x7ffe, 0x1, true, 2, 0x8000, PF | AF | SF | OF),
+            (0x7fffffff, 0x1, false, 4, 0x80000000, PF | AF | SF | OF),
+            (0xffffffff, 0x1, false, 4, 0x0, CF | PF | AF | ZF),
+            (0x80000000, 0x80000000, false, 4, 0x0, CF | PF | ZF | OF),
+            (0xf, 0x1, false, 4, 0x10, AF),
+            (0x1, 0x2, false, 4, 0x3, PF),
+            (0x7fffffff, 0x0, true, 4, 0x80000000, PF | AF | SF | OF),
+            (0xffffffff, 0xffffffff, true, 4, 0xffffffff, CF | PF | AF | SF),
+            (0xffffffff, 0x0, true, 4, 0x0, CF | PF | AF | ZF),
+            (0x7ffffffe, 0x1, true, 4, 0x80000000, PF | AF | SF | OF),
+            (0x7fffffffffffffff, 0x1, false, 8, 0x8000000000000000, PF | AF | SF | OF),
+            (0xffffffffffffffff, 0x1, false, 8, 0x0, CF | PF | AF | ZF),
+            (0x8000000000000000, 0x8000000000000000, false, 8, 0x0, CF | PF | ZF | OF),
+            (0xf, 0x1, false, 8, 0x10, AF),
+            (0x1, 0x2, false, 8, 0x3, PF),
+            (0x7fffffffffffffff, 0x0, true, 8, 0x8000000000000000, PF | AF | SF | OF),
+            (0xffffffffffffffff, 0xffffffffffffffff, true, 8, 0xffffffffffffffff, CF | PF | AF | SF),
+            (0xffffffffffffffff, 0x0, true, 8, 0x0, CF | PF | AF | ZF),
+            (0x7ffffffffffffffe, 0x1, true, 8, 0x8000000000000000, PF | AF | SF | OF),
+        ];
+        for &(a, b, carry, size, result, expected) in cases {
+            assert_eq!(add(a, b, carry, size), (result, expected), "0x{:x} + 0x{:x} + {} ({} bytes)", a, b, carry as u8, size);
+        }
+    }
+
+    #[test]
+    fn sub_flags_at_operand_size_boundaries() {
+        let cases: &[(u64, u64, bool, usize, u64, u64)] = &[
+            (0x80, 0x1, false, 1, 0x7f, AF | OF),
+            (0x0, 0x1, false, 1, 0xff, CF | PF | AF | SF),
+            (0x10, 0x1, false, 1, 0xf, PF | AF),
+            (0x7f, 0xff, false, 1, 0x80, CF | SF | OF),
+            (0x5, 0x5, false, 1, 0x0, PF | ZF),
+            (0x0, 0xff, true, 1, 0x0, CF | PF | AF | ZF),
+            (0x80, 0x0, true, 1, 0x7f, AF | OF),
+            (0x7f, 0x7f, true, 1, 0xff, CF | PF | AF | SF),
+            (0x1, 0x0, true, 1, 0x0, PF | ZF),
+            (0x8000, 0x1, false, 2, 0x7fff, PF | AF | OF),
+            (0x0, 0x1, false, 2, 0xffff, CF | PF | AF | SF),
+            (0x10, 0x1, false, 2, 0xf, PF | AF),
+            (0x7fff, 0xffff, false, 2, 0x8000, CF | PF | SF | OF),
+            (0x5, 0x5, false, 2, 0x0, PF | ZF),
+            (0x0, 0xffff, true, 2, 0x0, CF | PF | AF | ZF),
+            (0x8000, 0x0, true, 2, 0x7fff, PF | AF | OF),
+            (0x7fff, 0x7fff, true, 2, 0xffff, CF | PF | AF | SF),
+            (0x1, 0x0, true, 2, 0x0, PF | ZF),
+            (0x80000000, 0x1, false, 4, 0x7fffffff, PF | AF | OF),
+            (0x0, 0x1, false, 4, 0xffffffff, CF | PF | AF | SF),
+            (0x10, 0x1, false, 4, 0xf, PF | AF),
+            (0x7fffffff, 0xffffffff, false, 4, 0x80000000, CF | PF | SF | OF),
+            (0x5, 0x5, false, 4, 0x0, PF | ZF),
+            (0x0, 0xffffffff, true, 4, 0x0, CF | PF | AF | ZF),
+            (0x80000000, 0x0, true, 4, 0x7fffffff, PF | AF | OF),
+            (0x7fffffff, 0x7fffffff, true, 4, 0xffffffff, CF | PF | AF | SF),
+            (0x1, 0x0, true, 4, 0x0, PF | ZF),
+            (0x8000000000000000, 0x1, false, 8, 0x7fffffffffffffff, PF | AF | OF),
+            (0x0, 0x1, false, 8, 0xffffffffffffffff, CF | PF | AF | SF),
+            (0x10, 0x1, false, 8, 0xf, PF | AF),
+            (0x7fffffffffffffff, 0xffffffffffffffff, false, 8, 0x8000000000000000, CF | PF | SF | OF),
+            (0x5, 0x5, false, 8, 0x0, PF | ZF),
+            (0x0, 0xffffffffffffffff, true, 8, 0x0, CF | PF | AF | ZF),
+            (0x8000000000000000, 0x0, true, 8, 0x7fffffffffffffff, PF | AF | OF),
+            (0x7fffffffffffffff, 0x7fffffffffffffff, true, 8, 0xffffffffffffffff, CF | PF | AF | SF),
+            (0x1, 0x0, true, 8, 0x0, PF | ZF),
+        ];
+        for &(a, b, borrow, size, result, expected) in cases {
+            assert_eq!(sub(a, b, borrow, size), (result, expected), "0x{:x} - 0x{:x} - {} ({} bytes)", a, b, borrow as u8, size);
+        }
+    }
+
+    #[test]
+    fn inc_and_dec_keep_carry() {
+        assert_eq!(inc(0xff, 1, CF), (0, PF | AF | ZF | CF));
+        assert_eq!(inc(0x7fff_ffff, 4, 0), (0x8000_0000, PF | AF | SF | OF));
+        assert_eq!(dec(0, 8, 0), (u64::MAX, PF | AF | SF));
+        assert_eq!(dec(0x8000, 2, CF), (0x7fff, PF | AF | OF | CF));
+    }
+
+    #[test]
+    fn operands_are_truncated_to_their_size() {
+        assert_eq!(add(0x1_23ff, 0x1, false, 1), (0x0, CF | PF | AF | ZF));
+        assert_eq!(sub(0xffff_0000_0000_0000, 0x1, false, 4), (0xffff_ffff, CF | PF | AF | SF));
+    }
+}
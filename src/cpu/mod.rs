@@ -1,37 +1,75 @@
+pub mod address;
+pub mod arithmetic;
+pub mod avx;
+pub mod avx512;
+pub mod bits;
+pub mod control;
+pub mod cpuid;
 pub mod error;
+pub mod exchange;
+pub mod fetch;
+pub mod flags;
+pub mod operand;
 pub mod registers;
+pub mod softfloat;
+pub mod sse;
+pub mod sse_float;
+pub mod sse_integer;
+pub mod sse_string;
+pub mod string;
+#[cfg(test)]
+pub mod testing;
+pub mod transcendental;
+pub mod transfer;
+pub mod x87;
+pub mod xsave;
 
-use crate::mem::StackPointer;
+use std::collections::BTreeMap;
 
+use crate::mem::{Memory, Protection};
+
+use cpuid::CpuModel;
 use registers::Registers;
-use error::Error;
+use error::{Error, PageFault};
 
-use iced_x86::{Code, Instruction, OpKind, Register};
+use iced_x86::{EncodingKind, Instruction, Mnemonic, Register};
 // use log::debug;
 
-// TODO: implement memory, access stack via rbp - rsp
-const STACK_SIZE: usize = 1024; // 1 KiB
+/// Highest address of the stack (exclusive), the stack grows downwards from here
+pub const STACK_TOP: u64 = 0x7fff_ffff_f000;
+pub const STACK_SIZE: u64 = 8 * 1024 * 1024; // 8 MiB
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Cpu {
-    pub stack: Box<[u8; STACK_SIZE]>,
+    /// decoding mode (16, 32 or 64 bits)
+    pub bitness: u32,
+    pub memory: Memory,
     pub registers: Registers,
+    /// processor identification and features reported by cpuid
+    pub model: CpuModel,
+    /// decoded instructions by address, invalidated when their code pages are modified
+    decode_cache: BTreeMap<u64, Instruction>,
 }
 
 impl Default for Cpu {
     fn default() -> Self {
         // initialize stack
-        let mut stack = Vec::with_capacity(STACK_SIZE);
-        stack.resize(stack.capacity(), 0);
-        let stack = stack.into_boxed_slice().try_into().unwrap();
+        let mut memory = Memory::new();
+        memory.map(STACK_TOP - STACK_SIZE, STACK_SIZE, Protection::READ_WRITE).expect("stack must be mappable in an empty address space");
 
         // initialize stack pointer
-        let mut registers = Registers::default();
-        registers.rsp = StackPointer::new(0, STACK_SIZE as u64);
+        let registers = Registers {
+            rsp: STACK_TOP,
+            rflags: flags::RESERVED | flags::IF,
+            ..Default::default()
+        };
 
         Self {
-            stack,
+            bitness: 64,
+            memory,
             registers,
+            model: CpuModel::default(),
+            decode_cache: BTreeMap::new(),
         }
     }
 }
@@ -42,126 +80,152 @@ impl Cpu {
     }
 
     pub fn execute_instruction(&mut self, instruction: Instruction) -> Result<(), Error> {
-        match instruction.code() {
-            Code::Mov_r64_imm64 => self.set_register(instruction.op0_register(), instruction.immediate64()),
-
-            Code::Mov_rm64_r64 => {
-                match instruction.op0_kind() {
-                    OpKind::Register => self.set_register(instruction.op0_register(), self.get_register_u64(instruction.op1_register())?),
-                    _ => Err(Error::UnimplementedInstruction(instruction))
-                }
-            }
-
-            Code::Push_r64 => self.push_stack_register(instruction.op0_register()),
-            Code::Pushq_imm8 => self.push_stack_value(instruction.immediate8().to_le_bytes().as_slice()),
-            Code::Pop_r64 => self.pop_stack_register(instruction.op0_register()),
+        let result = self.execute(instruction);
+        result.map_err(|e| self.fault_at(e, instruction.ip()))
+    }
 
-            Code::Jmp_rel8_64 => {
-                self.registers.rip = instruction.near_branch64();
-                Ok(())
+    /// Attributes a page fault to the instruction at `rip`, recording the faulting address in CR2
+    fn fault_at(&mut self, e: Error, rip: u64) -> Error {
+        match e {
+            Error::PageFault(fault) => {
+                self.registers.cr2 = fault.address;
+                Error::PageFault(PageFault { rip, ..fault })
             }
-
-            Code::Xor_rm32_r32 => {
-                // TODO: truncate data to 32 bits before performing arithmetic?
-                match instruction.op0_kind() {
-                    OpKind::Register => self.set_register(instruction.op0_register(), self.get_register_u64(instruction.op0_register())? ^ self.get_register_u64(instruction.op1_register())?),
-                    _ => Err(Error::UnimplementedInstruction(instruction))
-                }
-            }
-
-            _ => Err(Error::UnimplementedInstruction(instruction))
+            e => e,
         }
     }
 
-    fn push_stack_register(&mut self, register: Register) -> Result<(), Error> {
-        let mut value = self.get_register_u64(register)?.to_le_bytes().to_vec();
-        value.resize(register.size(), 0);
-        self.push_stack_value(&value)
-    }
-
-    fn push_stack_value(&mut self, value: &[u8]) -> Result<(), Error> {
-        if value.len() > self.stack.len() {
-            return Err(Error::StackOverflowWrite);
-        }
-        let alignment = if value.len() % 8 != 0 {
-            8 - (value.len() % 8) as u64
-        } else {
-            0
-        };
-
-        // debug!("push size: {} b / alignment size: {} b", value.len(), alignment);
-        // debug!("push value: {:02x?}", &value);
-        // debug!("rsp / stack before push: {} (0x{:x}) / {:02x?}", self.registers.rsp.0, self.registers.rsp.0, &self.stack[self.registers.rsp.0 as usize..]);
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Error> {
+        self.check_lock_prefix(&instruction)?;
 
-        self.registers.rsp -= alignment;
-        for x in value.iter().rev() {
-            self.registers.rsp -= 1;
-            self.stack[self.registers.rsp.value() as usize] = *x;
+        match instruction.mnemonic() {
+            Mnemonic::Jmp | Mnemonic::Call | Mnemonic::Ret | Mnemonic::Loop | Mnemonic::Loope | Mnemonic::Loopne
+            | Mnemonic::Jrcxz | Mnemonic::Jecxz | Mnemonic::Jcxz | Mnemonic::Enter | Mnemonic::Leave => return self.execute_control_flow(&instruction),
+            _ if instruction.is_jcc_short_or_near() => return self.execute_control_flow(&instruction),
+            // before any other arm as movsd and cmpsd are also SSE mnemonics
+            _ if instruction.is_string_instruction() => return self.execute_string(&instruction),
+            Mnemonic::Add | Mnemonic::Adc | Mnemonic::Sub | Mnemonic::Sbb | Mnemonic::Cmp | Mnemonic::And | Mnemonic::Or
+            | Mnemonic::Xor | Mnemonic::Test | Mnemonic::Inc | Mnemonic::Dec | Mnemonic::Neg | Mnemonic::Not => return self.execute_arithmetic(&instruction),
+            Mnemonic::Mul | Mnemonic::Imul | Mnemonic::Div | Mnemonic::Idiv => return self.execute_multiply_divide(&instruction),
+            Mnemonic::Shl | Mnemonic::Sal | Mnemonic::Shr | Mnemonic::Sar | Mnemonic::Rol | Mnemonic::Ror | Mnemonic::Rcl
+            | Mnemonic::Rcr | Mnemonic::Shld | Mnemonic::Shrd => return self.execute_shift(&instruction),
+            Mnemonic::Bt | Mnemonic::Bts | Mnemonic::Btr | Mnemonic::Btc | Mnemonic::Bsf | Mnemonic::Bsr | Mnemonic::Bswap
+            | Mnemonic::Popcnt | Mnemonic::Lzcnt | Mnemonic::Tzcnt | Mnemonic::Andn | Mnemonic::Bextr | Mnemonic::Bzhi
+            | Mnemonic::Blsi | Mnemonic::Blsr | Mnemonic::Blsmsk | Mnemonic::Pdep | Mnemonic::Pext | Mnemonic::Shlx
+            | Mnemonic::Shrx | Mnemonic::Sarx | Mnemonic::Rorx | Mnemonic::Mulx | Mnemonic::Crc32 => return self.execute_bit_manipulation(&instruction),
+            _ if flags::is_cmovcc(&instruction) || flags::is_setcc(&instruction) => return self.execute_flag_instruction(&instruction),
+            Mnemonic::Lahf | Mnemonic::Sahf | Mnemonic::Pushf | Mnemonic::Pushfq | Mnemonic::Popf | Mnemonic::Popfq | Mnemonic::Clc
+            | Mnemonic::Stc | Mnemonic::Cmc | Mnemonic::Cld | Mnemonic::Std => return self.execute_flag_instruction(&instruction),
+            Mnemonic::Cpuid => return self.execute_cpuid(),
+            // the undefined instructions exist to raise #UD
+            Mnemonic::Ud0 | Mnemonic::Ud1 | Mnemonic::Ud2 => return Err(Error::InvalidOpcode(instruction.ip())),
+            Mnemonic::Xchg | Mnemonic::Xadd | Mnemonic::Cmpxchg | Mnemonic::Cmpxchg8b | Mnemonic::Cmpxchg16b => return self.execute_exchange(&instruction),
+            Mnemonic::Mov | Mnemonic::Movzx | Mnemonic::Movsx | Mnemonic::Movsxd | Mnemonic::Lea | Mnemonic::Cbw | Mnemonic::Cwde
+            | Mnemonic::Cdqe | Mnemonic::Cwd | Mnemonic::Cdq | Mnemonic::Cqo | Mnemonic::Push | Mnemonic::Pop | Mnemonic::Nop
+            | Mnemonic::Pause | Mnemonic::Endbr32 | Mnemonic::Endbr64 | Mnemonic::Movbe => return self.execute_data_transfer(&instruction),
+            Mnemonic::Fld | Mnemonic::Fst | Mnemonic::Fstp | Mnemonic::Fild | Mnemonic::Fist | Mnemonic::Fistp | Mnemonic::Fisttp
+            | Mnemonic::Fbld | Mnemonic::Fbstp | Mnemonic::Fld1 | Mnemonic::Fldz | Mnemonic::Fldpi | Mnemonic::Fldl2e | Mnemonic::Fldl2t
+            | Mnemonic::Fldlg2 | Mnemonic::Fldln2 | Mnemonic::Fadd | Mnemonic::Faddp | Mnemonic::Fiadd | Mnemonic::Fsub | Mnemonic::Fsubp
+            | Mnemonic::Fisub | Mnemonic::Fsubr | Mnemonic::Fsubrp | Mnemonic::Fisubr | Mnemonic::Fmul | Mnemonic::Fmulp | Mnemonic::Fimul
+            | Mnemonic::Fdiv | Mnemonic::Fdivp | Mnemonic::Fidiv | Mnemonic::Fdivr | Mnemonic::Fdivrp | Mnemonic::Fidivr | Mnemonic::Fcom
+            | Mnemonic::Fcomp | Mnemonic::Fcompp | Mnemonic::Fucom | Mnemonic::Fucomp | Mnemonic::Fucompp | Mnemonic::Ficom | Mnemonic::Ficomp
+            | Mnemonic::Ftst | Mnemonic::Fcomi | Mnemonic::Fcomip | Mnemonic::Fucomi | Mnemonic::Fucomip | Mnemonic::Fxam | Mnemonic::Fchs
+            | Mnemonic::Fabs | Mnemonic::Fsqrt | Mnemonic::Frndint | Mnemonic::Fxch | Mnemonic::Fcmovb | Mnemonic::Fcmove | Mnemonic::Fcmovbe
+            | Mnemonic::Fcmovu | Mnemonic::Fcmovnb | Mnemonic::Fcmovne | Mnemonic::Fcmovnbe | Mnemonic::Fcmovnu | Mnemonic::Fsin | Mnemonic::Fcos
+            | Mnemonic::Fsincos | Mnemonic::Fptan | Mnemonic::Fpatan | Mnemonic::F2xm1 | Mnemonic::Fyl2x | Mnemonic::Fyl2xp1 | Mnemonic::Fscale
+            | Mnemonic::Fprem | Mnemonic::Fprem1 | Mnemonic::Fxtract | Mnemonic::Wait | Mnemonic::Fnop | Mnemonic::Fninit | Mnemonic::Finit
+            | Mnemonic::Fnclex | Mnemonic::Fclex | Mnemonic::Fldcw | Mnemonic::Fnstcw | Mnemonic::Fstcw | Mnemonic::Fnstsw | Mnemonic::Fstsw
+            | Mnemonic::Fnstenv | Mnemonic::Fstenv | Mnemonic::Fldenv | Mnemonic::Fnsave | Mnemonic::Fsave | Mnemonic::Frstor | Mnemonic::Ffree
+            | Mnemonic::Ffreep | Mnemonic::Fincstp | Mnemonic::Fdecstp | Mnemonic::Fneni | Mnemonic::Fndisi | Mnemonic::Fnsetpm => return self.execute_x87(&instruction),
+            Mnemonic::Xsave | Mnemonic::Xsave64 | Mnemonic::Xrstor | Mnemonic::Xrstor64 | Mnemonic::Xgetbv | Mnemonic::Xsetbv => return self.execute_xsave(&instruction),
+            Mnemonic::Kaddb | Mnemonic::Kaddw | Mnemonic::Kaddd | Mnemonic::Kaddq | Mnemonic::Kandb | Mnemonic::Kandw | Mnemonic::Kandd
+            | Mnemonic::Kandq | Mnemonic::Kandnb | Mnemonic::Kandnw | Mnemonic::Kandnd | Mnemonic::Kandnq | Mnemonic::Kmovb | Mnemonic::Kmovw
+            | Mnemonic::Kmovd | Mnemonic::Kmovq | Mnemonic::Knotb | Mnemonic::Knotw | Mnemonic::Knotd | Mnemonic::Knotq | Mnemonic::Korb
+            | Mnemonic::Korw | Mnemonic::Kord | Mnemonic::Korq | Mnemonic::Kortestb | Mnemonic::Kortestw | Mnemonic::Kortestd
+            | Mnemonic::Kortestq | Mnemonic::Kshiftlb | Mnemonic::Kshiftlw | Mnemonic::Kshiftld | Mnemonic::Kshiftlq | Mnemonic::Kshiftrb
+            | Mnemonic::Kshiftrw | Mnemonic::Kshiftrd | Mnemonic::Kshiftrq | Mnemonic::Ktestb | Mnemonic::Ktestw | Mnemonic::Ktestd
+            | Mnemonic::Ktestq | Mnemonic::Kunpckbw | Mnemonic::Kunpckwd | Mnemonic::Kunpckdq | Mnemonic::Kxnorb | Mnemonic::Kxnorw
+            | Mnemonic::Kxnord | Mnemonic::Kxnorq | Mnemonic::Kxorb | Mnemonic::Kxorw | Mnemonic::Kxord | Mnemonic::Kxorq => return self.execute_opmask(&instruction),
+            // after the bit manipulation instructions which are also VEX-encoded
+            _ if instruction.encoding() == EncodingKind::VEX => return self.execute_avx(&instruction),
+            _ if instruction.encoding() == EncodingKind::EVEX => return self.execute_avx512(&instruction),
+            // MMX forms of the integer instructions are not implemented, their registers are rejected
+            Mnemonic::Movaps | Mnemonic::Movapd | Mnemonic::Movdqa | Mnemonic::Movups | Mnemonic::Movupd | Mnemonic::Movdqu | Mnemonic::Movntps
+            | Mnemonic::Movntpd | Mnemonic::Movntdq | Mnemonic::Movnti | Mnemonic::Movss | Mnemonic::Movsd | Mnemonic::Movd | Mnemonic::Movq
+            | Mnemonic::Movlps | Mnemonic::Movlpd | Mnemonic::Movhps | Mnemonic::Movhpd | Mnemonic::Movhlps | Mnemonic::Movlhps
+            | Mnemonic::Movmskps | Mnemonic::Movmskpd | Mnemonic::Pmovmskb | Mnemonic::Pextrw | Mnemonic::Pinsrw | Mnemonic::Maskmovdqu
+            | Mnemonic::Ldmxcsr | Mnemonic::Stmxcsr | Mnemonic::Fxsave | Mnemonic::Fxsave64 | Mnemonic::Fxrstor | Mnemonic::Fxrstor64
+            | Mnemonic::Sfence | Mnemonic::Lfence | Mnemonic::Mfence | Mnemonic::Prefetchnta | Mnemonic::Prefetcht0 | Mnemonic::Prefetcht1
+            | Mnemonic::Prefetcht2 | Mnemonic::Addps | Mnemonic::Addpd | Mnemonic::Addss | Mnemonic::Addsd | Mnemonic::Subps | Mnemonic::Subpd
+            | Mnemonic::Subss | Mnemonic::Subsd | Mnemonic::Mulps | Mnemonic::Mulpd | Mnemonic::Mulss | Mnemonic::Mulsd | Mnemonic::Divps
+            | Mnemonic::Divpd | Mnemonic::Divss | Mnemonic::Divsd | Mnemonic::Minps | Mnemonic::Minpd | Mnemonic::Minss | Mnemonic::Minsd
+            | Mnemonic::Maxps | Mnemonic::Maxpd | Mnemonic::Maxss | Mnemonic::Maxsd | Mnemonic::Sqrtps | Mnemonic::Sqrtpd | Mnemonic::Sqrtss
+            | Mnemonic::Sqrtsd | Mnemonic::Rcpps | Mnemonic::Rcpss | Mnemonic::Rsqrtps | Mnemonic::Rsqrtss | Mnemonic::Cmpps | Mnemonic::Cmppd
+            | Mnemonic::Cmpss | Mnemonic::Cmpsd | Mnemonic::Comiss | Mnemonic::Comisd | Mnemonic::Ucomiss | Mnemonic::Ucomisd
+            | Mnemonic::Cvtsi2ss | Mnemonic::Cvtsi2sd | Mnemonic::Cvtss2si | Mnemonic::Cvtsd2si | Mnemonic::Cvttss2si | Mnemonic::Cvttsd2si
+            | Mnemonic::Cvtss2sd | Mnemonic::Cvtsd2ss | Mnemonic::Cvtps2pd | Mnemonic::Cvtpd2ps | Mnemonic::Cvtdq2ps | Mnemonic::Cvtps2dq
+            | Mnemonic::Cvttps2dq | Mnemonic::Cvtdq2pd | Mnemonic::Cvtpd2dq | Mnemonic::Cvttpd2dq | Mnemonic::Andps | Mnemonic::Andpd
+            | Mnemonic::Andnps | Mnemonic::Andnpd | Mnemonic::Orps | Mnemonic::Orpd | Mnemonic::Xorps | Mnemonic::Xorpd | Mnemonic::Pand
+            | Mnemonic::Pandn | Mnemonic::Por | Mnemonic::Pxor | Mnemonic::Paddb | Mnemonic::Paddw | Mnemonic::Paddd | Mnemonic::Paddq
+            | Mnemonic::Psubb | Mnemonic::Psubw | Mnemonic::Psubd | Mnemonic::Psubq | Mnemonic::Paddsb | Mnemonic::Paddsw | Mnemonic::Psubsb
+            | Mnemonic::Psubsw | Mnemonic::Paddusb | Mnemonic::Paddusw | Mnemonic::Psubusb | Mnemonic::Psubusw | Mnemonic::Pmullw
+            | Mnemonic::Pmulhw | Mnemonic::Pmulhuw | Mnemonic::Pmuludq | Mnemonic::Pmaddwd | Mnemonic::Psadbw | Mnemonic::Pavgb
+            | Mnemonic::Pavgw | Mnemonic::Pminub | Mnemonic::Pmaxub | Mnemonic::Pminsw | Mnemonic::Pmaxsw | Mnemonic::Pcmpeqb
+            | Mnemonic::Pcmpeqw | Mnemonic::Pcmpeqd | Mnemonic::Pcmpgtb | Mnemonic::Pcmpgtw | Mnemonic::Pcmpgtd | Mnemonic::Psllw
+            | Mnemonic::Pslld | Mnemonic::Psllq | Mnemonic::Psrlw | Mnemonic::Psrld | Mnemonic::Psrlq | Mnemonic::Psraw | Mnemonic::Psrad
+            | Mnemonic::Pslldq | Mnemonic::Psrldq | Mnemonic::Packsswb | Mnemonic::Packssdw | Mnemonic::Packuswb | Mnemonic::Punpcklbw
+            | Mnemonic::Punpcklwd | Mnemonic::Punpckldq | Mnemonic::Punpcklqdq | Mnemonic::Punpckhbw | Mnemonic::Punpckhwd
+            | Mnemonic::Punpckhdq | Mnemonic::Punpckhqdq | Mnemonic::Unpcklps | Mnemonic::Unpcklpd | Mnemonic::Unpckhps | Mnemonic::Unpckhpd
+            | Mnemonic::Pshufd | Mnemonic::Pshuflw | Mnemonic::Pshufhw | Mnemonic::Shufps | Mnemonic::Shufpd | Mnemonic::Addsubps
+            | Mnemonic::Addsubpd | Mnemonic::Haddps | Mnemonic::Haddpd | Mnemonic::Hsubps | Mnemonic::Hsubpd | Mnemonic::Lddqu | Mnemonic::Movddup
+            | Mnemonic::Movshdup | Mnemonic::Movsldup | Mnemonic::Pshufb | Mnemonic::Palignr | Mnemonic::Pabsb | Mnemonic::Pabsw
+            | Mnemonic::Pabsd | Mnemonic::Psignb | Mnemonic::Psignw | Mnemonic::Psignd | Mnemonic::Phaddw | Mnemonic::Phaddd
+            | Mnemonic::Phaddsw | Mnemonic::Phsubw | Mnemonic::Phsubd | Mnemonic::Phsubsw | Mnemonic::Pmaddubsw | Mnemonic::Pmulhrsw
+            | Mnemonic::Blendps | Mnemonic::Blendpd | Mnemonic::Blendvps | Mnemonic::Blendvpd | Mnemonic::Pblendvb | Mnemonic::Pblendw
+            | Mnemonic::Dpps | Mnemonic::Dppd | Mnemonic::Extractps | Mnemonic::Insertps | Mnemonic::Movntdqa | Mnemonic::Mpsadbw
+            | Mnemonic::Packusdw | Mnemonic::Pcmpeqq | Mnemonic::Pcmpgtq | Mnemonic::Pextrb | Mnemonic::Pextrd | Mnemonic::Pextrq
+            | Mnemonic::Pinsrb | Mnemonic::Pinsrd | Mnemonic::Pinsrq | Mnemonic::Phminposuw | Mnemonic::Pminsb | Mnemonic::Pminsd
+            | Mnemonic::Pminuw | Mnemonic::Pminud | Mnemonic::Pmaxsb | Mnemonic::Pmaxsd | Mnemonic::Pmaxuw | Mnemonic::Pmaxud
+            | Mnemonic::Pmovsxbw | Mnemonic::Pmovsxbd | Mnemonic::Pmovsxbq | Mnemonic::Pmovsxwd | Mnemonic::Pmovsxwq | Mnemonic::Pmovsxdq
+            | Mnemonic::Pmovzxbw | Mnemonic::Pmovzxbd | Mnemonic::Pmovzxbq | Mnemonic::Pmovzxwd | Mnemonic::Pmovzxwq | Mnemonic::Pmovzxdq
+            | Mnemonic::Pmuldq | Mnemonic::Pmulld | Mnemonic::Ptest | Mnemonic::Roundps | Mnemonic::Roundpd | Mnemonic::Roundss
+            | Mnemonic::Roundsd | Mnemonic::Pcmpestri | Mnemonic::Pcmpestri64 | Mnemonic::Pcmpestrm | Mnemonic::Pcmpestrm64
+            | Mnemonic::Pcmpistri | Mnemonic::Pcmpistrm => return self.execute_sse(&instruction),
+            _ => {}
         }
 
-        // debug!("rsp / stack after push: {} (0x{:x}) / {:02x?}", self.registers.rsp.0, self.registers.rsp.0, &self.stack[self.registers.rsp.0 as usize..]);
+        Err(Error::UnimplementedInstruction(instruction))
+    }
 
+    /// Pushes the low `size` bytes of `value` onto the stack
+    fn push_stack_value(&mut self, value: u64, size: usize) -> Result<(), Error> {
+        let rsp = self.registers.rsp.wrapping_sub(size as u64);
+        self.memory.write_uint(rsp, size, value)?;
+        self.registers.rsp = rsp;
         Ok(())
     }
 
-    fn pop_stack_value(&mut self, size: usize) -> Result<Vec<u8>, Error> {
-        if size > self.stack.len() {
-            return Err(Error::StackOverflowRead);
-        }
-        let alignment = if size % 8 != 0 {
-            8 - (size % 8) as u64
-        } else {
-            0
-        };
-
-        // debug!("push size: {} b / alignment size: {} b", size, alignment);
-        // debug!("rsp / stack before pop: {} (0x{:x}) / {:02x?}", self.registers.rsp.0, self.registers.rsp.0, &self.stack[self.registers.rsp.0 as usize..]);
-
-        let mut value = Vec::new();
-        for _ in 0..size {
-            value.push(self.stack[self.registers.rsp.value() as usize]);
-            self.registers.rsp += 1;
-        }
-        self.registers.rsp += alignment;
-
-        // debug!("rsp / stack after pop: {} (0x{:x}) / {:02x?}", self.registers.rsp.0, self.registers.rsp.0, &self.stack[self.registers.rsp.0 as usize..]);
-        // debug!("pop value: {:02x?}", &value);
-
+    /// Pops `size` bytes off the stack, zero-extended to 64 bits
+    fn pop_stack_value(&mut self, size: usize) -> Result<u64, Error> {
+        let value = self.memory.read_uint(self.registers.rsp, size)?;
+        self.registers.rsp = self.registers.rsp.wrapping_add(size as u64);
         Ok(value)
     }
 
-    fn pop_stack_register(&mut self, register: Register) -> Result<(), Error> {
-        let bytes = self.pop_stack_value(register.size())?;
-        let size = bytes.len();
-        let value = match size {
-            1 => u8::from_le_bytes(bytes.try_into().map_err(|_e| Error::UnimplementedRegisterSize(size))?) as u64,
-            2 => u16::from_le_bytes(bytes.try_into().map_err(|_e| Error::UnimplementedRegisterSize(size))?) as u64,
-            4 => u32::from_le_bytes(bytes.try_into().map_err(|_e| Error::UnimplementedRegisterSize(size))?) as u64,
-            8 => u64::from_le_bytes(bytes.try_into().map_err(|_e| Error::UnimplementedRegisterSize(size))?),
-            size => return Err(Error::UnimplementedRegisterSize(size)),
-        };
-        self.set_register(register, value)
-    }
-
+    /// Reads a register, zero-extended to 64 bits. Partial general-purpose registers
+    /// (e.g. AL, AH, AX, EAX) read their part of the full register.
     fn get_register_u64(&self, register: Register) -> Result<u64, Error> {
+        if register.is_gpr() {
+            let value = self.general_purpose_register(register.full_register());
+            return Ok(match register {
+                Register::AH | Register::CH | Register::DH | Register::BH => (value >> 8) & 0xff,
+                register => value & flags::mask(register.size()),
+            });
+        }
+
         match register {
-            Register::AL | Register::AH | Register::AX | Register::EAX | Register::RAX => Ok(self.registers.rax),
-            Register::CL | Register::CH | Register::CX | Register::ECX | Register::RCX => Ok(self.registers.rcx),
-            Register::DL | Register::DH | Register::DX | Register::EDX | Register::RDX => Ok(self.registers.rdx),
-            Register::BL | Register::BH | Register::BX | Register::EBX | Register::RBX => Ok(self.registers.rbx),
-            Register::SPL | Register::SP | Register::ESP | Register::RSP => Ok(self.registers.rsp.into()),
-            Register::BPL | Register::BP | Register::EBP | Register::RBP => Ok(self.registers.rbp),
-            Register::SIL | Register::SI | Register::ESI | Register::RSI => Ok(self.registers.rsi),
-            Register::DIL | Register::DI | Register::EDI | Register::RDI => Ok(self.registers.rdi),
-            Register::R8L | Register::R8W | Register::R8D | Register::R8 => Ok(self.registers.r8),
-            Register::R9L | Register::R9W | Register::R9D | Register::R9 => Ok(self.registers.r9),
-            Register::R10L | Register::R10W | Register::R10D | Register::R10 => Ok(self.registers.r10),
-            Register::R11L | Register::R11W | Register::R11D | Register::R11 => Ok(self.registers.r11),
-            Register::R12L | Register::R12W | Register::R12D | Register::R12 => Ok(self.registers.r12),
-            Register::R13L | Register::R13W | Register::R13D | Register::R13 => Ok(self.registers.r13),
-            Register::R14L | Register::R14W | Register::R14D | Register::R14 => Ok(self.registers.r14),
-            Register::R15L | Register::R15W | Register::R15D | Register::R15 => Ok(self.registers.r15),
-            Register::EIP | Register::RIP => Ok(self.registers.rip),
+            Register::EIP => Ok(self.registers.rip & 0xffff_ffff),
+            Register::RIP => Ok(self.registers.rip),
             Register::ES => Ok(self.registers.es as u64),
             Register::CS => Ok(self.registers.cs as u64),
             Register::SS => Ok(self.registers.ss as u64),
@@ -212,25 +276,25 @@ impl Cpu {
         }
     }
 
+    /// Writes a register. Like on x86-64, 8 and 16-bit writes to general-purpose registers
+    /// keep the other bits of the full register, 32-bit writes clear the upper half.
     fn set_register(&mut self, register: Register, value: u64) -> Result<(), Error> {
+        if register.is_gpr() {
+            let full = self.general_purpose_register_mut(register.full_register());
+            *full = match register {
+                Register::AH | Register::CH | Register::DH | Register::BH => (*full & !0xff00) | ((value & 0xff) << 8),
+                register if register.size() == 4 => value & 0xffff_ffff,
+                register => {
+                    let mask = flags::mask(register.size());
+                    (*full & !mask) | (value & mask)
+                }
+            };
+            return Ok(());
+        }
+
         match register {
-            Register::AL | Register::AH | Register::AX | Register::EAX | Register::RAX => { self.registers.rax = value; },
-            Register::CL | Register::CH | Register::CX | Register::ECX | Register::RCX => { self.registers.rcx = value; },
-            Register::DL | Register::DH | Register::DX | Register::EDX | Register::RDX => { self.registers.rdx = value; },
-            Register::BL | Register::BH | Register::BX | Register::EBX | Register::RBX => { self.registers.rbx = value; },
-            Register::SPL | Register::SP | Register::ESP | Register::RSP => { self.registers.rsp = StackPointer::new(value, STACK_SIZE as u64); },
-            Register::BPL | Register::BP | Register::EBP | Register::RBP => { self.registers.rbp = value; },
-            Register::SIL | Register::SI | Register::ESI | Register::RSI => { self.registers.rsi = value; },
-            Register::DIL | Register::DI | Register::EDI | Register::RDI => { self.registers.rdi = value; },
-            Register::R8L | Register::R8W | Register::R8D | Register::R8 => { self.registers.r8 = value; },
-            Register::R9L | Register::R9W | Register::R9D | Register::R9 => { self.registers.r9 = value; },
-            Register::R10L | Register::R10W | Register::R10D | Register::R10 => { self.registers.r10 = value; },
-            Register::R11L | Register::R11W | Register::R11D | Register::R11 => { self.registers.r11 = value; },
-            Register::R12L | Register::R12W | Register::R12D | Register::R12 => { self.registers.r12 = value; },
-            Register::R13L | Register::R13W | Register::R13D | Register::R13 => { self.registers.r13 = value; },
-            Register::R14L | Register::R14W | Register::R14D | Register::R14 => { self.registers.r14 = value; },
-            Register::R15L | Register::R15W | Register::R15D | Register::R15 => { self.registers.r15 = value; },
-            Register::EIP | Register::RIP => { self.registers.rip = value; },
+            Register::EIP => { self.registers.rip = value & 0xffff_ffff; },
+            Register::RIP => { self.registers.rip = value; },
             Register::ES => { self.registers.es = value as u16; },
             Register::CS => { self.registers.cs = value as u16; },
             Register::SS => { self.registers.ss = value as u16; },
@@ -281,4 +345,49 @@ impl Cpu {
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Value of a 64-bit general-purpose register
+    fn general_purpose_register(&self, register: Register) -> u64 {
+        match register {
+            Register::RAX => self.registers.rax,
+            Register::RCX => self.registers.rcx,
+            Register::RDX => self.registers.rdx,
+            Register::RBX => self.registers.rbx,
+            Register::RSP => self.registers.rsp,
+            Register::RBP => self.registers.rbp,
+            Register::RSI => self.registers.rsi,
+            Register::RDI => self.registers.rdi,
+            Register::R8 => self.registers.r8,
+            Register::R9 => self.registers.r9,
+            Register::R10 => self.registers.r10,
+            Register::R11 => self.registers.r11,
+            Register::R12 => self.registers.r12,
+            Register::R13 => self.registers.r13,
+            Register::R14 => self.registers.r14,
+            Register::R15 => self.registers.r15,
+            register => unreachable!("{:?} is not a 64-bit general-purpose register", register),
+        }
+    }
+
+    fn general_purpose_register_mut(&mut self, register: Register) -> &mut u64 {
+        match register {
+            Register::RAX => &mut self.registers.rax,
+            Register::RCX => &mut self.registers.rcx,
+            Register::RDX => &mut self.registers.rdx,
+            Register::RBX => &mut self.registers.rbx,
+            Register::RSP => &mut self.registers.rsp,
+            Register::RBP => &mut self.registers.rbp,
+            Register::RSI => &mut self.registers.rsi,
+            Register::RDI => &mut self.registers.rdi,
+            Register::R8 => &mut self.registers.r8,
+            Register::R9 => &mut self.registers.r9,
+            Register::R10 => &mut self.registers.r10,
+            Register::R11 => &mut self.registers.r11,
+            Register::R12 => &mut self.registers.r12,
+            Register::R13 => &mut self.registers.r13,
+            Register::R14 => &mut self.registers.r14,
+            Register::R15 => &mut self.registers.r15,
+            register => unreachable!("{:?} is not a 64-bit general-purpose register", register),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use super::error::Error;
+use super::flags;
+use super::Cpu;
+
+use iced_x86::{Instruction, OpKind};
+
+impl Cpu {
+    /// Size in bytes of an operand of an instruction
+    pub fn operand_size(&self, instruction: &Instruction, operand: u32) -> usize {
+        match instruction.op_kind(operand) {
+            OpKind::Register => instruction.op_register(operand).size(),
+            OpKind::Memory => instruction.memory_size().size(),
+            OpKind::Immediate8 | OpKind::Immediate8_2nd => 1,
+            OpKind::Immediate16 | OpKind::Immediate8to16 => 2,
+            OpKind::Immediate32 | OpKind::Immediate8to32 => 4,
+            _ => 8,
+        }
+    }
+
+    /// Reads a register, memory or immediate operand, zero-extended to 64 bits.
+    /// Immediates are sign-extended to the operand size by the decoder.
+    pub fn read_operand(&self, instruction: &Instruction, operand: u32) -> Result<u64, Error> {
+        match instruction.op_kind(operand) {
+            OpKind::Register => self.get_register_u64(instruction.op_register(operand)),
+            OpKind::Memory => {
+                let address = self.memory_address(instruction)?;
+                Ok(self.memory.read_uint(address, instruction.memory_size().size())?)
+            }
+            OpKind::Immediate8 | OpKind::Immediate8_2nd | OpKind::Immediate16 | OpKind::Immediate32 | OpKind::Immediate64
+            | OpKind::Immediate8to16 | OpKind::Immediate8to32 | OpKind::Immediate8to64 | OpKind::Immediate32to64 => {
+                let size = self.operand_size(instruction, operand);
+                Ok(instruction.immediate(operand) & flags::mask(size))
+            }
+            _ => Err(Error::UnimplementedInstruction(*instruction)),
+        }
+    }
+
+    /// Writes a register or memory operand, truncated to the operand size
+    pub fn write_operand(&mut self, instruction: &Instruction, operand: u32, value: u64) -> Result<(), Error> {
+        match instruction.op_kind(operand) {
+            OpKind::Register => self.set_register(instruction.op_register(operand), value),
+            OpKind::Memory => {
+                let address = self.memory_address(instruction)?;
+                Ok(self.memory.write_uint(address, instruction.memory_size().size(), value)?)
+            }
+            _ => Err(Error::UnimplementedInstruction(*instruction)),
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use crate::mem::StackPointer;
+use super::sse::Sse;
+use super::x87::X87;
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Registers {
@@ -15,7 +16,7 @@ pub struct Registers {
     /// register base pointer (start of stack)
     pub rbp: u64,
     /// register stack pointer (current location in stack, growing downwards)
-    pub rsp: StackPointer,
+    pub rsp: u64,
     /// register source index (source for data copies)
     pub rsi: u64,
     /// register destination index (destination for data copies)
@@ -48,6 +49,10 @@ pub struct Registers {
     pub fs: u16,
     /// general-purpose segment
     pub gs: u16,
+    /// base address of the fs segment (thread pointer on Linux)
+    pub fs_base: u64,
+    /// base address of the gs segment
+    pub gs_base: u64,
     // register flags
     pub rflags: u64,
     /// control register 0
@@ -130,6 +135,10 @@ pub struct Registers {
     pub tr6: u32,
     /// test register 7
     pub tr7: u32,
+    /// x87 floating-point unit
+    pub x87: X87,
+    /// SSE vector registers and MXCSR
+    pub sse: Sse,
 }
 
 impl Registers {
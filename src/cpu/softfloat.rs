@@ -0,0 +1,918 @@
+use std::cmp::Ordering;
+
+// Software floating-point arithmetic on the binary formats of the x87 and SSE units. Values are unpacked
+// into a common representation with a 128-bit significand, computed exactly (or with a sticky bit below
+// the significand), and rounded once when they are packed into their destination format.
+
+// exception flags, at the same bit positions in the x87 status word and in MXCSR
+pub const INVALID: u32 = 1 << 0;
+pub const DENORMAL: u32 = 1 << 1;
+pub const DIVIDE_BY_ZERO: u32 = 1 << 2;
+pub const OVERFLOW: u32 = 1 << 3;
+pub const UNDERFLOW: u32 = 1 << 4;
+pub const INEXACT: u32 = 1 << 5;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// round to nearest, ties to even
+    #[default]
+    Nearest,
+    /// round towards negative infinity
+    Down,
+    /// round towards positive infinity
+    Up,
+    /// round towards zero (truncate)
+    Zero,
+}
+
+impl Rounding {
+    /// Rounding mode of the two-bit rounding control field of the x87 control word and MXCSR
+    pub fn from_bits(bits: u32) -> Self {
+        match bits & 3 {
+            0 => Self::Nearest,
+            1 => Self::Down,
+            2 => Self::Up,
+            _ => Self::Zero,
+        }
+    }
+}
+
+/// Rounding mode, exception masks and accumulated exception flags of a sequence of operations
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    pub rounding: Rounding,
+    /// exceptions which are masked, others make overflow and underflow deliver a wrapped result
+    pub masks: u32,
+    /// exponent adjustment of results delivered on unmasked overflow and underflow, 0 delivers no result
+    pub wrap: i32,
+    /// raised exception flags
+    pub flags: u32,
+    /// whether the last rounded result was rounded away from zero
+    pub rounded_up: bool,
+    /// whether tiny results are replaced by zero when the underflow exception is masked (the FTZ bit of MXCSR)
+    pub flush_to_zero: bool,
+}
+
+impl Context {
+    pub fn new(rounding: Rounding, masks: u32, wrap: i32) -> Self {
+        Self { rounding, masks, wrap, flags: 0, rounded_up: false, flush_to_zero: false }
+    }
+
+    pub fn raise(&mut self, flags: u32) {
+        self.flags |= flags;
+    }
+
+    fn masked(&self, flag: u32) -> bool {
+        self.masks & flag != 0
+    }
+}
+
+/// A binary floating-point format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    exponent_bits: u32,
+    fraction_bits: u32,
+    /// whether the integer bit of the significand is stored (only in the x87 extended format)
+    explicit_integer: bool,
+}
+
+pub const HALF: Format = Format { exponent_bits: 5, fraction_bits: 10, explicit_integer: false };
+pub const SINGLE: Format = Format { exponent_bits: 8, fraction_bits: 23, explicit_integer: false };
+pub const DOUBLE: Format = Format { exponent_bits: 11, fraction_bits: 52, explicit_integer: false };
+pub const EXTENDED: Format = Format { exponent_bits: 15, fraction_bits: 63, explicit_integer: true };
+
+impl Format {
+    /// Significand bits including the integer bit
+    pub fn precision(&self) -> u32 {
+        self.fraction_bits + 1
+    }
+
+    /// Size of an encoded value in bits
+    pub fn width(&self) -> u32 {
+        self.sign_bit() + 1
+    }
+
+    fn bias(&self) -> i32 {
+        (1 << (self.exponent_bits - 1)) - 1
+    }
+
+    fn min_exponent(&self) -> i32 {
+        1 - self.bias()
+    }
+
+    fn max_exponent(&self) -> i32 {
+        self.bias()
+    }
+
+    fn max_biased_exponent(&self) -> u128 {
+        (1 << self.exponent_bits) - 1
+    }
+
+    /// Number of stored significand bits
+    fn significand_bits(&self) -> u32 {
+        self.fraction_bits + self.explicit_integer as u32
+    }
+
+    fn sign_bit(&self) -> u32 {
+        self.significand_bits() + self.exponent_bits
+    }
+
+    fn fields(&self, bits: u128) -> (bool, u128, u128) {
+        let significand = bits & ((1 << self.significand_bits()) - 1);
+        let exponent = (bits >> self.significand_bits()) & self.max_biased_exponent();
+        (bits >> self.sign_bit() & 1 != 0, exponent, significand)
+    }
+
+    /// Whether an encoded value is denormal (or an x87 pseudo-denormal)
+    pub fn is_denormal(&self, bits: u128) -> bool {
+        let (_, exponent, significand) = self.fields(bits);
+        exponent == 0 && significand != 0
+    }
+
+    fn pack(&self, sign: bool, biased_exponent: u128, significand: u128) -> u128 {
+        let significand = match self.explicit_integer {
+            true => significand,
+            false => significand & ((1 << self.fraction_bits) - 1),
+        };
+        ((sign as u128) << self.sign_bit()) | (biased_exponent << self.significand_bits()) | significand
+    }
+
+    pub fn zero(&self, sign: bool) -> u128 {
+        self.pack(sign, 0, 0)
+    }
+
+    pub fn infinity(&self, sign: bool) -> u128 {
+        self.pack(sign, self.max_biased_exponent(), 1 << self.fraction_bits)
+    }
+
+    /// The default quiet NaN, called real indefinite by the x87 unit
+    pub fn default_nan(&self) -> u128 {
+        self.pack(true, self.max_biased_exponent(), 3 << (self.fraction_bits - 1))
+    }
+
+    /// Largest finite value with `precision` significand bits
+    fn max_finite(&self, sign: bool, precision: u32) -> u128 {
+        let significand = ((1 << precision) - 1) << (self.precision() - precision);
+        self.pack(sign, (self.max_exponent() + self.bias()) as u128, significand)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Zero,
+    Normal,
+    Infinity,
+    Nan,
+}
+
+/// An unpacked floating-point value `(-1)^sign * significand * 2^(exponent - 127)`. The significand of
+/// normal values has its most significant bit set. NaNs keep their payload below the integer bit,
+/// bit 126 is the quiet bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Float {
+    pub sign: bool,
+    pub class: Class,
+    pub exponent: i32,
+    pub significand: u128,
+}
+
+impl Float {
+    pub const fn zero(sign: bool) -> Self {
+        Self { sign, class: Class::Zero, exponent: 0, significand: 0 }
+    }
+
+    pub const fn infinity(sign: bool) -> Self {
+        Self { sign, class: Class::Infinity, exponent: 0, significand: 0 }
+    }
+
+    pub const fn default_nan() -> Self {
+        Self { sign: true, class: Class::Nan, exponent: 0, significand: 3 << 126 }
+    }
+
+    /// The value `significand * 2^exponent`, normalized
+    pub fn new(sign: bool, significand: u128, exponent: i32) -> Self {
+        if significand == 0 {
+            return Self::zero(sign);
+        }
+        let shift = significand.leading_zeros();
+        Self { sign, class: Class::Normal, exponent: exponent + 127 - shift as i32, significand: significand << shift }
+    }
+
+    pub fn from_i64(value: i64) -> Self {
+        Self::new(value < 0, value.unsigned_abs() as u128, 0)
+    }
+
+    pub fn from_u64(value: u64) -> Self {
+        Self::new(false, value as u128, 0)
+    }
+
+    pub fn one() -> Self {
+        Self::new(false, 1, 0)
+    }
+
+    pub fn unpack(bits: u128, format: Format) -> Self {
+        let (sign, exponent, significand) = format.fields(bits);
+        let fraction = significand & ((1 << format.fraction_bits) - 1);
+
+        if exponent == format.max_biased_exponent() {
+            return match fraction {
+                0 => Self::infinity(sign),
+                fraction => Self { sign, class: Class::Nan, exponent: 0, significand: (1 << 127) | (fraction << (127 - format.fraction_bits)) },
+            };
+        }
+
+        let significand = match (format.explicit_integer, exponent) {
+            (false, 1..) => significand | (1 << format.fraction_bits),
+            _ => significand,
+        };
+        let exponent = (exponent as i32).max(1) - format.bias() - format.fraction_bits as i32;
+        Self::new(sign, significand, exponent)
+    }
+
+    /// Rounds to `precision` significand bits and packs into `format`. Overflow, underflow and
+    /// inexact results raise their exceptions.
+    pub fn pack(self, format: Format, precision: u32, context: &mut Context) -> u128 {
+        context.rounded_up = false;
+        match self.class {
+            Class::Zero => return format.zero(self.sign),
+            Class::Infinity => return format.infinity(self.sign),
+            Class::Nan => {
+                let fraction = (self.significand >> (127 - format.fraction_bits)) | (1 << format.fraction_bits);
+                return format.pack(self.sign, format.max_biased_exponent(), fraction);
+            }
+            Class::Normal => {}
+        }
+
+        let (min_exponent, max_exponent) = (format.min_exponent(), format.max_exponent());
+        let rounding = context.rounding;
+
+        // tininess is detected after rounding, as if the exponent range was unbounded
+        let (significand, inexact, rounded_up) = round_shift(self.significand, 128 - precision, self.sign, rounding);
+        let carry = significand >> precision != 0;
+        let exponent = self.exponent + carry as i32;
+        let significand = significand >> carry as u32;
+
+        if exponent < min_exponent {
+            if !context.masked(UNDERFLOW) {
+                context.raise(UNDERFLOW | if inexact { INEXACT } else { 0 });
+                context.rounded_up = rounded_up;
+                if context.wrap != 0 {
+                    return self.pack_normal(format, precision, exponent + context.wrap, significand);
+                }
+            } else if context.flush_to_zero {
+                context.raise(UNDERFLOW | INEXACT);
+                return format.zero(self.sign);
+            }
+
+            // denormal results are rounded to a fixed position, given by the smallest normal exponent
+            let lsb = (self.exponent - (precision as i32 - 1)).max(min_exponent - format.fraction_bits as i32);
+            let shift = (lsb - (self.exponent - 127)) as u32;
+            let (significand, inexact, rounded_up) = round_shift(self.significand, shift, self.sign, rounding);
+            if inexact {
+                context.raise(UNDERFLOW | INEXACT);
+                context.rounded_up = rounded_up;
+            }
+            if significand == 0 {
+                return format.zero(self.sign);
+            }
+            let exponent = lsb + 127 - significand.leading_zeros() as i32;
+            if exponent >= min_exponent {
+                return format.pack(self.sign, 1, significand << (lsb - (exponent - format.fraction_bits as i32)));
+            }
+            return format.pack(self.sign, 0, significand << (lsb - (min_exponent - format.fraction_bits as i32)));
+        }
+
+        if exponent > max_exponent {
+            if !context.masked(OVERFLOW) && context.wrap != 0 {
+                context.raise(OVERFLOW | if inexact { INEXACT } else { 0 });
+                context.rounded_up = rounded_up;
+                return self.pack_normal(format, precision, exponent - context.wrap, significand);
+            }
+            context.raise(OVERFLOW | INEXACT);
+            let infinite = match rounding {
+                Rounding::Nearest => true,
+                Rounding::Zero => false,
+                Rounding::Up => !self.sign,
+                Rounding::Down => self.sign,
+            };
+            context.rounded_up = infinite;
+            return match infinite {
+                true => format.infinity(self.sign),
+                false => format.max_finite(self.sign, precision),
+            };
+        }
+
+        if inexact {
+            context.raise(INEXACT);
+            context.rounded_up = rounded_up;
+        }
+        self.pack_normal(format, precision, exponent, significand)
+    }
+
+    /// Packs a rounded normal significand of `precision` bits
+    fn pack_normal(self, format: Format, precision: u32, exponent: i32, significand: u128) -> u128 {
+        let biased_exponent = (exponent + format.bias()) as u128 & format.max_biased_exponent();
+        format.pack(self.sign, biased_exponent, significand << (format.precision() - precision))
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.class == Class::Nan
+    }
+
+    pub fn is_signaling(&self) -> bool {
+        self.is_nan() && self.significand & (1 << 126) == 0
+    }
+
+    pub fn quiet(self) -> Self {
+        match self.class {
+            Class::Nan => Self { significand: self.significand | (1 << 126), ..self },
+            _ => self,
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        Self { sign: !self.sign, ..self }
+    }
+
+    pub fn abs(self) -> Self {
+        Self { sign: false, ..self }
+    }
+
+    /// Quiets a signaling NaN operand, raising the invalid operation exception
+    pub fn propagate(self, context: &mut Context) -> Self {
+        if self.is_signaling() {
+            context.raise(INVALID);
+        }
+        self.quiet()
+    }
+
+    /// NaN result of an operation with at least one NaN operand
+    pub fn propagate_nan(a: Self, b: Self, context: &mut Context) -> Self {
+        if a.is_signaling() || b.is_signaling() {
+            context.raise(INVALID);
+        }
+        // a quiet NaN before a signaling one, otherwise the larger significand
+        let result = match (a.is_nan(), b.is_nan()) {
+            (true, false) => a,
+            (false, _) => b,
+            (true, true) => match (a.is_signaling(), b.is_signaling()) {
+                (false, true) => a,
+                (true, false) => b,
+                _ => {
+                    let (a_fraction, b_fraction) = (a.significand | (1 << 126), b.significand | (1 << 126));
+                    match a_fraction.cmp(&b_fraction) {
+                        Ordering::Greater => a,
+                        Ordering::Less => b,
+                        Ordering::Equal if b.sign => a,
+                        Ordering::Equal => b,
+                    }
+                }
+            },
+        };
+        result.quiet()
+    }
+
+    /// NaN result of an SSE operation with at least one NaN operand: the first operand if it is a NaN,
+    /// otherwise the second, quieted
+    pub fn propagate_first(a: Self, b: Self, context: &mut Context) -> Self {
+        if a.is_signaling() || b.is_signaling() {
+            context.raise(INVALID);
+        }
+        match a.is_nan() {
+            true => a.quiet(),
+            false => b.quiet(),
+        }
+    }
+
+    /// Result of an invalid operation, the default NaN
+    pub fn invalid(context: &mut Context) -> Self {
+        context.raise(INVALID);
+        Self::default_nan()
+    }
+
+    pub fn add(self, other: Self, context: &mut Context) -> Self {
+        if self.is_nan() || other.is_nan() {
+            return Self::propagate_nan(self, other, context);
+        }
+        match (self.class, other.class) {
+            (Class::Infinity, Class::Infinity) if self.sign != other.sign => return Self::invalid(context),
+            (Class::Infinity, _) => return self,
+            (_, Class::Infinity) => return other,
+            (Class::Zero, Class::Zero) if self.sign == other.sign => return self,
+            (Class::Zero, Class::Zero) => return Self::zero(context.rounding == Rounding::Down),
+            (Class::Zero, _) => return other,
+            (_, Class::Zero) => return self,
+            _ => {}
+        }
+
+        let (big, small) = match (self.exponent, self.significand) >= (other.exponent, other.significand) {
+            true => (self, other),
+            false => (other, self),
+        };
+        // one bit of headroom for the carry, the bits shifted out of the smaller operand become a sticky bit
+        let big_significand = big.significand >> 1 | (big.significand & 1);
+        let small_significand = shift_right_sticky(small.significand, 1 + (big.exponent - small.exponent) as u32);
+        let exponent = big.exponent + 1;
+
+        if big.sign == small.sign {
+            return Self::new(big.sign, big_significand + small_significand, exponent - 127);
+        }
+        match big_significand - small_significand {
+            0 => Self::zero(context.rounding == Rounding::Down),
+            difference => Self::new(big.sign, difference, exponent - 127),
+        }
+    }
+
+    pub fn sub(self, other: Self, context: &mut Context) -> Self {
+        if other.is_nan() {
+            return Self::propagate_nan(self, other, context);
+        }
+        self.add(other.negate(), context)
+    }
+
+    pub fn mul(self, other: Self, context: &mut Context) -> Self {
+        if self.is_nan() || other.is_nan() {
+            return Self::propagate_nan(self, other, context);
+        }
+        let sign = self.sign != other.sign;
+        match (self.class, other.class) {
+            (Class::Infinity, Class::Zero) | (Class::Zero, Class::Infinity) => return Self::invalid(context),
+            (Class::Infinity, _) | (_, Class::Infinity) => return Self::infinity(sign),
+            (Class::Zero, _) | (_, Class::Zero) => return Self::zero(sign),
+            _ => {}
+        }
+
+        let (high, low) = multiply_wide(self.significand, other.significand);
+        Self::new(sign, high | (low != 0) as u128, self.exponent + other.exponent + 1 - 127)
+    }
+
+    pub fn div(self, other: Self, context: &mut Context) -> Self {
+        if self.is_nan() || other.is_nan() {
+            return Self::propagate_nan(self, other, context);
+        }
+        let sign = self.sign != other.sign;
+        match (self.class, other.class) {
+            (Class::Infinity, Class::Infinity) | (Class::Zero, Class::Zero) => return Self::invalid(context),
+            (Class::Infinity, _) => return Self::infinity(sign),
+            (_, Class::Infinity) => return Self::zero(sign),
+            (Class::Zero, _) => return Self::zero(sign),
+            (_, Class::Zero) => {
+                context.raise(DIVIDE_BY_ZERO);
+                return Self::infinity(sign);
+            }
+            _ => {}
+        }
+
+        // long division producing 128 quotient bits, the remainder becomes the sticky bit
+        let divisor = other.significand;
+        let mut exponent = self.exponent - other.exponent;
+        let (mut carry, mut remainder) = (false, self.significand);
+        if remainder < divisor {
+            exponent -= 1;
+            carry = remainder >> 127 != 0;
+            remainder <<= 1;
+        }
+        let mut quotient = 0u128;
+        for bit in (0..128).rev() {
+            if carry || remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient |= 1 << bit;
+            }
+            carry = remainder >> 127 != 0;
+            remainder <<= 1;
+        }
+        let sticky = carry || remainder != 0;
+        Self::new(sign, (quotient >> 1) | (quotient & 1) | sticky as u128, exponent - 126)
+    }
+
+    pub fn sqrt(self, context: &mut Context) -> Self {
+        match self.class {
+            Class::Nan => return self.propagate(context),
+            Class::Zero => return self,
+            _ if self.sign => return Self::invalid(context),
+            Class::Infinity => return self,
+            Class::Normal => {}
+        }
+
+        // the root of significand * 2^shift has 120 bits, the shift makes the remaining exponent even
+        let shift = if (self.exponent - 127 - 111) % 2 == 0 { 111 } else { 112 };
+        let (root, exact) = square_root(self.significand, shift);
+        Self::new(false, (root << 1) | !exact as u128, (self.exponent - 127 - shift as i32) / 2 - 1)
+    }
+
+    /// Rounds to an integral value in the rounding mode of the context, raising the inexact exception
+    pub fn round_to_integral(self, rounding: Rounding, context: &mut Context) -> Self {
+        context.rounded_up = false;
+        match self.class {
+            Class::Nan => return self.propagate(context),
+            Class::Normal if self.exponent < 127 => {}
+            _ => return self,
+        }
+        let (magnitude, inexact, rounded_up) = round_shift(self.significand, (127 - self.exponent) as u32, self.sign, rounding);
+        if inexact {
+            context.raise(INEXACT);
+            context.rounded_up = rounded_up;
+        }
+        Self::new(self.sign, magnitude, 0)
+    }
+
+    /// Converts to a signed integer of `bits` bits, rounding in the given mode. NaNs and values out of
+    /// range raise the invalid operation exception and return `None`.
+    pub fn to_integer(self, bits: u32, rounding: Rounding, context: &mut Context) -> Option<i64> {
+        let flags = context.flags;
+        let integral = self.round_to_integral(rounding, context);
+        let limit = 1u128 << (bits - 1);
+        let magnitude = match integral.class {
+            Class::Zero => 0,
+            Class::Normal if integral.exponent < bits as i32 => integral.significand >> (127 - integral.exponent),
+            _ => u128::MAX,
+        };
+        if magnitude > limit || (magnitude == limit && !integral.sign) {
+            // the inexact exception is not raised together with the invalid operation exception
+            context.flags = flags | INVALID;
+            context.rounded_up = false;
+            return None;
+        }
+        let value = magnitude as i64;
+        Some(if integral.sign { value.wrapping_neg() } else { value })
+    }
+
+    /// Converts to an unsigned integer of `bits` bits, rounding in the given mode. NaNs, values out of range and
+    /// negative values which do not round to zero raise the invalid operation exception and return `None`.
+    pub fn to_unsigned(self, bits: u32, rounding: Rounding, context: &mut Context) -> Option<u64> {
+        let flags = context.flags;
+        let integral = self.round_to_integral(rounding, context);
+        let magnitude = match integral.class {
+            Class::Zero => 0,
+            Class::Normal if integral.exponent < bits as i32 => integral.significand >> (127 - integral.exponent),
+            _ => u128::MAX,
+        };
+        if magnitude >> bits != 0 || (magnitude != 0 && integral.sign) {
+            context.flags = flags | INVALID;
+            context.rounded_up = false;
+            return None;
+        }
+        Some(magnitude as u64)
+    }
+
+    /// Compares two values, `None` if they are unordered. Zeros compare equal whatever their signs.
+    pub fn compare(self, other: Self) -> Option<Ordering> {
+        if self.is_nan() || other.is_nan() {
+            return None;
+        }
+        let magnitude = |value: Self| -> (i32, u128) {
+            match value.class {
+                Class::Zero => (i32::MIN, 0),
+                Class::Infinity => (i32::MAX, 0),
+                _ => (value.exponent, value.significand),
+            }
+        };
+        match (self.class == Class::Zero && other.class == Class::Zero, self.sign, other.sign) {
+            (true, _, _) => Some(Ordering::Equal),
+            (false, false, true) => Some(Ordering::Greater),
+            (false, true, false) => Some(Ordering::Less),
+            (false, false, false) => Some(magnitude(self).cmp(&magnitude(other))),
+            (false, true, true) => Some(magnitude(other).cmp(&magnitude(self))),
+        }
+    }
+
+    /// Multiplies by 2^`scale`, exactly until the result is rounded
+    pub fn scale(self, scale: i32) -> Self {
+        match self.class {
+            Class::Normal => Self { exponent: self.exponent.saturating_add(scale), ..self },
+            _ => self,
+        }
+    }
+
+    /// Partial remainder of fprem (truncating quotient) and fprem1 (quotient rounded to nearest) of values with at
+    /// most 64 significand bits. Returns the remainder, the low bits of the quotient and whether the reduction is
+    /// complete; an incomplete reduction reduces the exponent difference by 32 to 63.
+    pub fn remainder(self, other: Self, nearest: bool, context: &mut Context) -> (Self, u64, bool) {
+        if self.is_nan() || other.is_nan() {
+            return (Self::propagate_nan(self, other, context), 0, true);
+        }
+        match (self.class, other.class) {
+            (Class::Infinity, _) | (_, Class::Zero) => return (Self::invalid(context), 0, true),
+            (Class::Zero, _) | (_, Class::Infinity) => return (self, 0, true),
+            _ => {}
+        }
+
+        let dividend = (self.significand >> 64) as u64;
+        let divisor = (other.significand >> 64) as u64;
+        let difference = self.exponent - other.exponent;
+
+        if difference >= 64 {
+            let shift = (difference & 31) | 32;
+            let scaled = (dividend as u128) << shift;
+            let quotient = scaled / divisor as u128;
+            let remainder = scaled - quotient * divisor as u128;
+            let result = Self::new(self.sign, remainder, other.exponent - 63 + difference - shift);
+            return (result, 0, false);
+        }
+
+        let (quotient, remainder, negative) = match difference {
+            difference if difference >= 0 => {
+                let scaled = (dividend as u128) << difference;
+                let quotient = scaled / divisor as u128;
+                let remainder = scaled - quotient * divisor as u128;
+                let twice = remainder * 2;
+                let round_up = nearest && (twice > divisor as u128 || (twice == divisor as u128 && quotient & 1 == 1));
+                match round_up {
+                    true => (quotient + 1, divisor as u128 - remainder, true),
+                    false => (quotient, remainder, false),
+                }
+            }
+            // |self| / |other| is in [0.5, 1) and rounds to 1 if above one half
+            -1 if nearest && dividend > divisor => return (Self::new(!self.sign, divisor as u128 * 2 - dividend as u128, self.exponent - 63), 1, true),
+            _ => return (self, 0, true),
+        };
+        let sign = self.sign != negative;
+        let result = match remainder {
+            0 => Self::zero(self.sign),
+            remainder => Self::new(sign, remainder, other.exponent - 63),
+        };
+        (result, quotient as u64, true)
+    }
+}
+
+/// Shifts right, collecting the bits shifted out in the lowest bit
+fn shift_right_sticky(value: u128, shift: u32) -> u128 {
+    match shift {
+        0 => value,
+        1..=127 => (value >> shift) | (value & ((1 << shift) - 1) != 0) as u128,
+        _ => (value != 0) as u128,
+    }
+}
+
+/// Shifts a significand right and rounds it, returning the rounded value, whether bits were lost and
+/// whether it was rounded up in magnitude
+fn round_shift(significand: u128, shift: u32, sign: bool, rounding: Rounding) -> (u128, bool, bool) {
+    let (value, half, rest) = match shift {
+        0 => (significand, false, false),
+        1..=128 => {
+            let value = significand.checked_shr(shift).unwrap_or(0);
+            let lost = match shift {
+                128 => significand,
+                shift => significand & ((1 << shift) - 1),
+            };
+            let half = 1u128 << (shift - 1);
+            (value, lost & half != 0, lost & (half - 1) != 0)
+        }
+        _ => (0, false, significand != 0),
+    };
+    let inexact = half || rest;
+    let round_up = match rounding {
+        Rounding::Nearest => half && (rest || value & 1 == 1),
+        Rounding::Zero => false,
+        Rounding::Up => inexact && !sign,
+        Rounding::Down => inexact && sign,
+    };
+    (value + round_up as u128, inexact, round_up)
+}
+
+/// Full 256-bit product of two 128-bit values, as high and low halves
+fn multiply_wide(a: u128, b: u128) -> (u128, u128) {
+    let (a_high, a_low) = (a >> 64, a & u64::MAX as u128);
+    let (b_high, b_low) = (b >> 64, b & u64::MAX as u128);
+    let low = a_low * b_low;
+    let middle_1 = a_high * b_low;
+    let middle_2 = a_low * b_high;
+    let high = a_high * b_high;
+
+    let (middle, middle_carry) = middle_1.overflowing_add(middle_2);
+    let (low, low_carry) = low.overflowing_add(middle << 64);
+    let high = high + (middle >> 64) + ((middle_carry as u128) << 64) + low_carry as u128;
+    (high, low)
+}
+
+/// Integer square root of `value << shift` (less than 2^240), and whether it is exact
+fn square_root(value: u128, shift: u32) -> (u128, bool) {
+    // 256-bit radicand as high and low halves
+    let (high, low) = match shift {
+        0 => (0, value),
+        shift => (value >> (128 - shift), value << shift),
+    };
+    let mut remainder: u128 = 0;
+    let mut root: u128 = 0;
+    for pair in (0..128).rev() {
+        let bits = match pair {
+            64.. => (high >> (2 * (pair - 64))) & 3,
+            _ => (low >> (2 * pair)) & 3,
+        };
+        remainder = (remainder << 2) | bits;
+        let trial = (root << 2) | 1;
+        root <<= 1;
+        if remainder >= trial {
+            remainder -= trial;
+            root |= 1;
+        }
+    }
+    (root, remainder == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use iced_x86::Code;
+
+    const ALL_MASKED: u32 = 0x3f;
+
+    fn double(bits: u64) -> Float {
+        Float::unpack(bits as u128, DOUBLE)
+    }
+
+    fn pack_double(value: Float, context: &mut Context) -> u64 {
+        value.pack(DOUBLE, DOUBLE.precision(), context) as u64
+    }
+
+    #[test]
+    fn add_mul_div_and_sqrt_round_in_every_mode() {
+        let cases = [
+            (Code::Addsd_xmm_xmmm64, Rounding::Nearest, 0x3ff0000000000000, 0x3c30000000000000, 0x3ff0000000000000, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Nearest, 0x3ff0000000000000, 0xbc30000000000000, 0x3ff0000000000000, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Nearest, 0x3fffffffffffffff, 0x3cb0000000000000, 0x4000000000000000, 0),
+            (Code::Addsd_xmm_xmmm64, Rounding::Nearest, 0xbff0000000000000, 0xbca8000000000000, 0xbff0000000000001, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Nearest, 0x7fefffffffffffff, 0x7fefffffffffffff, 0x7ff0000000000000, OVERFLOW | INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Nearest, 0x0010000000000000, 0x8010000000000001, 0x8000000000000001, 0),
+            (Code::Addsd_xmm_xmmm64, Rounding::Down, 0x3ff0000000000000, 0x3c30000000000000, 0x3ff0000000000000, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Down, 0x3ff0000000000000, 0xbc30000000000000, 0x3fefffffffffffff, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Down, 0x3fffffffffffffff, 0x3cb0000000000000, 0x4000000000000000, 0),
+            (Code::Addsd_xmm_xmmm64, Rounding::Down, 0xbff0000000000000, 0xbca8000000000000, 0xbff0000000000001, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Down, 0x7fefffffffffffff, 0x7fefffffffffffff, 0x7fefffffffffffff, OVERFLOW | INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Down, 0x0010000000000000, 0x8010000000000001, 0x8000000000000001, 0),
+            (Code::Addsd_xmm_xmmm64, Rounding::Up, 0x3ff0000000000000, 0x3c30000000000000, 0x3ff0000000000001, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Up, 0x3ff0000000000000, 0xbc30000000000000, 0x3ff0000000000000, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Up, 0x3fffffffffffffff, 0x3cb0000000000000, 0x4000000000000000, 0),
+            (Code::Addsd_xmm_xmmm64, Rounding::Up, 0xbff0000000000000, 0xbca8000000000000, 0xbff0000000000000, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Up, 0x7fefffffffffffff, 0x7fefffffffffffff, 0x7ff0000000000000, OVERFLOW | INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Up, 0x0010000000000000, 0x8010000000000001, 0x8000000000000001, 0),
+            (Code::Addsd_xmm_xmmm64, Rounding::Zero, 0x3ff0000000000000, 0x3c30000000000000, 0x3ff0000000000000, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Zero, 0x3ff0000000000000, 0xbc30000000000000, 0x3fefffffffffffff, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Zero, 0x3fffffffffffffff, 0x3cb0000000000000, 0x4000000000000000, 0),
+            (Code::Addsd_xmm_xmmm64, Rounding::Zero, 0xbff0000000000000, 0xbca8000000000000, 0xbff0000000000000, INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Zero, 0x7fefffffffffffff, 0x7fefffffffffffff, 0x7fefffffffffffff, OVERFLOW | INEXACT),
+            (Code::Addsd_xmm_xmmm64, Rounding::Zero, 0x0010000000000000, 0x8010000000000001, 0x8000000000000001, 0),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Nearest, 0x3fd5555555555555, 0x4008000000000000, 0x3ff0000000000000, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Nearest, 0x3ff0000000000001, 0x3ff0000000000001, 0x3ff0000000000002, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Nearest, 0xbff0000000000001, 0x3fffffffffffffff, 0xc000000000000000, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Nearest, 0x7fefffffffffffff, 0x4000000000000000, 0x7ff0000000000000, OVERFLOW | INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Nearest, 0x0170000000000000, 0x3c38000000000000, 0x0000000000006000, 0),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Down, 0x3fd5555555555555, 0x4008000000000000, 0x3fefffffffffffff, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Down, 0x3ff0000000000001, 0x3ff0000000000001, 0x3ff0000000000002, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Down, 0xbff0000000000001, 0x3fffffffffffffff, 0xc000000000000001, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Down, 0x7fefffffffffffff, 0x4000000000000000, 0x7fefffffffffffff, OVERFLOW | INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Down, 0x0170000000000000, 0x3c38000000000000, 0x0000000000006000, 0),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Up, 0x3fd5555555555555, 0x4008000000000000, 0x3ff0000000000000, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Up, 0x3ff0000000000001, 0x3ff0000000000001, 0x3ff0000000000003, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Up, 0xbff0000000000001, 0x3fffffffffffffff, 0xc000000000000000, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Up, 0x7fefffffffffffff, 0x4000000000000000, 0x7ff0000000000000, OVERFLOW | INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Up, 0x0170000000000000, 0x3c38000000000000, 0x0000000000006000, 0),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Zero, 0x3fd5555555555555, 0x4008000000000000, 0x3fefffffffffffff, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Zero, 0x3ff0000000000001, 0x3ff0000000000001, 0x3ff0000000000002, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Zero, 0xbff0000000000001, 0x3fffffffffffffff, 0xc000000000000000, INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Zero, 0x7fefffffffffffff, 0x4000000000000000, 0x7fefffffffffffff, OVERFLOW | INEXACT),
+            (Code::Mulsd_xmm_xmmm64, Rounding::Zero, 0x0170000000000000, 0x3c38000000000000, 0x0000000000006000, 0),
+            (Code::Divsd_xmm_xmmm64, Rounding::Nearest, 0x3ff0000000000000, 0x4008000000000000, 0x3fd5555555555555, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Nearest, 0xc000000000000000, 0x4008000000000000, 0xbfe5555555555555, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Nearest, 0x3ff0000000000000, 0x4024000000000000, 0x3fb999999999999a, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Nearest, 0x0010000000000000, 0x4008000000000000, 0x0005555555555555, UNDERFLOW | INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Nearest, 0x3ff0000000000000, 0x0000000000000000, 0x7ff0000000000000, DIVIDE_BY_ZERO),
+            (Code::Divsd_xmm_xmmm64, Rounding::Down, 0x3ff0000000000000, 0x4008000000000000, 0x3fd5555555555555, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Down, 0xc000000000000000, 0x4008000000000000, 0xbfe5555555555556, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Down, 0x3ff0000000000000, 0x4024000000000000, 0x3fb9999999999999, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Down, 0x0010000000000000, 0x4008000000000000, 0x0005555555555555, UNDERFLOW | INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Down, 0x3ff0000000000000, 0x0000000000000000, 0x7ff0000000000000, DIVIDE_BY_ZERO),
+            (Code::Divsd_xmm_xmmm64, Rounding::Up, 0x3ff0000000000000, 0x4008000000000000, 0x3fd5555555555556, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Up, 0xc000000000000000, 0x4008000000000000, 0xbfe5555555555555, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Up, 0x3ff0000000000000, 0x4024000000000000, 0x3fb999999999999a, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Up, 0x0010000000000000, 0x4008000000000000, 0x0005555555555556, UNDERFLOW | INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Up, 0x3ff0000000000000, 0x0000000000000000, 0x7ff0000000000000, DIVIDE_BY_ZERO),
+            (Code::Divsd_xmm_xmmm64, Rounding::Zero, 0x3ff0000000000000, 0x4008000000000000, 0x3fd5555555555555, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Zero, 0xc000000000000000, 0x4008000000000000, 0xbfe5555555555555, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Zero, 0x3ff0000000000000, 0x4024000000000000, 0x3fb9999999999999, INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Zero, 0x0010000000000000, 0x4008000000000000, 0x0005555555555555, UNDERFLOW | INEXACT),
+            (Code::Divsd_xmm_xmmm64, Rounding::Zero, 0x3ff0000000000000, 0x0000000000000000, 0x7ff0000000000000, DIVIDE_BY_ZERO),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Nearest, 0x4000000000000000, 0x0, 0x3ff6a09e667f3bcd, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Nearest, 0x4008000000000000, 0x0, 0x3ffbb67ae8584caa, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Nearest, 0x7fefffffffffffff, 0x0, 0x5fefffffffffffff, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Nearest, 0x0000000000000001, 0x0, 0x1e60000000000000, 0),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Nearest, 0x4010000000000000, 0x0, 0x4000000000000000, 0),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Down, 0x4000000000000000, 0x0, 0x3ff6a09e667f3bcc, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Down, 0x4008000000000000, 0x0, 0x3ffbb67ae8584caa, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Down, 0x7fefffffffffffff, 0x0, 0x5fefffffffffffff, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Down, 0x0000000000000001, 0x0, 0x1e60000000000000, 0),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Down, 0x4010000000000000, 0x0, 0x4000000000000000, 0),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Up, 0x4000000000000000, 0x0, 0x3ff6a09e667f3bcd, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Up, 0x4008000000000000, 0x0, 0x3ffbb67ae8584cab, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Up, 0x7fefffffffffffff, 0x0, 0x5ff0000000000000, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Up, 0x0000000000000001, 0x0, 0x1e60000000000000, 0),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Up, 0x4010000000000000, 0x0, 0x4000000000000000, 0),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Zero, 0x4000000000000000, 0x0, 0x3ff6a09e667f3bcc, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Zero, 0x4008000000000000, 0x0, 0x3ffbb67ae8584caa, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Zero, 0x7fefffffffffffff, 0x0, 0x5fefffffffffffff, INEXACT),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Zero, 0x0000000000000001, 0x0, 0x1e60000000000000, 0),
+            (Code::Sqrtsd_xmm_xmmm64, Rounding::Zero, 0x4010000000000000, 0x0, 0x4000000000000000, 0),
+        ];
+        for (code, rounding, a, b, result, flags) in cases {
+            let mut context = Context::new(rounding, ALL_MASKED, 0);
+            let value = match code {
+                Code::Addsd_xmm_xmmm64 => double(a).add(double(b), &mut context),
+                Code::Mulsd_xmm_xmmm64 => double(a).mul(double(b), &mut context),
+                Code::Divsd_xmm_xmmm64 => double(a).div(double(b), &mut context),
+                _ => double(a).sqrt(&mut context),
+            };
+            assert_eq!(pack_double(value, &mut context), result, "{:?} {:?} 0x{:x}, 0x{:x}", code, rounding, a, b);
+            assert_eq!(context.flags, flags, "{:?} {:?} 0x{:x}, 0x{:x}", code, rounding, a, b);
+        }
+    }
+
+    #[test]
+    fn invalid_operations_return_the_default_nan() {
+        let (infinity, zero, one) = (Float::infinity(false), Float::zero(false), Float::one());
+        let results = [
+            infinity.add(infinity.negate(), &mut Context::default()),
+            infinity.sub(infinity, &mut Context::default()),
+            zero.mul(infinity, &mut Context::default()),
+            zero.div(zero, &mut Context::default()),
+            infinity.div(infinity, &mut Context::default()),
+            one.negate().sqrt(&mut Context::default()),
+        ];
+        for result in results {
+            assert_eq!(result, Float::default_nan());
+        }
+
+        let mut context = Context::default();
+        one.negate().sqrt(&mut context);
+        assert_eq!(context.flags, INVALID);
+
+        // only signaling NaNs are invalid operands, and they are quieted
+        let quiet = double(0x7ff8_0000_0000_0001);
+        let signaling = double(0x7ff0_0000_0000_0001);
+        let mut context = Context::default();
+        assert_eq!(pack_double(quiet.add(one, &mut context), &mut context), 0x7ff8_0000_0000_0001);
+        assert_eq!(context.flags, 0);
+        assert_eq!(pack_double(signaling.mul(one, &mut context), &mut context), 0x7ff8_0000_0000_0001);
+        assert_eq!(context.flags, INVALID);
+    }
+
+    #[test]
+    fn denormals_are_recognized_and_produced() {
+        assert!(DOUBLE.is_denormal(0x0000_0000_0000_0001));
+        assert!(DOUBLE.is_denormal(0x800f_ffff_ffff_ffff));
+        assert!(!DOUBLE.is_denormal(0x0010_0000_0000_0000));
+        assert!(!DOUBLE.is_denormal(0x8000_0000_0000_0000));
+        // pseudo-denormals have the integer bit set with a zero exponent
+        assert!(EXTENDED.is_denormal(0x0000_8000_0000_0000_0001));
+
+        // a tiny inexact result underflows, an exact one does not
+        let mut context = Context::new(Rounding::Nearest, ALL_MASKED, 0);
+        let third = double(0x0010_0000_0000_0000).div(double(0x4008_0000_0000_0000), &mut context);
+        assert_eq!(pack_double(third, &mut context), 0x0005_5555_5555_5555);
+        assert_eq!(context.flags, UNDERFLOW | INEXACT);
+
+        let mut context = Context::new(Rounding::Nearest, ALL_MASKED, 0);
+        let half = double(0x0010_0000_0000_0000).mul(double(0x3fe0_0000_0000_0000), &mut context);
+        assert_eq!(pack_double(half, &mut context), 0x0008_0000_0000_0000);
+        assert_eq!(context.flags, 0);
+
+        // flush to zero replaces tiny results
+        let mut context = Context::new(Rounding::Nearest, ALL_MASKED, 0);
+        context.flush_to_zero = true;
+        let half = double(0x0010_0000_0000_0000).mul(double(0x3fe0_0000_0000_0000), &mut context);
+        assert_eq!(pack_double(half, &mut context), 0);
+        assert_eq!(context.flags, UNDERFLOW | INEXACT);
+    }
+
+    #[test]
+    fn doubles_round_trip() {
+        let values: [u64; 12] = [
+            0x0000_0000_0000_0000,
+            0x8000_0000_0000_0000,
+            0x0000_0000_0000_0001,
+            0x000f_ffff_ffff_ffff,
+            0x0010_0000_0000_0000,
+            0x3ff0_0000_0000_0001,
+            0xc009_21fb_5444_2d18,
+            0x7fef_ffff_ffff_ffff,
+            0x7ff0_0000_0000_0000,
+            0xfff0_0000_0000_0000,
+            0x7ff8_0000_0000_1234,
+            0xfff8_0000_0000_0000,
+        ];
+        for bits in values {
+            let mut context = Context::new(Rounding::Nearest, ALL_MASKED, 0);
+            assert_eq!(pack_double(double(bits), &mut context), bits, "0x{:x}", bits);
+
+            // through the extended format, as loaded and stored by the x87 unit
+            let extended = double(bits).pack(EXTENDED, EXTENDED.precision(), &mut context);
+            assert_eq!(pack_double(Float::unpack(extended, EXTENDED), &mut context), bits, "0x{:x}", bits);
+            assert_eq!(context.flags, 0, "0x{:x}", bits);
+        }
+
+        for value in [1.0f64, -0.1, 1e300, 5e-324, f64::MIN_POSITIVE, f64::EPSILON] {
+            let mut context = Context::new(Rounding::Nearest, ALL_MASKED, 0);
+            assert_eq!(f64::from_bits(pack_double(double(value.to_bits()), &mut context)), value);
+        }
+    }
+}
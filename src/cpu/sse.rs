@@ -0,0 +1,577 @@
+use super::avx;
+use super::error::Error;
+use super::softfloat::{self, Context, Rounding};
+use super::Cpu;
+
+use iced_x86::{EncodingKind, Instruction, Mnemonic, OpCodeOperandKind, OpKind, Register, RoundingControl};
+
+// MXCSR, bits 0 to 5 are the exception flags of softfloat
+const DENORMALS_ARE_ZERO: u32 = 1 << 6;
+const MASK_SHIFT: u32 = 7;
+const ROUNDING_SHIFT: u32 = 13;
+const FLUSH_TO_ZERO: u32 = 1 << 15;
+const EXCEPTIONS: u32 = 0x3f;
+
+/// Doublewords 0 and 2 of a vector
+const EVEN_DOUBLEWORDS: u128 = 0x0000_0000_ffff_ffff_0000_0000_ffff_ffff;
+
+/// MXCSR at reset: all exceptions masked, round to nearest
+const DEFAULT_MXCSR: u32 = 0x1f80;
+/// Writable bits of MXCSR, setting others with ldmxcsr or fxrstor is a general protection fault
+pub const MXCSR_MASK: u32 = 0xffff;
+
+/// Exceptions detected before computing a result, which take precedence over the others when they are unmasked
+const PRE_COMPUTATION: u32 = softfloat::INVALID | softfloat::DENORMAL | softfloat::DIVIDE_BY_ZERO;
+
+/// Number of 128-bit lanes of a vector register
+pub const LANES: usize = 4;
+
+/// Number of vector registers, the legacy and VEX encodings only reach the first 16
+pub const VECTOR_REGISTERS: usize = 32;
+
+/// Contents of a vector register or operand by 128-bit lanes, the lowest first
+pub type Vector = [u128; LANES];
+
+/// State of the SSE, AVX and AVX-512 units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sse {
+    /// vector registers ZMM0 to ZMM31, whose lowest lanes are XMM0 to XMM31 and two lowest lanes YMM0 to YMM31
+    pub zmm: [Vector; VECTOR_REGISTERS],
+    /// opmask registers k0 to k7
+    pub k: [u64; 8],
+    /// control and status register
+    pub mxcsr: u32,
+}
+
+impl Default for Sse {
+    fn default() -> Self {
+        Self { zmm: [[0; LANES]; VECTOR_REGISTERS], k: [0; 8], mxcsr: DEFAULT_MXCSR }
+    }
+}
+
+impl Sse {
+    /// Rounding mode, exception masks and flush-to-zero mode of MXCSR
+    pub fn context(&self) -> Context {
+        let rounding = Rounding::from_bits(self.mxcsr >> ROUNDING_SHIFT);
+        Context { flush_to_zero: self.mxcsr & FLUSH_TO_ZERO != 0, ..Context::new(rounding, (self.mxcsr >> MASK_SHIFT) & EXCEPTIONS, 0) }
+    }
+
+    /// Whether denormal operands are treated as zeros, without raising the denormal exception
+    pub fn denormals_are_zero(&self) -> bool {
+        self.mxcsr & DENORMALS_ARE_ZERO != 0
+    }
+
+    /// Records the exceptions raised by an instruction, returning whether one is unmasked. When an exception detected
+    /// before computing the result is unmasked, those computed afterwards are not reported.
+    fn report(&mut self, context: &Context) -> bool {
+        let masks = (self.mxcsr >> MASK_SHIFT) & EXCEPTIONS;
+        let flags = match context.flags & PRE_COMPUTATION & !masks {
+            0 => context.flags,
+            _ => context.flags & PRE_COMPUTATION,
+        };
+        self.mxcsr |= flags;
+        flags & !masks != 0
+    }
+}
+
+/// Index of an XMM, YMM or ZMM register
+fn vector_index(register: Register) -> usize {
+    register.number()
+}
+
+fn is_vector(register: Register) -> bool {
+    register.is_xmm() || register.is_ymm() || register.is_zmm()
+}
+
+/// Number of 128-bit lanes of a vector register
+fn register_lanes(register: Register) -> usize {
+    register.size() / 16
+}
+
+/// Indexes of the operands which are the two sources of an instruction: the destination and the source in the legacy
+/// encodings, the two operands after the destination when the VEX or EVEX encoding names a source in its vvvv field
+pub fn source_operands(instruction: &Instruction) -> (u32, u32) {
+    let op_code = instruction.op_code();
+    let vvvv = (0..instruction.op_count()).any(|operand| {
+        matches!(op_code.op_kind(operand), OpCodeOperandKind::xmm_vvvv | OpCodeOperandKind::ymm_vvvv | OpCodeOperandKind::zmm_vvvv)
+    });
+    match vvvv {
+        true => (1, 2),
+        false => (0, 1),
+    }
+}
+
+/// A vector from its bytes in memory order
+pub fn vector_from_bytes(bytes: &[u8]) -> Vector {
+    std::array::from_fn(|lane| u128::from_le_bytes(bytes[16 * lane..16 * lane + 16].try_into().unwrap()))
+}
+
+/// Length in bytes of the vectors an instruction operates on, given by the L bits of the VEX and EVEX encodings
+pub fn vector_length(instruction: &Instruction) -> usize {
+    16 << instruction.op_code().l()
+}
+
+/// Whether an EVEX instruction suppresses all floating-point exceptions, which embedded rounding implies
+fn suppresses_exceptions(instruction: &Instruction) -> bool {
+    instruction.suppress_all_exceptions() || instruction.rounding_control() != RoundingControl::None
+}
+
+/// The bytes of a vector in memory order
+pub fn vector_to_bytes(vector: Vector) -> [u8; 16 * LANES] {
+    let mut bytes = [0; 16 * LANES];
+    for (lane, value) in vector.into_iter().enumerate() {
+        bytes[16 * lane..16 * lane + 16].copy_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+impl Cpu {
+    /// Executes an SSE to SSE4.2 instruction, or its VEX form which is executed under the mnemonic of the legacy one
+    pub fn execute_sse(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let mnemonic = avx::legacy_mnemonic(instruction.mnemonic());
+        match mnemonic {
+            Mnemonic::Movaps | Mnemonic::Movapd | Mnemonic::Movdqa | Mnemonic::Movups | Mnemonic::Movupd | Mnemonic::Movdqu
+            | Mnemonic::Movntps | Mnemonic::Movntpd | Mnemonic::Movntdq | Mnemonic::Movnti | Mnemonic::Movss | Mnemonic::Movsd
+            | Mnemonic::Movd | Mnemonic::Movq | Mnemonic::Movlps | Mnemonic::Movlpd | Mnemonic::Movhps | Mnemonic::Movhpd
+            | Mnemonic::Movhlps | Mnemonic::Movlhps | Mnemonic::Movmskps | Mnemonic::Movmskpd | Mnemonic::Pmovmskb
+            | Mnemonic::Pextrw | Mnemonic::Pinsrw | Mnemonic::Maskmovdqu | Mnemonic::Lddqu | Mnemonic::Movntdqa | Mnemonic::Movddup
+            | Mnemonic::Movshdup | Mnemonic::Movsldup | Mnemonic::Pextrb | Mnemonic::Pextrd | Mnemonic::Pextrq | Mnemonic::Extractps
+            | Mnemonic::Pinsrb | Mnemonic::Pinsrd | Mnemonic::Pinsrq | Mnemonic::Insertps => self.sse_move(instruction, mnemonic),
+            Mnemonic::Ldmxcsr | Mnemonic::Stmxcsr | Mnemonic::Fxsave | Mnemonic::Fxsave64 | Mnemonic::Fxrstor | Mnemonic::Fxrstor64
+            | Mnemonic::Sfence | Mnemonic::Lfence | Mnemonic::Mfence | Mnemonic::Prefetchnta | Mnemonic::Prefetcht0
+            | Mnemonic::Prefetcht1 | Mnemonic::Prefetcht2 => self.sse_state(instruction, mnemonic),
+            Mnemonic::Addps | Mnemonic::Addpd | Mnemonic::Addss | Mnemonic::Addsd | Mnemonic::Subps | Mnemonic::Subpd | Mnemonic::Subss
+            | Mnemonic::Subsd | Mnemonic::Mulps | Mnemonic::Mulpd | Mnemonic::Mulss | Mnemonic::Mulsd | Mnemonic::Divps | Mnemonic::Divpd
+            | Mnemonic::Divss | Mnemonic::Divsd | Mnemonic::Minps | Mnemonic::Minpd | Mnemonic::Minss | Mnemonic::Minsd | Mnemonic::Maxps
+            | Mnemonic::Maxpd | Mnemonic::Maxss | Mnemonic::Maxsd | Mnemonic::Sqrtps | Mnemonic::Sqrtpd | Mnemonic::Sqrtss | Mnemonic::Sqrtsd
+            | Mnemonic::Rcpps | Mnemonic::Rcpss | Mnemonic::Rsqrtps | Mnemonic::Rsqrtss | Mnemonic::Cmpps | Mnemonic::Cmppd | Mnemonic::Cmpss
+            | Mnemonic::Cmpsd | Mnemonic::Comiss | Mnemonic::Comisd | Mnemonic::Ucomiss | Mnemonic::Ucomisd | Mnemonic::Cvtsi2ss
+            | Mnemonic::Cvtsi2sd | Mnemonic::Cvtss2si | Mnemonic::Cvtsd2si | Mnemonic::Cvttss2si | Mnemonic::Cvttsd2si | Mnemonic::Cvtss2sd
+            | Mnemonic::Cvtsd2ss | Mnemonic::Cvtps2pd | Mnemonic::Cvtpd2ps | Mnemonic::Cvtdq2ps | Mnemonic::Cvtps2dq | Mnemonic::Cvttps2dq
+            | Mnemonic::Cvtdq2pd | Mnemonic::Cvtpd2dq | Mnemonic::Cvttpd2dq | Mnemonic::Addsubps | Mnemonic::Addsubpd | Mnemonic::Haddps
+            | Mnemonic::Haddpd | Mnemonic::Hsubps | Mnemonic::Hsubpd | Mnemonic::Dpps | Mnemonic::Dppd | Mnemonic::Roundps
+            | Mnemonic::Roundpd | Mnemonic::Roundss | Mnemonic::Roundsd | Mnemonic::Vcvtph2ps | Mnemonic::Vcvtps2ph => self.sse_float(instruction, mnemonic),
+            Mnemonic::Pcmpestri | Mnemonic::Pcmpestri64 | Mnemonic::Pcmpestrm | Mnemonic::Pcmpestrm64 | Mnemonic::Pcmpistri
+            | Mnemonic::Pcmpistrm => self.sse_string(instruction, mnemonic),
+            _ => self.sse_integer(instruction, mnemonic),
+        }
+    }
+
+    /// Reads a vector register, a general-purpose register, a memory operand or an immediate, zero-extended to a
+    /// full vector. The element of a broadcast memory operand is repeated throughout the vector, and the elements of
+    /// a memory operand which the write mask excludes are zeros when reading them faults.
+    pub fn read_vector_operand(&self, instruction: &Instruction, operand: u32) -> Result<Vector, Error> {
+        match instruction.op_kind(operand) {
+            OpKind::Register => {
+                let register = instruction.op_register(operand);
+                match is_vector(register) {
+                    true => {
+                        let (value, lanes) = (self.registers.sse.zmm[vector_index(register)], register_lanes(register));
+                        Ok(std::array::from_fn(|lane| if lane < lanes { value[lane] } else { 0 }))
+                    }
+                    false => Ok(xmm_vector(self.get_register_u64(register)? as u128)),
+                }
+            }
+            OpKind::Memory => {
+                let address = self.vector_memory_address(instruction)?;
+                let size = instruction.memory_size().size();
+                let mut bytes = [0; 16 * LANES];
+                if instruction.is_broadcast() {
+                    let element = self.memory.read_uint(address, size)?;
+                    return Ok(avx::elements(8 * size as u32, |_| element));
+                }
+                match self.memory.read(address, &mut bytes[..size]) {
+                    Ok(()) => Ok(vector_from_bytes(&bytes)),
+                    Err(_) if instruction.op_mask() != Register::None => self.read_masked_memory(instruction, address),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            OpKind::Immediate8 => Ok(xmm_vector(instruction.immediate8() as u128)),
+            _ => Err(Error::UnimplementedInstruction(*instruction)),
+        }
+    }
+
+    /// Writes a vector register, a general-purpose register (like a move to it), an opmask register with the sign
+    /// bits of the elements, or the bytes of a memory operand. The write mask of an EVEX instruction selects the
+    /// elements written.
+    pub fn write_vector_operand(&mut self, instruction: &Instruction, operand: u32, value: Vector) -> Result<(), Error> {
+        match instruction.op_kind(operand) {
+            OpKind::Register => {
+                let register = instruction.op_register(operand);
+                match register {
+                    register if is_vector(register) => {
+                        let value = self.merge_masked(instruction, register, value);
+                        self.write_vector_register(instruction, register, value);
+                    }
+                    register if register.is_k() => self.write_opmask_result(instruction, register, value),
+                    _ => self.set_register(register, value[0] as u64)?,
+                }
+                Ok(())
+            }
+            OpKind::Memory => {
+                let address = self.vector_memory_address(instruction)?;
+                match instruction.op_mask() {
+                    Register::None => self.memory.write(address, &vector_to_bytes(value)[..instruction.memory_size().size()])?,
+                    _ => self.write_masked_memory(instruction, address, value)?,
+                }
+                Ok(())
+            }
+            _ => Err(Error::UnimplementedInstruction(*instruction)),
+        }
+    }
+
+    /// Writes the lanes of a vector register. The legacy encodings keep the lanes above XMM registers, the others zero
+    /// the lanes above the register written.
+    pub fn write_vector_register(&mut self, instruction: &Instruction, register: Register, value: Vector) {
+        let (lanes, legacy) = (register_lanes(register), instruction.encoding() == EncodingKind::Legacy);
+        let target = &mut self.registers.sse.zmm[vector_index(register)];
+        for (lane, target) in target.iter_mut().enumerate() {
+            match lane < lanes {
+                true => *target = value[lane],
+                false if !legacy => *target = 0,
+                false => {}
+            }
+        }
+    }
+
+    /// Reads the low lane of a vector operand, or a general-purpose register, a memory operand of up to 16 bytes or an
+    /// immediate, zero-extended to 128 bits
+    pub fn read_xmm_operand(&self, instruction: &Instruction, operand: u32) -> Result<u128, Error> {
+        Ok(self.read_vector_operand(instruction, operand)?[0])
+    }
+
+    /// Writes all bits of an XMM register, a general-purpose register (like a move to it) or the bytes of a memory
+    /// operand
+    pub fn write_xmm_operand(&mut self, instruction: &Instruction, operand: u32, value: u128) -> Result<(), Error> {
+        self.write_vector_operand(instruction, operand, xmm_vector(value))
+    }
+
+    /// Address of a vector memory operand. The legacy encodings require 16-byte operands to be aligned, except for
+    /// the unaligned moves and the string comparisons, the VEX and EVEX encodings only for the aligned moves.
+    fn vector_memory_address(&self, instruction: &Instruction) -> Result<u64, Error> {
+        let address = self.memory_address(instruction)?;
+        let size = instruction.memory_size().size() as u64;
+        let aligned = match instruction.encoding() {
+            EncodingKind::Legacy => {
+                size == 16
+                    && !matches!(
+                        instruction.mnemonic(),
+                        Mnemonic::Movups | Mnemonic::Movupd | Mnemonic::Movdqu | Mnemonic::Lddqu | Mnemonic::Pcmpestri | Mnemonic::Pcmpestri64
+                            | Mnemonic::Pcmpestrm | Mnemonic::Pcmpestrm64 | Mnemonic::Pcmpistri | Mnemonic::Pcmpistrm
+                    )
+            }
+            _ => matches!(
+                instruction.mnemonic(),
+                Mnemonic::Vmovaps | Mnemonic::Vmovapd | Mnemonic::Vmovdqa | Mnemonic::Vmovdqa32 | Mnemonic::Vmovdqa64 | Mnemonic::Vmovntps
+                    | Mnemonic::Vmovntpd | Mnemonic::Vmovntdq | Mnemonic::Vmovntdqa
+            ),
+        };
+        if aligned && address % size != 0 {
+            return Err(Error::GeneralProtectionFault(instruction.ip()));
+        }
+        Ok(address)
+    }
+
+    /// Result of an instruction operating on each 128-bit lane of its operands independently, `operation` being given
+    /// the lanes of its two sources and of its third source, which is the mask of a variable blend or the immediate.
+    /// The shifts take their count from the low lane for every lane. The conversions to wider elements take each lane
+    /// of their source from its low lane, those to narrower elements pack the results of the lanes of their source in
+    /// the low lane.
+    pub fn map_lanes(&self, instruction: &Instruction, mnemonic: Mnemonic, operation: impl FnMut(u128, u128, u128) -> Option<u128>) -> Result<Vector, Error> {
+        let operands = self.lane_operands(instruction, mnemonic)?;
+        self.compute_lanes(instruction, mnemonic, operands, operation)
+    }
+
+    /// The two sources of an instruction operating on lanes and its third source, the mask of a variable blend or the
+    /// immediate
+    pub fn lane_operands(&self, instruction: &Instruction, mnemonic: Mnemonic) -> Result<[Vector; 3], Error> {
+        let (first, second) = source_operands(instruction);
+        let (a, b) = (self.read_vector_operand(instruction, first)?, self.read_vector_operand(instruction, second)?);
+        let third = second + 1;
+        let c = match mnemonic {
+            // the legacy variable blends take their mask from XMM0
+            Mnemonic::Blendvps | Mnemonic::Blendvpd | Mnemonic::Pblendvb if instruction.encoding() == EncodingKind::Legacy => {
+                xmm_vector(self.registers.sse.zmm[0][0])
+            }
+            _ if instruction.op_count() > third => self.read_vector_operand(instruction, third)?,
+            _ => [0; LANES],
+        };
+        Ok([a, b, c])
+    }
+
+    /// The result of [`Cpu::map_lanes`] for the operands read by [`Cpu::lane_operands`]
+    pub fn compute_lanes(
+        &self,
+        instruction: &Instruction,
+        mnemonic: Mnemonic,
+        [a, b, c]: [Vector; 3],
+        mut operation: impl FnMut(u128, u128, u128) -> Option<u128>,
+    ) -> Result<Vector, Error> {
+        let third = source_operands(instruction).1 + 1;
+        let immediate = instruction.op_count() > third && instruction.op_kind(third) == OpKind::Immediate8;
+        let unimplemented = || Error::UnimplementedInstruction(*instruction);
+
+        if matches!(
+            mnemonic,
+            Mnemonic::Cvtpd2ps | Mnemonic::Cvtpd2dq | Mnemonic::Cvttpd2dq | Mnemonic::Vcvtps2ph | Mnemonic::Vcvtpd2udq | Mnemonic::Vcvttpd2udq
+                | Mnemonic::Vcvtqq2ps | Mnemonic::Vcvtuqq2ps
+        ) {
+            let mut result = [0; LANES];
+            for lane in 0..vector_length(instruction) / 16 {
+                result[lane / 2] |= operation(a[lane], b[lane], c[0]).ok_or_else(unimplemented)? << (64 * (lane % 2));
+            }
+            return Ok(result);
+        }
+
+        let lanes = match instruction.op0_kind() {
+            OpKind::Register if is_vector(instruction.op0_register()) => register_lanes(instruction.op0_register()),
+            _ => vector_length(instruction) / 16,
+        };
+        let mut result = [0; LANES];
+        for (lane, result) in result.iter_mut().enumerate().take(lanes) {
+            let b = match mnemonic {
+                Mnemonic::Psllw | Mnemonic::Pslld | Mnemonic::Psllq | Mnemonic::Psrlw | Mnemonic::Psrld | Mnemonic::Psrlq | Mnemonic::Psraw
+                | Mnemonic::Psrad | Mnemonic::Vpsraq | Mnemonic::Pslldq | Mnemonic::Psrldq | Mnemonic::Vprold | Mnemonic::Vprolq
+                | Mnemonic::Vprord | Mnemonic::Vprorq => b[0],
+                // a broadcast element is in every lane
+                Mnemonic::Cvtps2pd | Mnemonic::Cvtdq2pd | Mnemonic::Vcvtph2ps | Mnemonic::Vcvtudq2pd | Mnemonic::Vcvtps2qq
+                | Mnemonic::Vcvttps2qq | Mnemonic::Vcvtps2uqq | Mnemonic::Vcvttps2uqq | Mnemonic::Pmovsxbw | Mnemonic::Pmovsxbd
+                | Mnemonic::Pmovsxbq | Mnemonic::Pmovsxwd | Mnemonic::Pmovsxwq | Mnemonic::Pmovsxdq | Mnemonic::Pmovzxbw
+                | Mnemonic::Pmovzxbd | Mnemonic::Pmovzxbq | Mnemonic::Pmovzxwd | Mnemonic::Pmovzxwq | Mnemonic::Pmovzxdq => match instruction.is_broadcast() {
+                    true => b[0],
+                    false => vector_bits(&b, 8 * instruction.memory_size().size() / lanes * lane),
+                },
+                _ => b[lane],
+            };
+            let c = match immediate {
+                true => lane_immediate(mnemonic, c[0] as u8, lane) as u128,
+                false => c[lane],
+            };
+            *result = operation(a[lane], b, c).ok_or_else(unimplemented)?;
+        }
+        Ok(result)
+    }
+
+    /// Rounding mode, exception masks and flush-to-zero mode of MXCSR for an instruction. The embedded rounding of
+    /// an EVEX instruction overrides the rounding mode, and it or suppress-all-exceptions masks all the exceptions.
+    pub fn vector_context(&self, instruction: &Instruction) -> Context {
+        let mut context = self.registers.sse.context();
+        if let Some(rounding) = (instruction.rounding_control() as u32).checked_sub(1) {
+            context.rounding = Rounding::from_bits(rounding);
+        }
+        if suppresses_exceptions(instruction) {
+            context.masks = EXCEPTIONS;
+        }
+        context
+    }
+
+    /// Records the floating-point exceptions raised by an instruction in MXCSR, an unmasked one is a SIMD
+    /// floating-point exception (#XM) and the instruction does not write its result. Those of an instruction which
+    /// suppresses all exceptions are not recorded.
+    pub fn report_sse(&mut self, instruction: &Instruction, context: &Context) -> Result<(), Error> {
+        if suppresses_exceptions(instruction) {
+            return Ok(());
+        }
+        match self.registers.sse.report(context) {
+            true => Err(Error::SimdFloatingPointFault(instruction.ip())),
+            false => Ok(()),
+        }
+    }
+
+    /// The moves between vector registers, memory and general-purpose registers, the mask extractions and the element
+    /// insertions and extractions
+    fn sse_move(&mut self, instruction: &Instruction, mnemonic: Mnemonic) -> Result<(), Error> {
+        let (first, second) = source_operands(instruction);
+        let source = self.read_vector_operand(instruction, second)?;
+        let low = source[0];
+        let value = match mnemonic {
+            // between registers the scalar moves only replace the lowest element
+            Mnemonic::Movss | Mnemonic::Movsd if instruction.op_kind(first) == OpKind::Register && instruction.op_kind(second) == OpKind::Register => {
+                let mask = match mnemonic {
+                    Mnemonic::Movss => u32::MAX as u128,
+                    _ => u64::MAX as u128,
+                };
+                xmm_vector((self.read_xmm_operand(instruction, first)? & !mask) | (low & mask))
+            }
+            // other moves of less than 16 bytes zero-extend into XMM registers
+            Mnemonic::Movss | Mnemonic::Movsd | Mnemonic::Movd | Mnemonic::Movq => {
+                xmm_vector(low & (u128::MAX >> (128 - 8 * instruction.memory_size().size())))
+            }
+
+            // the high and low halves to or from memory or between registers
+            Mnemonic::Movlps | Mnemonic::Movlpd | Mnemonic::Movhps | Mnemonic::Movhpd | Mnemonic::Movhlps | Mnemonic::Movlhps => {
+                let store = instruction.op0_kind() == OpKind::Memory;
+                let half = match mnemonic {
+                    Mnemonic::Movhlps => low >> 64,
+                    Mnemonic::Movhps | Mnemonic::Movhpd if store => low >> 64,
+                    _ => low & u64::MAX as u128,
+                };
+                let destination = match store {
+                    true => return self.write_xmm_operand(instruction, 0, half),
+                    false => self.read_xmm_operand(instruction, first)?,
+                };
+                xmm_vector(match mnemonic {
+                    Mnemonic::Movhps | Mnemonic::Movhpd | Mnemonic::Movlhps => (destination & u64::MAX as u128) | (half << 64),
+                    _ => (destination & !(u64::MAX as u128)) | half,
+                })
+            }
+
+            // the sign bits of the elements of all lanes
+            Mnemonic::Movmskps | Mnemonic::Movmskpd | Mnemonic::Pmovmskb => {
+                let bits = match mnemonic {
+                    Mnemonic::Movmskps => 32,
+                    Mnemonic::Movmskpd => 64,
+                    _ => 8,
+                };
+                let mask = source.iter().enumerate().fold(0, |mask, (lane, &value)| mask | (sign_mask(value, bits) as u128) << (lane as u32 * 128 / bits));
+                xmm_vector(mask)
+            }
+
+            // the low quadword, the even or the odd doublewords duplicated in each lane
+            Mnemonic::Movddup => source.map(|lane| (lane & u64::MAX as u128) * (1 | 1 << 64)),
+            Mnemonic::Movsldup => source.map(|lane| (lane & EVEN_DOUBLEWORDS) * (1 | 1 << 32)),
+            Mnemonic::Movshdup => source.map(|lane| (lane & !EVEN_DOUBLEWORDS) | (lane & !EVEN_DOUBLEWORDS) >> 32),
+
+            // the element selected by the immediate, zero-extended into a general-purpose register
+            Mnemonic::Pextrb | Mnemonic::Pextrw | Mnemonic::Pextrd | Mnemonic::Pextrq | Mnemonic::Extractps => {
+                let bits = element_bits(mnemonic);
+                xmm_vector((low >> (bits * (instruction.immediate8() as u32 & (128 / bits - 1)))) & (u128::MAX >> (128 - bits)))
+            }
+            Mnemonic::Pinsrb | Mnemonic::Pinsrw | Mnemonic::Pinsrd | Mnemonic::Pinsrq => {
+                let bits = element_bits(mnemonic);
+                let shift = bits * (instruction.immediate8() as u32 & (128 / bits - 1));
+                let mask = (u128::MAX >> (128 - bits)) << shift;
+                xmm_vector((self.read_xmm_operand(instruction, first)? & !mask) | ((low << shift) & mask))
+            }
+            // a doubleword of the source register selected by bits 6 and 7 of the immediate, or the one in memory, at
+            // the element selected by bits 4 and 5, then the elements selected by bits 0 to 3 zeroed
+            Mnemonic::Insertps => {
+                let immediate = instruction.immediate8();
+                let element = match instruction.op_kind(second) {
+                    OpKind::Register => low >> (32 * (immediate >> 6)),
+                    _ => low,
+                } & u32::MAX as u128;
+                let shift = 32 * (immediate >> 4 & 3);
+                let inserted = (self.read_xmm_operand(instruction, first)? & !((u32::MAX as u128) << shift)) | element << shift;
+                xmm_vector((0..4).filter(|index| immediate >> index & 1 != 0).fold(inserted, |value, index| value & !((u32::MAX as u128) << (32 * index))))
+            }
+
+            // stores the bytes of the first register selected by the sign bits of the second to [rdi]
+            Mnemonic::Maskmovdqu => {
+                let register = match instruction.op0_kind() {
+                    OpKind::MemorySegEDI => Register::EDI,
+                    _ => Register::RDI,
+                };
+                let address = self.segment_base(instruction.memory_segment()).wrapping_add(self.get_register_u64(register)?);
+                let (data, mask) = (low, self.read_xmm_operand(instruction, 2)?);
+                for byte in (0..16).filter(|byte| mask >> (8 * byte + 7) & 1 != 0) {
+                    self.memory.write_uint(address.wrapping_add(byte), 1, (data >> (8 * byte)) as u64 & 0xff)?;
+                }
+                return Ok(());
+            }
+
+            // the full moves, aligned or not, and the non-temporal stores
+            _ => source,
+        };
+        self.write_vector_operand(instruction, 0, value)
+    }
+
+    /// ldmxcsr, stmxcsr, fxsave, fxrstor, the fences and the prefetches
+    fn sse_state(&mut self, instruction: &Instruction, mnemonic: Mnemonic) -> Result<(), Error> {
+        match mnemonic {
+            Mnemonic::Ldmxcsr => {
+                let value = self.memory.read_uint(self.memory_address(instruction)?, 4)? as u32;
+                self.load_mxcsr(instruction, value)?;
+            }
+            Mnemonic::Stmxcsr => {
+                let address = self.memory_address(instruction)?;
+                self.memory.write_uint(address, 4, self.registers.sse.mxcsr as u64)?;
+            }
+
+            // the 512-byte area of the x87 and SSE state, without the reserved bytes from offset 416
+            Mnemonic::Fxsave | Mnemonic::Fxsave64 => {
+                let address = self.fxsave_address(instruction)?;
+                self.store_x87_fxsave(address, mnemonic == Mnemonic::Fxsave64)?;
+                self.memory.write_uint(address + 24, 4, self.registers.sse.mxcsr as u64)?;
+                self.memory.write_uint(address + 28, 4, MXCSR_MASK as u64)?;
+                for (index, value) in self.registers.sse.zmm.into_iter().take(16).enumerate() {
+                    self.memory.write(address + 160 + 16 * index as u64, &value[0].to_le_bytes())?;
+                }
+            }
+            Mnemonic::Fxrstor | Mnemonic::Fxrstor64 => {
+                let address = self.fxsave_address(instruction)?;
+                let mxcsr = self.memory.read_uint(address + 24, 4)? as u32;
+                self.load_mxcsr(instruction, mxcsr)?;
+                self.load_x87_fxsave(address, mnemonic == Mnemonic::Fxrstor64)?;
+                for index in 0..16 {
+                    let mut bytes = [0; 16];
+                    self.memory.read(address + 160 + 16 * index as u64, &mut bytes)?;
+                    self.registers.sse.zmm[index][0] = u128::from_le_bytes(bytes);
+                }
+            }
+
+            // memory accesses are performed in order and there are no caches to prefetch into
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Loads MXCSR, setting reserved bits is a general protection fault
+    pub fn load_mxcsr(&mut self, instruction: &Instruction, value: u32) -> Result<(), Error> {
+        if value & !MXCSR_MASK != 0 {
+            return Err(Error::GeneralProtectionFault(instruction.ip()));
+        }
+        self.registers.sse.mxcsr = value;
+        Ok(())
+    }
+
+    /// The fxsave area must be aligned on 16 bytes
+    fn fxsave_address(&self, instruction: &Instruction) -> Result<u64, Error> {
+        let address = self.memory_address(instruction)?;
+        match address % 16 {
+            0 => Ok(address),
+            _ => Err(Error::GeneralProtectionFault(instruction.ip())),
+        }
+    }
+}
+
+/// A vector whose low lane is `value`, the others being zeros
+pub fn xmm_vector(value: u128) -> Vector {
+    std::array::from_fn(|lane| if lane == 0 { value } else { 0 })
+}
+
+/// The 128 bits of a vector from bit `offset`
+fn vector_bits(vector: &Vector, offset: usize) -> u128 {
+    let (lane, shift) = (offset / 128, offset % 128);
+    match shift {
+        0 => vector[lane],
+        _ => vector[lane] >> shift | vector.get(lane + 1).map_or(0, |next| next << (128 - shift)),
+    }
+}
+
+/// Immediate of an instruction for a lane, those which select elements by bits of the immediate use the next bits for
+/// each lane
+fn lane_immediate(mnemonic: Mnemonic, immediate: u8, lane: usize) -> u8 {
+    let bits = match mnemonic {
+        Mnemonic::Shufpd | Mnemonic::Blendpd => 2,
+        Mnemonic::Mpsadbw => 3,
+        Mnemonic::Blendps => 4,
+        _ => 0,
+    };
+    immediate >> (bits * lane)
+}
+
+/// Size of the elements inserted or extracted
+fn element_bits(mnemonic: Mnemonic) -> u32 {
+    match mnemonic {
+        Mnemonic::Pextrb | Mnemonic::Pinsrb => 8,
+        Mnemonic::Pextrw | Mnemonic::Pinsrw => 16,
+        Mnemonic::Pextrq | Mnemonic::Pinsrq => 64,
+        _ => 32,
+    }
+}
+
+/// The sign bits of the `bits`-bit elements of a vector
+fn sign_mask(value: u128, bits: u32) -> u32 {
+    (0..128 / bits).fold(0, |mask, element| mask | ((value >> (bits * (element + 1) - 1)) as u32 & 1) << element)
+}
@@ -0,0 +1,894 @@
+use std::cmp::Ordering;
+
+use super::avx::{set_element, vector_element};
+use super::avx512;
+use super::error::Error;
+use super::flags::{self, AF, CF, OF, PF, SF, ZF};
+use super::softfloat::{self, Class, Context, Float, Format, Rounding, DOUBLE, HALF, SINGLE};
+use super::sse::{self, xmm_vector, LANES};
+use super::sse_integer::signed;
+use super::Cpu;
+
+use iced_x86::{EncodingKind, Instruction, Mnemonic};
+
+/// Elementary operation of an arithmetic instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    Sqrt,
+    Reciprocal,
+    ReciprocalSqrt,
+    Reciprocal14,
+    ReciprocalSqrt14,
+    /// the exponent of the source as a floating-point value
+    Exponent,
+    /// the first source times 2 to the power of the second, rounded down to an integer
+    Scale,
+    Mantissa,
+    RoundScale,
+    Reduce,
+    Range,
+    Class,
+}
+
+/// Operation of an FMA instruction on its product and addend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fused {
+    MultiplyAdd,
+    MultiplySubtract,
+    NegatedMultiplyAdd,
+    NegatedMultiplySubtract,
+    /// subtraction in the even elements and addition in the odd ones
+    MultiplyAddSubtract,
+    /// addition in the even elements and subtraction in the odd ones
+    MultiplySubtractAdd,
+}
+
+/// Type of the elements of a conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Element {
+    Float(Format),
+    /// signed integer of 32 or 64 bits
+    Signed(u32),
+    /// unsigned integer of 32 or 64 bits
+    Unsigned(u32),
+}
+
+impl Element {
+    fn width(&self) -> u32 {
+        match *self {
+            Self::Float(format) => format.width(),
+            Self::Signed(bits) | Self::Unsigned(bits) => bits,
+        }
+    }
+}
+
+impl Cpu {
+    /// Executes the SSE to SSE4.1 floating-point arithmetic, comparison and conversion instructions, the F16C
+    /// conversions and the floating-point instructions of AVX-512
+    pub fn sse_float(&mut self, instruction: &Instruction, mnemonic: Mnemonic) -> Result<(), Error> {
+        let mut context = self.vector_context(instruction);
+        let denormals_are_zero = self.registers.sse.denormals_are_zero();
+
+        match mnemonic {
+            Mnemonic::Comiss | Mnemonic::Comisd | Mnemonic::Ucomiss | Mnemonic::Ucomisd => {
+                let format = if matches!(mnemonic, Mnemonic::Comiss | Mnemonic::Ucomiss) { SINGLE } else { DOUBLE };
+                let mask = u128::MAX >> (128 - format.width());
+                let operands = [self.read_xmm_operand(instruction, 0)? & mask, self.read_xmm_operand(instruction, 1)? & mask];
+                // comiss and comisd signal quiet NaNs too
+                let quiet = matches!(mnemonic, Mnemonic::Ucomiss | Mnemonic::Ucomisd);
+                let ordering = with_operands(operands, format, denormals_are_zero, &mut context, |[a, b], context| {
+                    let ordering = a.compare(b);
+                    if a.is_signaling() || b.is_signaling() || (!quiet && ordering.is_none()) {
+                        context.raise(softfloat::INVALID);
+                    }
+                    ordering
+                });
+                self.report_sse(instruction, &context)?;
+                let value = match ordering {
+                    None => ZF | PF | CF,
+                    Some(Ordering::Greater) => 0,
+                    Some(Ordering::Less) => CF,
+                    Some(Ordering::Equal) => ZF,
+                };
+                self.update_flags(value, ZF | PF | CF | OF | SF | AF);
+                Ok(())
+            }
+
+            Mnemonic::Cvtsi2ss | Mnemonic::Cvtsi2sd | Mnemonic::Vcvtusi2ss | Mnemonic::Vcvtusi2sd => {
+                let format = if matches!(mnemonic, Mnemonic::Cvtsi2ss | Mnemonic::Vcvtusi2ss) { SINGLE } else { DOUBLE };
+                let (first, second) = sse::source_operands(instruction);
+                let integer = self.read_xmm_operand(instruction, second)? as u64;
+                let value = match mnemonic {
+                    Mnemonic::Vcvtusi2ss | Mnemonic::Vcvtusi2sd => Float::from_u64(integer),
+                    _ => Float::from_i64(flags::sign_extend(integer, self.operand_size(instruction, second)) as i64),
+                };
+                let value = value.pack(format, format.precision(), &mut context);
+                self.report_sse(instruction, &context)?;
+                let mask = u128::MAX >> (128 - format.width());
+                let destination = self.read_xmm_operand(instruction, first)?;
+                self.write_xmm_operand(instruction, 0, (destination & !mask) | value)
+            }
+
+            Mnemonic::Cvtss2si | Mnemonic::Cvtsd2si | Mnemonic::Cvttss2si | Mnemonic::Cvttsd2si | Mnemonic::Vcvtss2usi
+            | Mnemonic::Vcvtsd2usi | Mnemonic::Vcvttss2usi | Mnemonic::Vcvttsd2usi => {
+                let format = match mnemonic {
+                    Mnemonic::Cvtss2si | Mnemonic::Cvttss2si | Mnemonic::Vcvtss2usi | Mnemonic::Vcvttss2usi => SINGLE,
+                    _ => DOUBLE,
+                };
+                let truncate = matches!(mnemonic, Mnemonic::Cvttss2si | Mnemonic::Cvttsd2si | Mnemonic::Vcvttss2usi | Mnemonic::Vcvttsd2usi);
+                let bits = 8 * instruction.op0_register().size() as u32;
+                let source = self.read_xmm_operand(instruction, 1)? & (u128::MAX >> (128 - format.width()));
+                let value = match mnemonic {
+                    Mnemonic::Vcvtss2usi | Mnemonic::Vcvtsd2usi | Mnemonic::Vcvttss2usi | Mnemonic::Vcvttsd2usi => {
+                        to_unsigned(source, format, bits, truncate, denormals_are_zero, &mut context)
+                    }
+                    _ => to_integer(source, format, bits, truncate, denormals_are_zero, &mut context) as u64,
+                };
+                self.report_sse(instruction, &context)?;
+                self.write_xmm_operand(instruction, 0, value as u128)
+            }
+
+            // the classes of the elements of the source, whose destination is an opmask register
+            Mnemonic::Vfpclassps | Mnemonic::Vfpclasspd | Mnemonic::Vfpclassss | Mnemonic::Vfpclasssd => {
+                let operands = [[0; LANES], self.read_vector_operand(instruction, 1)?, xmm_vector(instruction.immediate8() as u128)];
+                let result = self.compute_lanes(instruction, mnemonic, operands, |a, b, c| {
+                    Some(float_operation(mnemonic, a, b, c as u8, denormals_are_zero, &mut context))
+                })?;
+                self.write_vector_operand(instruction, 0, result)
+            }
+
+            // the elements of the first source replaced by the responses of the table of the second source to their
+            // classes. The elements the write mask excludes are not computed, their classes could raise exceptions.
+            Mnemonic::Vfixupimmps | Mnemonic::Vfixupimmpd | Mnemonic::Vfixupimmss | Mnemonic::Vfixupimmsd => {
+                let format = if matches!(mnemonic, Mnemonic::Vfixupimmps | Mnemonic::Vfixupimmss) { SINGLE } else { DOUBLE };
+                let (destination, source, table) =
+                    (self.read_vector_operand(instruction, 0)?, self.read_vector_operand(instruction, 1)?, self.read_vector_operand(instruction, 2)?);
+                let ((bits, count), mask) = (avx512::mask_layout(instruction), self.write_mask(instruction));
+                let immediate = instruction.immediate8();
+                // the scalar forms keep the other elements of the first source
+                let scalar = matches!(mnemonic, Mnemonic::Vfixupimmss | Mnemonic::Vfixupimmsd);
+                let mut value = if scalar { source } else { [0; LANES] };
+                for index in (0..count).filter(|&index| mask >> index & 1 != 0) {
+                    let [d, x, t] = [destination, source, table].map(|operand| vector_element(&operand, bits, index) as u128);
+                    set_element(&mut value, bits, index, fixup(d, x, t, immediate, format, denormals_are_zero, &mut context) as u64);
+                }
+                self.report_sse(instruction, &context)?;
+                self.write_vector_operand(instruction, 0, value)
+            }
+
+            _ => {
+                // the legacy comparisons have 8 predicates, the VEX ones 32
+                let legacy = instruction.encoding() == EncodingKind::Legacy;
+                let predicates = matches!(mnemonic, Mnemonic::Cmpps | Mnemonic::Cmppd | Mnemonic::Cmpss | Mnemonic::Cmpsd) && legacy;
+                // the elements the write mask excludes are computed on ones, which raise no exception, except in the
+                // destination of the scalar conversions whose other elements are kept
+                let [a, b, c] = self.lane_operands(instruction, mnemonic)?;
+                let a = match mnemonic {
+                    Mnemonic::Cvtss2sd | Mnemonic::Cvtsd2ss => a,
+                    _ => self.mask_float_source(instruction, a),
+                };
+                let b = self.mask_float_source(instruction, b);
+                let result = self.compute_lanes(instruction, mnemonic, [a, b, c], |a, b, c| {
+                    let immediate = if predicates { c as u8 & 7 } else { c as u8 };
+                    Some(float_operation(mnemonic, a, b, immediate, denormals_are_zero, &mut context))
+                })?;
+                self.report_sse(instruction, &context)?;
+                self.write_vector_operand(instruction, 0, result)
+            }
+        }
+    }
+}
+
+/// Result of an SSE floating-point instruction with the destination `a` and the source `b`, other than the scalar
+/// comparisons and the conversions from and to general-purpose registers. The exceptions are raised in `context`.
+pub fn float_operation(mnemonic: Mnemonic, a: u128, b: u128, immediate: u8, denormals_are_zero: bool, context: &mut Context) -> u128 {
+    let daz = denormals_are_zero;
+    let (operation, format, scalar) = match mnemonic {
+        Mnemonic::Addps => (Operation::Add, SINGLE, false),
+        Mnemonic::Addpd => (Operation::Add, DOUBLE, false),
+        Mnemonic::Addss => (Operation::Add, SINGLE, true),
+        Mnemonic::Addsd => (Operation::Add, DOUBLE, true),
+        Mnemonic::Subps => (Operation::Sub, SINGLE, false),
+        Mnemonic::Subpd => (Operation::Sub, DOUBLE, false),
+        Mnemonic::Subss => (Operation::Sub, SINGLE, true),
+        Mnemonic::Subsd => (Operation::Sub, DOUBLE, true),
+        Mnemonic::Mulps => (Operation::Mul, SINGLE, false),
+        Mnemonic::Mulpd => (Operation::Mul, DOUBLE, false),
+        Mnemonic::Mulss => (Operation::Mul, SINGLE, true),
+        Mnemonic::Mulsd => (Operation::Mul, DOUBLE, true),
+        Mnemonic::Divps => (Operation::Div, SINGLE, false),
+        Mnemonic::Divpd => (Operation::Div, DOUBLE, false),
+        Mnemonic::Divss => (Operation::Div, SINGLE, true),
+        Mnemonic::Divsd => (Operation::Div, DOUBLE, true),
+        Mnemonic::Minps => (Operation::Min, SINGLE, false),
+        Mnemonic::Minpd => (Operation::Min, DOUBLE, false),
+        Mnemonic::Minss => (Operation::Min, SINGLE, true),
+        Mnemonic::Minsd => (Operation::Min, DOUBLE, true),
+        Mnemonic::Maxps => (Operation::Max, SINGLE, false),
+        Mnemonic::Maxpd => (Operation::Max, DOUBLE, false),
+        Mnemonic::Maxss => (Operation::Max, SINGLE, true),
+        Mnemonic::Maxsd => (Operation::Max, DOUBLE, true),
+        Mnemonic::Sqrtps => (Operation::Sqrt, SINGLE, false),
+        Mnemonic::Sqrtpd => (Operation::Sqrt, DOUBLE, false),
+        Mnemonic::Sqrtss => (Operation::Sqrt, SINGLE, true),
+        Mnemonic::Sqrtsd => (Operation::Sqrt, DOUBLE, true),
+        Mnemonic::Rcpps => (Operation::Reciprocal, SINGLE, false),
+        Mnemonic::Rcpss => (Operation::Reciprocal, SINGLE, true),
+        Mnemonic::Rsqrtps => (Operation::ReciprocalSqrt, SINGLE, false),
+        Mnemonic::Rsqrtss => (Operation::ReciprocalSqrt, SINGLE, true),
+        Mnemonic::Vrcp14ps => (Operation::Reciprocal14, SINGLE, false),
+        Mnemonic::Vrcp14pd => (Operation::Reciprocal14, DOUBLE, false),
+        Mnemonic::Vrcp14ss => (Operation::Reciprocal14, SINGLE, true),
+        Mnemonic::Vrcp14sd => (Operation::Reciprocal14, DOUBLE, true),
+        Mnemonic::Vrsqrt14ps => (Operation::ReciprocalSqrt14, SINGLE, false),
+        Mnemonic::Vrsqrt14pd => (Operation::ReciprocalSqrt14, DOUBLE, false),
+        Mnemonic::Vrsqrt14ss => (Operation::ReciprocalSqrt14, SINGLE, true),
+        Mnemonic::Vrsqrt14sd => (Operation::ReciprocalSqrt14, DOUBLE, true),
+        Mnemonic::Vgetexpps => (Operation::Exponent, SINGLE, false),
+        Mnemonic::Vgetexppd => (Operation::Exponent, DOUBLE, false),
+        Mnemonic::Vgetexpss => (Operation::Exponent, SINGLE, true),
+        Mnemonic::Vgetexpsd => (Operation::Exponent, DOUBLE, true),
+        Mnemonic::Vscalefps => (Operation::Scale, SINGLE, false),
+        Mnemonic::Vscalefpd => (Operation::Scale, DOUBLE, false),
+        Mnemonic::Vscalefss => (Operation::Scale, SINGLE, true),
+        Mnemonic::Vscalefsd => (Operation::Scale, DOUBLE, true),
+        Mnemonic::Vgetmantps => (Operation::Mantissa, SINGLE, false),
+        Mnemonic::Vgetmantpd => (Operation::Mantissa, DOUBLE, false),
+        Mnemonic::Vgetmantss => (Operation::Mantissa, SINGLE, true),
+        Mnemonic::Vgetmantsd => (Operation::Mantissa, DOUBLE, true),
+        Mnemonic::Vrndscaleps => (Operation::RoundScale, SINGLE, false),
+        Mnemonic::Vrndscalepd => (Operation::RoundScale, DOUBLE, false),
+        Mnemonic::Vrndscaless => (Operation::RoundScale, SINGLE, true),
+        Mnemonic::Vrndscalesd => (Operation::RoundScale, DOUBLE, true),
+        Mnemonic::Vreduceps => (Operation::Reduce, SINGLE, false),
+        Mnemonic::Vreducepd => (Operation::Reduce, DOUBLE, false),
+        Mnemonic::Vreducess => (Operation::Reduce, SINGLE, true),
+        Mnemonic::Vreducesd => (Operation::Reduce, DOUBLE, true),
+        Mnemonic::Vrangeps => (Operation::Range, SINGLE, false),
+        Mnemonic::Vrangepd => (Operation::Range, DOUBLE, false),
+        Mnemonic::Vrangess => (Operation::Range, SINGLE, true),
+        Mnemonic::Vrangesd => (Operation::Range, DOUBLE, true),
+        Mnemonic::Vfpclassps => (Operation::Class, SINGLE, false),
+        Mnemonic::Vfpclasspd => (Operation::Class, DOUBLE, false),
+        Mnemonic::Vfpclassss => (Operation::Class, SINGLE, true),
+        Mnemonic::Vfpclasssd => (Operation::Class, DOUBLE, true),
+
+        // subtractions in the even elements and additions in the odd ones
+        Mnemonic::Addsubps | Mnemonic::Addsubpd => {
+            let format = if mnemonic == Mnemonic::Addsubps { SINGLE } else { DOUBLE };
+            return elements(format, |index| {
+                let operation = if index % 2 == 0 { Operation::Sub } else { Operation::Add };
+                arithmetic(operation, lane(a, format, index), lane(b, format, index), format, daz, context)
+            });
+        }
+
+        // sums or differences of adjacent elements, those of the destination in the low half of the result
+        Mnemonic::Haddps | Mnemonic::Haddpd | Mnemonic::Hsubps | Mnemonic::Hsubpd => {
+            let format = if matches!(mnemonic, Mnemonic::Haddps | Mnemonic::Hsubps) { SINGLE } else { DOUBLE };
+            let operation = if matches!(mnemonic, Mnemonic::Haddps | Mnemonic::Haddpd) { Operation::Add } else { Operation::Sub };
+            let half = 64 / format.width();
+            return elements(format, |index| {
+                let (source, pair) = if index < half { (a, index) } else { (b, index - half) };
+                arithmetic(operation, lane(source, format, 2 * pair), lane(source, format, 2 * pair + 1), format, daz, context)
+            });
+        }
+
+        // the products of the elements selected by the high bits of the immediate summed pairwise, in the elements
+        // selected by the low bits. The order of the operands, which decides the NaN of the result, follows the
+        // hardware: element i is (p[i ^ 1] + p[i]) + (p[i ^ 3] + p[i ^ 2]) for the products p.
+        Mnemonic::Dpps | Mnemonic::Dppd => {
+            let format = if mnemonic == Mnemonic::Dpps { SINGLE } else { DOUBLE };
+            let mut products = [0; 4];
+            for index in (0..128 / format.width()).filter(|index| immediate >> (4 + index) & 1 != 0) {
+                products[index as usize] = arithmetic(Operation::Mul, lane(a, format, index), lane(b, format, index), format, daz, context);
+            }
+            let sum = |index: usize, context: &mut Context| arithmetic(Operation::Add, products[index ^ 1], products[index], format, daz, context);
+            return elements(format, |index| {
+                let index = index as usize;
+                let result = match format == SINGLE {
+                    true => {
+                        let (low, high) = (sum(index, context), sum(index ^ 2, context));
+                        arithmetic(Operation::Add, low, high, format, daz, context)
+                    }
+                    false => sum(index, context),
+                };
+                if immediate >> index & 1 != 0 { result } else { 0 }
+            });
+        }
+
+        Mnemonic::Roundps | Mnemonic::Roundpd | Mnemonic::Roundss | Mnemonic::Roundsd => {
+            let format = if matches!(mnemonic, Mnemonic::Roundps | Mnemonic::Roundss) { SINGLE } else { DOUBLE };
+            let scalar = matches!(mnemonic, Mnemonic::Roundss | Mnemonic::Roundsd);
+            return map_elements(a, b, format, scalar, |_, y| round(y, format, immediate, 0, daz, context));
+        }
+
+        // predicates 0 to 31 of the immediate
+        Mnemonic::Cmpps | Mnemonic::Cmppd | Mnemonic::Cmpss | Mnemonic::Cmpsd => {
+            let format = if matches!(mnemonic, Mnemonic::Cmpps | Mnemonic::Cmpss) { SINGLE } else { DOUBLE };
+            let scalar = matches!(mnemonic, Mnemonic::Cmpss | Mnemonic::Cmpsd);
+            return map_elements(a, b, format, scalar, |x, y| compare(immediate & 31, x, y, format, daz, context));
+        }
+
+        // rounded in the mode of bits 0 and 1 of the immediate or in that of MXCSR when bit 2 is set, without flushing
+        // denormal results to zero
+        Mnemonic::Vcvtps2ph => {
+            let (rounding, flush_to_zero) = (context.rounding, context.flush_to_zero);
+            if immediate & 4 == 0 {
+                context.rounding = Rounding::from_bits(immediate as u32);
+            }
+            context.flush_to_zero = false;
+            let result = (0..4).fold(0, |result, index| {
+                result | convert(lane(b, SINGLE, index), Element::Float(SINGLE), Element::Float(HALF), false, daz, context) << (16 * index)
+            });
+            (context.rounding, context.flush_to_zero) = (rounding, flush_to_zero);
+            return result;
+        }
+
+        _ => {
+            let (from, to, truncate) = match mnemonic {
+                Mnemonic::Cvtss2sd => (Element::Float(SINGLE), Element::Float(DOUBLE), false),
+                Mnemonic::Cvtsd2ss => (Element::Float(DOUBLE), Element::Float(SINGLE), false),
+                Mnemonic::Cvtps2pd => (Element::Float(SINGLE), Element::Float(DOUBLE), false),
+                Mnemonic::Cvtpd2ps => (Element::Float(DOUBLE), Element::Float(SINGLE), false),
+                Mnemonic::Cvtdq2ps => (Element::Signed(32), Element::Float(SINGLE), false),
+                Mnemonic::Cvtps2dq => (Element::Float(SINGLE), Element::Signed(32), false),
+                Mnemonic::Cvttps2dq => (Element::Float(SINGLE), Element::Signed(32), true),
+                Mnemonic::Cvtdq2pd => (Element::Signed(32), Element::Float(DOUBLE), false),
+                Mnemonic::Cvtpd2dq => (Element::Float(DOUBLE), Element::Signed(32), false),
+                Mnemonic::Cvttpd2dq => (Element::Float(DOUBLE), Element::Signed(32), true),
+                Mnemonic::Vcvtph2ps => (Element::Float(HALF), Element::Float(SINGLE), false),
+                Mnemonic::Vcvtudq2ps => (Element::Unsigned(32), Element::Float(SINGLE), false),
+                Mnemonic::Vcvtps2udq => (Element::Float(SINGLE), Element::Unsigned(32), false),
+                Mnemonic::Vcvttps2udq => (Element::Float(SINGLE), Element::Unsigned(32), true),
+                Mnemonic::Vcvtudq2pd => (Element::Unsigned(32), Element::Float(DOUBLE), false),
+                Mnemonic::Vcvtpd2udq => (Element::Float(DOUBLE), Element::Unsigned(32), false),
+                Mnemonic::Vcvttpd2udq => (Element::Float(DOUBLE), Element::Unsigned(32), true),
+                Mnemonic::Vcvtqq2ps => (Element::Signed(64), Element::Float(SINGLE), false),
+                Mnemonic::Vcvtqq2pd => (Element::Signed(64), Element::Float(DOUBLE), false),
+                Mnemonic::Vcvtuqq2ps => (Element::Unsigned(64), Element::Float(SINGLE), false),
+                Mnemonic::Vcvtuqq2pd => (Element::Unsigned(64), Element::Float(DOUBLE), false),
+                Mnemonic::Vcvtps2qq => (Element::Float(SINGLE), Element::Signed(64), false),
+                Mnemonic::Vcvttps2qq => (Element::Float(SINGLE), Element::Signed(64), true),
+                Mnemonic::Vcvtpd2qq => (Element::Float(DOUBLE), Element::Signed(64), false),
+                Mnemonic::Vcvttpd2qq => (Element::Float(DOUBLE), Element::Signed(64), true),
+                Mnemonic::Vcvtps2uqq => (Element::Float(SINGLE), Element::Unsigned(64), false),
+                Mnemonic::Vcvttps2uqq => (Element::Float(SINGLE), Element::Unsigned(64), true),
+                Mnemonic::Vcvtpd2uqq => (Element::Float(DOUBLE), Element::Unsigned(64), false),
+                _ => (Element::Float(DOUBLE), Element::Unsigned(64), true),
+            };
+            // the scalar conversions keep the other elements of the destination, the others zero them
+            let (from_mask, to_mask) = (u128::MAX >> (128 - from.width()), u128::MAX >> (128 - to.width()));
+            let (merged, count) = match mnemonic {
+                Mnemonic::Cvtss2sd | Mnemonic::Cvtsd2ss => (a & !to_mask, 1),
+                _ => (0, 128 / from.width().max(to.width())),
+            };
+            return (0..count).fold(merged, |result, index| {
+                let value = convert(b >> (index * from.width()) & from_mask, from, to, truncate, daz, context);
+                result | (value & to_mask) << (index * to.width())
+            });
+        }
+    };
+    map_elements(a, b, format, scalar, |x, y| match operation {
+        Operation::Mantissa => mantissa(y, format, immediate, daz, context),
+        Operation::RoundScale => round(y, format, immediate, (immediate >> 4) as i32, daz, context),
+        Operation::Reduce => reduce(y, format, immediate, daz, context),
+        Operation::Range => range(x, y, immediate, format, daz, context),
+        Operation::Class => classify(y, format, immediate, daz),
+        _ => arithmetic(operation, x, y, format, daz, context),
+    })
+}
+
+/// Result of an FMA instruction on a lane of its destination `d` and sources `s1` and `s2`. The product of two of
+/// them and the third are added with a single rounding; the digits of the mnemonic are the order of the factors and
+/// the addend. When an operand is a NaN the result is the first NaN of the factors and the addend, so the product of
+/// an infinity and a zero is not invalid with a quiet NaN addend.
+pub fn fused_operation(mnemonic: Mnemonic, d: u128, s1: u128, s2: u128, denormals_are_zero: bool, context: &mut Context) -> u128 {
+    let (fused, order, format, scalar) = match mnemonic {
+        Mnemonic::Vfmadd132ps => (Fused::MultiplyAdd, [d, s2, s1], SINGLE, false),
+        Mnemonic::Vfmadd213ps => (Fused::MultiplyAdd, [s1, d, s2], SINGLE, false),
+        Mnemonic::Vfmadd231ps => (Fused::MultiplyAdd, [s1, s2, d], SINGLE, false),
+        Mnemonic::Vfmadd132pd => (Fused::MultiplyAdd, [d, s2, s1], DOUBLE, false),
+        Mnemonic::Vfmadd213pd => (Fused::MultiplyAdd, [s1, d, s2], DOUBLE, false),
+        Mnemonic::Vfmadd231pd => (Fused::MultiplyAdd, [s1, s2, d], DOUBLE, false),
+        Mnemonic::Vfmadd132ss => (Fused::MultiplyAdd, [d, s2, s1], SINGLE, true),
+        Mnemonic::Vfmadd213ss => (Fused::MultiplyAdd, [s1, d, s2], SINGLE, true),
+        Mnemonic::Vfmadd231ss => (Fused::MultiplyAdd, [s1, s2, d], SINGLE, true),
+        Mnemonic::Vfmadd132sd => (Fused::MultiplyAdd, [d, s2, s1], DOUBLE, true),
+        Mnemonic::Vfmadd213sd => (Fused::MultiplyAdd, [s1, d, s2], DOUBLE, true),
+        Mnemonic::Vfmadd231sd => (Fused::MultiplyAdd, [s1, s2, d], DOUBLE, true),
+        Mnemonic::Vfmsub132ps => (Fused::MultiplySubtract, [d, s2, s1], SINGLE, false),
+        Mnemonic::Vfmsub213ps => (Fused::MultiplySubtract, [s1, d, s2], SINGLE, false),
+        Mnemonic::Vfmsub231ps => (Fused::MultiplySubtract, [s1, s2, d], SINGLE, false),
+        Mnemonic::Vfmsub132pd => (Fused::MultiplySubtract, [d, s2, s1], DOUBLE, false),
+        Mnemonic::Vfmsub213pd => (Fused::MultiplySubtract, [s1, d, s2], DOUBLE, false),
+        Mnemonic::Vfmsub231pd => (Fused::MultiplySubtract, [s1, s2, d], DOUBLE, false),
+        Mnemonic::Vfmsub132ss => (Fused::MultiplySubtract, [d, s2, s1], SINGLE, true),
+        Mnemonic::Vfmsub213ss => (Fused::MultiplySubtract, [s1, d, s2], SINGLE, true),
+        Mnemonic::Vfmsub231ss => (Fused::MultiplySubtract, [s1, s2, d], SINGLE, true),
+        Mnemonic::Vfmsub132sd => (Fused::MultiplySubtract, [d, s2, s1], DOUBLE, true),
+        Mnemonic::Vfmsub213sd => (Fused::MultiplySubtract, [s1, d, s2], DOUBLE, true),
+        Mnemonic::Vfmsub231sd => (Fused::MultiplySubtract, [s1, s2, d], DOUBLE, true),
+        Mnemonic::Vfnmadd132ps => (Fused::NegatedMultiplyAdd, [d, s2, s1], SINGLE, false),
+        Mnemonic::Vfnmadd213ps => (Fused::NegatedMultiplyAdd, [s1, d, s2], SINGLE, false),
+        Mnemonic::Vfnmadd231ps => (Fused::NegatedMultiplyAdd, [s1, s2, d], SINGLE, false),
+        Mnemonic::Vfnmadd132pd => (Fused::NegatedMultiplyAdd, [d, s2, s1], DOUBLE, false),
+        Mnemonic::Vfnmadd213pd => (Fused::NegatedMultiplyAdd, [s1, d, s2], DOUBLE, false),
+        Mnemonic::Vfnmadd231pd => (Fused::NegatedMultiplyAdd, [s1, s2, d], DOUBLE, false),
+        Mnemonic::Vfnmadd132ss => (Fused::NegatedMultiplyAdd, [d, s2, s1], SINGLE, true),
+        Mnemonic::Vfnmadd213ss => (Fused::NegatedMultiplyAdd, [s1, d, s2], SINGLE, true),
+        Mnemonic::Vfnmadd231ss => (Fused::NegatedMultiplyAdd, [s1, s2, d], SINGLE, true),
+        Mnemonic::Vfnmadd132sd => (Fused::NegatedMultiplyAdd, [d, s2, s1], DOUBLE, true),
+        Mnemonic::Vfnmadd213sd => (Fused::NegatedMultiplyAdd, [s1, d, s2], DOUBLE, true),
+        Mnemonic::Vfnmadd231sd => (Fused::NegatedMultiplyAdd, [s1, s2, d], DOUBLE, true),
+        Mnemonic::Vfnmsub132ps => (Fused::NegatedMultiplySubtract, [d, s2, s1], SINGLE, false),
+        Mnemonic::Vfnmsub213ps => (Fused::NegatedMultiplySubtract, [s1, d, s2], SINGLE, false),
+        Mnemonic::Vfnmsub231ps => (Fused::NegatedMultiplySubtract, [s1, s2, d], SINGLE, false),
+        Mnemonic::Vfnmsub132pd => (Fused::NegatedMultiplySubtract, [d, s2, s1], DOUBLE, false),
+        Mnemonic::Vfnmsub213pd => (Fused::NegatedMultiplySubtract, [s1, d, s2], DOUBLE, false),
+        Mnemonic::Vfnmsub231pd => (Fused::NegatedMultiplySubtract, [s1, s2, d], DOUBLE, false),
+        Mnemonic::Vfnmsub132ss => (Fused::NegatedMultiplySubtract, [d, s2, s1], SINGLE, true),
+        Mnemonic::Vfnmsub213ss => (Fused::NegatedMultiplySubtract, [s1, d, s2], SINGLE, true),
+        Mnemonic::Vfnmsub231ss => (Fused::NegatedMultiplySubtract, [s1, s2, d], SINGLE, true),
+        Mnemonic::Vfnmsub132sd => (Fused::NegatedMultiplySubtract, [d, s2, s1], DOUBLE, true),
+        Mnemonic::Vfnmsub213sd => (Fused::NegatedMultiplySubtract, [s1, d, s2], DOUBLE, true),
+        Mnemonic::Vfnmsub231sd => (Fused::NegatedMultiplySubtract, [s1, s2, d], DOUBLE, true),
+        Mnemonic::Vfmaddsub132ps => (Fused::MultiplyAddSubtract, [d, s2, s1], SINGLE, false),
+        Mnemonic::Vfmaddsub213ps => (Fused::MultiplyAddSubtract, [s1, d, s2], SINGLE, false),
+        Mnemonic::Vfmaddsub231ps => (Fused::MultiplyAddSubtract, [s1, s2, d], SINGLE, false),
+        Mnemonic::Vfmaddsub132pd => (Fused::MultiplyAddSubtract, [d, s2, s1], DOUBLE, false),
+        Mnemonic::Vfmaddsub213pd => (Fused::MultiplyAddSubtract, [s1, d, s2], DOUBLE, false),
+        Mnemonic::Vfmaddsub231pd => (Fused::MultiplyAddSubtract, [s1, s2, d], DOUBLE, false),
+        Mnemonic::Vfmsubadd132ps => (Fused::MultiplySubtractAdd, [d, s2, s1], SINGLE, false),
+        Mnemonic::Vfmsubadd213ps => (Fused::MultiplySubtractAdd, [s1, d, s2], SINGLE, false),
+        Mnemonic::Vfmsubadd231ps => (Fused::MultiplySubtractAdd, [s1, s2, d], SINGLE, false),
+        Mnemonic::Vfmsubadd132pd => (Fused::MultiplySubtractAdd, [d, s2, s1], DOUBLE, false),
+        Mnemonic::Vfmsubadd213pd => (Fused::MultiplySubtractAdd, [s1, d, s2], DOUBLE, false),
+        _ => (Fused::MultiplySubtractAdd, [s1, s2, d], DOUBLE, false),
+    };
+
+    let width = format.width();
+    let mask = u128::MAX >> (128 - width);
+    let count = if scalar { 1 } else { 128 / width };
+    (0..count).fold(if scalar { d & !mask } else { 0 }, |result, index| {
+        let (negate_product, negate_addend) = match fused {
+            Fused::MultiplyAdd => (false, false),
+            Fused::MultiplySubtract => (false, true),
+            Fused::NegatedMultiplyAdd => (true, false),
+            Fused::NegatedMultiplySubtract => (true, true),
+            Fused::MultiplyAddSubtract => (false, index % 2 == 0),
+            Fused::MultiplySubtractAdd => (false, index % 2 != 0),
+        };
+        let operands = order.map(|operand| lane(operand, format, index));
+        let value = with_operands(operands, format, denormals_are_zero, context, |[x, y, z], context| {
+            if let Some(nan) = [x, y, z].into_iter().find(Float::is_nan) {
+                if [x, y, z].iter().any(Float::is_signaling) {
+                    context.raise(softfloat::INVALID);
+                }
+                return nan.quiet().pack(format, format.precision(), context);
+            }
+            // an invalid product is the default NaN, whatever the sign of the product
+            let product = match x.mul(y, context) {
+                product if product.is_nan() => return product.pack(format, format.precision(), context),
+                product if negate_product => product.negate(),
+                product => product,
+            };
+            let addend = if negate_addend { z.negate() } else { z };
+            product.add(addend, context).pack(format, format.precision(), context)
+        });
+        result | (value & mask) << (index * width)
+    })
+}
+
+/// Applies `operation` to the pairs of elements of `a` and `b`, or only to their lowest elements for a scalar
+/// instruction which keeps the other elements of `a`
+fn map_elements(a: u128, b: u128, format: Format, scalar: bool, mut operation: impl FnMut(u128, u128) -> u128) -> u128 {
+    let width = format.width();
+    let mask = u128::MAX >> (128 - width);
+    let count = if scalar { 1 } else { 128 / width };
+    (0..count).fold(if scalar { a & !mask } else { 0 }, |result, index| {
+        let shift = index * width;
+        result | (operation(a >> shift & mask, b >> shift & mask) & mask) << shift
+    })
+}
+
+/// The vector of the elements computed for each index
+fn elements(format: Format, mut element: impl FnMut(u32) -> u128) -> u128 {
+    let width = format.width();
+    (0..128 / width).fold(0, |result, index| result | (element(index) & (u128::MAX >> (128 - width))) << (index * width))
+}
+
+/// The element `index` of a vector
+fn lane(value: u128, format: Format, index: u32) -> u128 {
+    value >> (index * format.width()) & (u128::MAX >> (128 - format.width()))
+}
+
+fn sign(bits: u128, format: Format) -> bool {
+    bits >> (format.width() - 1) & 1 != 0
+}
+
+/// Unpacks the operands of an element, which are zeros with DAZ when they are denormals, and applies `operation` to
+/// them. Denormal operands otherwise raise the denormal exception, unless one of the operands is a NaN or the operation
+/// is invalid or divides by zero.
+fn with_operands<const N: usize, T>(
+    operands: [u128; N],
+    format: Format,
+    denormals_are_zero: bool,
+    context: &mut Context,
+    operation: impl FnOnce([Float; N], &mut Context) -> T,
+) -> T {
+    let denormal = !denormals_are_zero && operands.iter().any(|&bits| format.is_denormal(bits));
+    let operands = operands.map(|bits| Float::unpack(flush_denormal(bits, format, denormals_are_zero), format));
+    let flags = std::mem::take(&mut context.flags);
+    let result = operation(operands, context);
+    if denormal && context.flags & (softfloat::INVALID | softfloat::DIVIDE_BY_ZERO) == 0 && !operands.iter().any(Float::is_nan) {
+        context.raise(softfloat::DENORMAL);
+    }
+    context.flags |= flags;
+    result
+}
+
+/// The zero of the sign of a denormal with DAZ
+fn flush_denormal(bits: u128, format: Format, denormals_are_zero: bool) -> u128 {
+    match denormals_are_zero && format.is_denormal(bits) {
+        true => format.zero(sign(bits, format)),
+        false => bits,
+    }
+}
+
+fn arithmetic(operation: Operation, x: u128, y: u128, format: Format, daz: bool, context: &mut Context) -> u128 {
+    let precision = format.precision();
+    match operation {
+        Operation::Reciprocal | Operation::ReciprocalSqrt => return approximate(operation, y, format, true, true),
+        Operation::Reciprocal14 | Operation::ReciprocalSqrt14 => return approximate(operation, y, format, daz, context.flush_to_zero),
+        Operation::Sqrt => return with_operands([y], format, daz, context, |[b], context| b.sqrt(context).pack(format, precision, context)),
+        Operation::Scale => return scale(x, y, format, daz, context),
+        // the exponent of zeros is negative infinity
+        Operation::Exponent => {
+            return with_operands([y], format, daz, context, |[b], context| {
+                let exponent = match b.class {
+                    Class::Nan => b.propagate(context),
+                    Class::Zero => Float::infinity(true),
+                    Class::Infinity => Float::infinity(false),
+                    Class::Normal => Float::from_i64(b.exponent as i64),
+                };
+                exponent.pack(format, precision, context)
+            });
+        }
+        _ => {}
+    }
+
+    with_operands([x, y], format, daz, context, |[a, b], context| match operation {
+        // the source is returned if either operand is a NaN or both are zeros, unchanged but for DAZ
+        Operation::Min | Operation::Max => {
+            if a.is_nan() || b.is_nan() {
+                context.raise(softfloat::INVALID);
+                return flush_denormal(y, format, daz);
+            }
+            let first = match operation {
+                Operation::Min => a.compare(b) == Some(Ordering::Less),
+                _ => a.compare(b) == Some(Ordering::Greater),
+            };
+            flush_denormal(if first { x } else { y }, format, daz)
+        }
+        _ if a.is_nan() || b.is_nan() => Float::propagate_first(a, b, context).pack(format, precision, context),
+        Operation::Add => a.add(b, context).pack(format, precision, context),
+        Operation::Sub => a.sub(b, context).pack(format, precision, context),
+        Operation::Mul => a.mul(b, context).pack(format, precision, context),
+        _ => a.div(b, context).pack(format, precision, context),
+    })
+}
+
+/// Approximate reciprocal or reciprocal square root of an element, with a relative error of at most 2^-12, or 2^-14 for
+/// those of AVX-512. Denormal operands and results are zeros when `flush_operand` and `flush_result` are set, and no
+/// exception is raised. The results are correctly rounded to 12 or 14 bits, where processors use tables of their own,
+/// so the low bits differ from those of the hardware.
+fn approximate(operation: Operation, bits: u128, format: Format, flush_operand: bool, flush_result: bool) -> u128 {
+    let mut scratch = Context::default();
+    let value = Float::unpack(flush_denormal(bits, format, flush_operand), format);
+    let (reciprocal, precision) = match operation {
+        Operation::Reciprocal => (true, 12),
+        Operation::ReciprocalSqrt => (false, 12),
+        Operation::Reciprocal14 => (true, 14),
+        _ => (false, 14),
+    };
+    let result = match (reciprocal, value.class) {
+        (_, Class::Nan) => value.quiet(),
+        (_, Class::Zero) => Float::infinity(value.sign),
+        (false, _) if value.sign => Float::default_nan(),
+        (_, Class::Infinity) => Float::zero(value.sign),
+        (true, _) => Float::one().div(value, &mut scratch),
+        _ => Float::one().div(value.sqrt(&mut scratch), &mut scratch),
+    };
+    match result.pack(format, precision, &mut scratch) {
+        bits if flush_result && format.is_denormal(bits) => format.zero(result.sign),
+        bits => bits,
+    }
+}
+
+/// vscalef: the first element times 2 to the power of the second rounded down to an integer. Only a denormal first
+/// element raises the denormal exception, and not when the second is a NaN. A quiet NaN scaled by an infinity gives a
+/// positive infinity or zero.
+fn scale(x: u128, y: u128, format: Format, daz: bool, context: &mut Context) -> u128 {
+    let power = Float::unpack(flush_denormal(y, format, daz), format);
+    let flags = std::mem::take(&mut context.flags);
+    let result = with_operands([x], format, daz, context, |[a], context| {
+        let result = match (a.class, power.class) {
+            (Class::Nan, Class::Infinity) if !a.is_signaling() => match power.sign {
+                true => Float::zero(false),
+                false => Float::infinity(false),
+            },
+            _ if a.is_nan() || power.is_nan() => Float::propagate_first(a, power, context),
+            (Class::Zero, Class::Infinity) if !power.sign => Float::invalid(context),
+            (Class::Infinity, Class::Infinity) if power.sign => Float::invalid(context),
+            (Class::Zero | Class::Infinity, _) => a,
+            (_, Class::Infinity) if power.sign => Float::zero(a.sign),
+            (_, Class::Infinity) => Float::infinity(a.sign),
+            // powers beyond the range of the formats are clamped, the results overflowing or underflowing all the same
+            _ => {
+                let integer = power.to_integer(32, Rounding::Down, &mut Context::default());
+                a.scale(integer.unwrap_or(if power.sign { i64::MIN } else { i64::MAX }).clamp(-4096, 4096) as i32)
+            }
+        };
+        result.pack(format, format.precision(), context)
+    });
+    if power.is_nan() {
+        context.flags &= !softfloat::DENORMAL;
+    }
+    context.flags |= flags;
+    result
+}
+
+/// vgetmant: the significand of an element in the interval selected by bits 0 and 1 of the immediate, [1, 2), [1/2, 2),
+/// [1/2, 1) or [3/4, 3/2), with the sign of the element or positive when bit 2 is set. When bit 3 is set, negative
+/// elements are invalid. Zeros and infinities have the significand 1.
+fn mantissa(element: u128, format: Format, immediate: u8, daz: bool, context: &mut Context) -> u128 {
+    with_operands([element], format, daz, context, |[value], context| {
+        if value.is_nan() {
+            return value.propagate(context).pack(format, format.precision(), context);
+        }
+        if immediate & 8 != 0 && value.sign && value.class != Class::Zero {
+            return Float::invalid(context).pack(format, format.precision(), context);
+        }
+        let sign = immediate & 4 == 0 && value.sign;
+        let significand = match value.class {
+            Class::Normal => {
+                let halved = match immediate & 3 {
+                    0 => false,
+                    1 => value.exponent % 2 != 0,
+                    2 => true,
+                    _ => value.significand >> 126 & 1 != 0,
+                };
+                Float { sign, exponent: -(halved as i32), ..value }
+            }
+            _ => Float { sign, ..Float::one() },
+        };
+        significand.pack(format, format.precision(), context)
+    })
+}
+
+/// vreduce: an element minus its value rounded to the number of fraction bits given by bits 4 to 7 of the immediate,
+/// in the mode of bits 0 and 1 or in that of MXCSR when bit 2 is set. The subtraction is rounded in the same mode, bit
+/// 3 suppresses its precision exception, and the denormal and underflow exceptions are never raised. Infinities give
+/// zero.
+fn reduce(element: u128, format: Format, immediate: u8, daz: bool, context: &mut Context) -> u128 {
+    let rounding = if immediate & 4 != 0 { context.rounding } else { Rounding::from_bits(immediate as u32) };
+    let fraction_bits = (immediate >> 4) as i32;
+    let (flags, mode) = (context.flags, context.rounding);
+    context.rounding = rounding;
+    let result = with_operands([element], format, daz, context, |[value], context| match value.class {
+        Class::Nan => value.propagate(context).pack(format, format.precision(), context),
+        Class::Infinity => format.zero(false),
+        _ => {
+            let rounded = value.scale(fraction_bits).round_to_integral(rounding, &mut Context::default()).scale(-fraction_bits);
+            value.sub(rounded, context).pack(format, format.precision(), context)
+        }
+    });
+    let suppressed = softfloat::DENORMAL | softfloat::UNDERFLOW | if immediate & 8 != 0 { softfloat::INEXACT } else { 0 };
+    (context.flags, context.rounding) = (flags | context.flags & !suppressed, mode);
+    result
+}
+
+/// vrange: the minimum or the maximum of two elements or of their magnitudes, selected by bits 0 and 1 of the
+/// immediate, with the sign of the first element, of the element selected, positive or negative by bits 2 and 3.
+/// Negative zero is below positive zero. A quiet NaN gives the other element, and two quiet NaNs the first one.
+fn range(x: u128, y: u128, immediate: u8, format: Format, daz: bool, context: &mut Context) -> u128 {
+    let (x, y) = (flush_denormal(x, format, daz), flush_denormal(y, format, daz));
+    with_operands([x, y], format, daz, context, |[a, b], context| {
+        if a.is_signaling() || b.is_signaling() {
+            context.raise(softfloat::INVALID);
+            let nan = if a.is_signaling() || !b.is_signaling() { a } else { b };
+            return nan.quiet().pack(format, format.precision(), context);
+        }
+        let result = match (a.is_nan(), b.is_nan()) {
+            (true, false) => y,
+            (_, true) => x,
+            (false, false) => {
+                let order = |a: Float, b: Float| a.compare(b).unwrap_or(Ordering::Equal).then(b.sign.cmp(&a.sign));
+                let ordering = match immediate & 2 {
+                    0 => order(a, b),
+                    _ => order(a.abs(), b.abs()).then(order(a, b)),
+                };
+                let first = match immediate & 1 {
+                    0 => ordering != Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                };
+                if first { x } else { y }
+            }
+        };
+        let negative = match immediate >> 2 & 3 {
+            0 => sign(x, format),
+            1 => sign(result, format),
+            2 => false,
+            _ => true,
+        };
+        let sign_bit = format.zero(true);
+        if negative { result | sign_bit } else { result & !sign_bit }
+    })
+}
+
+/// vfpclass: all ones when the element is in one of the classes selected by the bits of the immediate: quiet NaN,
+/// positive zero, negative zero, positive infinity, negative infinity, denormal, negative finite value and signaling
+/// NaN. Denormals are zeros with DAZ.
+fn classify(element: u128, format: Format, immediate: u8, daz: bool) -> u128 {
+    let bits = flush_denormal(element, format, daz);
+    let value = Float::unpack(bits, format);
+    let classes = [
+        value.is_nan() && !value.is_signaling(),
+        value.class == Class::Zero && !value.sign,
+        value.class == Class::Zero && value.sign,
+        value.class == Class::Infinity && !value.sign,
+        value.class == Class::Infinity && value.sign,
+        format.is_denormal(bits),
+        value.class == Class::Normal && value.sign,
+        value.is_signaling(),
+    ];
+    match (0..8).any(|class| immediate >> class & 1 != 0 && classes[class]) {
+        true => u128::MAX,
+        false => 0,
+    }
+}
+
+/// vfixupimm: the response of the table to the class of the element `x`, which is 4 bits of the table for each class:
+/// quiet NaN, signaling NaN, zero, one, negative infinity, positive infinity, negative and positive values. The
+/// response is the destination, `x`, `x` made a quiet NaN, the default NaN, an infinity or zero of either sign or of
+/// the sign of `x`, -1, 1, 1/2, 90, pi/2 or the largest finite value of either sign. The bits of the immediate raise
+/// the invalid operation or divide-by-zero exception for some classes.
+fn fixup(destination: u128, x: u128, table: u128, immediate: u8, format: Format, daz: bool, context: &mut Context) -> u128 {
+    let source = flush_denormal(x, format, daz);
+    let value = Float::unpack(source, format);
+    let class = match value.class {
+        Class::Nan if value.is_signaling() => 1,
+        Class::Nan => 0,
+        Class::Zero => 2,
+        Class::Infinity if value.sign => 4,
+        Class::Infinity => 5,
+        _ if value.sign => 6,
+        _ if value == Float::one() => 3,
+        _ => 7,
+    };
+    let signals = |bit: u32| immediate >> bit & 1 != 0;
+    if (class == 2 && signals(0)) || (class == 3 && signals(2)) {
+        context.raise(softfloat::DIVIDE_BY_ZERO);
+    }
+    let invalid = match class {
+        1 => signals(4),
+        2 => signals(1),
+        3 => signals(3),
+        4 => signals(5),
+        5 => signals(7),
+        6 => signals(6),
+        _ => false,
+    };
+    if invalid {
+        context.raise(softfloat::INVALID);
+    }
+
+    let mut scratch = Context::default();
+    let constant = |value: Float, scratch: &mut Context| value.pack(format, format.precision(), scratch);
+    let (sign_bit, largest) = (format.zero(true), format.infinity(false) - 1);
+    match table >> (4 * class) & 15 {
+        0 => destination,
+        1 => source,
+        2 => source | (format.default_nan() & !sign_bit),
+        3 => format.default_nan(),
+        4 => format.infinity(true),
+        5 => format.infinity(false),
+        6 => format.infinity(value.sign),
+        7 => format.zero(true),
+        8 => format.zero(false),
+        9 => constant(Float::one().negate(), &mut scratch),
+        10 => constant(Float::one(), &mut scratch),
+        11 => constant(Float::one().scale(-1), &mut scratch),
+        12 => constant(Float::from_i64(90), &mut scratch),
+        13 if format == SINGLE => 0x3fc9_0fdb,
+        13 => 0x3ff9_21fb_5444_2d18,
+        14 => largest,
+        _ => largest | sign_bit,
+    }
+}
+
+/// Compares two elements with a predicate: equal, less, less or equal, unordered, and their negations. Predicates 8 to
+/// 15 give the opposite result for unordered operands, and predicates 16 to 31 are those below 16 which signal quiet
+/// NaNs when those do not, and conversely.
+fn compare(predicate: u8, x: u128, y: u128, format: Format, daz: bool, context: &mut Context) -> u128 {
+    let signaling = matches!(predicate & 15, 1 | 2 | 5 | 6 | 9 | 10 | 13 | 14) != (predicate & 16 != 0);
+    let ordering = with_operands([x, y], format, daz, context, |[a, b], context| {
+        let ordering = a.compare(b);
+        if a.is_signaling() || b.is_signaling() || (signaling && ordering.is_none()) {
+            context.raise(softfloat::INVALID);
+        }
+        ordering
+    });
+    let less_or_equal = matches!(ordering, Some(Ordering::Less | Ordering::Equal));
+    let result = match predicate & 7 {
+        0 => ordering == Some(Ordering::Equal),
+        1 => ordering == Some(Ordering::Less),
+        2 => less_or_equal,
+        3 => ordering.is_none(),
+        4 => ordering != Some(Ordering::Equal),
+        5 => ordering != Some(Ordering::Less),
+        6 => !less_or_equal,
+        _ => ordering.is_some(),
+    } != (ordering.is_none() && predicate & 8 != 0);
+    match result {
+        true => u128::MAX,
+        false => 0,
+    }
+}
+
+/// Rounds an element to an integral value, or to `fraction_bits` fraction bits for vrndscale, in the mode of bits 0
+/// and 1 of the immediate or in that of MXCSR when bit 2 is set. Bit 3 suppresses the precision exception, and
+/// denormals do not raise the denormal exception.
+fn round(element: u128, format: Format, immediate: u8, fraction_bits: i32, daz: bool, context: &mut Context) -> u128 {
+    let rounding = if immediate & 4 != 0 { context.rounding } else { Rounding::from_bits(immediate as u32) };
+    let flags = context.flags;
+    let result = with_operands([element], format, daz, context, |[value], context| {
+        let rounded = value.scale(fraction_bits).round_to_integral(rounding, context).scale(-fraction_bits);
+        rounded.pack(format, format.precision(), context)
+    });
+    let suppressed = softfloat::DENORMAL | softfloat::UNDERFLOW | if immediate & 8 != 0 { softfloat::INEXACT } else { 0 };
+    context.flags = flags | context.flags & !suppressed;
+    result
+}
+
+/// Converts a floating-point element to a signed integer of `bits` bits, rounding in the mode of MXCSR or towards
+/// zero. NaNs and values out of range are the integer indefinite value, the most negative integer. Denormals do not
+/// raise the denormal exception.
+fn to_integer(element: u128, format: Format, bits: u32, truncate: bool, daz: bool, context: &mut Context) -> i64 {
+    let rounding = if truncate { Rounding::Zero } else { context.rounding };
+    let flags = context.flags;
+    let value = with_operands([element], format, daz, context, |[value], context| value.to_integer(bits, rounding, context));
+    context.flags = flags | context.flags & !softfloat::DENORMAL;
+    value.unwrap_or(i64::MIN >> (64 - bits))
+}
+
+/// Converts a floating-point element to an unsigned integer of `bits` bits like [`to_integer`], the integer indefinite
+/// value being all ones
+fn to_unsigned(element: u128, format: Format, bits: u32, truncate: bool, daz: bool, context: &mut Context) -> u64 {
+    let rounding = if truncate { Rounding::Zero } else { context.rounding };
+    let flags = context.flags;
+    let value = with_operands([element], format, daz, context, |[value], context| value.to_unsigned(bits, rounding, context));
+    context.flags = flags | context.flags & !softfloat::DENORMAL;
+    value.unwrap_or(u64::MAX >> (64 - bits))
+}
+
+fn convert(bits: u128, from: Element, to: Element, truncate: bool, daz: bool, context: &mut Context) -> u128 {
+    match (from, to) {
+        (Element::Signed(width), Element::Float(format)) => Float::from_i64(signed(bits as u64, width)).pack(format, format.precision(), context),
+        (Element::Unsigned(_), Element::Float(format)) => Float::from_u64(bits as u64).pack(format, format.precision(), context),
+        (Element::Float(format), Element::Signed(width)) => to_integer(bits, format, width, truncate, daz, context) as u64 as u128,
+        (Element::Float(format), Element::Unsigned(width)) => to_unsigned(bits, format, width, truncate, daz, context) as u128,
+        // half-precision elements are converted exactly, their denormals are neither zeros with DAZ nor denormal operands
+        (Element::Float(HALF), Element::Float(format)) => Float::unpack(bits, HALF).propagate(context).pack(format, format.precision(), context),
+        (Element::Float(from), Element::Float(format)) => {
+            with_operands([bits], from, daz, context, |[value], context| value.propagate(context).pack(format, format.precision(), context))
+        }
+        _ => unreachable!("there are no conversions between integer elements"),
+    }
+}
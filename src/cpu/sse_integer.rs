@@ -0,0 +1,352 @@
+use super::error::Error;
+use super::flags::{AF, CF, OF, PF, SF, ZF};
+use super::sse::LANES;
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic};
+
+impl Cpu {
+    /// Executes the packed integer instructions of SSE2 to SSE4.2, the bitwise, shuffle, unpack and blend instructions
+    /// and ptest
+    pub fn sse_integer(&mut self, instruction: &Instruction, mnemonic: Mnemonic) -> Result<(), Error> {
+        // ZF is set when no bit is set in both operands, CF when all the bits set in the source are set in the destination
+        if mnemonic == Mnemonic::Ptest {
+            let (a, b) = (self.read_vector_operand(instruction, 0)?, self.read_vector_operand(instruction, 1)?);
+            let (and, and_not) = (0..LANES).fold((0, 0), |(and, and_not), lane| (and | (a[lane] & b[lane]), and_not | (!a[lane] & b[lane])));
+            let zero = |value: u128, flag: u64| if value == 0 { flag } else { 0 };
+            self.update_flags(zero(and, ZF) | zero(and_not, CF), ZF | CF | OF | SF | AF | PF);
+            return Ok(());
+        }
+
+        let result = self.map_lanes(instruction, mnemonic, |a, b, c| integer_operation(mnemonic, a, b, c))?;
+        self.write_vector_operand(instruction, 0, result)
+    }
+}
+
+/// Result of a packed integer, bitwise, shuffle, unpack or blend instruction with the destination `a`, the source `b`,
+/// which is the shift count for the shifts by an immediate, and the immediate or the mask of a variable blend `c`
+pub fn integer_operation(mnemonic: Mnemonic, a: u128, b: u128, c: u128) -> Option<u128> {
+    let immediate = c as u8;
+    Some(match mnemonic {
+        Mnemonic::Pand | Mnemonic::Andps | Mnemonic::Andpd => a & b,
+        Mnemonic::Pandn | Mnemonic::Andnps | Mnemonic::Andnpd => !a & b,
+        Mnemonic::Por | Mnemonic::Orps | Mnemonic::Orpd => a | b,
+        Mnemonic::Pxor | Mnemonic::Xorps | Mnemonic::Xorpd => a ^ b,
+
+        Mnemonic::Paddb => map(a, b, 8, u64::wrapping_add),
+        Mnemonic::Paddw => map(a, b, 16, u64::wrapping_add),
+        Mnemonic::Paddd => map(a, b, 32, u64::wrapping_add),
+        Mnemonic::Paddq => map(a, b, 64, u64::wrapping_add),
+        Mnemonic::Psubb => map(a, b, 8, u64::wrapping_sub),
+        Mnemonic::Psubw => map(a, b, 16, u64::wrapping_sub),
+        Mnemonic::Psubd => map(a, b, 32, u64::wrapping_sub),
+        Mnemonic::Psubq => map(a, b, 64, u64::wrapping_sub),
+        Mnemonic::Paddsb => map(a, b, 8, |x, y| saturate_signed(signed(x, 8) + signed(y, 8), 8)),
+        Mnemonic::Paddsw => map(a, b, 16, |x, y| saturate_signed(signed(x, 16) + signed(y, 16), 16)),
+        Mnemonic::Psubsb => map(a, b, 8, |x, y| saturate_signed(signed(x, 8) - signed(y, 8), 8)),
+        Mnemonic::Psubsw => map(a, b, 16, |x, y| saturate_signed(signed(x, 16) - signed(y, 16), 16)),
+        Mnemonic::Paddusb => map(a, b, 8, |x, y| saturate_unsigned(x as i64 + y as i64, 8)),
+        Mnemonic::Paddusw => map(a, b, 16, |x, y| saturate_unsigned(x as i64 + y as i64, 16)),
+        Mnemonic::Psubusb => map(a, b, 8, |x, y| saturate_unsigned(x as i64 - y as i64, 8)),
+        Mnemonic::Psubusw => map(a, b, 16, |x, y| saturate_unsigned(x as i64 - y as i64, 16)),
+
+        Mnemonic::Pmullw => map(a, b, 16, u64::wrapping_mul),
+        Mnemonic::Pmulhw => map(a, b, 16, |x, y| ((signed(x, 16) * signed(y, 16)) >> 16) as u64),
+        Mnemonic::Pmulhuw => map(a, b, 16, |x, y| (x * y) >> 16),
+        Mnemonic::Pmuludq => map(a, b, 64, |x, y| (x & 0xffff_ffff) * (y & 0xffff_ffff)),
+        // sums of the products of adjacent signed words
+        Mnemonic::Pmaddwd => vector(32, |index| {
+            let product = |word| signed(element(a, 16, word), 16) * signed(element(b, 16, word), 16);
+            (product(2 * index) + product(2 * index + 1)) as u64
+        }),
+        // sums of the absolute differences of the bytes of each quadword
+        Mnemonic::Psadbw => vector(64, |index| {
+            (8 * index..8 * index + 8).map(|byte| element(a, 8, byte).abs_diff(element(b, 8, byte))).sum()
+        }),
+        Mnemonic::Pavgb => map(a, b, 8, |x, y| (x + y + 1) >> 1),
+        Mnemonic::Pavgw => map(a, b, 16, |x, y| (x + y + 1) >> 1),
+        Mnemonic::Pminub => map(a, b, 8, u64::min),
+        Mnemonic::Pminuw => map(a, b, 16, u64::min),
+        Mnemonic::Pminud => map(a, b, 32, u64::min),
+        Mnemonic::Pmaxub => map(a, b, 8, u64::max),
+        Mnemonic::Pmaxuw => map(a, b, 16, u64::max),
+        Mnemonic::Pmaxud => map(a, b, 32, u64::max),
+        Mnemonic::Pminsb => map(a, b, 8, |x, y| if signed(x, 8) < signed(y, 8) { x } else { y }),
+        Mnemonic::Pminsw => map(a, b, 16, |x, y| if signed(x, 16) < signed(y, 16) { x } else { y }),
+        Mnemonic::Pminsd => map(a, b, 32, |x, y| if signed(x, 32) < signed(y, 32) { x } else { y }),
+        Mnemonic::Pmaxsb => map(a, b, 8, |x, y| if signed(x, 8) > signed(y, 8) { x } else { y }),
+        Mnemonic::Pmaxsw => map(a, b, 16, |x, y| if signed(x, 16) > signed(y, 16) { x } else { y }),
+        Mnemonic::Pmaxsd => map(a, b, 32, |x, y| if signed(x, 32) > signed(y, 32) { x } else { y }),
+        Mnemonic::Vpminuq => map(a, b, 64, u64::min),
+        Mnemonic::Vpmaxuq => map(a, b, 64, u64::max),
+        Mnemonic::Vpminsq => map(a, b, 64, |x, y| if (x as i64) < (y as i64) { x } else { y }),
+        Mnemonic::Vpmaxsq => map(a, b, 64, |x, y| if (x as i64) > (y as i64) { x } else { y }),
+        // the lowest unsigned word and its index in bits 16 to 18
+        Mnemonic::Phminposuw => {
+            let (index, minimum) = (0..8).map(|index| (index, element(b, 16, index))).min_by_key(|&(index, word)| (word, index)).unwrap();
+            minimum as u128 | (index as u128) << 16
+        }
+        // sums of the absolute differences of four bytes of the source, selected by bits 0 and 1 of the immediate,
+        // with eleven overlapping blocks of the destination, starting at the byte selected by bit 2
+        Mnemonic::Mpsadbw => {
+            let (a_offset, b_offset) = (4 * (immediate as u32 >> 2 & 1), 4 * (immediate as u32 & 3));
+            vector(16, |index| (0..4).map(|byte| element(a, 8, a_offset + index + byte).abs_diff(element(b, 8, b_offset + byte))).sum())
+        }
+
+        Mnemonic::Pmulld => map(a, b, 32, u64::wrapping_mul),
+        Mnemonic::Vpmullq => map(a, b, 64, u64::wrapping_mul),
+        Mnemonic::Pmuldq => map(a, b, 64, |x, y| (signed(x & 0xffff_ffff, 32) * signed(y & 0xffff_ffff, 32)) as u64),
+        Mnemonic::Pmulhrsw => map(a, b, 16, |x, y| ((((signed(x, 16) * signed(y, 16)) >> 14) + 1) >> 1) as u64),
+        // unsigned bytes of the destination times signed bytes of the source, adjacent products summed with saturation
+        Mnemonic::Pmaddubsw => vector(16, |index| {
+            let product = |byte| element(a, 8, byte) as i64 * signed(element(b, 8, byte), 8);
+            saturate_signed(product(2 * index) + product(2 * index + 1), 16)
+        }),
+
+        // sums or differences of adjacent elements, those of the destination in the low half of the result
+        Mnemonic::Phaddw => horizontal(a, b, 16, u64::wrapping_add),
+        Mnemonic::Phaddd => horizontal(a, b, 32, u64::wrapping_add),
+        Mnemonic::Phaddsw => horizontal(a, b, 16, |x, y| saturate_signed(signed(x, 16) + signed(y, 16), 16)),
+        Mnemonic::Phsubw => horizontal(a, b, 16, u64::wrapping_sub),
+        Mnemonic::Phsubd => horizontal(a, b, 32, u64::wrapping_sub),
+        Mnemonic::Phsubsw => horizontal(a, b, 16, |x, y| saturate_signed(signed(x, 16) - signed(y, 16), 16)),
+
+        Mnemonic::Pabsb => map(b, 0, 8, |x, _| signed(x, 8).unsigned_abs()),
+        Mnemonic::Pabsw => map(b, 0, 16, |x, _| signed(x, 16).unsigned_abs()),
+        Mnemonic::Pabsd => map(b, 0, 32, |x, _| signed(x, 32).unsigned_abs()),
+        Mnemonic::Vpabsq => map(b, 0, 64, |x, _| (x as i64).unsigned_abs()),
+        Mnemonic::Vplzcntd => map(b, 0, 32, |x, _| (x as u32).leading_zeros() as u64),
+        Mnemonic::Vplzcntq => map(b, 0, 64, |x, _| x.leading_zeros() as u64),
+        // the elements of the destination negated, zeroed or kept by the sign of those of the source
+        Mnemonic::Psignb | Mnemonic::Psignw | Mnemonic::Psignd => {
+            let bits = match mnemonic {
+                Mnemonic::Psignb => 8,
+                Mnemonic::Psignw => 16,
+                _ => 32,
+            };
+            map(a, b, bits, |x, y| match signed(y, bits).signum() {
+                -1 => x.wrapping_neg(),
+                0 => 0,
+                _ => x,
+            })
+        }
+
+        Mnemonic::Pcmpeqb => map(a, b, 8, |x, y| if x == y { u64::MAX } else { 0 }),
+        Mnemonic::Pcmpeqw => map(a, b, 16, |x, y| if x == y { u64::MAX } else { 0 }),
+        Mnemonic::Pcmpeqd => map(a, b, 32, |x, y| if x == y { u64::MAX } else { 0 }),
+        Mnemonic::Pcmpeqq => map(a, b, 64, |x, y| if x == y { u64::MAX } else { 0 }),
+        Mnemonic::Pcmpgtb => map(a, b, 8, |x, y| if signed(x, 8) > signed(y, 8) { u64::MAX } else { 0 }),
+        Mnemonic::Pcmpgtw => map(a, b, 16, |x, y| if signed(x, 16) > signed(y, 16) { u64::MAX } else { 0 }),
+        Mnemonic::Pcmpgtd => map(a, b, 32, |x, y| if signed(x, 32) > signed(y, 32) { u64::MAX } else { 0 }),
+        Mnemonic::Pcmpgtq => map(a, b, 64, |x, y| if (x as i64) > (y as i64) { u64::MAX } else { 0 }),
+        // predicates of the immediate on signed or unsigned elements: equal, less, less or equal, false, and their
+        // negations
+        Mnemonic::Vpcmpb | Mnemonic::Vpcmpw | Mnemonic::Vpcmpd | Mnemonic::Vpcmpq | Mnemonic::Vpcmpub | Mnemonic::Vpcmpuw
+        | Mnemonic::Vpcmpud | Mnemonic::Vpcmpuq => {
+            let (bits, unsigned) = match mnemonic {
+                Mnemonic::Vpcmpb => (8, false),
+                Mnemonic::Vpcmpw => (16, false),
+                Mnemonic::Vpcmpd => (32, false),
+                Mnemonic::Vpcmpq => (64, false),
+                Mnemonic::Vpcmpub => (8, true),
+                Mnemonic::Vpcmpuw => (16, true),
+                Mnemonic::Vpcmpud => (32, true),
+                _ => (64, true),
+            };
+            map(a, b, bits, |x, y| {
+                let ordering = match unsigned {
+                    true => x.cmp(&y),
+                    false => signed(x, bits).cmp(&signed(y, bits)),
+                };
+                let result = match immediate & 3 {
+                    0 => ordering.is_eq(),
+                    1 => ordering.is_lt(),
+                    2 => ordering.is_le(),
+                    _ => false,
+                } != (immediate & 4 != 0);
+                if result { u64::MAX } else { 0 }
+            })
+        }
+        // whether the AND of the elements is not zero, or zero for vptestnm
+        Mnemonic::Vptestmb => map(a, b, 8, |x, y| if x & y != 0 { u64::MAX } else { 0 }),
+        Mnemonic::Vptestmw => map(a, b, 16, |x, y| if x & y != 0 { u64::MAX } else { 0 }),
+        Mnemonic::Vptestmd => map(a, b, 32, |x, y| if x & y != 0 { u64::MAX } else { 0 }),
+        Mnemonic::Vptestmq => map(a, b, 64, |x, y| if x & y != 0 { u64::MAX } else { 0 }),
+        Mnemonic::Vptestnmb => map(a, b, 8, |x, y| if x & y == 0 { u64::MAX } else { 0 }),
+        Mnemonic::Vptestnmw => map(a, b, 16, |x, y| if x & y == 0 { u64::MAX } else { 0 }),
+        Mnemonic::Vptestnmd => map(a, b, 32, |x, y| if x & y == 0 { u64::MAX } else { 0 }),
+        Mnemonic::Vptestnmq => map(a, b, 64, |x, y| if x & y == 0 { u64::MAX } else { 0 }),
+
+        // the count is the low quadword of the source, logical shifts by the element size or more give zeros and
+        // arithmetic ones fill the elements with their sign
+        Mnemonic::Psllw | Mnemonic::Pslld | Mnemonic::Psllq | Mnemonic::Psrlw | Mnemonic::Psrld | Mnemonic::Psrlq
+        | Mnemonic::Psraw | Mnemonic::Psrad | Mnemonic::Vpsraq => {
+            let count = b as u64;
+            let bits = match mnemonic {
+                Mnemonic::Psllw | Mnemonic::Psrlw | Mnemonic::Psraw => 16,
+                Mnemonic::Pslld | Mnemonic::Psrld | Mnemonic::Psrad => 32,
+                _ => 64,
+            };
+            match mnemonic {
+                Mnemonic::Psraw | Mnemonic::Psrad | Mnemonic::Vpsraq => map(a, 0, bits, |x, _| (signed(x, bits) >> count.min(bits as u64 - 1)) as u64),
+                _ if count >= bits as u64 => 0,
+                Mnemonic::Psllw | Mnemonic::Pslld | Mnemonic::Psllq => map(a, 0, bits, |x, _| x << count),
+                _ => map(a, 0, bits, |x, _| x >> count),
+            }
+        }
+        // rotations by the immediate, modulo the element size, or by the elements of the second source
+        Mnemonic::Vprold => map(a, 0, 32, |x, _| (x as u32).rotate_left(b as u32) as u64),
+        Mnemonic::Vprolq => map(a, 0, 64, |x, _| x.rotate_left(b as u32)),
+        Mnemonic::Vprord => map(a, 0, 32, |x, _| (x as u32).rotate_right(b as u32) as u64),
+        Mnemonic::Vprorq => map(a, 0, 64, |x, _| x.rotate_right(b as u32)),
+        Mnemonic::Vprolvd => map(a, b, 32, |x, y| (x as u32).rotate_left(y as u32) as u64),
+        Mnemonic::Vprolvq => map(a, b, 64, |x, y| x.rotate_left(y as u32)),
+        Mnemonic::Vprorvd => map(a, b, 32, |x, y| (x as u32).rotate_right(y as u32) as u64),
+        Mnemonic::Vprorvq => map(a, b, 64, |x, y| x.rotate_right(y as u32)),
+        // shifts of the whole register by bytes
+        Mnemonic::Pslldq | Mnemonic::Psrldq if b > 15 => 0,
+        Mnemonic::Pslldq => a << (8 * b),
+        Mnemonic::Psrldq => a >> (8 * b),
+        // the bytes of the concatenation of the destination and the source, from the byte given by the immediate
+        Mnemonic::Palignr => match immediate as u32 {
+            0 => b,
+            shift @ 1..=15 => (b >> (8 * shift)) | (a << (128 - 8 * shift)),
+            shift @ 16..=31 => a >> (8 * (shift - 16)),
+            _ => 0,
+        },
+
+        // sums of the absolute differences of the bytes of the first source with those of the doublewords of the
+        // second selected by the immediate: the words of each quadword compare the low, then the high four bytes of
+        // the first source with four bytes of the selection starting one byte further for each word
+        Mnemonic::Vdbpsadbw => {
+            let selection = vector(32, |index| element(b, 32, select(immediate, 2, index)));
+            vector(16, |index| {
+                let (quadword, word) = (8 * (index / 4), index % 4);
+                (0..4).map(|byte| element(a, 8, quadword + 4 * (word / 2) + byte).abs_diff(element(selection, 8, quadword + word + byte))).sum()
+            })
+        }
+
+        // the elements of the destination in the low half of the result, those of the source in the high half
+        Mnemonic::Packsswb => vector(8, |index| saturate_signed(signed(element(if index < 8 { a } else { b }, 16, index % 8), 16), 8)),
+        Mnemonic::Packssdw => vector(16, |index| saturate_signed(signed(element(if index < 4 { a } else { b }, 32, index % 4), 32), 16)),
+        Mnemonic::Packuswb => vector(8, |index| saturate_unsigned(signed(element(if index < 8 { a } else { b }, 16, index % 8), 16), 8)),
+        Mnemonic::Packusdw => vector(16, |index| saturate_unsigned(signed(element(if index < 4 { a } else { b }, 32, index % 4), 32), 16)),
+
+        // the low elements of the source sign or zero-extended
+        Mnemonic::Pmovsxbw => extend(b, 8, 16, true),
+        Mnemonic::Pmovsxbd => extend(b, 8, 32, true),
+        Mnemonic::Pmovsxbq => extend(b, 8, 64, true),
+        Mnemonic::Pmovsxwd => extend(b, 16, 32, true),
+        Mnemonic::Pmovsxwq => extend(b, 16, 64, true),
+        Mnemonic::Pmovsxdq => extend(b, 32, 64, true),
+        Mnemonic::Pmovzxbw => extend(b, 8, 16, false),
+        Mnemonic::Pmovzxbd => extend(b, 8, 32, false),
+        Mnemonic::Pmovzxbq => extend(b, 8, 64, false),
+        Mnemonic::Pmovzxwd => extend(b, 16, 32, false),
+        Mnemonic::Pmovzxwq => extend(b, 16, 64, false),
+        Mnemonic::Pmovzxdq => extend(b, 32, 64, false),
+
+        // interleaves the elements of the low or high halves
+        Mnemonic::Punpcklbw => interleave(a, b, 8, false),
+        Mnemonic::Punpcklwd => interleave(a, b, 16, false),
+        Mnemonic::Punpckldq | Mnemonic::Unpcklps => interleave(a, b, 32, false),
+        Mnemonic::Punpcklqdq | Mnemonic::Unpcklpd => interleave(a, b, 64, false),
+        Mnemonic::Punpckhbw => interleave(a, b, 8, true),
+        Mnemonic::Punpckhwd => interleave(a, b, 16, true),
+        Mnemonic::Punpckhdq | Mnemonic::Unpckhps => interleave(a, b, 32, true),
+        Mnemonic::Punpckhqdq | Mnemonic::Unpckhpd => interleave(a, b, 64, true),
+
+        // elements selected by fields of the immediate
+        Mnemonic::Pshufd => vector(32, |index| element(b, 32, select(immediate, 2, index))),
+        Mnemonic::Pshuflw => vector(16, |index| match index {
+            0..=3 => element(b, 16, select(immediate, 2, index)),
+            _ => element(b, 16, index),
+        }),
+        Mnemonic::Pshufhw => vector(16, |index| match index {
+            0..=3 => element(b, 16, index),
+            _ => element(b, 16, 4 + select(immediate, 2, index - 4)),
+        }),
+        Mnemonic::Shufps => vector(32, |index| element(if index < 2 { a } else { b }, 32, select(immediate, 2, index))),
+        Mnemonic::Shufpd => vector(64, |index| element(if index < 1 { a } else { b }, 64, select(immediate, 1, index))),
+        // bytes of the destination selected by the low bits of those of the source, or zeros for a set sign bit
+        Mnemonic::Pshufb => vector(8, |index| match element(b, 8, index) {
+            selector if selector & 0x80 != 0 => 0,
+            selector => element(a, 8, selector as u32 & 15),
+        }),
+
+        // elements of the source where the bits of the immediate or the sign bits of the mask are set
+        Mnemonic::Pblendw => blend(a, b, 16, |index| immediate >> index & 1 != 0),
+        Mnemonic::Blendps => blend(a, b, 32, |index| immediate >> index & 1 != 0),
+        Mnemonic::Blendpd => blend(a, b, 64, |index| immediate >> index & 1 != 0),
+        Mnemonic::Pblendvb => blend(a, b, 8, |index| element(c, 8, index) >> 7 != 0),
+        Mnemonic::Blendvps => blend(a, b, 32, |index| element(c, 32, index) >> 31 != 0),
+        Mnemonic::Blendvpd => blend(a, b, 64, |index| element(c, 64, index) >> 63 != 0),
+
+        _ => return None,
+    })
+}
+
+fn mask(bits: u32) -> u64 {
+    u64::MAX >> (64 - bits)
+}
+
+/// The `bits`-bit element `index` of a vector
+pub fn element(value: u128, bits: u32, index: u32) -> u64 {
+    (value >> (bits * index)) as u64 & mask(bits)
+}
+
+/// Sign-extends a `bits`-bit element
+pub fn signed(value: u64, bits: u32) -> i64 {
+    ((value << (64 - bits)) as i64) >> (64 - bits)
+}
+
+/// The vector of the `bits`-bit elements computed for each index
+pub fn vector(bits: u32, mut element: impl FnMut(u32) -> u64) -> u128 {
+    (0..128 / bits).fold(0, |value, index| value | ((element(index) & mask(bits)) as u128) << (bits * index))
+}
+
+/// Applies `operation` to the pairs of `bits`-bit elements of `a` and `b`
+fn map(a: u128, b: u128, bits: u32, operation: impl Fn(u64, u64) -> u64) -> u128 {
+    vector(bits, |index| operation(element(a, bits, index), element(b, bits, index)))
+}
+
+fn saturate_signed(value: i64, bits: u32) -> u64 {
+    value.clamp(-(1 << (bits - 1)), (1 << (bits - 1)) - 1) as u64
+}
+
+fn saturate_unsigned(value: i64, bits: u32) -> u64 {
+    value.clamp(0, mask(bits) as i64) as u64
+}
+
+/// Interleaves the `bits`-bit elements of the low or high halves of `a` and `b`, starting with `a`
+fn interleave(a: u128, b: u128, bits: u32, high: bool) -> u128 {
+    let half = if high { 64 / bits } else { 0 };
+    vector(bits, |index| element(if index % 2 == 0 { a } else { b }, bits, half + index / 2))
+}
+
+/// Applies `operation` to the pairs of adjacent `bits`-bit elements of `a`, then of `b`
+fn horizontal(a: u128, b: u128, bits: u32, operation: impl Fn(u64, u64) -> u64) -> u128 {
+    let half = 64 / bits;
+    vector(bits, |index| {
+        let (source, pair) = if index < half { (a, index) } else { (b, index - half) };
+        operation(element(source, bits, 2 * pair), element(source, bits, 2 * pair + 1))
+    })
+}
+
+/// The elements of `b` for the indexes selected, those of `a` for the others
+fn blend(a: u128, b: u128, bits: u32, selected: impl Fn(u32) -> bool) -> u128 {
+    vector(bits, |index| element(if selected(index) { b } else { a }, bits, index))
+}
+
+/// Extends the low `from`-bit elements of a vector to `to` bits
+fn extend(value: u128, from: u32, to: u32, sign: bool) -> u128 {
+    vector(to, |index| match sign {
+        true => signed(element(value, from, index), from) as u64,
+        false => element(value, from, index),
+    })
+}
+
+/// The `bits`-bit field `index` of an immediate
+fn select(immediate: u8, bits: u32, index: u32) -> u32 {
+    (immediate as u32 >> (bits * index)) & ((1 << bits) - 1)
+}
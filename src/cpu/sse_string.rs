@@ -0,0 +1,117 @@
+use super::error::Error;
+use super::flags::{AF, CF, OF, PF, SF, ZF};
+use super::sse::xmm_vector;
+use super::sse_integer::{element, signed, vector};
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic, Register};
+
+/// Comparison of the elements of the two strings, bits 2 and 3 of the immediate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregation {
+    /// whether each element of the second string is equal to any of the first
+    EqualAny,
+    /// whether each element of the second string is in any of the ranges given by pairs of the first
+    Ranges,
+    /// whether the elements at the same index are equal
+    EqualEach,
+    /// whether the first string is found at each index of the second
+    EqualOrdered,
+}
+
+impl Cpu {
+    /// Executes pcmpestri, pcmpestrm, pcmpistri and pcmpistrm, which compare strings of bytes or words. The lengths
+    /// are the absolute values of rax and rdx for the explicit forms, the strings otherwise end at their first null
+    /// element. The index forms write the lowest or highest index of the result in ecx, the mask forms write it to
+    /// XMM0 as bits or as elements.
+    pub fn sse_string(&mut self, instruction: &Instruction, mnemonic: Mnemonic) -> Result<(), Error> {
+        let (a, b) = (self.read_xmm_operand(instruction, 0)?, self.read_xmm_operand(instruction, 1)?);
+        let immediate = instruction.immediate8();
+        let count = if immediate & 1 == 0 { 16 } else { 8 };
+        let bits = 128 / count;
+
+        let explicit_length = |value: i64| value.unsigned_abs().min(count as u64) as u32;
+        let implicit_length = |value: u128| (0..count).find(|&index| element(value, bits, index) == 0).unwrap_or(count);
+        let (length_a, length_b) = match mnemonic {
+            Mnemonic::Pcmpestri | Mnemonic::Pcmpestrm => (
+                explicit_length(self.get_register_u64(Register::EAX)? as i32 as i64),
+                explicit_length(self.get_register_u64(Register::EDX)? as i32 as i64),
+            ),
+            Mnemonic::Pcmpestri64 | Mnemonic::Pcmpestrm64 => (
+                explicit_length(self.get_register_u64(Register::RAX)? as i64),
+                explicit_length(self.get_register_u64(Register::RDX)? as i64),
+            ),
+            _ => (implicit_length(a), implicit_length(b)),
+        };
+
+        let aggregation = match immediate >> 2 & 3 {
+            0 => Aggregation::EqualAny,
+            1 => Aggregation::Ranges,
+            2 => Aggregation::EqualEach,
+            _ => Aggregation::EqualOrdered,
+        };
+        let result = compare_strings(a, b, length_a, length_b, count, immediate & 2 != 0, aggregation);
+
+        // negated, or negated only for the elements of the second string
+        let result = match immediate >> 4 & 3 {
+            1 => !result & ((1 << count) - 1),
+            3 => result ^ ((1 << length_b) - 1),
+            _ => result,
+        };
+
+        match mnemonic {
+            Mnemonic::Pcmpestri | Mnemonic::Pcmpestri64 | Mnemonic::Pcmpistri => {
+                let index = match (result, immediate & 0x40 != 0) {
+                    (0, _) => count,
+                    (result, true) => 31 - result.leading_zeros(),
+                    (result, false) => result.trailing_zeros(),
+                };
+                self.set_register(Register::ECX, index as u64)?;
+            }
+            _ => {
+                let mask = match immediate & 0x40 != 0 {
+                    true => vector(bits, |index| if result >> index & 1 != 0 { u64::MAX } else { 0 }),
+                    false => result as u128,
+                };
+                self.write_vector_register(instruction, Register::XMM0, xmm_vector(mask));
+            }
+        }
+
+        let flag = |flag: u64, set: bool| if set { flag } else { 0 };
+        let flags = flag(CF, result != 0) | flag(ZF, length_b < count) | flag(SF, length_a < count) | flag(OF, result & 1 != 0);
+        self.update_flags(flags, CF | ZF | SF | OF | AF | PF);
+        Ok(())
+    }
+}
+
+/// Bit mask of the comparisons of the strings `a` and `b` of `count` elements, of which the first `length_a` and
+/// `length_b` are valid
+fn compare_strings(a: u128, b: u128, length_a: u32, length_b: u32, count: u32, signed_elements: bool, aggregation: Aggregation) -> u32 {
+    let bits = 128 / count;
+    let value = |vector: u128, index: u32| match signed_elements {
+        true => signed(element(vector, bits, index), bits),
+        false => element(vector, bits, index) as i64,
+    };
+
+    let bit = |index: u32| {
+        let (valid_a, valid_b) = (index < length_a, index < length_b);
+        match aggregation {
+            Aggregation::EqualAny => valid_b && (0..length_a).any(|i| value(a, i) == value(b, index)),
+            Aggregation::Ranges => {
+                valid_b && (0..length_a.saturating_sub(1)).step_by(2).any(|i| value(a, i) <= value(b, index) && value(b, index) <= value(a, i + 1))
+            }
+            // past the end of both strings the elements are equal
+            Aggregation::EqualEach => match (valid_a, valid_b) {
+                (true, true) => value(a, index) == value(b, index),
+                (valid_a, valid_b) => !valid_a && !valid_b,
+            },
+            // the end of the first string matches anything, that of the second only the end of the first
+            Aggregation::EqualOrdered => (0..count - index).all(|i| match (i < length_a, index + i < length_b) {
+                (false, _) => true,
+                (true, false) => false,
+                (true, true) => value(a, i) == value(b, index + i),
+            }),
+        }
+    };
+    (0..count).filter(|&index| bit(index)).fold(0, |result, index| result | 1 << index)
+}
@@ -0,0 +1,106 @@
+use super::arithmetic::accumulator;
+use super::error::Error;
+use super::flags::{self, DF, ZF};
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic, OpKind, Register};
+
+impl Cpu {
+    /// Executes one iteration of a string instruction: movs, stos, lods, cmps or scas. With a rep prefix the
+    /// instruction is restarted until the counter runs out (or the repe/repne condition fails), like an interrupted
+    /// rep instruction on real hardware, so that every iteration is a separate step.
+    pub fn execute_string(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let address_size = string_address_size(instruction);
+        let (counter, source, destination) = match address_size {
+            2 => (Register::CX, Register::SI, Register::DI),
+            4 => (Register::ECX, Register::ESI, Register::EDI),
+            _ => (Register::RCX, Register::RSI, Register::RDI),
+        };
+
+        // f2 acts like f3 on the instructions which do not compare
+        let repeat = instruction.has_repe_prefix() || instruction.has_repne_prefix();
+        if repeat && self.get_register_u64(counter)? == 0 {
+            return Ok(());
+        }
+
+        let size = instruction.memory_size().size();
+        let step = match self.flag(DF) {
+            true => (size as u64).wrapping_neg(),
+            false => size as u64,
+        };
+        // only the source segment can be overridden, the destination is always es which is flat
+        let source_address = self.segment_base(instruction.memory_segment()).wrapping_add(self.get_register_u64(source)?);
+        let destination_address = self.get_register_u64(destination)?;
+
+        let (advance_source, advance_destination) = match instruction.mnemonic() {
+            Mnemonic::Movsb | Mnemonic::Movsw | Mnemonic::Movsd | Mnemonic::Movsq => {
+                let value = self.memory.read_uint(source_address, size)?;
+                self.memory.write_uint(destination_address, size, value)?;
+                (true, true)
+            }
+            Mnemonic::Stosb | Mnemonic::Stosw | Mnemonic::Stosd | Mnemonic::Stosq => {
+                let value = self.get_register_u64(accumulator(size))?;
+                self.memory.write_uint(destination_address, size, value)?;
+                (false, true)
+            }
+            Mnemonic::Lodsb | Mnemonic::Lodsw | Mnemonic::Lodsd | Mnemonic::Lodsq => {
+                let value = self.memory.read_uint(source_address, size)?;
+                self.set_register(accumulator(size), value)?;
+                (true, false)
+            }
+            Mnemonic::Cmpsb | Mnemonic::Cmpsw | Mnemonic::Cmpsd | Mnemonic::Cmpsq => {
+                let a = self.memory.read_uint(source_address, size)?;
+                let b = self.memory.read_uint(destination_address, size)?;
+                self.update_flags(flags::sub(a, b, false, size).1, flags::STATUS);
+                (true, true)
+            }
+            Mnemonic::Scasb | Mnemonic::Scasw | Mnemonic::Scasd | Mnemonic::Scasq => {
+                let a = self.get_register_u64(accumulator(size))?;
+                let b = self.memory.read_uint(destination_address, size)?;
+                self.update_flags(flags::sub(a, b, false, size).1, flags::STATUS);
+                (false, true)
+            }
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        };
+
+        if advance_source {
+            let value = self.get_register_u64(source)?.wrapping_add(step);
+            self.set_register(source, value)?;
+        }
+        if advance_destination {
+            let value = self.get_register_u64(destination)?.wrapping_add(step);
+            self.set_register(destination, value)?;
+        }
+
+        if repeat {
+            let count = self.get_register_u64(counter)?.wrapping_sub(1);
+            self.set_register(counter, count)?;
+
+            let compares = matches!(instruction.mnemonic(), Mnemonic::Cmpsb | Mnemonic::Cmpsw | Mnemonic::Cmpsd | Mnemonic::Cmpsq
+                | Mnemonic::Scasb | Mnemonic::Scasw | Mnemonic::Scasd | Mnemonic::Scasq);
+            let condition = !compares || match instruction.has_repe_prefix() {
+                true => self.flag(ZF),
+                false => !self.flag(ZF),
+            };
+            if count & flags::mask(counter.size()) != 0 && condition {
+                self.registers.rip = instruction.ip();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Address size of a string instruction, which selects rSI, rDI and rCX
+fn string_address_size(instruction: &Instruction) -> usize {
+    let kinds = (0..instruction.op_count()).map(|operand| instruction.op_kind(operand));
+    for kind in kinds {
+        match kind {
+            OpKind::MemorySegSI | OpKind::MemoryESDI => return 2,
+            OpKind::MemorySegESI | OpKind::MemoryESEDI => return 4,
+            OpKind::MemorySegRSI | OpKind::MemoryESRDI => return 8,
+            _ => {}
+        }
+    }
+    8
+}
@@ -0,0 +1,30 @@
+use super::flags::RESERVED;
+use super::Cpu;
+
+use crate::mem::{Protection, PAGE_SIZE};
+
+// Fixtures of the unit tests of the processor. The expected values in their tables were recorded on an Intel
+// x86-64 processor, by C programs executing each case with inline assembly (after fesetround or fldcw for the
+// rounding mode) and printing the destination together with RFLAGS, the x87 status word or the fenv flags.
+
+/// Address of the data page of [`cpu_with_data`]
+pub const DATA: u64 = 0x1000;
+
+/// A 64-bit processor whose status flags are `rflags`
+pub fn cpu_with_flags(rflags: u64) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.bitness = 64;
+    cpu.registers.rflags = RESERVED | rflags;
+    cpu
+}
+
+/// A 64-bit processor with the quadwords `values` stored at [`DATA`], which rbx points to
+pub fn cpu_with_data(values: &[u64]) -> Cpu {
+    let mut cpu = cpu_with_flags(0);
+    cpu.memory.map(DATA, PAGE_SIZE, Protection::READ_WRITE).unwrap();
+    for (i, &value) in values.iter().enumerate() {
+        cpu.memory.write_uint(DATA + 8 * i as u64, 8, value).unwrap();
+    }
+    cpu.registers.rbx = DATA;
+    cpu
+}
@@ -0,0 +1,288 @@
+use super::softfloat::{Class, Context, Float, Rounding};
+
+// Transcendental functions of the x87 unit, evaluated with series in the 128-bit unpacked representation and
+// rounded by the caller. The errors of the series stay around 2^-120, far below the rounding of a result to at
+// most 64 bits. The last term of a series is still added, so that its sticky bit makes the sum inexact.
+// The results are thus correctly rounded, where the hardware is only accurate to within about one unit in the last
+// place, so the last bit of a result and C1 may differ from those of a given processor.
+
+pub const PI: Float = constant(1, 0xc90f_daa2_2168_c234_c4c6_628b_80dc_1cd1);
+pub const LN_2: Float = constant(-1, 0xb172_17f7_d1cf_79ab_c9e3_b398_03f2_f6af);
+pub const LOG2_E: Float = constant(0, 0xb8aa_3b29_5c17_f0bb_be87_fed0_691d_3e88);
+pub const LOG2_10: Float = constant(1, 0xd49a_784b_cd1b_8afe_492b_f6ff_4daf_db4c);
+pub const LOG10_2: Float = constant(-2, 0x9a20_9a84_fbcf_f798_8f89_59ac_0b7c_9178);
+const SQRT_2: Float = constant(0, 0xb504_f333_f9de_6484_597d_89b3_754a_be9f);
+
+/// pi/2 with the 66 significant bits used by the argument reduction of fsin, fcos, fsincos and fptan, in units
+/// of 2^-65
+const HALF_PI_66: u128 = 0x3_243f_6a88_85a3_08d3;
+
+/// Terms smaller than the sum by this many binary orders of magnitude end a series
+const SERIES_PRECISION: i32 = 130;
+
+const fn constant(exponent: i32, significand: u128) -> Float {
+    Float { sign: false, class: Class::Normal, exponent, significand }
+}
+
+/// Context of the intermediate operations, whose exceptions are not reported
+fn scratch() -> Context {
+    Context::default()
+}
+
+fn add(a: Float, b: Float) -> Float {
+    a.add(b, &mut scratch())
+}
+
+fn sub(a: Float, b: Float) -> Float {
+    a.sub(b, &mut scratch())
+}
+
+fn mul(a: Float, b: Float) -> Float {
+    a.mul(b, &mut scratch())
+}
+
+fn div(a: Float, b: Float) -> Float {
+    a.div(b, &mut scratch())
+}
+
+fn integer(value: i64) -> Float {
+    Float::from_i64(value)
+}
+
+/// Whether `term` no longer contributes to `sum`, other than by making it inexact
+fn negligible(term: Float, sum: Float) -> bool {
+    term.class == Class::Zero || (sum.class == Class::Normal && term.exponent < sum.exponent - SERIES_PRECISION)
+}
+
+/// Reduces a finite `x` with `|x| < 2^63` by the closest multiple `n` of pi/2 (with 66 bits, like the hardware),
+/// returning `x - n * pi/2` exactly and `n` modulo 4
+fn reduce(x: Float) -> (Float, u32) {
+    let magnitude = x.abs();
+    if x.class == Class::Zero || magnitude.exponent < -1 {
+        return (x, 0);
+    }
+
+    // |x| in units of 2^-65, exact as |x| has at most 64 significant bits
+    let units = (magnitude.significand >> 64) << (magnitude.exponent + 2);
+    let (mut quotient, remainder) = (units / HALF_PI_66, units % HALF_PI_66);
+    let reduced = match remainder > HALF_PI_66 / 2 {
+        true => {
+            quotient += 1;
+            Float::new(true, HALF_PI_66 - remainder, -65)
+        }
+        false => Float::new(false, remainder, -65),
+    };
+    let reduced = if x.sign { reduced.negate() } else { reduced };
+    let quadrant = if x.sign { quotient.wrapping_neg() } else { quotient };
+    (reduced, (quadrant & 3) as u32)
+}
+
+/// Sine of `|x| <= pi/4` by its Taylor series
+fn sin_series(x: Float) -> Float {
+    let square = mul(x, x);
+    let (mut sum, mut term) = (x, x);
+    for k in 1.. {
+        term = div(mul(term, square), integer((2 * k) * (2 * k + 1))).negate();
+        sum = add(sum, term);
+        if negligible(term, sum) {
+            break;
+        }
+    }
+    sum
+}
+
+/// Cosine of `|x| <= pi/4` by its Taylor series
+fn cos_series(x: Float) -> Float {
+    let square = mul(x, x);
+    let (mut sum, mut term) = (Float::one(), Float::one());
+    for k in 1.. {
+        term = div(mul(term, square), integer((2 * k - 1) * (2 * k))).negate();
+        sum = add(sum, term);
+        if negligible(term, sum) {
+            break;
+        }
+    }
+    sum
+}
+
+/// Sine and cosine of a finite `x` with `|x| < 2^63`
+pub fn sin_cos(x: Float) -> (Float, Float) {
+    if x.class == Class::Zero {
+        return (x, Float::one());
+    }
+    let (reduced, quadrant) = reduce(x);
+    let (sin, cos) = (sin_series(reduced), cos_series(reduced));
+    match quadrant {
+        0 => (sin, cos),
+        1 => (cos, sin.negate()),
+        2 => (sin.negate(), cos.negate()),
+        _ => (cos.negate(), sin),
+    }
+}
+
+/// Arc tangent of a finite `x >= 0`
+fn atan(x: Float) -> Float {
+    if x.class == Class::Zero {
+        return x;
+    }
+    if x.compare(Float::one()) == Some(std::cmp::Ordering::Greater) {
+        return sub(PI.scale(-1), atan(div(Float::one(), x)));
+    }
+
+    // atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))), applied twice leaves |x| <= tan(pi/16)
+    let mut x = x;
+    for _ in 0..2 {
+        let root = add(Float::one(), mul(x, x)).sqrt(&mut scratch());
+        x = div(x, add(Float::one(), root));
+    }
+
+    let square = mul(x, x);
+    let (mut sum, mut power) = (x, x);
+    for k in 1.. {
+        power = mul(power, square).negate();
+        let term = div(power, integer(2 * k + 1));
+        sum = add(sum, term);
+        if negligible(term, sum) {
+            break;
+        }
+    }
+    sum.scale(2)
+}
+
+/// Angle of the point (`x`, `y`) from the positive x axis, in (-pi, pi], following the special cases of fpatan
+pub fn atan2(y: Float, x: Float) -> Float {
+    let angle = match (y.class, x.class) {
+        (Class::Zero, _) => match x.sign {
+            false => Float::zero(false),
+            true => PI,
+        },
+        (Class::Infinity, Class::Infinity) => match x.sign {
+            false => PI.scale(-2),
+            true => mul(PI, integer(3)).scale(-2),
+        },
+        (Class::Infinity, _) | (_, Class::Zero) => PI.scale(-1),
+        (_, Class::Infinity) => match x.sign {
+            false => Float::zero(false),
+            true => PI,
+        },
+        _ => {
+            let angle = atan(div(y.abs(), x.abs()));
+            match x.sign {
+                false => angle,
+                true => sub(PI, angle),
+            }
+        }
+    };
+    Float { sign: y.sign, ..angle }
+}
+
+/// Inverse hyperbolic tangent of `|x| < 1`, times two, by its series
+fn atanh_twice(x: Float) -> Float {
+    let square = mul(x, x);
+    let (mut sum, mut power) = (x, x);
+    for k in 1.. {
+        power = mul(power, square);
+        let term = div(power, integer(2 * k + 1));
+        sum = add(sum, term);
+        if negligible(term, sum) {
+            break;
+        }
+    }
+    sum.scale(1)
+}
+
+/// Binary logarithm of a finite `x > 0`
+pub fn log2(x: Float) -> Float {
+    // x = m * 2^k with m in [sqrt(2)/2, sqrt(2)), ln(m) = 2 atanh((m - 1) / (m + 1))
+    let (mut m, mut k) = (Float { exponent: 0, ..x }, x.exponent);
+    if m.significand > SQRT_2.significand {
+        m = m.scale(-1);
+        k += 1;
+    }
+    let ln = atanh_twice(div(sub(m, Float::one()), add(m, Float::one())));
+    add(integer(k as i64), mul(ln, LOG2_E))
+}
+
+/// Binary logarithm of `1 + x` for a finite `x > -1`, precise for small `x`
+pub fn log2_1p(x: Float) -> Float {
+    // ln(1 + x) = 2 atanh(x / (2 + x))
+    let ln = atanh_twice(div(x, add(integer(2), x)));
+    mul(ln, LOG2_E)
+}
+
+/// `2^x - 1` of a finite `x`, precise for small `x`
+pub fn exp2_m1(x: Float) -> Float {
+    // beyond the range of f2xm1, 2^x - 1 = 2^n (2^f - 1 + 1) - 1 with the integral part n and the fraction f
+    if x.class == Class::Normal && x.exponent >= 0 {
+        let integral = x.round_to_integral(Rounding::Zero, &mut scratch());
+        let n = integral.to_integer(32, Rounding::Zero, &mut scratch()).unwrap_or(if x.sign { i32::MIN as i64 } else { i32::MAX as i64 });
+        let power = add(exp2_m1(sub(x, integral)), Float::one()).scale(n.clamp(-(1 << 20), 1 << 20) as i32);
+        return sub(power, Float::one());
+    }
+
+    // e^t - 1 by its Taylor series, with t = x ln(2)
+    let t = mul(x, LN_2);
+    let (mut sum, mut term) = (t, t);
+    for k in 2.. {
+        term = div(mul(term, t), integer(k));
+        sum = add(sum, term);
+        if negligible(term, sum) {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::softfloat::{DOUBLE, EXTENDED};
+
+    fn double(value: f64) -> Float {
+        Float::unpack(value.to_bits() as u128, DOUBLE)
+    }
+
+    fn to_double(value: Float) -> f64 {
+        f64::from_bits(value.pack(DOUBLE, DOUBLE.precision(), &mut scratch()) as u64)
+    }
+
+    // the values loaded by fldpi, fldln2, fldl2e, fldl2t and fldlg2
+    #[test]
+    fn constants_round_to_the_loaded_values() {
+        let cases = [
+            (PI, 0x4000_c90f_daa2_2168_c235),
+            (LN_2, 0x3ffe_b172_17f7_d1cf_79ac),
+            (LOG2_E, 0x3fff_b8aa_3b29_5c17_f0bc),
+            (LOG2_10, 0x4000_d49a_784b_cd1b_8afe),
+            (LOG10_2, 0x3ffd_9a20_9a84_fbcf_f799),
+        ];
+        for (constant, loaded) in cases {
+            assert_eq!(constant.pack(EXTENDED, EXTENDED.precision(), &mut scratch()), loaded);
+        }
+    }
+
+    #[test]
+    fn functions_are_correctly_rounded_to_double() {
+        let (sin, cos) = sin_cos(double(0.5));
+        assert_eq!(to_double(sin), 0.5f64.sin());
+        assert_eq!(to_double(cos), 0.5f64.cos());
+        assert_eq!(to_double(atan2(double(3.0), double(1.0))), 3f64.atan());
+        assert_eq!(to_double(log2(double(10.0))), std::f64::consts::LOG2_10);
+        assert_eq!(to_double(log2_1p(double(0.5))), 1.5f64.log2());
+        assert_eq!(to_double(atan2(double(0.0), double(-1.0))), std::f64::consts::PI);
+        assert_eq!(to_double(atan2(double(1.0), double(1.0))), std::f64::consts::FRAC_PI_4);
+        assert_eq!(to_double(atan2(double(-1.0), double(0.0))), -std::f64::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    fn exact_results_round_to_their_value() {
+        let exact = |value: Float, expected: f64| assert_eq!(to_double(value), expected);
+        exact(log2(double(8.0)), 3.0);
+        exact(log2(double(0.25)), -2.0);
+        exact(log2_1p(double(1.0)), 1.0);
+        exact(exp2_m1(double(1.0)), 1.0);
+        exact(exp2_m1(double(-1.0)), -0.5);
+        exact(exp2_m1(double(10.0)), 1023.0);
+        exact(sin_cos(double(0.0)).1, 1.0);
+    }
+}
@@ -0,0 +1,109 @@
+use super::error::Error;
+use super::flags;
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic, OpKind, Register};
+
+impl Cpu {
+    /// Executes the data movement instructions: mov, movzx, movsx, movsxd, lea, the accumulator sign extensions
+    /// cbw, cwde, cdqe, cwd, cdq and cqo, push, pop, movbe and the nop variants
+    pub fn execute_data_transfer(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        match instruction.mnemonic() {
+            Mnemonic::Mov => {
+                self.check_segment_register_write(instruction)?;
+                self.check_system_register_access(instruction)?;
+                let value = self.read_operand(instruction, 1)?;
+                self.write_operand(instruction, 0, value)?;
+            }
+
+            Mnemonic::Movzx => {
+                let value = self.read_operand(instruction, 1)?;
+                self.write_operand(instruction, 0, value)?;
+            }
+
+            // movsxd with a 32-bit destination is a plain move
+            Mnemonic::Movsx | Mnemonic::Movsxd => {
+                let value = flags::sign_extend(self.read_operand(instruction, 1)?, self.operand_size(instruction, 1));
+                self.write_operand(instruction, 0, value)?;
+            }
+
+            // the effective address without segment base, truncated to the destination size
+            Mnemonic::Lea => {
+                let address = self.effective_address(instruction)?;
+                self.write_operand(instruction, 0, address)?;
+            }
+
+            Mnemonic::Cbw | Mnemonic::Cwde | Mnemonic::Cdqe => {
+                let (source, destination) = match instruction.mnemonic() {
+                    Mnemonic::Cbw => (Register::AL, Register::AX),
+                    Mnemonic::Cwde => (Register::AX, Register::EAX),
+                    _ => (Register::EAX, Register::RAX),
+                };
+                let value = flags::sign_extend(self.get_register_u64(source)?, source.size());
+                self.set_register(destination, value)?;
+            }
+
+            // fill rDX with the sign of rAX
+            Mnemonic::Cwd | Mnemonic::Cdq | Mnemonic::Cqo => {
+                let (source, destination) = match instruction.mnemonic() {
+                    Mnemonic::Cwd => (Register::AX, Register::DX),
+                    Mnemonic::Cdq => (Register::EAX, Register::EDX),
+                    _ => (Register::RAX, Register::RDX),
+                };
+                let negative = self.get_register_u64(source)? & flags::sign_bit(source.size()) != 0;
+                self.set_register(destination, if negative { u64::MAX } else { 0 })?;
+            }
+
+            // push rsp pushes the value before the push, segment registers are zero-extended
+            Mnemonic::Push => {
+                let size = instruction.stack_pointer_increment().unsigned_abs() as usize;
+                let value = self.read_operand(instruction, 0)?;
+                self.push_stack_value(value, size)?;
+            }
+
+            // a memory destination addressed through rsp uses the value after the pop
+            Mnemonic::Pop => {
+                self.check_segment_register_write(instruction)?;
+                let size = instruction.stack_pointer_increment() as usize;
+                let value = self.pop_stack_value(size)?;
+                self.write_operand(instruction, 0, value)?;
+            }
+
+            // a move between a register and memory reversing the order of the bytes
+            Mnemonic::Movbe => {
+                self.check_features(instruction)?;
+                let size = self.operand_size(instruction, 0);
+                let value = self.read_operand(instruction, 1)?.swap_bytes() >> (64 - 8 * size);
+                self.write_operand(instruction, 0, value)?;
+            }
+
+            // multi-byte nops do not access their memory operand
+            Mnemonic::Nop | Mnemonic::Pause | Mnemonic::Endbr32 | Mnemonic::Endbr64 => {}
+
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        }
+
+        Ok(())
+    }
+
+    /// cs can only be loaded by far control transfers
+    fn check_segment_register_write(&self, instruction: &Instruction) -> Result<(), Error> {
+        if instruction.op0_kind() == OpKind::Register && instruction.op0_register() == Register::CS {
+            return Err(Error::InvalidOpcode(instruction.ip()));
+        }
+        Ok(())
+    }
+
+    /// Control, debug and test registers are only accessible at privilege level 0
+    fn check_system_register_access(&self, instruction: &Instruction) -> Result<(), Error> {
+        let privileged = (0..instruction.op_count())
+            .filter(|&operand| instruction.op_kind(operand) == OpKind::Register)
+            .map(|operand| instruction.op_register(operand))
+            .any(|register| register.is_cr() || register.is_dr() || register.is_tr());
+        match privileged {
+            true => Err(Error::GeneralProtectionFault(instruction.ip())),
+            false => Ok(()),
+        }
+    }
+}
+
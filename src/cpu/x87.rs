@@ -0,0 +1,1235 @@
+use std::cmp::Ordering;
+
+use super::error::Error;
+use super::flags::{AF, CF, OF, PF, SF, ZF};
+use super::softfloat::{self, Class, Context, Float, Format, Rounding, DOUBLE, EXTENDED, SINGLE};
+use super::transcendental;
+use super::Cpu;
+
+use iced_x86::{Code, Instruction, MemorySize, Mnemonic, OpKind, Register};
+
+// status word, bits 0 to 5 are the exception flags of softfloat
+const STACK_FAULT: u16 = 1 << 6;
+const ERROR_SUMMARY: u16 = 1 << 7;
+const C0: u16 = 1 << 8;
+const C1: u16 = 1 << 9;
+const C2: u16 = 1 << 10;
+const C3: u16 = 1 << 14;
+const BUSY: u16 = 1 << 15;
+const TOP_SHIFT: u32 = 11;
+const EXCEPTIONS: u16 = 0x3f;
+
+// tag word entries
+const TAG_VALID: u16 = 0;
+const TAG_ZERO: u16 = 1;
+const TAG_SPECIAL: u16 = 2;
+const TAG_EMPTY: u16 = 3;
+
+/// Control word after finit: all exceptions masked, 64-bit precision, round to nearest
+const DEFAULT_CONTROL: u16 = 0x037f;
+/// Writable bits of the control word, bit 6 always reads as one
+const CONTROL_MASK: u16 = 0x1f7f;
+const CONTROL_ONE: u16 = 1 << 6;
+
+/// Exponent adjustment of results delivered with unmasked overflow and underflow exceptions
+const WRAP: i32 = 3 << 13;
+
+/// Exceptions which leave the destination unchanged when they are unmasked
+const NO_RESULT: u32 = softfloat::INVALID | softfloat::DENORMAL | softfloat::DIVIDE_BY_ZERO;
+/// Stores to memory are not performed on unmasked overflow and underflow either
+const NO_MEMORY_RESULT: u32 = NO_RESULT | softfloat::OVERFLOW | softfloat::UNDERFLOW;
+
+/// State of the x87 floating-point unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct X87 {
+    /// physical data registers in the 80-bit extended format, ST(i) is register (TOP + i) mod 8
+    pub registers: [u128; 8],
+    pub control: u16,
+    pub status: u16,
+    /// two bits per physical register: valid, zero, special or empty
+    pub tag: u16,
+    /// address of the last non-control instruction
+    pub instruction_pointer: u64,
+    /// address of the memory operand of the last instruction with an unmasked exception
+    pub data_pointer: u64,
+    /// address of the memory operand of the current instruction
+    operand_pointer: u64,
+}
+
+impl Default for X87 {
+    fn default() -> Self {
+        Self {
+            registers: [0; 8],
+            control: DEFAULT_CONTROL,
+            status: 0,
+            tag: 0xffff,
+            instruction_pointer: 0,
+            data_pointer: 0,
+            operand_pointer: 0,
+        }
+    }
+}
+
+impl X87 {
+    fn top(&self) -> usize {
+        (self.status >> TOP_SHIFT) as usize & 7
+    }
+
+    fn set_top(&mut self, top: usize) {
+        self.status = (self.status & !(7 << TOP_SHIFT)) | ((top as u16 & 7) << TOP_SHIFT);
+    }
+
+    fn physical(&self, index: usize) -> usize {
+        (self.top() + index) & 7
+    }
+
+    fn is_empty(&self, index: usize) -> bool {
+        (self.tag >> (2 * self.physical(index))) & 3 == TAG_EMPTY
+    }
+
+    /// ST(`index`)
+    fn st(&self, index: usize) -> u128 {
+        self.registers[self.physical(index)]
+    }
+
+    fn set_st(&mut self, index: usize, value: u128) {
+        let register = self.physical(index);
+        self.registers[register] = value;
+        self.set_tag(register, tag(value));
+    }
+
+    fn set_tag(&mut self, register: usize, tag: u16) {
+        self.tag = (self.tag & !(3 << (2 * register))) | (tag << (2 * register));
+    }
+
+    fn free(&mut self, index: usize) {
+        self.set_tag(self.physical(index), TAG_EMPTY);
+    }
+
+    fn push(&mut self, value: u128) {
+        self.set_top(self.top().wrapping_sub(1));
+        self.set_st(0, value);
+    }
+
+    fn pop(&mut self) {
+        self.free(0);
+        self.set_top(self.top() + 1);
+    }
+
+    /// Tag word with the tags of the non-empty registers computed from their contents
+    fn tag_word(&self) -> u16 {
+        (0..8).fold(0, |word, register| {
+            let tag = match (self.tag >> (2 * register)) & 3 {
+                TAG_EMPTY => TAG_EMPTY,
+                _ => tag(self.registers[register]),
+            };
+            word | (tag << (2 * register))
+        })
+    }
+
+    fn context(&self) -> Context {
+        let rounding = Rounding::from_bits((self.control >> 10) as u32);
+        Context::new(rounding, (self.control & EXCEPTIONS) as u32, WRAP)
+    }
+
+    /// Significand bits of the results of the basic arithmetic instructions, selected by the precision control
+    fn precision(&self) -> u32 {
+        match (self.control >> 8) & 3 {
+            0 => 24,
+            2 => 53,
+            _ => 64,
+        }
+    }
+
+    fn rounding(&self) -> Rounding {
+        Rounding::from_bits((self.control >> 10) as u32)
+    }
+
+    fn set_condition(&mut self, mask: u16, value: u16) {
+        self.status = (self.status & !mask) | (value & mask);
+    }
+
+    /// Sets or clears the error summary and busy bits, depending on whether an unmasked exception is pending
+    fn update_error_summary(&mut self) {
+        match self.status & !self.control & EXCEPTIONS {
+            0 => self.status &= !(ERROR_SUMMARY | BUSY),
+            _ => self.status |= ERROR_SUMMARY | BUSY,
+        }
+    }
+
+    /// Records the exceptions raised by an instruction, sets C1 if its result was rounded up, and returns the
+    /// raised exceptions which are unmasked
+    fn report(&mut self, context: &Context) -> u32 {
+        self.status |= context.flags as u16 & EXCEPTIONS;
+        self.set_condition(C1, if context.rounded_up { C1 } else { 0 });
+        self.update_error_summary();
+        let unmasked = context.flags & !(self.control & EXCEPTIONS) as u32;
+        if unmasked != 0 {
+            self.data_pointer = self.operand_pointer;
+        }
+        unmasked
+    }
+
+    /// Records a stack overflow or underflow, returning whether it is masked so that the instruction proceeds with
+    /// the real indefinite value
+    fn stack_fault(&mut self, overflow: bool) -> bool {
+        self.status |= softfloat::INVALID as u16 | STACK_FAULT;
+        self.set_condition(C1, if overflow { C1 } else { 0 });
+        self.update_error_summary();
+        self.control & softfloat::INVALID as u16 != 0
+    }
+
+    /// Checks that the registers ST(`indices`) are not empty, returning whether one is, or `None` if the resulting
+    /// stack underflow is unmasked and the instruction must not proceed
+    fn check_underflow(&mut self, indices: &[usize]) -> Option<bool> {
+        if indices.iter().all(|&index| !self.is_empty(index)) {
+            return Some(false);
+        }
+        self.stack_fault(false).then_some(true)
+    }
+
+    /// Checks that the register below the top of the stack is empty for a push. Returns whether the push overflowed,
+    /// which makes it push the real indefinite value, or `None` if the instruction must not proceed.
+    fn check_overflow(&mut self) -> Option<bool> {
+        match self.is_empty(7) {
+            true => Some(false),
+            false => self.stack_fault(true).then_some(true),
+        }
+    }
+
+    fn initialize(&mut self) {
+        *self = Self { registers: self.registers, ..Self::default() };
+    }
+}
+
+/// Tag of a register value
+fn tag(value: u128) -> u16 {
+    let exponent = (value >> 64) & 0x7fff;
+    let integer = value & (1 << 63) != 0;
+    match exponent {
+        0 if value & u64::MAX as u128 == 0 => TAG_ZERO,
+        0 | 0x7fff => TAG_SPECIAL,
+        _ if !integer => TAG_SPECIAL,
+        _ => TAG_VALID,
+    }
+}
+
+/// Whether an extended value has an encoding which the x87 unit rejects: pseudo-NaNs, pseudo-infinities and unnormals
+fn is_unsupported(value: u128) -> bool {
+    let exponent = (value >> 64) & 0x7fff;
+    exponent != 0 && value & (1 << 63) == 0
+}
+
+/// Unpacks an operand, raising the denormal exception for denormals and the invalid operation exception for
+/// unsupported encodings, which become the real indefinite value
+fn real(value: u128, format: Format, context: &mut Context) -> Float {
+    if format == EXTENDED && is_unsupported(value) {
+        context.raise(softfloat::INVALID);
+        return Float::default_nan();
+    }
+    if format.is_denormal(value) {
+        context.raise(softfloat::DENORMAL);
+    }
+    Float::unpack(value, format)
+}
+
+/// The operand of a conversion to an integer, which does not report denormal operands
+fn integer_operand(value: u128, context: &mut Context) -> Float {
+    let flags = context.flags;
+    let value = real(value, EXTENDED, context);
+    context.flags = flags | context.flags & !softfloat::DENORMAL;
+    value
+}
+
+/// A NaN operand takes precedence over the denormal exception of another operand
+fn nan_precedence(context: &mut Context, operands: &[Float]) {
+    if operands.iter().any(Float::is_nan) {
+        context.flags &= !softfloat::DENORMAL;
+    }
+}
+
+fn sign(value: u128) -> bool {
+    value >> 79 & 1 != 0
+}
+
+/// Index of a register ST(i)
+fn st_index(register: Register) -> usize {
+    register as usize - Register::ST0 as usize
+}
+
+/// Decodes an 18-digit packed BCD integer
+fn from_bcd(value: u128) -> Float {
+    let magnitude = (0..18).rev().fold(0i64, |sum, digit| sum * 10 + ((value >> (4 * digit)) & 0xf) as i64);
+    Float::new(value >> 79 & 1 != 0, magnitude as u128, 0)
+}
+
+/// Encodes an integer as 18-digit packed BCD, with the sign of negative zero
+fn to_bcd(value: i64, negative: bool) -> u128 {
+    let mut magnitude = value.unsigned_abs();
+    let mut bcd = (negative as u128) << 79;
+    for digit in 0..18 {
+        bcd |= ((magnitude % 10) as u128) << (4 * digit);
+        magnitude /= 10;
+    }
+    bcd
+}
+
+/// Packed BCD integer indefinite, stored by fbstp on a masked invalid operation
+const BCD_INDEFINITE: u128 = 0xffff_c000_0000_0000_0000;
+
+impl Cpu {
+    /// Executes an x87 floating-point instruction. Instructions other than the no-wait control instructions first
+    /// deliver a pending unmasked exception as a floating-point error (#MF).
+    pub fn execute_x87(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let mnemonic = instruction.mnemonic();
+        let no_wait = matches!(mnemonic, Mnemonic::Fninit | Mnemonic::Fnclex | Mnemonic::Fnstcw | Mnemonic::Fnstsw
+            | Mnemonic::Fnstenv | Mnemonic::Fnsave | Mnemonic::Fneni | Mnemonic::Fndisi | Mnemonic::Fnsetpm);
+        if !no_wait && self.registers.x87.status & ERROR_SUMMARY != 0 {
+            return Err(Error::FloatingPointFault(instruction.ip()));
+        }
+
+        let control = no_wait || matches!(mnemonic, Mnemonic::Wait | Mnemonic::Finit | Mnemonic::Fclex | Mnemonic::Fstcw
+            | Mnemonic::Fstsw | Mnemonic::Fstenv | Mnemonic::Fsave | Mnemonic::Fldcw | Mnemonic::Fldenv | Mnemonic::Frstor);
+        if !control {
+            self.registers.x87.instruction_pointer = instruction.ip();
+            self.registers.x87.operand_pointer = match instruction.op_count() > 0 && instruction.op0_kind() == OpKind::Memory {
+                true => self.memory_address(instruction)?,
+                false => 0,
+            };
+        }
+
+        match mnemonic {
+            Mnemonic::Fld | Mnemonic::Fild | Mnemonic::Fbld | Mnemonic::Fld1 | Mnemonic::Fldz | Mnemonic::Fldpi | Mnemonic::Fldl2e
+            | Mnemonic::Fldl2t | Mnemonic::Fldlg2 | Mnemonic::Fldln2 => self.x87_load(instruction),
+            Mnemonic::Fst | Mnemonic::Fstp | Mnemonic::Fist | Mnemonic::Fistp | Mnemonic::Fisttp | Mnemonic::Fbstp => self.x87_store(instruction),
+            Mnemonic::Fadd | Mnemonic::Faddp | Mnemonic::Fiadd | Mnemonic::Fsub | Mnemonic::Fsubp | Mnemonic::Fisub | Mnemonic::Fsubr
+            | Mnemonic::Fsubrp | Mnemonic::Fisubr | Mnemonic::Fmul | Mnemonic::Fmulp | Mnemonic::Fimul | Mnemonic::Fdiv | Mnemonic::Fdivp
+            | Mnemonic::Fidiv | Mnemonic::Fdivr | Mnemonic::Fdivrp | Mnemonic::Fidivr => self.x87_arithmetic(instruction),
+            Mnemonic::Fcom | Mnemonic::Fcomp | Mnemonic::Fcompp | Mnemonic::Fucom | Mnemonic::Fucomp | Mnemonic::Fucompp | Mnemonic::Ficom
+            | Mnemonic::Ficomp | Mnemonic::Ftst | Mnemonic::Fcomi | Mnemonic::Fcomip | Mnemonic::Fucomi | Mnemonic::Fucomip => self.x87_compare(instruction),
+            Mnemonic::Fchs | Mnemonic::Fabs | Mnemonic::Fsqrt | Mnemonic::Frndint | Mnemonic::Fxam | Mnemonic::Fxch | Mnemonic::Fcmovb
+            | Mnemonic::Fcmove | Mnemonic::Fcmovbe | Mnemonic::Fcmovu | Mnemonic::Fcmovnb | Mnemonic::Fcmovne | Mnemonic::Fcmovnbe
+            | Mnemonic::Fcmovnu => self.x87_register(instruction),
+            Mnemonic::Fsin | Mnemonic::Fcos | Mnemonic::Fsincos | Mnemonic::Fptan | Mnemonic::Fpatan | Mnemonic::F2xm1 | Mnemonic::Fyl2x
+            | Mnemonic::Fyl2xp1 | Mnemonic::Fscale | Mnemonic::Fprem | Mnemonic::Fprem1 | Mnemonic::Fxtract => self.x87_transcendental(instruction),
+            _ => self.x87_control(instruction),
+        }
+    }
+
+    /// Reads a 10-byte memory operand
+    fn read_u80(&self, address: u64) -> Result<u128, Error> {
+        let mut bytes = [0; 16];
+        self.memory.read(address, &mut bytes[..10])?;
+        Ok(u128::from_le_bytes(bytes))
+    }
+
+    fn write_u80(&mut self, address: u64, value: u128) -> Result<(), Error> {
+        self.memory.write(address, &value.to_le_bytes()[..10])?;
+        Ok(())
+    }
+
+    /// Reads a floating-point, integer or BCD memory operand
+    fn read_x87_memory(&self, instruction: &Instruction, context: &mut Context) -> Result<Float, Error> {
+        let address = self.memory_address(instruction)?;
+        Ok(match instruction.memory_size() {
+            MemorySize::Float32 => real(self.memory.read_uint(address, 4)? as u128, SINGLE, context),
+            MemorySize::Float64 => real(self.memory.read_uint(address, 8)? as u128, DOUBLE, context),
+            MemorySize::Float80 => real(self.read_u80(address)?, EXTENDED, context),
+            MemorySize::Int16 => Float::from_i64(self.memory.read_uint(address, 2)? as i16 as i64),
+            MemorySize::Int32 => Float::from_i64(self.memory.read_uint(address, 4)? as i32 as i64),
+            MemorySize::Int64 => Float::from_i64(self.memory.read_uint(address, 8)? as i64),
+            MemorySize::Bcd => from_bcd(self.read_u80(address)?),
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        })
+    }
+
+    /// fld, fild, fbld and the constant loads
+    fn x87_load(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let x87 = self.registers.x87;
+        let mut context = x87.context();
+
+        // constants are rounded to 64 bits in the current rounding mode without raising the inexact exception
+        let constant = |value: Float| value.pack(EXTENDED, 64, &mut x87.context());
+
+        let value = match instruction.mnemonic() {
+            Mnemonic::Fld1 => constant(Float::one()),
+            Mnemonic::Fldz => EXTENDED.zero(false),
+            Mnemonic::Fldpi => constant(transcendental::PI),
+            Mnemonic::Fldl2e => constant(transcendental::LOG2_E),
+            Mnemonic::Fldl2t => constant(transcendental::LOG2_10),
+            Mnemonic::Fldlg2 => constant(transcendental::LOG10_2),
+            Mnemonic::Fldln2 => constant(transcendental::LN_2),
+            // a register is copied unchanged, so is an 80-bit memory operand
+            _ if instruction.op0_kind() == OpKind::Register => {
+                let index = st_index(instruction.op0_register());
+                match self.registers.x87.is_empty(index) {
+                    true if !self.registers.x87.stack_fault(false) => return Ok(()),
+                    true => EXTENDED.default_nan(),
+                    false => self.registers.x87.st(index),
+                }
+            }
+            _ if instruction.memory_size() == MemorySize::Float80 => self.read_u80(self.memory_address(instruction)?)?,
+            _ => self.read_x87_memory(instruction, &mut context)?.propagate(&mut context).pack(EXTENDED, 64, &mut context),
+        };
+
+        // an overflowing push discards the exceptions of the conversion
+        let x87 = &mut self.registers.x87;
+        match x87.check_overflow() {
+            None => {}
+            Some(true) => x87.push(EXTENDED.default_nan()),
+            Some(false) if x87.report(&context) & NO_RESULT != 0 => {}
+            Some(false) => x87.push(value),
+        }
+        Ok(())
+    }
+
+    /// fst, fstp, fist, fistp, fisttp and fbstp
+    fn x87_store(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let mnemonic = instruction.mnemonic();
+        let pop = matches!(mnemonic, Mnemonic::Fstp | Mnemonic::Fistp | Mnemonic::Fisttp | Mnemonic::Fbstp);
+        let x87 = &mut self.registers.x87;
+        let mut context = Context { wrap: 0, ..x87.context() };
+
+        let empty = x87.is_empty(0);
+        if empty && !x87.stack_fault(false) {
+            return Ok(());
+        }
+        let source = x87.st(0);
+
+        if instruction.op0_kind() == OpKind::Register {
+            let value = if empty { EXTENDED.default_nan() } else { source };
+            x87.set_st(st_index(instruction.op0_register()), value);
+            if !empty {
+                x87.set_condition(C1, 0);
+            }
+        } else {
+            let address = self.memory_address(instruction)?;
+            let size = instruction.memory_size();
+            let integer_bits = match size {
+                MemorySize::Int16 => 16,
+                MemorySize::Int32 => 32,
+                _ => 64,
+            };
+            let rounding = match mnemonic {
+                Mnemonic::Fisttp => Rounding::Zero,
+                _ => self.registers.x87.rounding(),
+            };
+
+            let (value, store_size) = match size {
+                _ if empty => match size {
+                    MemorySize::Float32 => (SINGLE.default_nan(), 4),
+                    MemorySize::Float64 => (DOUBLE.default_nan(), 8),
+                    MemorySize::Float80 => (EXTENDED.default_nan(), 10),
+                    MemorySize::Bcd => (BCD_INDEFINITE, 10),
+                    _ => (1 << (integer_bits - 1), integer_bits / 8),
+                },
+                MemorySize::Float80 => (source, 10),
+                MemorySize::Float32 => (real(source, EXTENDED, &mut context).propagate(&mut context).pack(SINGLE, 24, &mut context), 4),
+                MemorySize::Float64 => (real(source, EXTENDED, &mut context).propagate(&mut context).pack(DOUBLE, 53, &mut context), 8),
+                MemorySize::Bcd => {
+                    let value = integer_operand(source, &mut context);
+                    let flags = context.flags;
+                    match value.to_integer(64, rounding, &mut context) {
+                        Some(integer) if integer.unsigned_abs() < 1_000_000_000_000_000_000 => (to_bcd(integer, value.sign), 10),
+                        _ => {
+                            context.flags = flags | softfloat::INVALID;
+                            (BCD_INDEFINITE, 10)
+                        }
+                    }
+                }
+                _ => {
+                    let value = integer_operand(source, &mut context);
+                    // the integer indefinite value is the most negative integer
+                    let integer = value.to_integer(integer_bits, rounding, &mut context).unwrap_or(-1 << (integer_bits - 1));
+                    (integer as u64 as u128, integer_bits / 8)
+                }
+            };
+
+            if !empty && self.registers.x87.report(&context) & NO_MEMORY_RESULT != 0 {
+                return Ok(());
+            }
+            match store_size {
+                10 => self.write_u80(address, value)?,
+                _ => self.memory.write_uint(address, store_size as usize, value as u64)?,
+            }
+        }
+
+        if pop {
+            self.registers.x87.pop();
+        }
+        Ok(())
+    }
+
+    /// The arithmetic instructions fadd, fsub, fsubr, fmul, fdiv and fdivr, with their popping and integer forms
+    fn x87_arithmetic(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let mnemonic = instruction.mnemonic();
+        let mut context = self.registers.x87.context();
+
+        // one memory operand combines with ST(0), two registers combine into the first
+        let (destination, source) = match instruction.op_count() {
+            1 => (0, None),
+            _ => (st_index(instruction.op0_register()), Some(st_index(instruction.op1_register()))),
+        };
+        let memory = match source {
+            None => Some(self.read_x87_memory(instruction, &mut context)?),
+            Some(_) => None,
+        };
+
+        let x87 = &mut self.registers.x87;
+        let pop = matches!(mnemonic, Mnemonic::Faddp | Mnemonic::Fsubp | Mnemonic::Fsubrp | Mnemonic::Fmulp | Mnemonic::Fdivp | Mnemonic::Fdivrp);
+        match x87.check_underflow(&[destination, source.unwrap_or(destination)]) {
+            None => return Ok(()),
+            Some(true) => {
+                x87.set_st(destination, EXTENDED.default_nan());
+                if pop {
+                    x87.pop();
+                }
+                return Ok(());
+            }
+            Some(false) => {}
+        }
+
+        let a = real(x87.st(destination), EXTENDED, &mut context);
+        let b = match (memory, source) {
+            (Some(value), _) => value,
+            (None, Some(source)) => real(x87.st(source), EXTENDED, &mut context),
+            (None, None) => unreachable!(),
+        };
+        nan_precedence(&mut context, &[a, b]);
+        let result = match mnemonic {
+            Mnemonic::Fadd | Mnemonic::Faddp | Mnemonic::Fiadd => a.add(b, &mut context),
+            Mnemonic::Fsub | Mnemonic::Fsubp | Mnemonic::Fisub => a.sub(b, &mut context),
+            Mnemonic::Fsubr | Mnemonic::Fsubrp | Mnemonic::Fisubr => b.sub(a, &mut context),
+            Mnemonic::Fmul | Mnemonic::Fmulp | Mnemonic::Fimul => a.mul(b, &mut context),
+            Mnemonic::Fdiv | Mnemonic::Fdivp | Mnemonic::Fidiv => a.div(b, &mut context),
+            _ => b.div(a, &mut context),
+        };
+        let value = result.pack(EXTENDED, x87.precision(), &mut context);
+
+        if x87.report(&context) & NO_RESULT != 0 {
+            return Ok(());
+        }
+        x87.set_st(destination, value);
+        if pop {
+            x87.pop();
+        }
+        Ok(())
+    }
+
+    /// fcom, fucom, ficom and ftst, which set C0, C2 and C3, and fcomi and fucomi, which set ZF, PF and CF
+    fn x87_compare(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let mnemonic = instruction.mnemonic();
+        let mut context = self.registers.x87.context();
+
+        let (source, memory) = match (mnemonic, instruction.op_count()) {
+            (Mnemonic::Ftst, _) => (None, Some(Float::zero(false))),
+            (_, 0) => (Some(1), None),
+            (_, 1) => (None, Some(self.read_x87_memory(instruction, &mut context)?)),
+            _ => (Some(st_index(instruction.op1_register())), None),
+        };
+        let pops = match mnemonic {
+            Mnemonic::Fcomp | Mnemonic::Fucomp | Mnemonic::Ficomp | Mnemonic::Fcomip | Mnemonic::Fucomip => 1,
+            Mnemonic::Fcompp | Mnemonic::Fucompp => 2,
+            _ => 0,
+        };
+        let unordered_only = matches!(mnemonic, Mnemonic::Fucom | Mnemonic::Fucomp | Mnemonic::Fucompp | Mnemonic::Fucomi | Mnemonic::Fucomip);
+        let to_rflags = matches!(mnemonic, Mnemonic::Fcomi | Mnemonic::Fcomip | Mnemonic::Fucomi | Mnemonic::Fucomip);
+
+        let x87 = &mut self.registers.x87;
+        let empty = x87.is_empty(0) || source.is_some_and(|index| x87.is_empty(index));
+        let ordering = match empty {
+            true if !x87.stack_fault(false) => return Ok(()),
+            true => None,
+            false => {
+                let a = real(x87.st(0), EXTENDED, &mut context);
+                let b = match source {
+                    Some(index) => real(x87.st(index), EXTENDED, &mut context),
+                    None => memory.unwrap_or(Float::zero(false)),
+                };
+                nan_precedence(&mut context, &[a, b]);
+                if (a.is_nan() || b.is_nan()) && (!unordered_only || a.is_signaling() || b.is_signaling()) {
+                    context.raise(softfloat::INVALID);
+                }
+                // fcomi and fucomi leave C1 unchanged
+                let c1 = x87.status & C1;
+                let unmasked = x87.report(&context);
+                if to_rflags {
+                    x87.set_condition(C1, c1);
+                }
+                if unmasked & NO_RESULT != 0 {
+                    return Ok(());
+                }
+                a.compare(b)
+            }
+        };
+
+        // greater, less, equal and unordered
+        let (c3, c2, c0) = match ordering {
+            Some(Ordering::Greater) => (false, false, false),
+            Some(Ordering::Less) => (false, false, true),
+            Some(Ordering::Equal) => (true, false, false),
+            None => (true, true, true),
+        };
+        match to_rflags {
+            true => {
+                let value = if c3 { ZF } else { 0 } | if c2 { PF } else { 0 } | if c0 { CF } else { 0 };
+                self.update_flags(value, ZF | PF | CF | OF | SF | AF);
+            }
+            false => {
+                let value = if c3 { C3 } else { 0 } | if c2 { C2 } else { 0 } | if c0 { C0 } else { 0 };
+                self.registers.x87.set_condition(C3 | C2 | C0, value);
+            }
+        }
+        for _ in 0..pops {
+            self.registers.x87.pop();
+        }
+        Ok(())
+    }
+
+    /// The instructions operating on ST(0) in place, fxch and fcmovcc
+    fn x87_register(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let mnemonic = instruction.mnemonic();
+        let rflags = self.registers.rflags;
+        let x87 = &mut self.registers.x87;
+        let mut context = x87.context();
+        let source = match instruction.op_count() {
+            2 => st_index(instruction.op1_register()),
+            _ => 0,
+        };
+
+        if mnemonic == Mnemonic::Fxam {
+            // C1 is the sign, C3, C2 and C0 the class
+            let value = x87.st(0);
+            let class = match x87.is_empty(0) {
+                true => C3 | C0,
+                false => match Float::unpack(value, EXTENDED).class {
+                    _ if is_unsupported(value) => 0,
+                    Class::Nan => C0,
+                    Class::Infinity => C2 | C0,
+                    Class::Zero => C3,
+                    Class::Normal if EXTENDED.is_denormal(value) => C3 | C2,
+                    Class::Normal => C2,
+                },
+            };
+            x87.set_condition(C3 | C2 | C1 | C0, class | if sign(value) { C1 } else { 0 });
+            return Ok(());
+        }
+
+        // an empty operand makes the result the real indefinite value, fxch exchanges it
+        match x87.check_underflow(&[0, source]) {
+            None => return Ok(()),
+            Some(true) if mnemonic == Mnemonic::Fxch => {
+                for index in [0, source] {
+                    if x87.is_empty(index) {
+                        x87.set_st(index, EXTENDED.default_nan());
+                    }
+                }
+            }
+            Some(true) => {
+                x87.set_st(0, EXTENDED.default_nan());
+                return Ok(());
+            }
+            Some(false) => {}
+        }
+        let value = x87.st(0);
+
+        let result = match mnemonic {
+            Mnemonic::Fchs => value ^ (1 << 79),
+            Mnemonic::Fabs => value & !(1 << 79),
+            Mnemonic::Fsqrt => {
+                let precision = x87.precision();
+                real(value, EXTENDED, &mut context).sqrt(&mut context).pack(EXTENDED, precision, &mut context)
+            }
+            Mnemonic::Frndint => {
+                let rounding = x87.rounding();
+                let integral = real(value, EXTENDED, &mut context).round_to_integral(rounding, &mut context);
+                let rounded_up = context.rounded_up;
+                let result = integral.pack(EXTENDED, 64, &mut context);
+                context.rounded_up = rounded_up;
+                result
+            }
+            Mnemonic::Fxch => {
+                let other = x87.st(source);
+                x87.set_st(source, value);
+                other
+            }
+            _ => {
+                let condition = match mnemonic {
+                    Mnemonic::Fcmovb => rflags & CF != 0,
+                    Mnemonic::Fcmove => rflags & ZF != 0,
+                    Mnemonic::Fcmovbe => rflags & (CF | ZF) != 0,
+                    Mnemonic::Fcmovu => rflags & PF != 0,
+                    Mnemonic::Fcmovnb => rflags & CF == 0,
+                    Mnemonic::Fcmovne => rflags & ZF == 0,
+                    Mnemonic::Fcmovnbe => rflags & (CF | ZF) == 0,
+                    _ => rflags & PF == 0,
+                };
+                match condition {
+                    true => x87.st(source),
+                    false => value,
+                }
+            }
+        };
+
+        // fcmovcc leaves C1 unchanged
+        if !matches!(mnemonic, Mnemonic::Fcmovb | Mnemonic::Fcmove | Mnemonic::Fcmovbe | Mnemonic::Fcmovu | Mnemonic::Fcmovnb
+            | Mnemonic::Fcmovne | Mnemonic::Fcmovnbe | Mnemonic::Fcmovnu) && x87.report(&context) & NO_RESULT != 0 {
+            return Ok(());
+        }
+        x87.set_st(0, result);
+        Ok(())
+    }
+
+    /// The transcendental instructions, fscale, fprem, fprem1 and fxtract
+    fn x87_transcendental(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let mnemonic = instruction.mnemonic();
+        let x87 = &mut self.registers.x87;
+        let mut context = x87.context();
+        let indefinite = EXTENDED.default_nan();
+
+        let binary = matches!(mnemonic, Mnemonic::Fpatan | Mnemonic::Fyl2x | Mnemonic::Fyl2xp1 | Mnemonic::Fscale | Mnemonic::Fprem | Mnemonic::Fprem1);
+        let pushes = matches!(mnemonic, Mnemonic::Fsincos | Mnemonic::Fptan | Mnemonic::Fxtract);
+        let operands: &[usize] = if binary { &[0, 1] } else { &[0] };
+
+        // the trigonometric instructions and the partial remainders clear C2 unless their reduction is incomplete
+        if matches!(mnemonic, Mnemonic::Fsin | Mnemonic::Fcos | Mnemonic::Fsincos | Mnemonic::Fptan | Mnemonic::Fprem | Mnemonic::Fprem1) {
+            x87.set_condition(C2, 0);
+        }
+
+        match x87.check_underflow(operands) {
+            None => return Ok(()),
+            Some(true) => {
+                match mnemonic {
+                    Mnemonic::Fpatan | Mnemonic::Fyl2x | Mnemonic::Fyl2xp1 => {
+                        x87.set_st(1, indefinite);
+                        x87.pop();
+                    }
+                    _ => {
+                        x87.set_st(0, indefinite);
+                        if pushes {
+                            x87.push(indefinite);
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            Some(false) => {}
+        }
+        if pushes && !x87.is_empty(7) {
+            if x87.stack_fault(true) {
+                x87.set_st(0, indefinite);
+                x87.push(indefinite);
+            }
+            return Ok(());
+        }
+
+        let x = real(x87.st(0), EXTENDED, &mut context);
+        let y = match binary {
+            true => real(x87.st(1), EXTENDED, &mut context),
+            false => Float::zero(false),
+        };
+        nan_precedence(&mut context, &[x, y]);
+
+        // results of ST(0) and of the register pushed or ST(1), and the condition codes
+        let mut pushed = None;
+        let mut second = None;
+        let mut condition = (0, 0);
+
+        let result = match mnemonic {
+            Mnemonic::Fsin | Mnemonic::Fcos | Mnemonic::Fsincos | Mnemonic::Fptan => match x.class {
+                Class::Nan => {
+                    let nan = x.propagate(&mut context);
+                    pushed = pushes.then_some(nan);
+                    nan
+                }
+                Class::Infinity => {
+                    let nan = Float::invalid(&mut context);
+                    pushed = pushes.then_some(nan);
+                    nan
+                }
+                // out of range operands are left unchanged with C2 set
+                Class::Normal if x.exponent >= 63 => {
+                    condition = (C2, C2);
+                    x
+                }
+                // tiny operands are returned without rounding, reported inexact and, when denormal, underflowing
+                Class::Normal if x.exponent < -68 => {
+                    let denormal = x.exponent < -16382 && mnemonic != Mnemonic::Fcos;
+                    context.raise(softfloat::INEXACT | if denormal { softfloat::UNDERFLOW } else { 0 });
+                    match mnemonic {
+                        Mnemonic::Fsin => x,
+                        Mnemonic::Fcos => Float::one(),
+                        _ => {
+                            pushed = Some(Float::one());
+                            x
+                        }
+                    }
+                }
+                _ => {
+                    let (sin, cos) = transcendental::sin_cos(x);
+                    match mnemonic {
+                        Mnemonic::Fsin => sin,
+                        Mnemonic::Fcos => cos,
+                        Mnemonic::Fsincos => {
+                            pushed = Some(cos);
+                            sin
+                        }
+                        _ => {
+                            pushed = Some(Float::one());
+                            sin.div(cos, &mut Context::default())
+                        }
+                    }
+                }
+            },
+
+            Mnemonic::Fpatan | Mnemonic::Fyl2x | Mnemonic::Fyl2xp1 if x.is_nan() || y.is_nan() => {
+                second = Some(Float::propagate_nan(x, y, &mut context));
+                x
+            }
+            Mnemonic::Fpatan => {
+                second = Some(transcendental::atan2(y, x));
+                x
+            }
+            Mnemonic::Fyl2x => {
+                let logarithm = match x.class {
+                    _ if x.sign && x.class != Class::Zero => Float::invalid(&mut context),
+                    Class::Zero => {
+                        if y.class == Class::Normal {
+                            context.raise(softfloat::DIVIDE_BY_ZERO);
+                        }
+                        Float::infinity(true)
+                    }
+                    Class::Infinity => x,
+                    _ => transcendental::log2(x),
+                };
+                second = Some(y.mul(logarithm, &mut context));
+                x
+            }
+            Mnemonic::Fyl2xp1 => {
+                let logarithm = match x.class {
+                    Class::Zero => x,
+                    _ if x.compare(Float::from_i64(-1)) != Some(Ordering::Greater) => Float::invalid(&mut context),
+                    Class::Infinity => x,
+                    _ => transcendental::log2_1p(x),
+                };
+                second = Some(y.mul(logarithm, &mut context));
+                x
+            }
+
+            Mnemonic::F2xm1 => match x.class {
+                Class::Nan => x.propagate(&mut context),
+                Class::Zero => x,
+                Class::Infinity if x.sign => Float::from_i64(-1),
+                Class::Infinity => x,
+                // the exact results of +1 and -1 are still reported as inexact
+                _ => {
+                    context.raise(softfloat::INEXACT);
+                    transcendental::exp2_m1(x)
+                }
+            },
+
+            Mnemonic::Fscale => match (x.class, y.class) {
+                _ if x.is_nan() || y.is_nan() => Float::propagate_nan(x, y, &mut context),
+                (Class::Zero, Class::Infinity) if !y.sign => Float::invalid(&mut context),
+                (Class::Infinity, Class::Infinity) if y.sign => Float::invalid(&mut context),
+                (Class::Normal, Class::Infinity) => match y.sign {
+                    false => Float::infinity(x.sign),
+                    true => Float::zero(x.sign),
+                },
+                (Class::Normal, _) => {
+                    let scale = y.to_integer(64, Rounding::Zero, &mut Context::default()).unwrap_or(if y.sign { i64::MIN } else { i64::MAX });
+                    x.scale(scale.clamp(-(1 << 20), 1 << 20) as i32)
+                }
+                _ => x,
+            },
+
+            Mnemonic::Fprem | Mnemonic::Fprem1 => {
+                // C0, C3 and C1 are the low three bits of the quotient of a complete reduction
+                let (remainder, quotient, complete) = x.remainder(y, mnemonic == Mnemonic::Fprem1, &mut context);
+                let quotient_bits = [(4, C0), (2, C3), (1, C1)].into_iter().filter(|&(bit, _)| quotient & bit != 0).fold(0, |bits, (_, code)| bits | code);
+                let condition = match (remainder.is_nan(), complete) {
+                    (true, _) => (0, 0),
+                    (false, true) => (C3 | C1 | C0, quotient_bits),
+                    (false, false) => (C3 | C2 | C1 | C0, C2),
+                };
+                let value = remainder.pack(EXTENDED, 64, &mut context);
+                if x87.report(&context) & NO_RESULT != 0 {
+                    return Ok(());
+                }
+                x87.set_st(0, value);
+                x87.set_condition(condition.0, condition.1);
+                return Ok(());
+            }
+
+            // ST(0) becomes the significand and the exponent is pushed below it
+            _ => match x.class {
+                Class::Nan => {
+                    let nan = x.propagate(&mut context);
+                    pushed = Some(nan);
+                    nan
+                }
+                Class::Zero => {
+                    context.raise(softfloat::DIVIDE_BY_ZERO);
+                    pushed = Some(x);
+                    Float::infinity(true)
+                }
+                Class::Infinity => {
+                    pushed = Some(x);
+                    Float::infinity(false)
+                }
+                Class::Normal => {
+                    pushed = Some(Float { exponent: 0, ..x });
+                    Float::from_i64(x.exponent as i64)
+                }
+            },
+        };
+
+        // C1 reports the rounding of the result which is not a constant or unchanged operand
+        let pushed_value = pushed.map(|value| value.pack(EXTENDED, 64, &mut context));
+        let value = result.pack(EXTENDED, 64, &mut context);
+        let second_value = second.map(|value| value.pack(EXTENDED, 64, &mut context));
+
+        if x87.report(&context) & NO_RESULT != 0 {
+            return Ok(());
+        }
+        x87.set_condition(condition.0, condition.1);
+        match (second_value, pushed_value) {
+            (Some(second), _) => {
+                x87.set_st(1, second);
+                x87.pop();
+            }
+            (None, Some(pushed)) => {
+                x87.set_st(0, value);
+                x87.push(pushed);
+            }
+            (None, None) => x87.set_st(0, value),
+        }
+        Ok(())
+    }
+
+    /// The control instructions, which manage the control, status and tag words, the environment and the stack
+    fn x87_control(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        match instruction.mnemonic() {
+            Mnemonic::Wait | Mnemonic::Fnop | Mnemonic::Fneni | Mnemonic::Fndisi | Mnemonic::Fnsetpm => {}
+            Mnemonic::Fninit | Mnemonic::Finit => self.registers.x87.initialize(),
+            Mnemonic::Fnclex | Mnemonic::Fclex => self.registers.x87.status &= !(EXCEPTIONS | STACK_FAULT | ERROR_SUMMARY | BUSY),
+
+            Mnemonic::Fldcw => {
+                let value = self.memory.read_uint(self.memory_address(instruction)?, 2)? as u16;
+                let x87 = &mut self.registers.x87;
+                x87.control = (value & CONTROL_MASK) | CONTROL_ONE;
+                x87.update_error_summary();
+            }
+            Mnemonic::Fnstcw | Mnemonic::Fstcw => {
+                let address = self.memory_address(instruction)?;
+                self.memory.write_uint(address, 2, self.registers.x87.control as u64)?;
+            }
+            Mnemonic::Fnstsw | Mnemonic::Fstsw => {
+                let status = self.registers.x87.status as u64;
+                match instruction.op0_kind() {
+                    OpKind::Register => self.set_register(Register::AX, status)?,
+                    _ => {
+                        let address = self.memory_address(instruction)?;
+                        self.memory.write_uint(address, 2, status)?;
+                    }
+                }
+            }
+
+            Mnemonic::Fnstenv | Mnemonic::Fstenv => {
+                let address = self.memory_address(instruction)?;
+                self.store_x87_environment(address, instruction.code())?;
+                self.registers.x87.control |= EXCEPTIONS;
+            }
+            Mnemonic::Fldenv => {
+                let address = self.memory_address(instruction)?;
+                self.load_x87_environment(address, instruction.code())?;
+            }
+            Mnemonic::Fnsave | Mnemonic::Fsave => {
+                let address = self.memory_address(instruction)?;
+                let offset = self.store_x87_environment(address, instruction.code())?;
+                for index in 0..8 {
+                    self.write_u80(address + offset + 10 * index as u64, self.registers.x87.st(index))?;
+                }
+                self.registers.x87.initialize();
+            }
+            Mnemonic::Frstor => {
+                let address = self.memory_address(instruction)?;
+                let offset = self.load_x87_environment(address, instruction.code())?;
+                let x87 = self.registers.x87;
+                for index in 0..8 {
+                    let value = self.read_u80(address + offset + 10 * index as u64)?;
+                    self.registers.x87.registers[x87.physical(index)] = value;
+                }
+            }
+
+            Mnemonic::Ffree | Mnemonic::Ffreep => {
+                let x87 = &mut self.registers.x87;
+                x87.free(st_index(instruction.op0_register()));
+                x87.set_condition(C1, 0);
+                if instruction.mnemonic() == Mnemonic::Ffreep {
+                    x87.set_top(x87.top() + 1);
+                }
+            }
+            Mnemonic::Fincstp | Mnemonic::Fdecstp => {
+                let x87 = &mut self.registers.x87;
+                let top = match instruction.mnemonic() {
+                    Mnemonic::Fincstp => x87.top() + 1,
+                    _ => x87.top().wrapping_sub(1),
+                };
+                x87.set_top(top);
+                x87.set_condition(C1, 0);
+            }
+
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        }
+        Ok(())
+    }
+
+    /// Stores the control, status and tag words and the instruction and operand pointers in the 14-byte (16-bit operand
+    /// size) or 28-byte format, returning the size
+    fn store_x87_environment(&mut self, address: u64, code: Code) -> Result<u64, Error> {
+        let x87 = self.registers.x87;
+        let fields = [x87.control, x87.status, x87.tag_word()];
+        match code {
+            Code::Fnstenv_m14byte | Code::Fstenv_m14byte | Code::Fnsave_m94byte | Code::Fsave_m94byte => {
+                for (index, field) in fields.into_iter().enumerate() {
+                    self.memory.write_uint(address + 2 * index as u64, 2, field as u64)?;
+                }
+                self.memory.write_uint(address + 6, 2, x87.instruction_pointer & 0xffff)?;
+                self.memory.write_uint(address + 8, 2, 0)?;
+                self.memory.write_uint(address + 10, 2, x87.data_pointer & 0xffff)?;
+                self.memory.write_uint(address + 12, 2, 0)?;
+                Ok(14)
+            }
+            _ => {
+                // the reserved upper halves read as ones, the selectors and the opcode as zero
+                for (index, field) in fields.into_iter().enumerate() {
+                    self.memory.write_uint(address + 4 * index as u64, 4, 0xffff_0000 | field as u64)?;
+                }
+                self.memory.write_uint(address + 12, 4, x87.instruction_pointer & 0xffff_ffff)?;
+                self.memory.write_uint(address + 16, 4, 0)?;
+                self.memory.write_uint(address + 20, 4, x87.data_pointer & 0xffff_ffff)?;
+                self.memory.write_uint(address + 24, 4, 0xffff_0000)?;
+                Ok(28)
+            }
+        }
+    }
+
+    /// Loads the environment stored by [`Cpu::store_x87_environment`], returning its size
+    fn load_x87_environment(&mut self, address: u64, code: Code) -> Result<u64, Error> {
+        let (stride, size) = match code {
+            Code::Fldenv_m14byte | Code::Frstor_m94byte => (2, 14),
+            _ => (4, 28),
+        };
+        let field = |index: u64| self.memory.read_uint(address + stride * index, 2);
+        let (control, status, tag) = (field(0)? as u16, field(1)? as u16, field(2)? as u16);
+        let (instruction_pointer, data_pointer) = (self.memory.read_uint(address + 3 * stride, stride as usize)?, self.memory.read_uint(address + 5 * stride, stride as usize)?);
+
+        let x87 = &mut self.registers.x87;
+        x87.control = (control & CONTROL_MASK) | CONTROL_ONE;
+        x87.status = status;
+        x87.tag = tag;
+        x87.instruction_pointer = instruction_pointer;
+        x87.data_pointer = data_pointer;
+        x87.update_error_summary();
+        Ok(size)
+    }
+
+    /// Stores the x87 part of the fxsave area: the control and status words, the abridged tag word with one bit per
+    /// non-empty physical register, the instruction and operand pointers (with 64 bits in the 64-bit format and
+    /// otherwise 32 bits and a zero selector) and the registers in stack order, padded to 16 bytes
+    pub fn store_x87_fxsave(&mut self, address: u64, wide: bool) -> Result<(), Error> {
+        let x87 = self.registers.x87;
+        let abridged = (0..8).filter(|&register| (x87.tag >> (2 * register)) & 3 != TAG_EMPTY).fold(0, |tag, register| tag | (1 << register));
+        self.memory.write_uint(address, 2, x87.control as u64)?;
+        self.memory.write_uint(address + 2, 2, x87.status as u64)?;
+        self.memory.write_uint(address + 4, 4, abridged)?;
+        match wide {
+            true => {
+                self.memory.write_uint(address + 8, 8, x87.instruction_pointer)?;
+                self.memory.write_uint(address + 16, 8, x87.data_pointer)?;
+            }
+            false => {
+                self.memory.write_uint(address + 8, 8, x87.instruction_pointer & 0xffff_ffff)?;
+                self.memory.write_uint(address + 16, 8, x87.data_pointer & 0xffff_ffff)?;
+            }
+        }
+        for index in 0..8 {
+            self.memory.write(address + 32 + 16 * index as u64, &x87.st(index).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Loads the x87 part of the fxsave area stored by [`Cpu::store_x87_fxsave`], the tags of the non-empty registers
+    /// are computed from their contents
+    pub fn load_x87_fxsave(&mut self, address: u64, wide: bool) -> Result<(), Error> {
+        let control = self.memory.read_uint(address, 2)? as u16;
+        let status = self.memory.read_uint(address + 2, 2)? as u16;
+        let abridged = self.memory.read_uint(address + 4, 1)?;
+        let pointer_size = if wide { 8 } else { 4 };
+        let instruction_pointer = self.memory.read_uint(address + 8, pointer_size)?;
+        let data_pointer = self.memory.read_uint(address + 16, pointer_size)?;
+        let mut values = [0; 8];
+        for (index, value) in values.iter_mut().enumerate() {
+            *value = self.read_u80(address + 32 + 16 * index as u64)?;
+        }
+
+        let x87 = &mut self.registers.x87;
+        x87.control = (control & CONTROL_MASK) | CONTROL_ONE;
+        x87.status = status;
+        x87.instruction_pointer = instruction_pointer;
+        x87.data_pointer = data_pointer;
+        for (index, value) in values.into_iter().enumerate() {
+            let register = x87.physical(index);
+            x87.registers[register] = value;
+            x87.set_tag(register, if abridged >> register & 1 != 0 { tag(value) } else { TAG_EMPTY });
+        }
+        x87.update_error_summary();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::testing::{cpu_with_data, DATA};
+
+    use iced_x86::MemoryOperand;
+
+    const IE: u16 = softfloat::INVALID as u16;
+    const DE: u16 = softfloat::DENORMAL as u16;
+    const PE: u16 = softfloat::INEXACT as u16;
+    const INDEFINITE: u128 = 0xffff_c000_0000_0000_0000;
+
+    fn load_double(cpu: &mut Cpu, index: i64) {
+        let operand = MemoryOperand::with_base_displ(Register::RBX, 8 * index);
+        cpu.execute_instruction(Instruction::with1(Code::Fld_m64fp, operand).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn arithmetic_rounds_to_extended_precision_in_every_mode() {
+        let cases = [
+            (Code::Fmulp_sti_st0, Rounding::Nearest, 0x3ff0000000000001, 0x3ff0000000000001, 0x3fff_8000_0000_0000_1000, PE),
+            (Code::Fmulp_sti_st0, Rounding::Nearest, 0xbff0000000000001, 0x3fffffffffffffff, 0xc000_8000_0000_0000_0400, C1 | PE),
+            (Code::Faddp_sti_st0, Rounding::Nearest, 0x3ff0000000000000, 0x3b90000000000000, 0x3fff_8000_0000_0000_0000, PE),
+            (Code::Faddp_sti_st0, Rounding::Nearest, 0x3ff0000000000000, 0xbb90000000000000, 0x3fff_8000_0000_0000_0000, C1 | PE),
+            (Code::Faddp_sti_st0, Rounding::Nearest, 0xbff0000000000000, 0xbbf8000000000000, 0xbfff_8000_0000_0000_0001, C1 | PE),
+            (Code::Fdivp_sti_st0, Rounding::Nearest, 0x3ff0000000000000, 0x4008000000000000, 0x3ffd_aaaa_aaaa_aaaa_aaab, C1 | PE),
+            (Code::Fdivp_sti_st0, Rounding::Nearest, 0xc000000000000000, 0x4008000000000000, 0xbffe_aaaa_aaaa_aaaa_aaab, C1 | PE),
+            (Code::Fdivp_sti_st0, Rounding::Nearest, 0x3ff0000000000000, 0x4024000000000000, 0x3ffb_cccc_cccc_cccc_cccd, C1 | PE),
+            (Code::Fsqrt, Rounding::Nearest, 0x4000000000000000, 0x0000000000000000, 0x3fff_b504_f333_f9de_6484, PE),
+            (Code::Fsqrt, Rounding::Nearest, 0x4008000000000000, 0x0000000000000000, 0x3fff_ddb3_d742_c265_539e, C1 | PE),
+            (Code::Fmulp_sti_st0, Rounding::Down, 0x3ff0000000000001, 0x3ff0000000000001, 0x3fff_8000_0000_0000_1000, PE),
+            (Code::Fmulp_sti_st0, Rounding::Down, 0xbff0000000000001, 0x3fffffffffffffff, 0xc000_8000_0000_0000_0400, C1 | PE),
+            (Code::Faddp_sti_st0, Rounding::Down, 0x3ff0000000000000, 0x3b90000000000000, 0x3fff_8000_0000_0000_0000, PE),
+            (Code::Faddp_sti_st0, Rounding::Down, 0x3ff0000000000000, 0xbb90000000000000, 0x3ffe_ffff_ffff_ffff_ffff, PE),
+            (Code::Faddp_sti_st0, Rounding::Down, 0xbff0000000000000, 0xbbf8000000000000, 0xbfff_8000_0000_0000_0001, C1 | PE),
+            (Code::Fdivp_sti_st0, Rounding::Down, 0x3ff0000000000000, 0x4008000000000000, 0x3ffd_aaaa_aaaa_aaaa_aaaa, PE),
+            (Code::Fdivp_sti_st0, Rounding::Down, 0xc000000000000000, 0x4008000000000000, 0xbffe_aaaa_aaaa_aaaa_aaab, C1 | PE),
+            (Code::Fdivp_sti_st0, Rounding::Down, 0x3ff0000000000000, 0x4024000000000000, 0x3ffb_cccc_cccc_cccc_cccc, PE),
+            (Code::Fsqrt, Rounding::Down, 0x4000000000000000, 0x0000000000000000, 0x3fff_b504_f333_f9de_6484, PE),
+            (Code::Fsqrt, Rounding::Down, 0x4008000000000000, 0x0000000000000000, 0x3fff_ddb3_d742_c265_539d, PE),
+            (Code::Fmulp_sti_st0, Rounding::Up, 0x3ff0000000000001, 0x3ff0000000000001, 0x3fff_8000_0000_0000_1001, C1 | PE),
+            (Code::Fmulp_sti_st0, Rounding::Up, 0xbff0000000000001, 0x3fffffffffffffff, 0xc000_8000_0000_0000_03ff, PE),
+            (Code::Faddp_sti_st0, Rounding::Up, 0x3ff0000000000000, 0x3b90000000000000, 0x3fff_8000_0000_0000_0001, C1 | PE),
+            (Code::Faddp_sti_st0, Rounding::Up, 0x3ff0000000000000, 0xbb90000000000000, 0x3fff_8000_0000_0000_0000, C1 | PE),
+            (Code::Faddp_sti_st0, Rounding::Up, 0xbff0000000000000, 0xbbf8000000000000, 0xbfff_8000_0000_0000_0000, PE),
+            (Code::Fdivp_sti_st0, Rounding::Up, 0x3ff0000000000000, 0x4008000000000000, 0x3ffd_aaaa_aaaa_aaaa_aaab, C1 | PE),
+            (Code::Fdivp_sti_st0, Rounding::Up, 0xc000000000000000, 0x4008000000000000, 0xbffe_aaaa_aaaa_aaaa_aaaa, PE),
+            (Code::Fdivp_sti_st0, Rounding::Up, 0x3ff0000000000000, 0x4024000000000000, 0x3ffb_cccc_cccc_cccc_cccd, C1 | PE),
+            (Code::Fsqrt, Rounding::Up, 0x4000000000000000, 0x0000000000000000, 0x3fff_b504_f333_f9de_6485, C1 | PE),
+            (Code::Fsqrt, Rounding::Up, 0x4008000000000000, 0x0000000000000000, 0x3fff_ddb3_d742_c265_539e, C1 | PE),
+            (Code::Fmulp_sti_st0, Rounding::Zero, 0x3ff0000000000001, 0x3ff0000000000001, 0x3fff_8000_0000_0000_1000, PE),
+            (Code::Fmulp_sti_st0, Rounding::Zero, 0xbff0000000000001, 0x3fffffffffffffff, 0xc000_8000_0000_0000_03ff, PE),
+            (Code::Faddp_sti_st0, Rounding::Zero, 0x3ff0000000000000, 0x3b90000000000000, 0x3fff_8000_0000_0000_0000, PE),
+            (Code::Faddp_sti_st0, Rounding::Zero, 0x3ff0000000000000, 0xbb90000000000000, 0x3ffe_ffff_ffff_ffff_ffff, PE),
+            (Code::Faddp_sti_st0, Rounding::Zero, 0xbff0000000000000, 0xbbf8000000000000, 0xbfff_8000_0000_0000_0000, PE),
+            (Code::Fdivp_sti_st0, Rounding::Zero, 0x3ff0000000000000, 0x4008000000000000, 0x3ffd_aaaa_aaaa_aaaa_aaaa, PE),
+            (Code::Fdivp_sti_st0, Rounding::Zero, 0xc000000000000000, 0x4008000000000000, 0xbffe_aaaa_aaaa_aaaa_aaaa, PE),
+            (Code::Fdivp_sti_st0, Rounding::Zero, 0x3ff0000000000000, 0x4024000000000000, 0x3ffb_cccc_cccc_cccc_cccc, PE),
+            (Code::Fsqrt, Rounding::Zero, 0x4000000000000000, 0x0000000000000000, 0x3fff_b504_f333_f9de_6484, PE),
+            (Code::Fsqrt, Rounding::Zero, 0x4008000000000000, 0x0000000000000000, 0x3fff_ddb3_d742_c265_539d, PE),
+        ];
+        for (code, rounding, a, b, result, flags) in cases {
+            let mut cpu = cpu_with_data(&[a, b]);
+            cpu.registers.x87.control = DEFAULT_CONTROL | (rounding as u16) << 10;
+            load_double(&mut cpu, 0);
+            let instruction = match code {
+                Code::Fsqrt => Instruction::with(code),
+                _ => {
+                    load_double(&mut cpu, 1);
+                    Instruction::with2(code, Register::ST1, Register::ST0).unwrap()
+                }
+            };
+            cpu.execute_instruction(instruction).unwrap();
+            let x87 = &cpu.registers.x87;
+            assert_eq!(x87.st(0), result, "{:?} {:?} 0x{:x}, 0x{:x}", code, rounding, a, b);
+            assert_eq!(x87.status & (C1 | EXCEPTIONS), flags, "{:?} {:?} 0x{:x}, 0x{:x}", code, rounding, a, b);
+        }
+    }
+
+    #[test]
+    fn denormal_operands_raise_the_denormal_exception() {
+        let mut cpu = cpu_with_data(&[0x0000_0000_0000_0001, 0x3ff0_0000_0000_0000]);
+        load_double(&mut cpu, 1);
+        assert_eq!(cpu.registers.x87.status & EXCEPTIONS, 0);
+        load_double(&mut cpu, 0);
+        assert_eq!(cpu.registers.x87.status & EXCEPTIONS, DE);
+        // the smallest denormal double is normal in the extended format
+        assert_eq!(cpu.registers.x87.st(0), 0x3bcd_8000_0000_0000_0000);
+
+        // extended operands are only denormal with a zero exponent
+        cpu.registers.x87.status &= !EXCEPTIONS;
+        cpu.execute_instruction(Instruction::with2(Code::Fadd_st0_sti, Register::ST0, Register::ST1).unwrap()).unwrap();
+        assert_eq!(cpu.registers.x87.status & EXCEPTIONS, PE);
+        assert_eq!(real(0x0000_0000_0000_0000_0001, EXTENDED, &mut Context::default()), Float::new(false, 1, -16445));
+        let mut context = Context::default();
+        real(0x0000_0000_0000_0000_0001, EXTENDED, &mut context);
+        assert_eq!(context.flags, softfloat::DENORMAL);
+    }
+
+    #[test]
+    fn invalid_operations_produce_the_real_indefinite() {
+        let mut cpu = cpu_with_data(&[0xbff0_0000_0000_0000, 0x7ff0_0000_0000_0001]);
+        load_double(&mut cpu, 0);
+        cpu.execute_instruction(Instruction::with(Code::Fsqrt)).unwrap();
+        assert_eq!(cpu.registers.x87.st(0), INDEFINITE);
+        assert_eq!(cpu.registers.x87.status, 0x3800 | IE);
+
+        // a signaling NaN operand is invalid and is quieted
+        cpu.registers.x87 = X87::default();
+        load_double(&mut cpu, 1);
+        assert_eq!(cpu.registers.x87.st(0), 0x7fff_c000_0000_0000_0800);
+        assert_eq!(cpu.registers.x87.status & EXCEPTIONS, IE);
+
+        // operations on empty registers underflow the stack
+        cpu.registers.x87 = X87::default();
+        cpu.execute_instruction(Instruction::with2(Code::Fadd_st0_sti, Register::ST0, Register::ST1).unwrap()).unwrap();
+        assert_eq!(cpu.registers.x87.st(0), INDEFINITE);
+        assert_eq!(cpu.registers.x87.status, STACK_FAULT | IE);
+    }
+
+    #[test]
+    fn doubles_round_trip_through_the_stack() {
+        let cases = [
+            (0x0000_0000_0000_0000, 0x0000_0000_0000_0000, 0),
+            (0x8000_0000_0000_0000, 0x8000_0000_0000_0000, 0),
+            (0x0000_0000_0000_0001, 0x0000_0000_0000_0001, DE),
+            (0x000f_ffff_ffff_ffff, 0x000f_ffff_ffff_ffff, DE),
+            (0x0010_0000_0000_0000, 0x0010_0000_0000_0000, 0),
+            (0xc009_21fb_5444_2d18, 0xc009_21fb_5444_2d18, 0),
+            (0x7fef_ffff_ffff_ffff, 0x7fef_ffff_ffff_ffff, 0),
+            (0x7ff0_0000_0000_0000, 0x7ff0_0000_0000_0000, 0),
+            (0x7ff8_0000_0000_1234, 0x7ff8_0000_0000_1234, 0),
+            (0x7ff0_0000_0000_0001, 0x7ff8_0000_0000_0001, IE),
+        ];
+        for (value, result, flags) in cases {
+            let mut cpu = cpu_with_data(&[value]);
+            load_double(&mut cpu, 0);
+            let operand = MemoryOperand::with_base_displ(Register::RBX, 8);
+            cpu.execute_instruction(Instruction::with1(Code::Fstp_m64fp, operand).unwrap()).unwrap();
+            assert_eq!(cpu.memory.read_uint(DATA + 8, 8).unwrap(), result, "0x{:x}", value);
+            assert_eq!(cpu.registers.x87.status, flags, "0x{:x}", value);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use super::cpuid::{
+    XSAVE_AVX_OFFSET, XSAVE_HI16_ZMM_OFFSET, XSAVE_OPMASK_OFFSET, XSAVE_ZMM_HI256_OFFSET, XSTATE_AVX, XSTATE_HI16_ZMM, XSTATE_OPMASK,
+    XSTATE_SSE, XSTATE_X87, XSTATE_ZMM_HI256,
+};
+use super::error::Error;
+use super::sse::{vector_from_bytes, vector_to_bytes, MXCSR_MASK};
+use super::x87::X87;
+use super::Cpu;
+
+use iced_x86::{Instruction, Mnemonic, Register};
+
+/// Offset of the xsave header, whose first quadword is XSTATE_BV: the components saved in the area, the others being in
+/// their initial state
+const HEADER: u64 = 512;
+
+impl Cpu {
+    /// Executes xsave and xrstor in the standard format of the area, and xgetbv. XCR0 is fixed by the CPU model, so
+    /// xsetbv is a general protection fault like in user mode.
+    pub fn execute_xsave(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        self.check_features(instruction)?;
+        let general_protection = Error::GeneralProtectionFault(instruction.ip());
+        match instruction.mnemonic() {
+            Mnemonic::Xgetbv => match self.get_register_u64(Register::ECX)? {
+                0 => {
+                    let xcr0 = self.model.xcr0();
+                    self.set_register(Register::EAX, xcr0 & 0xffff_ffff)?;
+                    self.set_register(Register::EDX, xcr0 >> 32)?;
+                }
+                _ => return Err(general_protection),
+            },
+            Mnemonic::Xsetbv => return Err(general_protection),
+
+            // the components requested by EDX:EAX and enabled in XCR0 are saved and marked in XSTATE_BV
+            Mnemonic::Xsave | Mnemonic::Xsave64 => {
+                let address = self.xsave_address(instruction)?;
+                let requested = self.requested_components();
+                if requested & XSTATE_X87 != 0 {
+                    self.store_x87_fxsave(address, instruction.mnemonic() == Mnemonic::Xsave64)?;
+                }
+                if requested & (XSTATE_SSE | XSTATE_AVX) != 0 {
+                    self.memory.write_uint(address + 24, 4, self.registers.sse.mxcsr as u64)?;
+                    self.memory.write_uint(address + 28, 4, MXCSR_MASK as u64)?;
+                }
+                for (index, value) in self.registers.sse.zmm.into_iter().enumerate() {
+                    let offset = index as u64;
+                    match index {
+                        0..16 => {
+                            if requested & XSTATE_SSE != 0 {
+                                self.memory.write(address + 160 + 16 * offset, &value[0].to_le_bytes())?;
+                            }
+                            if requested & XSTATE_AVX != 0 {
+                                self.memory.write(address + XSAVE_AVX_OFFSET as u64 + 16 * offset, &value[1].to_le_bytes())?;
+                            }
+                            if requested & XSTATE_ZMM_HI256 != 0 {
+                                self.memory.write(address + XSAVE_ZMM_HI256_OFFSET as u64 + 32 * offset, &vector_to_bytes(value)[32..])?;
+                            }
+                        }
+                        _ if requested & XSTATE_HI16_ZMM != 0 => {
+                            self.memory.write(address + XSAVE_HI16_ZMM_OFFSET as u64 + 64 * (offset - 16), &vector_to_bytes(value))?;
+                        }
+                        _ => {}
+                    }
+                }
+                if requested & XSTATE_OPMASK != 0 {
+                    for (index, value) in self.registers.sse.k.into_iter().enumerate() {
+                        self.memory.write_uint(address + XSAVE_OPMASK_OFFSET as u64 + 8 * index as u64, 8, value)?;
+                    }
+                }
+                let saved = self.memory.read_uint(address + HEADER, 8)?;
+                self.memory.write_uint(address + HEADER, 8, saved | requested)?;
+            }
+
+            // the requested components saved in the area are loaded, the others put in their initial state
+            Mnemonic::Xrstor | Mnemonic::Xrstor64 => {
+                let address = self.xsave_address(instruction)?;
+                let requested = self.requested_components();
+                let saved = self.memory.read_uint(address + HEADER, 8)?;
+                let mut reserved = [0; 16];
+                self.memory.read(address + HEADER + 8, &mut reserved)?;
+                if saved & !self.model.xcr0() != 0 || reserved.iter().any(|&byte| byte != 0) {
+                    return Err(general_protection);
+                }
+                if requested & (XSTATE_SSE | XSTATE_AVX) != 0 {
+                    let mxcsr = self.memory.read_uint(address + 24, 4)? as u32;
+                    self.load_mxcsr(instruction, mxcsr)?;
+                }
+
+                let (loaded, initialized) = (requested & saved, requested & !saved);
+                if loaded & XSTATE_X87 != 0 {
+                    self.load_x87_fxsave(address, instruction.mnemonic() == Mnemonic::Xrstor64)?;
+                }
+                if initialized & XSTATE_X87 != 0 {
+                    self.registers.x87 = X87::default();
+                }
+                for index in 0..16 {
+                    let mut bytes = [0; 16];
+                    let register = &mut self.registers.sse.zmm[index];
+                    if loaded & XSTATE_SSE != 0 {
+                        self.memory.read(address + 160 + 16 * index as u64, &mut bytes)?;
+                        register[0] = u128::from_le_bytes(bytes);
+                    }
+                    if loaded & XSTATE_AVX != 0 {
+                        self.memory.read(address + XSAVE_AVX_OFFSET as u64 + 16 * index as u64, &mut bytes)?;
+                        register[1] = u128::from_le_bytes(bytes);
+                    }
+                    if loaded & XSTATE_ZMM_HI256 != 0 {
+                        let mut bytes = [0; 64];
+                        self.memory.read(address + XSAVE_ZMM_HI256_OFFSET as u64 + 32 * index as u64, &mut bytes[..32])?;
+                        register[2..].copy_from_slice(&vector_from_bytes(&bytes)[..2]);
+                    }
+                    if initialized & XSTATE_SSE != 0 {
+                        register[0] = 0;
+                    }
+                    if initialized & XSTATE_AVX != 0 {
+                        register[1] = 0;
+                    }
+                    if initialized & XSTATE_ZMM_HI256 != 0 {
+                        register[2..].fill(0);
+                    }
+                }
+                for index in 16..32 {
+                    if loaded & XSTATE_HI16_ZMM != 0 {
+                        let mut bytes = [0; 64];
+                        self.memory.read(address + XSAVE_HI16_ZMM_OFFSET as u64 + 64 * (index as u64 - 16), &mut bytes)?;
+                        self.registers.sse.zmm[index] = vector_from_bytes(&bytes);
+                    }
+                    if initialized & XSTATE_HI16_ZMM != 0 {
+                        self.registers.sse.zmm[index] = [0; 4];
+                    }
+                }
+                for index in 0..8 {
+                    if loaded & XSTATE_OPMASK != 0 {
+                        self.registers.sse.k[index] = self.memory.read_uint(address + XSAVE_OPMASK_OFFSET as u64 + 8 * index as u64, 8)?;
+                    }
+                    if initialized & XSTATE_OPMASK != 0 {
+                        self.registers.sse.k[index] = 0;
+                    }
+                }
+            }
+            _ => return Err(Error::UnimplementedInstruction(*instruction)),
+        }
+        Ok(())
+    }
+
+    /// Components selected by EDX:EAX among those enabled in XCR0
+    fn requested_components(&self) -> u64 {
+        let requested = self.registers.rdx << 32 | (self.registers.rax & 0xffff_ffff);
+        requested & self.model.xcr0()
+    }
+
+    /// The xsave area must be aligned on 64 bytes
+    fn xsave_address(&self, instruction: &Instruction) -> Result<u64, Error> {
+        let address = self.memory_address(instruction)?;
+        match address % 64 {
+            0 => Ok(address),
+            _ => Err(Error::GeneralProtectionFault(instruction.ip())),
+        }
+    }
+}
@@ -20,9 +20,25 @@ async fn main() -> Result<()> {
     logger::try_init(args.log_level)?;
 
     // read binary file
-    let binary = tokio::fs::read(args.binary_path).await?;
-    let execution = program::execute_from_binary_slice(&binary)?;
-    info!("program exited with code {}", execution.exit_code);
+    let binary = tokio::fs::read(&args.binary_path).await?;
+
+    let mut argv = vec![args.binary_path.to_string_lossy().into_owned()];
+    argv.extend(args.args);
+    let options = program::Options {
+        argv,
+        envp: args.env,
+        load_base: args.load_base,
+        random_seed: args.random_seed,
+        sysroot: args.sysroot,
+        linker: args.linker,
+        cpu_model: args.cpu_model,
+    };
+
+    let execution = program::execute_from_binary_slice(&binary, &options)?;
+    match execution.signal {
+        Some(signal) => info!("program was terminated by signal {}", signal),
+        None => info!("program exited with code {}", execution.exit_code),
+    }
     info!("stdout: {:?}", execution.stdout);
     info!("stderr: {:?}", execution.stderr);
 
@@ -0,0 +1,25 @@
+use super::Access;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// guest access to an address which is not mapped
+    Unmapped { address: u64, access: Access },
+    /// guest access not allowed by the page protection
+    ProtectionViolation { address: u64, access: Access },
+    /// host-side operation on an address which is not mapped
+    NotMapped(u64),
+    AlreadyMapped(u64),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unmapped { address, access } => write!(f, "{} of unmapped address 0x{:x}", access, address),
+            Self::ProtectionViolation { address, access } => write!(f, "{} of address 0x{:x} violates page protection", access, address),
+            Self::NotMapped(address) => write!(f, "address 0x{:x} is not mapped", address),
+            Self::AlreadyMapped(address) => write!(f, "address 0x{:x} is already mapped", address),
+        }
+    }
+}
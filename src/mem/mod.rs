@@ -1,102 +1,321 @@
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct StackPointer {
-    pointer: u64,
-    stack_size: u64,
+pub mod error;
+
+use std::collections::BTreeMap;
+
+use error::Error;
+
+pub const PAGE_SIZE: u64 = 0x1000; // 4 KiB
+
+/// Lowest address handed out by [`Memory::find_free`], like Linux' vm.mmap_min_addr
+pub const MIN_MAP_ADDRESS: u64 = 0x10000;
+
+pub fn page_align_down(address: u64) -> u64 {
+    address & !(PAGE_SIZE - 1)
 }
 
-impl StackPointer {
-    pub fn new(value: u64, stack_size: u64) -> Self {
-        Self {
-            pointer: value % stack_size,
-            stack_size
-        }
-    }
+pub fn page_align_up(address: u64) -> u64 {
+    page_align_down(address.saturating_add(PAGE_SIZE - 1))
+}
 
-    pub fn value(&self) -> u64 {
-        self.pointer
-    }
+/// Kind of memory access, used for protection checks and page fault reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
 
-    pub fn set_wrapping(&mut self, value: u64) {
-        self.pointer = value % self.stack_size;
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read => write!(f, "read"),
+            Self::Write => write!(f, "write"),
+            Self::Execute => write!(f, "execute"),
+        }
     }
 }
 
-impl Into<u64> for StackPointer {
-    fn into(self) -> u64 {
-        self.pointer
-    }
+/// Page protection, mirroring the x86-64 page table permissions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Protection {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
 }
 
-impl std::ops::Add<StackPointer> for StackPointer {
-    type Output = StackPointer;
+impl Protection {
+    pub const READ_WRITE: Self = Self { read: true, write: true, execute: false };
 
-    fn add(self, x: StackPointer) -> Self::Output {
-        StackPointer::new(self.pointer + x.pointer, self.stack_size)
+    /// Checks whether an access is allowed. Like on real hardware, any
+    /// accessible page is readable (there are no write-only or execute-only pages).
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read || self.write || self.execute,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
     }
 }
 
-impl std::ops::AddAssign<StackPointer> for StackPointer {
-    fn add_assign(&mut self, rhs: StackPointer) {
-        *self = *self + rhs;
+impl std::fmt::Display for Protection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' },
+        )
     }
 }
 
-impl std::ops::Add<u64> for StackPointer {
-    type Output = StackPointer;
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Page {
+    protection: Protection,
+    /// page contents, allocated on first write (unallocated pages read as zero)
+    data: Option<Box<[u8; PAGE_SIZE as usize]>>,
+}
 
-    fn add(self, x: u64) -> Self::Output {
-        StackPointer::new(self.pointer + (x % self.stack_size), self.stack_size)
-    }
+/// Sparse 64-bit guest virtual address space, consisting of mapped pages
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Memory {
+    /// mapped pages, keyed by page base address
+    pages: BTreeMap<u64, Page>,
+    /// executable pages modified since the last call to [`Memory::take_modified_code_pages`]
+    modified_code_pages: Vec<u64>,
 }
 
-impl std::ops::AddAssign<u64> for StackPointer {
-    fn add_assign(&mut self, rhs: u64) {
-        *self = *self + rhs;
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
     }
-}
 
-impl std::ops::Sub<StackPointer> for StackPointer {
-    type Output = StackPointer;
+    /// Maps a zero-filled region covering every page in `address..address + size`
+    pub fn map(&mut self, address: u64, size: u64, protection: Protection) -> Result<(), Error> {
+        let start = page_align_down(address);
+        let end = page_align_up(address.saturating_add(size));
+
+        if let Some((&page_base, _)) = self.pages.range(start..end).next() {
+            return Err(Error::AlreadyMapped(page_base));
+        }
+
+        for page_base in (start..end).step_by(PAGE_SIZE as usize) {
+            self.pages.insert(page_base, Page { protection, data: None });
+        }
 
-    fn sub(self, x: StackPointer) -> Self::Output {
-        StackPointer::new(self.pointer + x.pointer, self.stack_size)
+        Ok(())
     }
-}
 
-impl std::ops::SubAssign<StackPointer> for StackPointer {
-    fn sub_assign(&mut self, rhs: StackPointer) {
-        *self = *self - rhs;
+    /// Checks whether no page in `address..address + size` is mapped
+    pub fn is_free(&self, address: u64, size: u64) -> bool {
+        let start = page_align_down(address);
+        let end = page_align_up(address.saturating_add(size));
+        self.pages.range(start..end).next().is_none()
     }
-}
 
-impl std::ops::Sub<u64> for StackPointer {
-    type Output = StackPointer;
+    /// Finds the highest unmapped region of `size` bytes ending at or below `top`,
+    /// searching downwards like the Linux mmap allocator does
+    pub fn find_free(&self, size: u64, top: u64) -> Option<u64> {
+        let size = page_align_up(size);
+        let mut end = page_align_down(top);
+        for (&page_base, _) in self.pages.range(..end).rev() {
+            if end - (page_base + PAGE_SIZE) >= size {
+                break;
+            }
+            end = page_base;
+        }
+        end.checked_sub(size).filter(|&address| address >= MIN_MAP_ADDRESS)
+    }
 
-    fn sub(self, mut x: u64) -> Self::Output {
-        x %= self.stack_size;
+    /// Unmaps every page in `address..address + size`, ignoring pages which are not mapped
+    pub fn unmap(&mut self, address: u64, size: u64) {
+        let start = page_align_down(address);
+        let end = page_align_up(address.saturating_add(size));
 
-        if self.pointer >= x {
-            StackPointer::new(self.pointer - x, self.stack_size)
-        } else {
-            StackPointer::new(self.stack_size - (x - self.pointer), self.stack_size)
+        let page_bases: Vec<u64> = self.pages.range(start..end).map(|(&page_base, _)| page_base).collect();
+        for page_base in page_bases {
+            if let Some(page) = self.pages.remove(&page_base) {
+                if page.protection.execute {
+                    self.modified_code_pages.push(page_base);
+                }
+            }
         }
     }
-}
 
-impl std::ops::SubAssign<u64> for StackPointer {
-    fn sub_assign(&mut self, rhs: u64) {
-        *self = *self - rhs;
+    /// Moves every page in `address..address + size`, which must all be mapped, to `new_address` with
+    /// their contents and protection. Pages already mapped at the destination are replaced.
+    pub fn remap(&mut self, address: u64, size: u64, new_address: u64) -> Result<(), Error> {
+        let start = page_align_down(address);
+        let end = page_align_up(address.saturating_add(size));
+
+        for page_base in (start..end).step_by(PAGE_SIZE as usize) {
+            if !self.pages.contains_key(&page_base) {
+                return Err(Error::NotMapped(page_base));
+            }
+        }
+        let pages: Vec<(u64, Page)> = (start..end).step_by(PAGE_SIZE as usize)
+            .filter_map(|page_base| self.pages.remove(&page_base).map(|page| (page_base, page)))
+            .collect();
+        self.unmap(new_address, end - start);
+        for (page_base, page) in pages {
+            let new_page_base = page_base - start + new_address;
+            if page.protection.execute {
+                self.modified_code_pages.push(page_base);
+                self.modified_code_pages.push(new_page_base);
+            }
+            self.pages.insert(new_page_base, page);
+        }
+
+        Ok(())
     }
-}
 
-impl std::cmp::PartialEq<u64> for StackPointer {
-    fn eq(&self, other: &u64) -> bool {
-        self.pointer.eq(other)
+    /// Changes the protection of every page in `address..address + size`, which must all be mapped
+    pub fn protect(&mut self, address: u64, size: u64, protection: Protection) -> Result<(), Error> {
+        let start = page_align_down(address);
+        let end = page_align_up(address.saturating_add(size));
+
+        for page_base in (start..end).step_by(PAGE_SIZE as usize) {
+            if !self.pages.contains_key(&page_base) {
+                return Err(Error::NotMapped(page_base));
+            }
+        }
+        for (&page_base, page) in self.pages.range_mut(start..end) {
+            if page.protection.execute || protection.execute {
+                self.modified_code_pages.push(page_base);
+            }
+            page.protection = protection;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the protection of the page containing `address`, if it is mapped
+    pub fn protection(&self, address: u64) -> Option<Protection> {
+        self.pages.get(&page_align_down(address)).map(|page| page.protection)
+    }
+
+    pub fn read(&self, address: u64, buf: &mut [u8]) -> Result<(), Error> {
+        self.check_access(address, buf.len(), Access::Read)?;
+        self.read_unchecked(address, buf);
+        Ok(())
+    }
+
+    pub fn write(&mut self, address: u64, buf: &[u8]) -> Result<(), Error> {
+        self.check_access(address, buf.len(), Access::Write)?;
+        self.write_unchecked(address, buf);
+        Ok(())
+    }
+
+    /// Copies `buf` into mapped memory regardless of page protections, as done by a program loader
+    pub fn load(&mut self, address: u64, buf: &[u8]) -> Result<(), Error> {
+        self.check_mapped(address, buf.len())?;
+        self.write_unchecked(address, buf);
+        Ok(())
+    }
+
+    /// Reads instruction bytes into `buf`, stopping at the first byte which is not
+    /// executable. Returns the number of bytes read.
+    pub fn fetch(&self, address: u64, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        let _ = self.for_each_page(address, buf.len(), |current, page| match page {
+            Some(page) if page.protection.allows(Access::Execute) => {
+                len = (page_align_down(current).wrapping_add(PAGE_SIZE).wrapping_sub(address) as usize).min(buf.len());
+                Ok(())
+            }
+            _ => Err(Error::Unmapped { address: current, access: Access::Execute }),
+        });
+        self.read_unchecked(address, &mut buf[..len]);
+        len
+    }
+
+    /// Returns the base addresses of executable pages which have been written to or
+    /// had their protection changed since the last call, e.g. to invalidate decoded instructions
+    pub fn take_modified_code_pages(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.modified_code_pages)
+    }
+
+    /// Reads a little-endian unsigned integer of `size` bytes (1, 2, 4 or 8), zero-extended to 64 bits
+    pub fn read_uint(&self, address: u64, size: usize) -> Result<u64, Error> {
+        let mut bytes = [0u8; 8];
+        self.read(address, &mut bytes[..size.min(8)])?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Writes the low `size` bytes (1, 2, 4 or 8) of `value` in little-endian byte order
+    pub fn write_uint(&mut self, address: u64, size: usize, value: u64) -> Result<(), Error> {
+        self.write(address, &value.to_le_bytes()[..size.min(8)])
     }
-}
 
-impl std::cmp::PartialOrd<u64> for StackPointer {
-    fn partial_cmp(&self, other: &u64) -> Option<std::cmp::Ordering> {
-        Some(self.pointer.cmp(other))
+    /// Checks that every page in `address..address + size` is mapped and allows `access`
+    pub fn check_access(&self, address: u64, size: usize, access: Access) -> Result<(), Error> {
+        self.for_each_page(address, size, |current, page| match page {
+            None => Err(Error::Unmapped { address: current, access }),
+            Some(page) if !page.protection.allows(access) => Err(Error::ProtectionViolation { address: current, access }),
+            Some(_) => Ok(()),
+        })
     }
-}
\ No newline at end of file
+
+    fn check_mapped(&self, address: u64, size: usize) -> Result<(), Error> {
+        self.for_each_page(address, size, |current, page| match page {
+            None => Err(Error::NotMapped(current)),
+            Some(_) => Ok(()),
+        })
+    }
+
+    /// Calls `f` with the first accessed address and the page (if mapped) of every page touched by `address..address + size`
+    fn for_each_page<F>(&self, address: u64, size: usize, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(u64, Option<&Page>) -> Result<(), Error>,
+    {
+        if size == 0 {
+            return Ok(());
+        }
+        let last_page_base = page_align_down(address.wrapping_add(size as u64 - 1));
+        let mut current = address;
+        loop {
+            let page_base = page_align_down(current);
+            f(current, self.pages.get(&page_base))?;
+            if page_base == last_page_base {
+                return Ok(());
+            }
+            current = page_base.wrapping_add(PAGE_SIZE);
+        }
+    }
+
+    fn read_unchecked(&self, address: u64, buf: &mut [u8]) {
+        let mut offset = 0;
+        while offset < buf.len() {
+            let current = address.wrapping_add(offset as u64);
+            let page_base = page_align_down(current);
+            let page_offset = (current - page_base) as usize;
+            let len = (PAGE_SIZE as usize - page_offset).min(buf.len() - offset);
+
+            match self.pages.get(&page_base).and_then(|page| page.data.as_ref()) {
+                Some(data) => buf[offset..offset + len].copy_from_slice(&data[page_offset..page_offset + len]),
+                None => buf[offset..offset + len].fill(0),
+            }
+
+            offset += len;
+        }
+    }
+
+    fn write_unchecked(&mut self, address: u64, buf: &[u8]) {
+        let mut offset = 0;
+        while offset < buf.len() {
+            let current = address.wrapping_add(offset as u64);
+            let page_base = page_align_down(current);
+            let page_offset = (current - page_base) as usize;
+            let len = (PAGE_SIZE as usize - page_offset).min(buf.len() - offset);
+
+            if let Some(page) = self.pages.get_mut(&page_base) {
+                if page.protection.execute && self.modified_code_pages.last() != Some(&page_base) {
+                    self.modified_code_pages.push(page_base);
+                }
+                let data = page.data.get_or_insert_with(|| Box::new([0; PAGE_SIZE as usize]));
+                data[page_offset..page_offset + len].copy_from_slice(&buf[offset..offset + len]);
+            }
+
+            offset += len;
+        }
+    }
+}
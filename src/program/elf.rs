@@ -0,0 +1,230 @@
+use super::error::Error;
+use super::stack::{AT_BASE, AT_CLKTCK, AT_EGID, AT_ENTRY, AT_EUID, AT_FLAGS, AT_GID, AT_HWCAP, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_SECURE, AT_UID};
+use super::random::Random;
+use super::{file_range, load_segment};
+
+use crate::cpu::Cpu;
+use crate::mem::{page_align_down, Protection, PAGE_SIZE};
+
+use goblin::elf::program_header::{PF_R, PF_W, PF_X, PT_LOAD, PT_PHDR};
+use goblin::elf::header::ET_DYN;
+use goblin::elf::reloc::R_X86_64_RELATIVE;
+use goblin::elf::Elf;
+
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadedElf {
+    /// offset added to every virtual address of the image
+    pub bias: u64,
+    /// address of the lowest loaded page
+    pub base: u64,
+    /// entry point address
+    pub entry: u64,
+    /// initial program break, directly after the highest loaded segment
+    pub brk: u64,
+    /// address of the program headers in memory
+    pub phdr: u64,
+    /// size of a program header entry
+    pub phent: u64,
+    /// number of program headers
+    pub phnum: u64,
+}
+
+/// uid and gid reported to the program
+const USER_ID: u64 = 1000;
+
+impl LoadedElf {
+    /// Auxiliary vector entries describing the loaded image, started through the
+    /// interpreter loaded at `interpreter_base` (0 for statically linked programs)
+    pub fn auxv(&self, interpreter_base: u64) -> Vec<(u64, u64)> {
+        vec![
+            (AT_HWCAP, 0),
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_CLKTCK, 100),
+            (AT_PHDR, self.phdr),
+            (AT_PHENT, self.phent),
+            (AT_PHNUM, self.phnum),
+            (AT_BASE, interpreter_base),
+            (AT_FLAGS, 0),
+            (AT_ENTRY, self.entry),
+            (AT_UID, USER_ID),
+            (AT_EUID, USER_ID),
+            (AT_GID, USER_ID),
+            (AT_EGID, USER_ID),
+            (AT_SECURE, 0),
+        ]
+    }
+}
+
+pub fn protection(p_flags: u32) -> Protection {
+    Protection {
+        read: p_flags & PF_R != 0,
+        write: p_flags & PF_W != 0,
+        execute: p_flags & PF_X != 0,
+    }
+}
+
+/// Address position-independent executables are loaded at by default,
+/// matching the Linux kernel with address space layout randomization disabled
+pub const DEFAULT_PIE_BASE: u64 = 0x5555_5555_4000;
+
+/// Number of random page offset bits applied to a randomized load base (mmap_rnd_bits)
+const PIE_RANDOM_BITS: u32 = 28;
+
+/// Computes the load bias (the offset added to every virtual address) of an ELF image.
+/// Only position-independent (`ET_DYN`) images can be relocated, for those the
+/// lowest segment is placed at `base`, optionally shifted by a random page offset.
+pub fn load_bias(elf: &Elf, base: u64, random: Option<&mut Random>) -> Result<u64, Error> {
+    if elf.header.e_type != ET_DYN {
+        return Ok(0);
+    }
+
+    let (lowest_vaddr, _) = load_range(elf)?;
+
+    let mut base = base;
+    if let Some(random) = random {
+        base = base.wrapping_add((random.next_u64() & ((1 << PIE_RANDOM_BITS) - 1)) * PAGE_SIZE);
+    }
+    // segments must keep their alignment relative to each other
+    let base = base & !(load_alignment(elf)? - 1);
+
+    Ok(base.wrapping_sub(lowest_vaddr))
+}
+
+/// Page-aligned range of virtual addresses covered by the `PT_LOAD` segments
+fn load_range(elf: &Elf) -> Result<(u64, u64), Error> {
+    let load_headers = || elf.program_headers.iter().filter(|phdr| phdr.p_type == PT_LOAD);
+    let lowest = load_headers().map(|phdr| page_align_down(phdr.p_vaddr)).min().unwrap_or_default();
+    let mut highest = 0;
+    for phdr in load_headers() {
+        let end = phdr.p_vaddr.checked_add(phdr.p_memsz).ok_or(Error::InvalidElf)?;
+        highest = highest.max(page_align_up_checked(end)?);
+    }
+    Ok((lowest, highest))
+}
+
+/// Largest alignment of the `PT_LOAD` segments, at least the page size
+fn load_alignment(elf: &Elf) -> Result<u64, Error> {
+    elf.program_headers.iter()
+        .filter(|phdr| phdr.p_type == PT_LOAD)
+        .map(|phdr| phdr.p_align)
+        .max()
+        .unwrap_or(PAGE_SIZE)
+        .max(PAGE_SIZE)
+        .checked_next_power_of_two()
+        .ok_or(Error::InvalidElf)
+}
+
+/// Loads the program interpreter (dynamic linker) from the host file at `path`
+pub fn load_interpreter(cpu: &mut Cpu, path: &Path, top: u64) -> Result<LoadedElf, Error> {
+    let binary = std::fs::read(path).map_err(|e| Error::ElfInterpreterRead(path.to_path_buf(), e))?;
+    let elf = Elf::parse(&binary)?;
+    load_shared(cpu, &elf, &binary, top)
+}
+
+/// Loads a shared object. A position-independent image is placed in the highest
+/// free region below `top`, like an mmap.
+pub fn load_shared(cpu: &mut Cpu, elf: &Elf, binary: &[u8], top: u64) -> Result<LoadedElf, Error> {
+    let bias = if elf.header.e_type == ET_DYN {
+        let (lowest, highest) = load_range(elf)?;
+        let alignment = load_alignment(elf)?;
+        let size = (highest - lowest).checked_add(alignment).ok_or(Error::InvalidElf)?;
+        let base = cpu.memory.find_free(size, top).ok_or(Error::OutOfMemory)?;
+        page_align_up_to(base, alignment)?.wrapping_sub(lowest)
+    } else {
+        0
+    };
+
+    load(cpu, elf, binary, bias)
+}
+
+fn page_align_up_to(address: u64, alignment: u64) -> Result<u64, Error> {
+    address.checked_add(alignment - 1).map(|address| address & !(alignment - 1)).ok_or(Error::InvalidElf)
+}
+
+/// Rounds up to the next page boundary, failing instead of saturating at the top of the address space
+fn page_align_up_checked(address: u64) -> Result<u64, Error> {
+    page_align_up_to(address, PAGE_SIZE)
+}
+
+/// Maps every `PT_LOAD` segment of an ELF image into guest memory at `p_vaddr + bias`, the same way the
+/// Linux kernel does: whole file pages are mapped at the page containing the segment, and
+/// `p_memsz - p_filesz` bytes after the file contents are zero-filled.
+pub fn load(cpu: &mut Cpu, elf: &Elf, binary: &[u8], bias: u64) -> Result<LoadedElf, Error> {
+    let mut brk = 0;
+    let mut phdr_address = None;
+    let mut loaded = false;
+
+    for phdr in elf.program_headers.iter().filter(|phdr| phdr.p_type == PT_LOAD) {
+        if phdr.p_filesz > phdr.p_memsz {
+            return Err(Error::ElfSegmentInvalid);
+        }
+        // the file offset and virtual address must be congruent modulo the alignment
+        if phdr.p_align > 1 && phdr.p_vaddr.wrapping_sub(phdr.p_offset) % phdr.p_align != 0 {
+            return Err(Error::ElfSegmentMisaligned);
+        }
+
+        let vaddr = bias.wrapping_add(phdr.p_vaddr);
+        let page_start = page_align_down(vaddr);
+        let page_offset = vaddr - page_start;
+        let file_offset = phdr.p_offset.checked_sub(page_offset).ok_or(Error::ElfSegmentMisaligned)?;
+        // p_filesz <= p_memsz, so checking the memory size covers the file size as well
+        let mem_end = vaddr.checked_add(phdr.p_memsz).ok_or(Error::InvalidElf)?;
+        let mapped_size = page_offset.checked_add(phdr.p_memsz).ok_or(Error::InvalidElf)?;
+        let data = file_range(binary, file_offset, page_offset + phdr.p_filesz)?;
+        load_segment(cpu, page_start, mapped_size, data, protection(phdr.p_flags))?;
+
+        // zero-fill the rest of the last file page, which may hold data from a previously loaded segment
+        let file_end = vaddr + phdr.p_filesz;
+        let zero_end = mem_end.min(page_align_up_checked(file_end)?);
+        if zero_end > file_end {
+            cpu.memory.load(file_end, &vec![0; (zero_end - file_end) as usize])?;
+        }
+
+        // the program headers are usually mapped as part of the first segment
+        let phdr_offset = elf.header.e_phoff;
+        if phdr_address.is_none() && phdr_offset >= phdr.p_offset && phdr_offset - phdr.p_offset < phdr.p_filesz {
+            phdr_address = Some(vaddr + (phdr_offset - phdr.p_offset));
+        }
+
+        brk = brk.max(page_align_up_checked(mem_end)?);
+        loaded = true;
+    }
+
+    if !loaded {
+        return Err(Error::ElfLoadHeaderMissing);
+    }
+
+    // PT_PHDR takes precedence if present
+    let phdr = elf.program_headers.iter()
+        .find(|phdr| phdr.p_type == PT_PHDR)
+        .map(|phdr| bias.wrapping_add(phdr.p_vaddr))
+        .or(phdr_address)
+        .unwrap_or_default();
+
+    Ok(LoadedElf {
+        bias,
+        base: bias.wrapping_add(load_range(elf)?.0),
+        entry: bias.wrapping_add(elf.header.e_entry),
+        brk,
+        phdr,
+        phent: elf.header.e_phentsize as u64,
+        phnum: elf.program_headers.len() as u64,
+    })
+}
+
+/// Applies the `R_X86_64_RELATIVE` relocations of a loaded position-independent image. Other
+/// relocation types need symbol resolution and are left to the dynamic linker.
+pub fn relocate_relative(cpu: &mut Cpu, elf: &Elf, bias: u64) -> Result<(), Error> {
+    if bias == 0 {
+        return Ok(());
+    }
+
+    for reloc in elf.dynrelas.iter().filter(|reloc| reloc.r_type == R_X86_64_RELATIVE) {
+        let value = bias.wrapping_add_signed(reloc.r_addend.unwrap_or_default());
+        cpu.memory.load(bias.wrapping_add(reloc.r_offset), &value.to_le_bytes())?;
+    }
+
+    Ok(())
+}
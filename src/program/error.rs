@@ -1,18 +1,27 @@
 #[derive(Debug)]
 pub enum Error {
     Cpu(crate::cpu::error::Error),
+    Memory(crate::mem::error::Error),
     Goblin(goblin::error::Error),
     Iced(iced_x86::IcedError),
     FromUtf8Error(std::string::FromUtf8Error),
-    UnimplementedInstructionPointerOutsideProgramSpace,
-    UnimplementedSyscall(u64),
     UnimplementedFileDescriptor(u64),
     UnimplementedBinaryFileFormat,
     ProgramDidNotExit,
     ElfLoadHeaderMissing,
+    ElfSegmentInvalid,
+    ElfSegmentMisaligned,
+    ElfTlsSegmentInvalid,
+    ElfInterpreterRead(std::path::PathBuf, std::io::Error),
+    InvalidElf,
+    SharedLibraryNotFound(String),
+    UndefinedSymbol(String),
+    UnimplementedRelocation(u32),
+    OutOfMemory,
     PeOptionalHeaderMissing,
     MachOLoadCommandMissing,
     MachFatNoX86,
+    SegmentOutOfBounds,
 }
 
 impl std::error::Error for Error {}
@@ -21,18 +30,27 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Cpu(e) => write!(f, "CPU error: {}", e),
+            Self::Memory(e) => write!(f, "memory error: {}", e),
             Self::Goblin(e) => write!(f, "error parsing binary program file: {}", e),
             Self::Iced(e) => write!(f, "error parsing program code: {}", e),
             Self::FromUtf8Error(e) => write!(f, "error during write syscall: invalid utf-8 string: {}", e),
-            Self::UnimplementedInstructionPointerOutsideProgramSpace => write!(f, "instruction pointer changed by program and is no longer within the program space - memory is not implemented"),
-            Self::UnimplementedSyscall(number) => write!(f, "syscall {} (0x{:x}) is not implemented", number, number),
             Self::UnimplementedFileDescriptor(fd) => write!(f, "file descriptor {} (0x{:x}) is not implemented", fd, fd),
             Self::UnimplementedBinaryFileFormat => write!(f, "unimplemented binary file format"),
             Self::ProgramDidNotExit => write!(f, "program did not exit in a clean manner"),
             Self::ElfLoadHeaderMissing => write!(f, "unable to find ELF load header"),
+            Self::ElfSegmentInvalid => write!(f, "ELF load segment is larger in the file than in memory"),
+            Self::ElfInterpreterRead(path, e) => write!(f, "unable to read ELF interpreter {}: {}", path.display(), e),
+            Self::InvalidElf => write!(f, "ELF segment addresses, sizes or alignment overflow the address space"),
+            Self::SharedLibraryNotFound(name) => write!(f, "unable to find shared library {}", name),
+            Self::UndefinedSymbol(name) => write!(f, "undefined symbol {}", name),
+            Self::UnimplementedRelocation(r_type) => write!(f, "relocation type {} is not implemented", r_type),
+            Self::OutOfMemory => write!(f, "not enough free guest address space"),
+            Self::ElfTlsSegmentInvalid => write!(f, "ELF TLS segment is larger in the file than in memory"),
+            Self::ElfSegmentMisaligned => write!(f, "ELF load segment file offset and virtual address are not congruent modulo the alignment"),
             Self::PeOptionalHeaderMissing => write!(f, "unable to find PE optional header"),
-            Self::MachOLoadCommandMissing => write!(f, "unable to find Mach-O load command LC_MAIN"),
+            Self::MachOLoadCommandMissing => write!(f, "unable to find Mach-O entry point load command (LC_MAIN or LC_UNIXTHREAD)"),
             Self::MachFatNoX86 => write!(f, "unable to find an x86 binary in fat Mach binary"),
+            Self::SegmentOutOfBounds => write!(f, "segment data lies outside of the binary program file"),
         }
     }
 }
@@ -43,6 +61,12 @@ impl From<crate::cpu::error::Error> for Error {
     }
 }
 
+impl From<crate::mem::error::Error> for Error {
+    fn from(e: crate::mem::error::Error) -> Self {
+        Self::Memory(e)
+    }
+}
+
 impl From<goblin::error::Error> for Error {
     fn from(e: goblin::error::Error) -> Self {
         Self::Goblin(e)
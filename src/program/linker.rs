@@ -0,0 +1,452 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use super::error::Error;
+use super::tls::{self, TlsLayout, TlsModule};
+use super::{call_function, elf, Process};
+
+use crate::cpu::Cpu;
+use crate::mem::{page_align_down, Access, Protection};
+
+use goblin::elf::dynamic::{DT_INIT, DT_INIT_ARRAY, DT_INIT_ARRAYSZ, DT_PREINIT_ARRAY, DT_PREINIT_ARRAYSZ};
+use goblin::elf::program_header::{PT_GNU_RELRO, PT_TLS};
+use goblin::elf::reloc::{
+    R_X86_64_64, R_X86_64_COPY, R_X86_64_DTPMOD64, R_X86_64_DTPOFF64, R_X86_64_GLOB_DAT, R_X86_64_IRELATIVE,
+    R_X86_64_JUMP_SLOT, R_X86_64_NONE, R_X86_64_RELATIVE, R_X86_64_TPOFF64,
+};
+use goblin::elf::section_header::SHN_UNDEF;
+use goblin::elf::sym::{STB_LOCAL, STB_WEAK, STT_GNU_IFUNC};
+use goblin::elf::{Elf, Reloc};
+use log::debug;
+
+/// Directories searched for shared objects after DT_RUNPATH/DT_RPATH, like the default ld.so search path
+const DEFAULT_LIBRARY_PATHS: &[&str] = &[
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib64",
+    "/usr/lib64",
+    "/lib",
+    "/usr/lib",
+];
+
+// packed relative relocations, not defined by goblin
+const DT_RELRSZ: u64 = 35;
+const DT_RELR: u64 = 36;
+
+/// DT_SONAME of the C library
+const LIBC_SONAME: &str = "libc.so.6";
+
+/// Function of the C library ld.so calls with `initial = true` after relocation, before any initialization function
+const LIBC_EARLY_INIT: &str = "__libc_early_init";
+
+/// Shared object read from the sysroot
+struct Library {
+    /// name as given in DT_NEEDED
+    name: String,
+    binary: Vec<u8>,
+}
+
+/// Object taking part in symbol resolution, in global scope order (the executable first)
+struct Object<'a> {
+    name: &'a str,
+    elf: &'a Elf<'a>,
+    bias: u64,
+    /// defined dynamic symbols by name, in their default version
+    symbols: HashMap<&'a str, Symbol>,
+    /// defined dynamic symbols by name and version, including non-default versions
+    versioned_symbols: HashMap<(&'a str, &'a str), Symbol>,
+    /// thread-local storage module, if the object has a PT_TLS segment
+    tls: Option<TlsModule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Symbol {
+    /// relocated address (or offset in the TLS block for thread-local symbols)
+    value: u64,
+    size: u64,
+    ifunc: bool,
+}
+
+/// Loads the shared objects a dynamically linked executable depends on and performs
+/// its relocations in the emulator instead of running the program interpreter.
+/// Initialization functions of the shared objects and then of the executable are run
+/// before returning, so the program can be started at its entry point. The thread
+/// pointer of the initial thread is set up with the static TLS block of all loaded objects.
+pub fn link(cpu: &mut Cpu, process: &mut Process, executable: &Elf, bias: u64, random: [u8; 16]) -> Result<(), Error> {
+    let libraries = find_libraries(process, executable)?;
+    let elfs = libraries.iter()
+        .map(|library| Elf::parse(&library.binary))
+        .collect::<Result<Vec<Elf>, goblin::error::Error>>()?;
+
+    let mut objects = vec![Object::new(executable, "", bias)];
+    for (library, library_elf) in libraries.iter().zip(&elfs) {
+        let loaded = elf::load_shared(cpu, library_elf, &library.binary, process.mmap_top)?;
+        debug!("LINKER: loaded {} at 0x{:x}", library.name, loaded.base);
+        objects.push(Object::new(library_elf, &library.name, loaded.bias));
+    }
+
+    // resolvers of indirect functions may already access thread-local data
+    let tls_layout = assign_tls_modules(&mut objects)?;
+    let thread_pointer = tls::allocate(cpu, process.mmap_top, &tls_layout, random)?;
+    cpu.registers.fs_base = thread_pointer;
+
+    // dependencies are relocated before the objects using them, the executable last
+    for index in (0..objects.len()).rev() {
+        relocate(cpu, process, &objects, index)?;
+    }
+
+    // initialization images may contain relocated pointers
+    tls::copy_images(cpu, thread_pointer, &tls_layout)?;
+
+    // libc sets up its internal state before any initialization function runs
+    let early_init = objects.iter()
+        .find(|object| object.elf.soname == Some(LIBC_SONAME))
+        .and_then(|libc| libc.symbols.get(LIBC_EARLY_INIT));
+    if let Some(early_init) = early_init {
+        call_function(cpu, process, early_init.value, &[1])?;
+    }
+
+    // like ld.so, which runs them for the executable as well since glibc 2.34 (its startup code no
+    // longer does), dependencies are initialized before the objects using them and the executable last
+    let args = initial_arguments(cpu)?;
+    let preinit_array = dynamic_value(executable, DT_PREINIT_ARRAY);
+    run_init_array(cpu, process, bias.wrapping_add(preinit_array), dynamic_value(executable, DT_PREINIT_ARRAYSZ), &args)?;
+    for object in objects.iter().skip(1).rev().chain(objects.first()) {
+        if process.exit_code.is_some() {
+            break;
+        }
+        // goblin's DynamicInfo holds file offsets for these, so the addresses are read from the entries
+        let init = dynamic_value(object.elf, DT_INIT);
+        if init != 0 {
+            call_function(cpu, process, object.bias.wrapping_add(init), &args)?;
+        }
+        let init_array = dynamic_value(object.elf, DT_INIT_ARRAY);
+        run_init_array(cpu, process, object.bias.wrapping_add(init_array), dynamic_value(object.elf, DT_INIT_ARRAYSZ), &args)?;
+    }
+
+    Ok(())
+}
+
+impl<'a> Object<'a> {
+    fn new(elf: &'a Elf<'a>, name: &'a str, bias: u64) -> Self {
+        let mut symbols = HashMap::new();
+        let mut versioned_symbols = HashMap::new();
+        for (index, sym) in elf.dynsyms.iter().enumerate() {
+            if sym.st_shndx == SHN_UNDEF as usize || sym.st_bind() == STB_LOCAL {
+                continue;
+            }
+            let Some(name) = elf.dynstrtab.get_at(sym.st_name) else {
+                continue;
+            };
+            let value = if sym.st_type() == goblin::elf::sym::STT_TLS {
+                sym.st_value
+            } else {
+                bias.wrapping_add(sym.st_value)
+            };
+            let symbol = Symbol {
+                value,
+                size: sym.st_size,
+                ifunc: sym.st_type() == STT_GNU_IFUNC,
+            };
+            if let Some(version) = version_name(elf, index) {
+                versioned_symbols.entry((name, version)).or_insert(symbol);
+            }
+            // non-default versions are only used by references asking for them
+            if !elf.versym.as_ref().and_then(|versym| versym.get_at(index)).is_some_and(|versym| versym.is_hidden()) {
+                symbols.entry(name).or_insert(symbol);
+            }
+        }
+
+        Self {
+            name,
+            elf,
+            bias,
+            symbols,
+            versioned_symbols,
+            tls: None,
+        }
+    }
+}
+
+/// Name of the version of a dynamic symbol, either required from another object (in DT_VERNEED)
+/// or defined by the object itself (in DT_VERDEF)
+fn version_name<'a>(elf: &'a Elf, index: usize) -> Option<&'a str> {
+    let version = elf.versym.as_ref()?.get_at(index)?.version();
+    // 0 and 1 are the local and global (unversioned) indices
+    if version < 2 {
+        return None;
+    }
+    let needed = elf.verneed.as_ref().and_then(|verneed| {
+        verneed.iter()
+            .flat_map(|need| need.iter().collect::<Vec<_>>())
+            .find(|aux| aux.vna_other == version)
+            .map(|aux| aux.vna_name)
+    });
+    let defined = || elf.verdef.as_ref().and_then(|verdef| {
+        verdef.iter()
+            .find(|def| def.vd_ndx == version)
+            .and_then(|def| def.iter().next())
+            .map(|aux| aux.vda_name)
+    });
+    elf.dynstrtab.get_at(needed.or_else(defined)?)
+}
+
+/// Finds the shared objects needed by the executable, breadth-first like ld.so
+fn find_libraries(process: &Process, executable: &Elf) -> Result<Vec<Library>, Error> {
+    let mut libraries: Vec<Library> = Vec::new();
+    let mut queue: VecDeque<(String, Vec<String>)> = executable.libraries.iter()
+        .map(|name| (name.to_string(), search_paths(executable, None)))
+        .collect();
+
+    while let Some((name, paths)) = queue.pop_front() {
+        if libraries.iter().any(|library| library.name == name) {
+            continue;
+        }
+
+        let (path, binary) = read_library(process, &name, &paths)?;
+        let library_elf = Elf::parse(&binary)?;
+        let origin = path.parent().map(Path::to_path_buf);
+        for needed in &library_elf.libraries {
+            queue.push_back((needed.to_string(), search_paths(&library_elf, origin.as_deref())));
+        }
+        drop(library_elf);
+
+        libraries.push(Library { name, binary });
+    }
+
+    Ok(libraries)
+}
+
+/// DT_RUNPATH (or DT_RPATH if there is none) of an object, with `$ORIGIN` substituted
+fn search_paths(object: &Elf, origin: Option<&Path>) -> Vec<String> {
+    let paths = if object.runpaths.is_empty() { &object.rpaths } else { &object.runpaths };
+    paths.iter()
+        .flat_map(|paths| paths.split(':'))
+        .filter_map(|path| match (path.contains("$ORIGIN"), origin) {
+            (true, Some(origin)) => Some(path.replace("$ORIGIN", &origin.to_string_lossy())),
+            (true, None) => None,
+            (false, _) => Some(path.to_string()),
+        })
+        .collect()
+}
+
+/// Reads a shared object from the sysroot, returning its guest path and contents
+fn read_library(process: &Process, name: &str, paths: &[String]) -> Result<(PathBuf, Vec<u8>), Error> {
+    let candidates: Vec<PathBuf> = if name.contains('/') {
+        vec![PathBuf::from(name)]
+    } else {
+        paths.iter()
+            .map(String::as_str)
+            .chain(DEFAULT_LIBRARY_PATHS.iter().copied())
+            .map(|directory| Path::new(directory).join(name))
+            .collect()
+    };
+
+    for path in candidates {
+        if let Ok(binary) = process.sysroot_path(&path).and_then(std::fs::read) {
+            return Ok((path, binary));
+        }
+    }
+    Err(Error::SharedLibraryNotFound(name.to_string()))
+}
+
+/// Lays out the static TLS block, with the module of the executable closest to the thread pointer
+fn assign_tls_modules(objects: &mut [Object]) -> Result<TlsLayout, Error> {
+    let mut layout = TlsLayout::default();
+    for object in objects.iter_mut() {
+        if let Some(phdr) = object.elf.program_headers.iter().find(|phdr| phdr.p_type == PT_TLS) {
+            object.tls = Some(layout.add(phdr, object.bias)?);
+        }
+    }
+    Ok(layout)
+}
+
+/// Looks up a symbol in global scope order, optionally skipping the executable (for copy relocations).
+/// A reference to a specific version is bound to that version, or to a definition in its default version.
+fn lookup<'a>(objects: &'a [Object], name: &str, version: Option<&str>, skip_executable: bool) -> Option<(&'a Object<'a>, Symbol)> {
+    objects.iter()
+        .skip(skip_executable as usize)
+        .find_map(|object| {
+            version.and_then(|version| object.versioned_symbols.get(&(name, version)))
+                .or_else(|| object.symbols.get(name))
+                .map(|symbol| (object, *symbol))
+        })
+}
+
+fn relocate(cpu: &mut Cpu, process: &mut Process, objects: &[Object], index: usize) -> Result<(), Error> {
+    let object = &objects[index];
+    let mut irelative = Vec::new();
+
+    relocate_relr(cpu, object)?;
+
+    let relocs = object.elf.dynrelas.iter()
+        .chain(object.elf.dynrels.iter())
+        .chain(object.elf.pltrelocs.iter());
+    for reloc in relocs {
+        let address = object.bias.wrapping_add(reloc.r_offset);
+        let addend = match reloc.r_addend {
+            Some(addend) => addend,
+            // REL relocations keep the addend at the relocated location
+            None => cpu.memory.read_uint(address, 8).unwrap_or_default() as i64,
+        };
+
+        match reloc.r_type {
+            R_X86_64_NONE => {}
+            R_X86_64_RELATIVE => {
+                write_u64(cpu, address, object.bias.wrapping_add_signed(addend))?;
+            }
+            R_X86_64_IRELATIVE => {
+                irelative.push((address, object.bias.wrapping_add_signed(addend)));
+            }
+            R_X86_64_64 | R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => {
+                let value = match resolve(cpu, process, objects, object, &reloc)? {
+                    Some((_, symbol)) => symbol.value,
+                    None => 0,
+                };
+                let value = if reloc.r_type == R_X86_64_64 { value.wrapping_add_signed(addend) } else { value };
+                write_u64(cpu, address, value)?;
+            }
+            R_X86_64_COPY => {
+                let name = symbol_name(object, &reloc);
+                let (_, symbol) = lookup(objects, name, version_name(object.elf, reloc.r_sym), true).ok_or_else(|| Error::UndefinedSymbol(name.to_string()))?;
+                // st_size is only trusted once the whole source is known to be mapped
+                cpu.memory.check_access(symbol.value, symbol.size as usize, Access::Read)?;
+                let mut data = vec![0; symbol.size as usize];
+                cpu.memory.read(symbol.value, &mut data)?;
+                cpu.memory.load(address, &data)?;
+            }
+            R_X86_64_DTPMOD64 => {
+                let module = match reloc.r_sym {
+                    0 => object.tls,
+                    _ => resolve(cpu, process, objects, object, &reloc)?.and_then(|(defining, _)| defining.tls),
+                };
+                write_u64(cpu, address, module.map(|module| module.id).unwrap_or_default())?;
+            }
+            R_X86_64_DTPOFF64 => {
+                let value = resolve(cpu, process, objects, object, &reloc)?.map(|(_, symbol)| symbol.value).unwrap_or_default();
+                write_u64(cpu, address, value.wrapping_add_signed(addend))?;
+            }
+            R_X86_64_TPOFF64 => {
+                let (module, value) = match reloc.r_sym {
+                    0 => (object.tls, 0),
+                    _ => match resolve(cpu, process, objects, object, &reloc)? {
+                        Some((defining, symbol)) => (defining.tls, symbol.value),
+                        None => (None, 0),
+                    },
+                };
+                let offset = module.ok_or_else(|| Error::UndefinedSymbol(symbol_name(object, &reloc).to_string()))?.offset;
+                write_u64(cpu, address, value.wrapping_add_signed(addend).wrapping_sub(offset))?;
+            }
+            r_type => return Err(Error::UnimplementedRelocation(r_type)),
+        }
+    }
+
+    // indirect functions may depend on other relocations being applied
+    for (address, resolver) in irelative {
+        let value = call_function(cpu, process, resolver, &[])?;
+        write_u64(cpu, address, value)?;
+    }
+
+    // make relocated read-only data read-only again, like _dl_protect_relro the start is rounded
+    // down and the end as well, leaving a partial last page writable
+    for phdr in object.elf.program_headers.iter().filter(|phdr| phdr.p_type == PT_GNU_RELRO) {
+        let start = page_align_down(object.bias.wrapping_add(phdr.p_vaddr));
+        let end = page_align_down(object.bias.wrapping_add(phdr.p_vaddr).wrapping_add(phdr.p_memsz));
+        if end > start {
+            cpu.memory.protect(start, end - start, Protection { read: true, ..Default::default() })?;
+        }
+    }
+
+    debug!("LINKER: relocated {}", if object.name.is_empty() { "executable" } else { object.name });
+    Ok(())
+}
+
+/// Applies the DT_RELR relative relocations, which are encoded as an address followed by
+/// bitmaps of the 63 words after it that are relocated as well
+fn relocate_relr(cpu: &mut Cpu, object: &Object) -> Result<(), Error> {
+    let (relr, relrsz) = (dynamic_value(object.elf, DT_RELR), dynamic_value(object.elf, DT_RELRSZ));
+
+    let mut next = 0u64;
+    for index in 0..relrsz / 8 {
+        let offset = relr.checked_add(index * 8).ok_or(Error::InvalidElf)?;
+        let entry = cpu.memory.read_uint(object.bias.wrapping_add(offset), 8)?;
+        if entry & 1 == 0 {
+            let address = object.bias.wrapping_add(entry);
+            relocate_relative_word(cpu, address, object.bias)?;
+            next = address.checked_add(8).ok_or(Error::InvalidElf)?;
+        } else {
+            for bit in (1..64).filter(|bit| entry & (1 << bit) != 0) {
+                let address = next.checked_add((bit - 1) * 8).ok_or(Error::InvalidElf)?;
+                relocate_relative_word(cpu, address, object.bias)?;
+            }
+            next = next.checked_add(63 * 8).ok_or(Error::InvalidElf)?;
+        }
+    }
+    Ok(())
+}
+
+fn relocate_relative_word(cpu: &mut Cpu, address: u64, bias: u64) -> Result<(), Error> {
+    let value = cpu.memory.read_uint(address, 8)?;
+    write_u64(cpu, address, value.wrapping_add(bias))
+}
+
+fn symbol_name<'a>(object: &'a Object, reloc: &Reloc) -> &'a str {
+    object.elf.dynsyms.get(reloc.r_sym)
+        .and_then(|sym| object.elf.dynstrtab.get_at(sym.st_name))
+        .unwrap_or_default()
+}
+
+/// Resolves the symbol of a relocation, calling the resolver of indirect functions.
+/// Returns `None` for undefined weak symbols.
+fn resolve<'a>(cpu: &mut Cpu, process: &mut Process, objects: &'a [Object], object: &Object, reloc: &Reloc) -> Result<Option<(&'a Object<'a>, Symbol)>, Error> {
+    let name = symbol_name(object, reloc);
+    let weak = object.elf.dynsyms.get(reloc.r_sym).is_some_and(|sym| sym.st_bind() == STB_WEAK);
+
+    match lookup(objects, name, version_name(object.elf, reloc.r_sym), false) {
+        Some((defining, symbol)) if symbol.ifunc => {
+            let value = call_function(cpu, process, symbol.value, &[])?;
+            Ok(Some((defining, Symbol { value, ..symbol })))
+        }
+        Some(found) => Ok(Some(found)),
+        None if weak => Ok(None),
+        None => Err(Error::UndefinedSymbol(name.to_string())),
+    }
+}
+
+fn write_u64(cpu: &mut Cpu, address: u64, value: u64) -> Result<(), Error> {
+    cpu.memory.load(address, &value.to_le_bytes())?;
+    Ok(())
+}
+
+/// Reads argc, argv and envp from the initial process stack
+fn initial_arguments(cpu: &Cpu) -> Result<[u64; 3], Error> {
+    let rsp = cpu.registers.rsp;
+    let argc = cpu.memory.read_uint(rsp, 8)?;
+    let argv = rsp + 8;
+    let envp = argv + (argc + 1) * 8;
+    Ok([argc, argv, envp])
+}
+
+/// Value of a dynamic section entry, 0 if there is none
+fn dynamic_value(elf: &Elf, tag: u64) -> u64 {
+    elf.dynamic.iter()
+        .flat_map(|dynamic| &dynamic.dyns)
+        .find(|dyn_| dyn_.d_tag == tag)
+        .map(|dyn_| dyn_.d_val)
+        .unwrap_or_default()
+}
+
+fn run_init_array(cpu: &mut Cpu, process: &mut Process, address: u64, size: u64, args: &[u64]) -> Result<(), Error> {
+    for index in 0..size / 8 {
+        let entry = address.checked_add(index * 8).ok_or(Error::InvalidElf)?;
+        let function = cpu.memory.read_uint(entry, 8)?;
+        // entries of 0 and -1 are placeholders
+        if function != 0 && function != u64::MAX {
+            call_function(cpu, process, function, args)?;
+        }
+        if process.exit_code.is_some() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -1,128 +1,352 @@
+pub mod elf;
 pub mod error;
+pub mod linker;
+pub mod random;
+pub mod signal;
+pub mod stack;
+pub mod syscall;
+pub mod tls;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
 use error::Error;
+use random::Random;
 use goblin::mach::cputype::{CPU_TYPE_X86, CPU_TYPE_X86_64};
 
-use crate::cpu::Cpu;
-use crate::mem::StackPointer;
+use crate::cpu::cpuid::CpuModel;
+use crate::cpu::{Cpu, STACK_TOP};
+use crate::mem::{page_align_down, page_align_up, Protection, PAGE_SIZE};
 
-use goblin::mach::load_command::{CommandVariant, LC_MAIN};
+use goblin::elf::program_header::PT_TLS;
 use goblin::mach::Mach;
-use goblin::elf::program_header::PT_LOAD;
-use goblin::elf::ProgramHeader;
+use goblin::mach::constants::{VM_PROT_EXECUTE, VM_PROT_READ, VM_PROT_WRITE};
+use goblin::pe::section_table::{IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_READ, IMAGE_SCN_MEM_WRITE};
 use goblin::Object;
-use iced_x86::{Code, Decoder, DecoderOptions};
-use log::debug;
+use iced_x86::Code;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Execution {
     pub exit_code: u64,
+    /// signal which terminated the program, `exit_code` is then 128 + signal like in a shell
+    pub signal: Option<u64>,
     pub stdout: String,
     pub stderr: String,
 }
 
-fn handle_syscall(cpu: &mut Cpu, exit_code: &mut Option<u64>, stdout: &mut String, stderr: &mut String) -> Result<(), Error> {
-    // syscall number:    rax
-    // syscall arguments: rdi, rsi, rdx, r10, r8, r9
-    match cpu.registers.rax {
-        0x1 => { // 0x1 = write(fd = rdi, buf = rsi, count = rdx);
-            let count = cpu.registers.rdx as usize;
-            if count > cpu.stack.len() {
-                return Err(crate::cpu::error::Error::StackOverflowRead.into());
-            }
-            let mut buf_ptr = StackPointer::new(cpu.registers.rsi, cpu.stack.len() as u64);
-            let mut buf = Vec::new();
-            for _ in 0..count {
-                buf.push(cpu.stack[buf_ptr.value() as usize]);
-                buf_ptr += 1;
-            }
-            let buf_str = String::from_utf8(buf)?;
-            match cpu.registers.rdi {
-                0x1 => {
-                    debug!("SYSCALL: write(STDOUT, {:?})", &buf_str);
-                    stdout.push_str(&buf_str);
-                }
-                0x2 => {
-                    debug!("SYSCALL: write(STDERR, {:?})", &buf_str);
-                    stderr.push_str(&buf_str);
-                }
-                fd => return Err(Error::UnimplementedFileDescriptor(fd)),
-            }
+/// Contents of the AT_RANDOM auxiliary vector entry when not randomizing, for deterministic execution
+const AT_RANDOM_BYTES: [u8; 16] = *b"alex86emu random";
+
+/// Options for starting a program
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// program arguments, starting with the program name
+    pub argv: Vec<String>,
+    /// environment variables, in `KEY=VALUE` form
+    pub envp: Vec<String>,
+    /// address position-independent executables are loaded at, defaults to [`elf::DEFAULT_PIE_BASE`]
+    pub load_base: Option<u64>,
+    /// seed for randomizing the load base, mmap region and AT_RANDOM bytes, no randomization when unset
+    pub random_seed: Option<u64>,
+    /// host directory used as the guest root directory, for the ELF interpreter and file access (defaults to `/`)
+    pub sysroot: Option<PathBuf>,
+    /// how dynamically linked programs are linked
+    pub linker: DynamicLinker,
+    /// processor reported to the program by cpuid
+    pub cpu_model: CpuModel,
+}
 
-            Ok(())
-        }
+/// Dynamic linker used for programs with a `PT_INTERP` header
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicLinker {
+    /// run the program interpreter (e.g. `ld-linux-x86-64.so.2`) from the sysroot
+    #[default]
+    Interpreter,
+    /// load shared libraries and relocate the program in the emulator, see [`linker::link`]
+    Builtin,
+}
 
-        0x3c => { // 0x3c = exit(status = rdi)
-            let status = cpu.registers.rdi;
-            *exit_code = Some(status);
-            debug!("SYSCALL: exit(0x{:x})", status);
+impl std::str::FromStr for DynamicLinker {
+    type Err = String;
 
-            Ok(())
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpreter" => Ok(Self::Interpreter),
+            "builtin" => Ok(Self::Builtin),
+            _ => Err(format!("unknown dynamic linker {:?}, expected interpreter or builtin", s)),
         }
+    }
+}
 
-        number => Err(Error::UnimplementedSyscall(number)),
+impl std::fmt::Display for DynamicLinker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Interpreter => write!(f, "interpreter"),
+            Self::Builtin => write!(f, "builtin"),
+        }
     }
 }
 
-pub fn execute_from_x86_decoder(decoder: &mut Decoder) -> Result<Execution, Error> {
-	let relative_instruction_pointer = decoder.ip() - decoder.position() as u64;
+/// Highest address of the region memory mappings are placed in (growing downwards),
+/// leaving a gap of 128 MiB below the stack like the Linux kernel does
+pub const MMAP_TOP: u64 = STACK_TOP - 128 * 1024 * 1024;
 
-	// debug!("stack: {:02x?}", &self.stack[self.registers.rsp.0 as usize..]);
-	// debug!("registers: {:?}", self.registers);
+/// Number of random page offset bits applied to a randomized mmap region (mmap_rnd_bits)
+const MMAP_RANDOM_BITS: u32 = 28;
 
-    let mut exit_code: Option<u64> = None;
-	let mut stdout = String::new();
-	let mut stderr = String::new();
+/// Largest number of symbolic links followed while resolving a path, as in the Linux kernel
+const MAX_SYMLINKS: usize = 40;
 
-    {
-        let mut cpu = Cpu::new();
-        // TODO: initialize cpu.registers.rbp to start of stack (end of memory)
+// errno values of path resolution
+const EACCES: i32 = 13;
+const ELOOP: i32 = 40;
 
-        loop {
-            // update instruction pointer
-            let instruction = decoder.decode();
-            if instruction.code() == Code::INVALID {
-                return Err(Error::ProgramDidNotExit);
-            }
+/// File opened by the program, from the sysroot
+#[derive(Debug)]
+pub struct OpenFile {
+    /// absolute path of the file in the guest
+    pub path: PathBuf,
+    pub file: File,
+    /// current file offset
+    pub position: u64,
+}
+
+/// Operating system state of the emulated process
+#[derive(Debug, Default)]
+pub struct Process {
+    pub exit_code: Option<u64>,
+    /// signal which terminated the process
+    pub signal: Option<u64>,
+    pub stdout: String,
+    pub stderr: String,
+    /// start of the heap, directly after the highest loaded segment
+    pub brk_start: u64,
+    /// current program break (end of the heap)
+    pub brk: u64,
+    /// highest address of the region memory mappings are placed in
+    pub mmap_top: u64,
+    /// host directory serving as the root directory of the guest file system
+    pub sysroot: PathBuf,
+    /// open files, by file descriptor
+    pub files: BTreeMap<u64, OpenFile>,
+    /// address cleared when the thread exits, set by set_tid_address
+    pub clear_child_tid: u64,
+    /// head of the robust futex list, set by set_robust_list
+    pub robust_list: u64,
+    /// registered restartable sequence area, its length and abort signature
+    pub rseq: Option<(u64, u32, u32)>,
+    /// soft and hard resource limits changed by prlimit64, by resource
+    pub resource_limits: BTreeMap<u64, (u64, u64)>,
+    /// `struct sigaction` of signals with an action set by rt_sigaction (handlers are never invoked)
+    pub signal_actions: BTreeMap<u64, [u8; 32]>,
+    /// blocked signals, bit `n - 1` for signal `n`
+    pub signal_mask: u64,
+    /// source of the bytes returned by getrandom
+    pub random: Random,
+}
+
+impl Process {
+    pub fn new(brk: u64, mmap_top: u64, sysroot: PathBuf) -> Self {
+        Self {
+            brk_start: brk,
+            brk,
+            mmap_top,
+            sysroot,
+            ..Default::default()
+        }
+    }
+
+    /// Translates an absolute guest path into a host path inside the sysroot, following symbolic links
+    pub fn sysroot_path(&self, path: &Path) -> io::Result<PathBuf> {
+        self.resolve_path(path, true)
+    }
 
-            cpu.registers.rip = decoder.ip();
+    /// Translates an absolute guest path into a host path inside the sysroot like [`Process::sysroot_path`],
+    /// without following a symbolic link in the last component (as lstat and readlink do)
+    pub fn sysroot_path_nofollow(&self, path: &Path) -> io::Result<PathBuf> {
+        self.resolve_path(path, false)
+    }
 
-            if instruction.code() == Code::Syscall {
-                handle_syscall(&mut cpu, &mut exit_code, &mut stdout, &mut stderr)?;
-            } else if let Err(e) = cpu.execute_instruction(instruction) {
-                return Err(Error::Cpu(e));
+    /// Resolves a guest path one component at a time, so that symbolic links are looked up inside the sysroot.
+    /// Absolute link targets start over at the sysroot, and `..` above the guest root directory is rejected.
+    fn resolve_path(&self, path: &Path, follow_last: bool) -> io::Result<PathBuf> {
+        let mut resolved: Vec<OsString> = Vec::new();
+        let mut remaining: VecDeque<OsString> = relative_components(path).collect();
+        let mut links = 0;
+
+        while let Some(name) = remaining.pop_front() {
+            if name == ".." {
+                if resolved.pop().is_none() {
+                    return Err(io::Error::from_raw_os_error(EACCES));
+                }
+                continue;
             }
 
-            // check for program exit via syscall
-            if exit_code.is_some() {
-                break;
+            let host_path: PathBuf = self.sysroot.iter().chain(resolved.iter().map(OsString::as_os_str)).chain([name.as_os_str()]).collect();
+            let is_symlink = std::fs::symlink_metadata(&host_path).is_ok_and(|metadata| metadata.file_type().is_symlink());
+            if !is_symlink || (remaining.is_empty() && !follow_last) {
+                resolved.push(name);
+                continue;
             }
 
-            if cpu.registers.rip != decoder.ip() {
-                // instruction pointer modified by program
-                // update instruction pointer
-                let rip_position = cpu.registers.rip.checked_sub(relative_instruction_pointer).ok_or(Error::UnimplementedInstructionPointerOutsideProgramSpace)? as usize;
-                decoder.set_ip(cpu.registers.rip);
-                decoder.set_position(rip_position).map_err(|_e| Error::UnimplementedInstructionPointerOutsideProgramSpace)?;
+            links += 1;
+            if links > MAX_SYMLINKS {
+                return Err(io::Error::from_raw_os_error(ELOOP));
+            }
+            let target = std::fs::read_link(&host_path)?;
+            if target.has_root() {
+                resolved.clear();
             }
+            for component in relative_components(&target).collect::<Vec<_>>().into_iter().rev() {
+                remaining.push_front(component);
+            }
+        }
+
+        Ok(self.sysroot.iter().chain(resolved.iter().map(OsString::as_os_str)).collect())
+    }
+
+    /// Terminates the process with a signal, as its default action does (no signal handlers are supported)
+    pub fn terminate(&mut self, signal: u64) {
+        self.signal = Some(signal);
+        self.exit_code = Some(128 + signal);
+    }
+
+    /// Lowest file descriptor which is not in use
+    pub fn next_fd(&self) -> u64 {
+        (3..).find(|fd| !self.files.contains_key(fd)).unwrap_or_default()
+    }
+}
+
+/// Names and `..` components of a path, without the root directory and `.` components
+fn relative_components(path: &Path) -> impl Iterator<Item = OsString> + '_ {
+    path.components().filter_map(|component| match component {
+        Component::Normal(name) => Some(name.to_os_string()),
+        Component::ParentDir => Some(OsString::from("..")),
+        Component::RootDir | Component::CurDir | Component::Prefix(_) => None,
+    })
+}
+
+fn pe_protection(characteristics: u32) -> Protection {
+    Protection {
+        read: characteristics & IMAGE_SCN_MEM_READ != 0,
+        write: characteristics & IMAGE_SCN_MEM_WRITE != 0,
+        execute: characteristics & IMAGE_SCN_MEM_EXECUTE != 0,
+    }
+}
+
+fn mach_protection(prot: u32) -> Protection {
+    Protection {
+        read: prot & VM_PROT_READ != 0,
+        write: prot & VM_PROT_WRITE != 0,
+        execute: prot & VM_PROT_EXECUTE != 0,
+    }
+}
+
+/// Maps the pages covering `address..address + size` and copies `data` to the start of the region.
+/// Pages shared with previously loaded segments keep their contents but take the protection of this segment,
+/// as the later mapping replaces the shared page in the Linux kernel. Combining both protections would make
+/// a page holding the end of the code and the start of the data writable and executable.
+fn load_segment(cpu: &mut Cpu, address: u64, size: u64, data: &[u8], protection: Protection) -> Result<(), Error> {
+    let start = page_align_down(address);
+    let end = page_align_up(address.saturating_add(size.max(data.len() as u64)));
+    for page_base in (start..end).step_by(PAGE_SIZE as usize) {
+        match cpu.memory.protection(page_base) {
+            Some(_) => cpu.memory.protect(page_base, PAGE_SIZE, protection)?,
+            None => cpu.memory.map(page_base, PAGE_SIZE, protection)?,
+        }
+    }
+    cpu.memory.load(address, data)?;
+    Ok(())
+}
+
+fn file_range(binary: &[u8], offset: u64, size: u64) -> Result<&[u8], Error> {
+    let start = offset as usize;
+    let end = start.checked_add(size as usize).ok_or(Error::SegmentOutOfBounds)?;
+    binary.get(start..end).ok_or(Error::SegmentOutOfBounds)
+}
+
+/// Return address pushed by [`call_function`], in the non-canonical address
+/// range so that it can never be a valid code address
+const RETURN_SENTINEL: u64 = 0xdead_0000_0000_0000;
+
+/// Size of the area below the stack pointer which functions may use without adjusting it
+const RED_ZONE_SIZE: u64 = 128;
+
+/// Executes instructions until the program exits, or until `rip` reaches `stop`
+fn run(cpu: &mut Cpu, process: &mut Process, stop: Option<u64>) -> Result<(), Error> {
+	// debug!("registers: {:?}", self.registers);
 
-            // debug!("stack: {:02x?}", &cpu.stack[cpu.registers.rsp.0 as usize..]);
-            // debug!("registers: {:?}", cpu.registers);
+    // check for program exit via syscall
+    while process.exit_code.is_none() && Some(cpu.registers.rip) != stop {
+        // instruction fetch faults are delivered like those of execution, syscalls are left to the process
+        let result = cpu.fetch().and_then(|instruction| {
+            // update instruction pointer
+            cpu.registers.rip = instruction.next_ip();
+
+            match instruction.code() {
+                Code::Syscall => Ok(true),
+                _ => cpu.execute_instruction(instruction).map(|_| false),
+            }
+        });
+
+        match result {
+            Ok(true) => syscall::handle_syscall(cpu, process)?,
+            Ok(false) => {}
+            Err(e) => match signal::for_exception(&e) {
+                Some(signal) => process.terminate(signal),
+                None => return Err(Error::Cpu(e)),
+            },
         }
+
+        // debug!("registers: {:?}", cpu.registers);
     }
 
-    if let Some(exit_code) = exit_code {
+    Ok(())
+}
+
+/// Calls a guest function using the System V calling convention with up to six
+/// integer arguments, returning the value of rax. All registers are restored afterwards.
+pub fn call_function(cpu: &mut Cpu, process: &mut Process, address: u64, args: &[u64]) -> Result<u64, Error> {
+    let saved_registers = cpu.registers;
+
+    let mut rsp = (cpu.registers.rsp - RED_ZONE_SIZE) & !0xf;
+    rsp -= 8;
+    cpu.memory.write_uint(rsp, 8, RETURN_SENTINEL)?;
+    cpu.registers.rsp = rsp;
+
+    let argument_registers = [&mut cpu.registers.rdi, &mut cpu.registers.rsi, &mut cpu.registers.rdx, &mut cpu.registers.rcx, &mut cpu.registers.r8, &mut cpu.registers.r9];
+    for (register, value) in argument_registers.into_iter().zip(args) {
+        *register = *value;
+    }
+    cpu.registers.rip = address;
+
+    run(cpu, process, Some(RETURN_SENTINEL))?;
+
+    let result = cpu.registers.rax;
+    cpu.registers = saved_registers;
+    Ok(result)
+}
+
+pub fn execute_from_cpu(cpu: &mut Cpu, mut process: Process) -> Result<Execution, Error> {
+    run(cpu, &mut process, None)?;
+
+    if let Some(exit_code) = process.exit_code {
         Ok(Execution {
             exit_code,
-            stdout,
-            stderr,
+            signal: process.signal,
+            stdout: process.stdout,
+            stderr: process.stderr,
         })
     } else {
         Err(Error::ProgramDidNotExit)
     }
 }
 
-pub fn execute_from_binary_slice(binary: &[u8]) -> Result<Execution, Error> {
-    match Object::parse(&binary) {
+pub fn execute_from_binary_slice(binary: &[u8], options: &Options) -> Result<Execution, Error> {
+    match Object::parse(binary) {
         Ok(Object::Elf(elf)) => {
             let bitness = if elf.is_64 {
                 64
@@ -130,23 +354,57 @@ pub fn execute_from_binary_slice(binary: &[u8]) -> Result<Execution, Error> {
                 32
             };
 
-            let mut load_headers: Vec<&ProgramHeader> = elf.program_headers.iter().filter(|phdr| phdr.p_type == PT_LOAD).collect();
-            load_headers.sort_by(|a, b| a.p_vaddr.cmp(&b.p_vaddr));
+            let sysroot = options.sysroot.clone().unwrap_or_else(|| PathBuf::from("/"));
+            let mut cpu = Cpu::new();
+            cpu.model = options.cpu_model.clone();
+            let mut random = options.random_seed.map(Random::new);
+            let bias = elf::load_bias(&elf, options.load_base.unwrap_or(elf::DEFAULT_PIE_BASE), random.as_mut())?;
+            let loaded = elf::load(&mut cpu, &elf, binary, bias)?;
 
-            if let Some(load_base_header) = load_headers.first() {
-                let relative_instruction_pointer = load_base_header.p_vaddr;
-                let mut decoder = Decoder::with_ip(bitness, &binary, relative_instruction_pointer, DecoderOptions::NONE);
+            let mut mmap_top = MMAP_TOP;
+            if let Some(random) = random.as_mut() {
+                mmap_top -= (random.next_u64() & ((1 << MMAP_RANDOM_BITS) - 1)) * PAGE_SIZE;
+            }
+            let mut process = Process::new(loaded.brk, mmap_top, sysroot);
 
-                let entry_point_rva = elf.header.e_entry;
-                let entry_point_addr = entry_point_rva.saturating_sub(relative_instruction_pointer) as usize;
-                decoder.set_position(entry_point_addr)?;
-                decoder.set_ip(entry_point_rva);
+            let mut at_random = AT_RANDOM_BYTES;
+            if let Some(random) = random.as_mut() {
+                random.fill_bytes(&mut at_random);
+                process.random = Random::new(random.next_u64());
+            }
 
-                let execution = execute_from_x86_decoder(&mut decoder)?;
-                Ok(execution)
-            } else {
-                Err(Error::ElfLoadHeaderMissing)
+            cpu.bitness = bitness;
+
+            match (elf.interpreter, options.linker) {
+                // dynamically linked programs are started through their interpreter, which relocates the program
+                (Some(interpreter), DynamicLinker::Interpreter) => {
+                    let path = process.sysroot_path(Path::new(interpreter))
+                        .map_err(|e| Error::ElfInterpreterRead(PathBuf::from(interpreter), e))?;
+                    let interpreter = elf::load_interpreter(&mut cpu, &path, process.mmap_top)?;
+                    stack::init(&mut cpu, &options.argv, &options.envp, &loaded.auxv(interpreter.base), at_random)?;
+                    cpu.registers.rip = interpreter.entry;
+                }
+                // the built-in linker runs initialization functions, which need the initial stack
+                (Some(_), DynamicLinker::Builtin) => {
+                    stack::init(&mut cpu, &options.argv, &options.envp, &loaded.auxv(0), at_random)?;
+                    linker::link(&mut cpu, &mut process, &elf, bias, at_random)?;
+                    cpu.registers.rip = loaded.entry;
+                }
+                (None, _) => {
+                    elf::relocate_relative(&mut cpu, &elf, bias)?;
+                    // statically linked C libraries set up their own thread pointer, but code without one may use fs directly
+                    let mut tls_layout = tls::TlsLayout::default();
+                    if let Some(phdr) = elf.program_headers.iter().find(|phdr| phdr.p_type == PT_TLS) {
+                        tls_layout.add(phdr, bias)?;
+                    }
+                    cpu.registers.fs_base = tls::init(&mut cpu, process.mmap_top, &tls_layout, at_random)?;
+                    stack::init(&mut cpu, &options.argv, &options.envp, &loaded.auxv(0), at_random)?;
+                    cpu.registers.rip = loaded.entry;
+                }
             }
+
+            let execution = execute_from_cpu(&mut cpu, process)?;
+            Ok(execution)
         }
 
         Ok(Object::PE(pe)) => {
@@ -158,14 +416,24 @@ pub fn execute_from_binary_slice(binary: &[u8]) -> Result<Execution, Error> {
 
             if let Some(optional_header) = pe.header.optional_header {
                 let relative_instruction_pointer = optional_header.windows_fields.image_base;
-                let entry_point_rva = optional_header.standard_fields.address_of_entry_point;
-                let entry_point_addr = entry_point_rva.saturating_sub(relative_instruction_pointer) as usize;
 
-                let mut decoder = Decoder::with_ip(bitness, &binary, entry_point_rva, DecoderOptions::NONE);
-                decoder.set_position(entry_point_addr)?;
-                // decoder.set_ip(entry_point_rva);
+                let mut cpu = Cpu::new();
+                cpu.model = options.cpu_model.clone();
+                for section in &pe.sections {
+                    let address = relative_instruction_pointer + section.virtual_address as u64;
+                    // raw data is padded to the file alignment, so it may be larger than the section itself
+                    let raw_size = match section.virtual_size {
+                        0 => section.size_of_raw_data,
+                        virtual_size => section.size_of_raw_data.min(virtual_size),
+                    };
+                    let data = file_range(binary, section.pointer_to_raw_data as u64, raw_size as u64)?;
+                    load_segment(&mut cpu, address, section.virtual_size as u64, data, pe_protection(section.characteristics))?;
+                }
+
+                cpu.bitness = bitness;
+                cpu.registers.rip = relative_instruction_pointer + optional_header.standard_fields.address_of_entry_point;
 
-                let execution = execute_from_x86_decoder(&mut decoder)?;
+                let execution = execute_from_cpu(&mut cpu, Process::new(0, MMAP_TOP, PathBuf::from("/")))?;
                 Ok(execution)
             } else {
                 Err(Error::PeOptionalHeaderMissing)
@@ -179,19 +447,27 @@ pub fn execute_from_binary_slice(binary: &[u8]) -> Result<Execution, Error> {
                 32
             };
 
-            if let Some(CommandVariant::Main(main_load_command)) = mach_o.load_commands.into_iter().map(|cmd| cmd.command).find(|cmd| cmd.cmd() == LC_MAIN) {
-                let entry_point_rva = mach_o.entry;
-                let entry_point_addr = main_load_command.entryoff as usize;
-
-                let mut decoder = Decoder::with_ip(bitness, &binary, entry_point_rva, DecoderOptions::NONE);
-                decoder.set_position(entry_point_addr)?;
-                // decoder.set_ip(entry_point_rva);
+            let mut cpu = Cpu::new();
+            cpu.model = options.cpu_model.clone();
+            for segment in mach_o.segments.iter() {
+                // skip segments without access, e.g. __PAGEZERO
+                if segment.initprot == 0 {
+                    continue;
+                }
+                let data = file_range(binary, segment.fileoff, segment.filesize)?;
+                load_segment(&mut cpu, segment.vmaddr, segment.vmsize, data, mach_protection(segment.initprot))?;
+            }
 
-                let execution = execute_from_x86_decoder(&mut decoder)?;
-                Ok(execution)
-            } else {
-                Err(Error::MachOLoadCommandMissing)
+            // goblin resolves the entry point of LC_MAIN and LC_UNIXTHREAD to a virtual address
+            if mach_o.entry == 0 {
+                return Err(Error::MachOLoadCommandMissing);
             }
+
+            cpu.bitness = bitness;
+            cpu.registers.rip = mach_o.entry;
+
+            let execution = execute_from_cpu(&mut cpu, Process::new(0, MMAP_TOP, PathBuf::from("/")))?;
+            Ok(execution)
         }
 
         Ok(Object::Mach(Mach::Fat(mach_fat))) => {
@@ -205,7 +481,7 @@ pub fn execute_from_binary_slice(binary: &[u8]) -> Result<Execution, Error> {
                         return Err(Error::MachFatNoX86);
                     }
 
-                    execute_from_binary_slice(&binary[start..end])
+                    execute_from_binary_slice(&binary[start..end], options)
                 } else {
                     Err(Error::MachFatNoX86)
                 }
@@ -0,0 +1,21 @@
+use crate::cpu::error::Error;
+
+// signal numbers, see <asm/signal.h>
+pub const SIGILL: u64 = 4;
+pub const SIGFPE: u64 = 8;
+pub const SIGKILL: u64 = 9;
+pub const SIGSEGV: u64 = 11;
+pub const SIGSTOP: u64 = 19;
+
+/// Number of signals, including the real-time signals
+pub const NSIG: u64 = 64;
+
+/// Signal the Linux kernel sends for a processor exception, if it is delivered to the program
+pub fn for_exception(e: &Error) -> Option<u64> {
+    match e {
+        Error::DivideFault(_) | Error::FloatingPointFault(_) | Error::SimdFloatingPointFault(_) => Some(SIGFPE),
+        Error::InvalidOpcode(_) => Some(SIGILL),
+        Error::PageFault(_) | Error::GeneralProtectionFault(_) => Some(SIGSEGV),
+        _ => None,
+    }
+}
@@ -0,0 +1,86 @@
+use super::error::Error;
+
+use crate::cpu::Cpu;
+
+// auxiliary vector entry types, see <linux/auxvec.h>
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_BASE: u64 = 7;
+pub const AT_FLAGS: u64 = 8;
+pub const AT_ENTRY: u64 = 9;
+pub const AT_UID: u64 = 11;
+pub const AT_EUID: u64 = 12;
+pub const AT_GID: u64 = 13;
+pub const AT_EGID: u64 = 14;
+pub const AT_PLATFORM: u64 = 15;
+pub const AT_HWCAP: u64 = 16;
+pub const AT_CLKTCK: u64 = 17;
+pub const AT_SECURE: u64 = 23;
+pub const AT_RANDOM: u64 = 25;
+pub const AT_EXECFN: u64 = 31;
+
+const PLATFORM: &str = "x86_64";
+
+/// Builds the initial process stack as laid out by the Linux kernel for the System V ABI:
+///
+/// ```text
+/// STACK_TOP -> execfn, environment and argument strings, platform string, AT_RANDOM bytes
+///              (padding to 16 bytes)
+///              auxv (type, value) pairs, terminated by AT_NULL
+///              envp pointers, terminated by NULL
+///              argv pointers, terminated by NULL
+///       rsp -> argc
+/// ```
+///
+/// `auxv` holds the entries describing the loaded image, entries pointing into
+/// the string area (AT_RANDOM, AT_EXECFN, AT_PLATFORM) are added here.
+pub fn init(cpu: &mut Cpu, argv: &[String], envp: &[String], auxv: &[(u64, u64)], random: [u8; 16]) -> Result<(), Error> {
+    let mut sp = cpu.registers.rsp;
+
+    let execfn = push_string(cpu, &mut sp, argv.first().map(String::as_str).unwrap_or_default())?;
+    let envp_pointers = envp.iter().rev().map(|s| push_string(cpu, &mut sp, s)).collect::<Result<Vec<u64>, Error>>()?;
+    let argv_pointers = argv.iter().rev().map(|s| push_string(cpu, &mut sp, s)).collect::<Result<Vec<u64>, Error>>()?;
+    let platform = push_string(cpu, &mut sp, PLATFORM)?;
+    let random_pointer = push_bytes(cpu, &mut sp, &random)?;
+
+    let mut auxv = auxv.to_vec();
+    auxv.push((AT_RANDOM, random_pointer));
+    auxv.push((AT_EXECFN, execfn));
+    auxv.push((AT_PLATFORM, platform));
+    auxv.push((AT_NULL, 0));
+
+    let mut words = Vec::with_capacity(1 + argv.len() + 1 + envp.len() + 1 + auxv.len() * 2);
+    words.push(argv.len() as u64);
+    words.extend(argv_pointers.iter().rev());
+    words.push(0);
+    words.extend(envp_pointers.iter().rev());
+    words.push(0);
+    for (key, value) in auxv {
+        words.push(key);
+        words.push(value);
+    }
+
+    // rsp must be 16-byte aligned at argc
+    sp = (sp - words.len() as u64 * 8) & !0xf;
+    for (i, word) in words.iter().enumerate() {
+        cpu.memory.write_uint(sp + i as u64 * 8, 8, *word)?;
+    }
+
+    cpu.registers.rsp = sp;
+    Ok(())
+}
+
+fn push_bytes(cpu: &mut Cpu, sp: &mut u64, bytes: &[u8]) -> Result<u64, Error> {
+    *sp -= bytes.len() as u64;
+    cpu.memory.write(*sp, bytes)?;
+    Ok(*sp)
+}
+
+fn push_string(cpu: &mut Cpu, sp: &mut u64, s: &str) -> Result<u64, Error> {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.push(0);
+    push_bytes(cpu, sp, &bytes)
+}
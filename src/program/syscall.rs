@@ -0,0 +1,980 @@
+use std::fs::{File, Metadata};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::{Component, Path, PathBuf};
+
+use super::error::Error;
+use super::signal::{NSIG, SIGKILL, SIGSTOP};
+use super::{OpenFile, Process};
+
+use crate::cpu::{Cpu, STACK_TOP};
+use crate::mem::{page_align_down, page_align_up, Access, Protection, MIN_MAP_ADDRESS, PAGE_SIZE};
+
+use log::debug;
+
+// errno values, returned negated in rax
+const EPERM: i64 = 1;
+const ENOENT: i64 = 2;
+const ESRCH: i64 = 3;
+const EBADF: i64 = 9;
+const ENOMEM: i64 = 12;
+const EACCES: i64 = 13;
+const EFAULT: i64 = 14;
+const EBUSY: i64 = 16;
+const EEXIST: i64 = 17;
+const EINVAL: i64 = 22;
+const EROFS: i64 = 30;
+const ENAMETOOLONG: i64 = 36;
+const ENOSYS: i64 = 38;
+
+// open flags
+const O_ACCMODE: u64 = 0o3;
+const O_CREAT: u64 = 0o100;
+const O_TRUNC: u64 = 0o1000;
+const AT_FDCWD: i64 = -100;
+const AT_SYMLINK_NOFOLLOW: u64 = 0x100;
+const AT_EMPTY_PATH: u64 = 0x1000;
+
+// access modes
+const W_OK: u64 = 2;
+
+// lseek whence
+const SEEK_SET: u64 = 0;
+const SEEK_CUR: u64 = 1;
+const SEEK_END: u64 = 2;
+
+// mmap protection and flags
+const PROT_READ: u64 = 0x1;
+const PROT_WRITE: u64 = 0x2;
+const PROT_EXEC: u64 = 0x4;
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+const MAP_FIXED_NOREPLACE: u64 = 0x100000;
+
+// mremap flags
+const MREMAP_MAYMOVE: u64 = 0x1;
+const MREMAP_FIXED: u64 = 0x2;
+
+// rt_sigprocmask actions
+const SIG_BLOCK: u64 = 0;
+const SIG_UNBLOCK: u64 = 1;
+const SIG_SETMASK: u64 = 2;
+
+/// Size of the signal sets passed to the rt_sig* syscalls
+const SIGSET_SIZE: u64 = 8;
+/// Size of the kernel `struct sigaction` on x86-64
+const SIGACTION_SIZE: usize = 32;
+
+// resource limits
+const RLIMIT_STACK: u64 = 3;
+const RLIMIT_CORE: u64 = 4;
+const RLIMIT_NOFILE: u64 = 7;
+const RLIMIT_MEMLOCK: u64 = 8;
+const RLIMIT_NICE: u64 = 13;
+const RLIMIT_RTPRIO: u64 = 14;
+const RLIM_NLIMITS: u64 = 16;
+const RLIM_INFINITY: u64 = u64::MAX;
+
+// getrandom flags
+const GRND_NONBLOCK: u64 = 0x1;
+const GRND_RANDOM: u64 = 0x2;
+const GRND_INSECURE: u64 = 0x4;
+
+// rseq
+const RSEQ_FLAG_UNREGISTER: u64 = 0x1;
+const RSEQ_CPU_ID_UNINITIALIZED: u64 = u32::MAX as u64;
+/// Size and alignment of the original `struct rseq`
+const RSEQ_MIN_SIZE: u64 = 32;
+
+/// Size of the `struct robust_list_head` set_robust_list expects
+const ROBUST_LIST_HEAD_SIZE: u64 = 24;
+
+/// Process and thread id reported to the program
+const PROCESS_ID: u64 = 1000;
+
+/// Fields of `struct utsname` returned by uname: sysname, nodename, release, version, machine and domainname
+const UTSNAME: [&str; 6] = ["Linux", "alex86emu", "6.1.0", "#1 SMP PREEMPT_DYNAMIC", "x86_64", "(none)"];
+/// Size of each `struct utsname` field
+const UTSNAME_LENGTH: usize = 65;
+
+// arch_prctl codes
+const ARCH_SET_GS: u64 = 0x1001;
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+const ARCH_GET_GS: u64 = 0x1004;
+
+// file types for st_mode
+const S_IFCHR: u32 = 0o020000;
+
+const PATH_MAX: usize = 4096;
+
+/// Size of `struct stat` on x86-64
+const STAT_SIZE: usize = 144;
+
+/// Largest number of bytes copied between a file and guest memory at once
+const IO_CHUNK_SIZE: usize = 64 * 1024;
+
+pub fn handle_syscall(cpu: &mut Cpu, process: &mut Process) -> Result<(), Error> {
+    // syscall number:    rax
+    // syscall arguments: rdi, rsi, rdx, r10, r8, r9
+    let (arg0, arg1, arg2, arg3, arg4, arg5) = (
+        cpu.registers.rdi,
+        cpu.registers.rsi,
+        cpu.registers.rdx,
+        cpu.registers.r10,
+        cpu.registers.r8,
+        cpu.registers.r9,
+    );
+
+    let result = match cpu.registers.rax {
+        0x0 => { // 0x0 = read(fd = rdi, buf = rsi, count = rdx)
+            let result = read(cpu, process, arg0, arg1, arg2, None);
+            debug!("SYSCALL: read({}, 0x{:x}, {}) = {}", arg0, arg1, arg2, result);
+            result
+        }
+
+        0x1 => { // 0x1 = write(fd = rdi, buf = rsi, count = rdx);
+            // the buffer is only allocated once the guest has shown it is mapped
+            if !is_user_range(arg1, arg2) || cpu.memory.check_access(arg1, arg2 as usize, Access::Read).is_err() {
+                debug!("SYSCALL: write(0x{:x}) with inaccessible buffer 0x{:x}", arg0, arg1);
+                cpu.registers.rax = -EFAULT as u64;
+                return Ok(());
+            }
+            let mut buf = vec![0; arg2 as usize];
+            if cpu.memory.read(arg1, &mut buf).is_err() {
+                debug!("SYSCALL: write(0x{:x}) with inaccessible buffer 0x{:x}", arg0, arg1);
+                cpu.registers.rax = -EFAULT as u64;
+                return Ok(());
+            }
+            let count = buf.len() as i64;
+            let buf_str = String::from_utf8(buf)?;
+            match arg0 {
+                0x1 => {
+                    debug!("SYSCALL: write(STDOUT, {:?})", &buf_str);
+                    process.stdout.push_str(&buf_str);
+                }
+                0x2 => {
+                    debug!("SYSCALL: write(STDERR, {:?})", &buf_str);
+                    process.stderr.push_str(&buf_str);
+                }
+                fd if process.files.contains_key(&fd) => {
+                    debug!("SYSCALL: write({}) to read-only file", fd);
+                    cpu.registers.rax = -EBADF as u64;
+                    return Ok(());
+                }
+                fd => return Err(Error::UnimplementedFileDescriptor(fd)),
+            }
+
+            count
+        }
+
+        0x2 => { // 0x2 = open(pathname = rdi, flags = rsi, mode = rdx)
+            let result = openat(cpu, process, AT_FDCWD, arg0, arg1);
+            debug!("SYSCALL: open({:?}, 0x{:x}) = {}", read_c_string(cpu, arg0).unwrap_or_default(), arg1, result);
+            result
+        }
+
+        0x3 => { // 0x3 = close(fd = rdi)
+            let result = match process.files.remove(&arg0) {
+                Some(_) => 0,
+                None if arg0 <= 2 => 0,
+                None => -EBADF,
+            };
+            debug!("SYSCALL: close({}) = {}", arg0, result);
+            result
+        }
+
+        0x4 => { // 0x4 = stat(pathname = rdi, statbuf = rsi)
+            let result = stat_path(cpu, process, AT_FDCWD, arg0, arg1, true);
+            debug!("SYSCALL: stat({:?}, 0x{:x}) = {}", read_c_string(cpu, arg0).unwrap_or_default(), arg1, result);
+            result
+        }
+
+        0x5 => { // 0x5 = fstat(fd = rdi, statbuf = rsi)
+            let result = fstat(cpu, process, arg0, arg1);
+            debug!("SYSCALL: fstat({}, 0x{:x}) = {}", arg0, arg1, result);
+            result
+        }
+
+        0x6 => { // 0x6 = lstat(pathname = rdi, statbuf = rsi)
+            let result = stat_path(cpu, process, AT_FDCWD, arg0, arg1, false);
+            debug!("SYSCALL: lstat({:?}, 0x{:x}) = {}", read_c_string(cpu, arg0).unwrap_or_default(), arg1, result);
+            result
+        }
+
+        0x8 => { // 0x8 = lseek(fd = rdi, offset = rsi, whence = rdx)
+            let result = lseek(process, arg0, arg1 as i64, arg2);
+            debug!("SYSCALL: lseek({}, {}, {}) = {}", arg0, arg1 as i64, arg2, result);
+            result
+        }
+
+        0x9 => { // 0x9 = mmap(addr = rdi, length = rsi, prot = rdx, flags = r10, fd = r8, offset = r9)
+            let result = mmap(cpu, process, arg0, arg1, arg2, arg3, arg4, arg5);
+            debug!("SYSCALL: mmap(0x{:x}, 0x{:x}, 0x{:x}, 0x{:x}, {}, 0x{:x}) = 0x{:x}", arg0, arg1, arg2, arg3, arg4 as i64, arg5, result);
+            result
+        }
+
+        0xa => { // 0xa = mprotect(addr = rdi, len = rsi, prot = rdx)
+            let result = if arg0 != page_align_down(arg0) {
+                -EINVAL
+            } else {
+                match cpu.memory.protect(arg0, arg1, protection(arg2)) {
+                    Ok(_) => 0,
+                    Err(_) => -ENOMEM,
+                }
+            };
+            debug!("SYSCALL: mprotect(0x{:x}, 0x{:x}, 0x{:x}) = {}", arg0, arg1, arg2, result);
+            result
+        }
+
+        0xb => { // 0xb = munmap(addr = rdi, length = rsi)
+            let result = if arg0 != page_align_down(arg0) || arg1 == 0 {
+                -EINVAL
+            } else {
+                cpu.memory.unmap(arg0, arg1);
+                0
+            };
+            debug!("SYSCALL: munmap(0x{:x}, 0x{:x}) = {}", arg0, arg1, result);
+            result
+        }
+
+        0xc => { // 0xc = brk(addr = rdi)
+            let result = brk(cpu, process, arg0) as i64;
+            debug!("SYSCALL: brk(0x{:x}) = 0x{:x}", arg0, result);
+            result
+        }
+
+        0xd => { // 0xd = rt_sigaction(sig = rdi, act = rsi, oact = rdx, sigsetsize = r10)
+            let result = rt_sigaction(cpu, process, arg0, arg1, arg2, arg3);
+            debug!("SYSCALL: rt_sigaction({}, 0x{:x}, 0x{:x}, {}) = {}", arg0, arg1, arg2, arg3, result);
+            result
+        }
+
+        0xe => { // 0xe = rt_sigprocmask(how = rdi, set = rsi, oset = rdx, sigsetsize = r10)
+            let result = rt_sigprocmask(cpu, process, arg0, arg1, arg2, arg3);
+            debug!("SYSCALL: rt_sigprocmask({}, 0x{:x}, 0x{:x}, {}) = {}", arg0, arg1, arg2, arg3, result);
+            result
+        }
+
+        0x11 => { // 0x11 = pread64(fd = rdi, buf = rsi, count = rdx, offset = r10)
+            let result = read(cpu, process, arg0, arg1, arg2, Some(arg3));
+            debug!("SYSCALL: pread64({}, 0x{:x}, {}, {}) = {}", arg0, arg1, arg2, arg3, result);
+            result
+        }
+
+        0x15 => { // 0x15 = access(pathname = rdi, mode = rsi)
+            let result = access(cpu, process, AT_FDCWD, arg0, arg1);
+            debug!("SYSCALL: access({:?}, 0x{:x}) = {}", read_c_string(cpu, arg0).unwrap_or_default(), arg1, result);
+            result
+        }
+
+        0x19 => { // 0x19 = mremap(old_address = rdi, old_size = rsi, new_size = rdx, flags = r10, new_address = r8)
+            let result = mremap(cpu, process, arg0, arg1, arg2, arg3, arg4);
+            debug!("SYSCALL: mremap(0x{:x}, 0x{:x}, 0x{:x}, 0x{:x}, 0x{:x}) = 0x{:x}", arg0, arg1, arg2, arg3, arg4, result);
+            result
+        }
+
+        0x3c | 0xe7 => { // 0x3c = exit(status = rdi), 0xe7 = exit_group(status = rdi)
+            let status = arg0;
+            process.exit_code = Some(status);
+            debug!("SYSCALL: exit(0x{:x})", status);
+
+            return Ok(());
+        }
+
+        0x3f => { // 0x3f = uname(buf = rdi)
+            let mut buf = [0u8; UTSNAME_LENGTH * UTSNAME.len()];
+            for (field, value) in buf.chunks_mut(UTSNAME_LENGTH).zip(UTSNAME) {
+                field[..value.len()].copy_from_slice(value.as_bytes());
+            }
+            let result = match cpu.memory.write(arg0, &buf) {
+                Ok(_) => 0,
+                Err(_) => -EFAULT,
+            };
+            debug!("SYSCALL: uname(0x{:x}) = {}", arg0, result);
+            result
+        }
+
+        0x59 => { // 0x59 = readlink(pathname = rdi, buf = rsi, bufsiz = rdx)
+            let result = readlinkat(cpu, process, AT_FDCWD, arg0, arg1, arg2);
+            debug!("SYSCALL: readlink({:?}, 0x{:x}, {}) = {}", read_c_string(cpu, arg0).unwrap_or_default(), arg1, arg2, result);
+            result
+        }
+
+        0x9e => { // 0x9e = arch_prctl(code = rdi, addr = rsi)
+            let result = arch_prctl(cpu, arg0, arg1);
+            debug!("SYSCALL: arch_prctl(0x{:x}, 0x{:x}) = {}", arg0, arg1, result);
+            result
+        }
+
+        0xda => { // 0xda = set_tid_address(tidptr = rdi)
+            process.clear_child_tid = arg0;
+            debug!("SYSCALL: set_tid_address(0x{:x}) = {}", arg0, PROCESS_ID);
+            PROCESS_ID as i64
+        }
+
+        0x101 => { // 0x101 = openat(dirfd = rdi, pathname = rsi, flags = rdx, mode = r10)
+            let result = openat(cpu, process, arg0 as i64, arg1, arg2);
+            debug!("SYSCALL: openat({}, {:?}, 0x{:x}) = {}", arg0 as i64, read_c_string(cpu, arg1).unwrap_or_default(), arg2, result);
+            result
+        }
+
+        0x106 => { // 0x106 = newfstatat(dirfd = rdi, pathname = rsi, statbuf = rdx, flags = r10)
+            let path = read_c_string(cpu, arg1);
+            let result = match &path {
+                Err(errno) => -errno,
+                Ok(path) if path.is_empty() && arg3 & AT_EMPTY_PATH != 0 => fstat(cpu, process, arg0, arg2),
+                Ok(path) if path.is_empty() => -ENOENT,
+                Ok(_) => stat_path(cpu, process, arg0 as i64, arg1, arg2, arg3 & AT_SYMLINK_NOFOLLOW == 0),
+            };
+            let path = path.unwrap_or_default();
+            debug!("SYSCALL: newfstatat({}, {:?}, 0x{:x}, 0x{:x}) = {}", arg0 as i64, path, arg2, arg3, result);
+            result
+        }
+
+        0x10d => { // 0x10d = faccessat(dirfd = rdi, pathname = rsi, mode = rdx)
+            let result = access(cpu, process, arg0 as i64, arg1, arg2);
+            debug!("SYSCALL: faccessat({}, {:?}, 0x{:x}) = {}", arg0 as i64, read_c_string(cpu, arg1).unwrap_or_default(), arg2, result);
+            result
+        }
+
+        0x10b => { // 0x10b = readlinkat(dirfd = rdi, pathname = rsi, buf = rdx, bufsiz = r10)
+            let result = readlinkat(cpu, process, arg0 as i64, arg1, arg2, arg3);
+            debug!("SYSCALL: readlinkat({}, {:?}, 0x{:x}, {}) = {}", arg0 as i64, read_c_string(cpu, arg1).unwrap_or_default(), arg2, arg3, result);
+            result
+        }
+
+        0x111 => { // 0x111 = set_robust_list(head = rdi, len = rsi)
+            let result = if arg1 != ROBUST_LIST_HEAD_SIZE {
+                -EINVAL
+            } else {
+                process.robust_list = arg0;
+                0
+            };
+            debug!("SYSCALL: set_robust_list(0x{:x}, {}) = {}", arg0, arg1, result);
+            result
+        }
+
+        0x12e => { // 0x12e = prlimit64(pid = rdi, resource = rsi, new_limit = rdx, old_limit = r10)
+            let result = prlimit64(cpu, process, arg0, arg1, arg2, arg3);
+            debug!("SYSCALL: prlimit64({}, {}, 0x{:x}, 0x{:x}) = {}", arg0, arg1, arg2, arg3, result);
+            result
+        }
+
+        0x13e => { // 0x13e = getrandom(buf = rdi, count = rsi, flags = rdx)
+            let result = getrandom(cpu, process, arg0, arg1, arg2);
+            debug!("SYSCALL: getrandom(0x{:x}, {}, 0x{:x}) = {}", arg0, arg1, arg2, result);
+            result
+        }
+
+        0x14e => { // 0x14e = rseq(rseq = rdi, rseq_len = rsi, flags = rdx, sig = r10)
+            let result = rseq(cpu, process, arg0, arg1, arg2, arg3);
+            debug!("SYSCALL: rseq(0x{:x}, {}, 0x{:x}, 0x{:x}) = {}", arg0, arg1, arg2, arg3, result);
+            result
+        }
+
+        number => {
+            // like the kernel for syscalls it does not have, the program may fall back to another one
+            debug!("SYSCALL: unimplemented syscall {} (0x{:x}) = {}", number, number, -ENOSYS);
+            -ENOSYS
+        }
+    };
+
+    cpu.registers.rax = result as u64;
+    Ok(())
+}
+
+fn arch_prctl(cpu: &mut Cpu, code: u64, addr: u64) -> i64 {
+    match code {
+        ARCH_SET_FS => cpu.registers.fs_base = addr,
+        ARCH_SET_GS => cpu.registers.gs_base = addr,
+        ARCH_GET_FS | ARCH_GET_GS => {
+            let base = if code == ARCH_GET_FS { cpu.registers.fs_base } else { cpu.registers.gs_base };
+            if cpu.memory.write_uint(addr, 8, base).is_err() {
+                return -EFAULT;
+            }
+        }
+        _ => return -EINVAL,
+    }
+    0
+}
+
+/// Moves the program break to `addr`, returning the new program break (or the
+/// current one if the request could not be satisfied, as the Linux kernel does)
+fn brk(cpu: &mut Cpu, process: &mut Process, addr: u64) -> u64 {
+    if addr < process.brk_start {
+        return process.brk;
+    }
+
+    let old_end = page_align_up(process.brk);
+    let new_end = page_align_up(addr);
+    if new_end > old_end {
+        if cpu.memory.map(old_end, new_end - old_end, Protection::READ_WRITE).is_err() {
+            // heap would collide with an existing mapping
+            return process.brk;
+        }
+    } else if new_end < old_end {
+        cpu.memory.unmap(new_end, old_end - new_end);
+    }
+
+    process.brk = addr;
+    process.brk
+}
+
+fn protection(prot: u64) -> Protection {
+    Protection {
+        read: prot & PROT_READ != 0,
+        write: prot & PROT_WRITE != 0,
+        execute: prot & PROT_EXEC != 0,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn mmap(cpu: &mut Cpu, process: &mut Process, addr: u64, length: u64, prot: u64, flags: u64, fd: u64, offset: u64) -> i64 {
+    if length == 0 || offset != page_align_down(offset) {
+        return -EINVAL;
+    }
+    let size = page_align_up(length);
+
+    let file = if flags & MAP_ANONYMOUS == 0 {
+        match process.files.get(&fd) {
+            Some(open_file) => Some(&open_file.file),
+            None => return -EBADF,
+        }
+    } else {
+        None
+    };
+
+    let address = if flags & (MAP_FIXED | MAP_FIXED_NOREPLACE) != 0 {
+        if addr != page_align_down(addr) {
+            return -EINVAL;
+        }
+        if !is_user_range(addr, size) {
+            return -ENOMEM;
+        }
+        if !cpu.memory.is_free(addr, size) {
+            if flags & MAP_FIXED_NOREPLACE != 0 {
+                return -EEXIST;
+            }
+            cpu.memory.unmap(addr, size);
+        }
+        addr
+    } else if addr >= MIN_MAP_ADDRESS && is_user_range(page_align_down(addr), size) && cpu.memory.is_free(page_align_down(addr), size) {
+        // like the kernel, hints below the lowest mappable address or outside the user address space are ignored
+        page_align_down(addr)
+    } else {
+        match cpu.memory.find_free(size, process.mmap_top) {
+            Some(address) => address,
+            None => return -ENOMEM,
+        }
+    };
+
+    if cpu.memory.map(address, size, protection(prot)).is_err() {
+        return -ENOMEM;
+    }
+
+    if let Some(file) = file {
+        // bytes past the end of the file read as zero, and are left as mapped
+        let file_size = match file.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_) => {
+                cpu.memory.unmap(address, size);
+                return -EACCES;
+            }
+        };
+        let mut data = vec![0; size.min(file_size.saturating_sub(offset)) as usize];
+        let mut read = 0;
+        while read < data.len() {
+            match file.read_at(&mut data[read..], offset + read as u64) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(_) => {
+                    cpu.memory.unmap(address, size);
+                    return -EACCES;
+                }
+            }
+        }
+        if read > 0 && cpu.memory.load(address, &data[..read]).is_err() {
+            return -ENOMEM;
+        }
+    }
+
+    address as i64
+}
+
+/// Resizes and possibly moves a mapping, keeping its contents. Grown mappings take the protection of their last page.
+fn mremap(cpu: &mut Cpu, process: &mut Process, old_address: u64, old_size: u64, new_size: u64, flags: u64, new_address: u64) -> i64 {
+    if flags & !(MREMAP_MAYMOVE | MREMAP_FIXED) != 0 || (flags & MREMAP_FIXED != 0 && flags & MREMAP_MAYMOVE == 0) {
+        return -EINVAL;
+    }
+    if old_address != page_align_down(old_address) || new_size == 0 || old_size == 0 {
+        return -EINVAL;
+    }
+    let old_size = page_align_up(old_size);
+    let new_size = page_align_up(new_size);
+    if !is_user_range(old_address, old_size) || !is_user_range(new_address, new_size) {
+        return -EINVAL;
+    }
+    let Some(protection) = cpu.memory.protection(old_address + old_size - PAGE_SIZE) else {
+        return -EFAULT;
+    };
+
+    let address = if flags & MREMAP_FIXED != 0 {
+        if new_address != page_align_down(new_address) || (new_address < old_address + old_size && old_address < new_address + new_size) {
+            return -EINVAL;
+        }
+        new_address
+    } else if new_size <= old_size || cpu.memory.is_free(old_address + old_size, new_size - old_size) {
+        old_address
+    } else if flags & MREMAP_MAYMOVE != 0 {
+        match cpu.memory.find_free(new_size, process.mmap_top) {
+            Some(address) => address,
+            None => return -ENOMEM,
+        }
+    } else {
+        return -ENOMEM;
+    };
+
+    let kept_size = old_size.min(new_size);
+    if address != old_address {
+        if cpu.memory.remap(old_address, kept_size, address).is_err() {
+            return -EFAULT;
+        }
+        cpu.memory.unmap(address + kept_size, new_size - kept_size);
+    }
+    cpu.memory.unmap(old_address + kept_size, old_size - kept_size);
+    if new_size > old_size && cpu.memory.map(address + old_size, new_size - old_size, protection).is_err() {
+        return -ENOMEM;
+    }
+
+    address as i64
+}
+
+fn read(cpu: &mut Cpu, process: &mut Process, fd: u64, buf: u64, count: u64, offset: Option<u64>) -> i64 {
+    if fd == 0 {
+        // stdin is always at end of file
+        return 0;
+    }
+    let Some(open_file) = process.files.get_mut(&fd) else {
+        return -EBADF;
+    };
+
+    if !is_user_range(buf, count) {
+        return -EFAULT;
+    }
+    // copied in chunks, as the count is only bounded by the guest
+    let mut position = offset.unwrap_or(open_file.position);
+    let mut data = vec![0; (count as usize).min(IO_CHUNK_SIZE)];
+    let mut total = 0;
+    while total < count {
+        let len = ((count - total) as usize).min(data.len());
+        let n = match open_file.file.read_at(&mut data[..len], position) {
+            Ok(n) => n,
+            Err(_) if total > 0 => break,
+            Err(e) => return -(e.raw_os_error().unwrap_or(EINVAL as i32) as i64),
+        };
+        if cpu.memory.write(buf.wrapping_add(total), &data[..n]).is_err() {
+            if total > 0 {
+                break;
+            }
+            return -EFAULT;
+        }
+        position += n as u64;
+        total += n as u64;
+        if n < len {
+            break;
+        }
+    }
+    if offset.is_none() {
+        open_file.position = position;
+    }
+    total as i64
+}
+
+/// Whether a guest buffer lies within the user address space, checked up front like the kernel's access_ok()
+fn is_user_range(address: u64, size: u64) -> bool {
+    address.checked_add(size).is_some_and(|end| end <= STACK_TOP)
+}
+
+fn getrandom(cpu: &mut Cpu, process: &mut Process, buf: u64, count: u64, flags: u64) -> i64 {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0 || flags & (GRND_RANDOM | GRND_INSECURE) == (GRND_RANDOM | GRND_INSECURE) {
+        return -EINVAL;
+    }
+    if !is_user_range(buf, count) {
+        return -EFAULT;
+    }
+    let mut data = vec![0; (count as usize).min(IO_CHUNK_SIZE)];
+    let mut total = 0;
+    while total < count {
+        let len = ((count - total) as usize).min(data.len());
+        process.random.fill_bytes(&mut data[..len]);
+        if cpu.memory.write(buf + total, &data[..len]).is_err() {
+            return if total > 0 { total as i64 } else { -EFAULT };
+        }
+        total += len as u64;
+    }
+    total as i64
+}
+
+/// Limits of a resource not changed by the program, as set up for a process started from a shell
+fn default_resource_limit(resource: u64) -> (u64, u64) {
+    match resource {
+        RLIMIT_STACK => (8 * 1024 * 1024, RLIM_INFINITY),
+        RLIMIT_CORE => (0, RLIM_INFINITY),
+        RLIMIT_NOFILE => (1024, 4096),
+        RLIMIT_MEMLOCK => (8 * 1024 * 1024, 8 * 1024 * 1024),
+        RLIMIT_NICE | RLIMIT_RTPRIO => (0, 0),
+        _ => (RLIM_INFINITY, RLIM_INFINITY),
+    }
+}
+
+fn prlimit64(cpu: &mut Cpu, process: &mut Process, pid: u64, resource: u64, new_limit: u64, old_limit: u64) -> i64 {
+    if pid != 0 && pid != PROCESS_ID {
+        return -ESRCH;
+    }
+    if resource >= RLIM_NLIMITS {
+        return -EINVAL;
+    }
+    let (soft, hard) = process.resource_limits.get(&resource).copied().unwrap_or_else(|| default_resource_limit(resource));
+
+    // both limits are 16-byte struct rlimit buffers
+    if (new_limit != 0 && !is_user_range(new_limit, 16)) || (old_limit != 0 && !is_user_range(old_limit, 16)) {
+        return -EFAULT;
+    }
+
+    let new = if new_limit != 0 {
+        let (Ok(new_soft), Ok(new_hard)) = (cpu.memory.read_uint(new_limit, 8), cpu.memory.read_uint(new_limit + 8, 8)) else {
+            return -EFAULT;
+        };
+        if new_soft > new_hard {
+            return -EINVAL;
+        }
+        // raising the hard limit needs privileges
+        if new_hard > hard {
+            return -EPERM;
+        }
+        Some((new_soft, new_hard))
+    } else {
+        None
+    };
+
+    if old_limit != 0 && (cpu.memory.write_uint(old_limit, 8, soft).is_err() || cpu.memory.write_uint(old_limit + 8, 8, hard).is_err()) {
+        return -EFAULT;
+    }
+    if let Some(new) = new {
+        process.resource_limits.insert(resource, new);
+    }
+    0
+}
+
+/// Registers or unregisters the restartable sequence area. The program is never preempted or
+/// migrated, so the area only ever reports CPU 0 and no critical section is aborted.
+fn rseq(cpu: &mut Cpu, process: &mut Process, address: u64, len: u64, flags: u64, sig: u64) -> i64 {
+    let (len, sig) = (len as u32, sig as u32);
+    if flags & RSEQ_FLAG_UNREGISTER != 0 {
+        if flags != RSEQ_FLAG_UNREGISTER {
+            return -EINVAL;
+        }
+        return match process.rseq {
+            Some((registered, registered_len, _)) if registered != address || registered_len != len => -EINVAL,
+            Some((_, _, registered_sig)) if registered_sig != sig => -EPERM,
+            Some(_) => {
+                // cpu_id_start and cpu_id
+                if cpu.memory.write_uint(address, 4, 0).is_err() || cpu.memory.write_uint(address + 4, 4, RSEQ_CPU_ID_UNINITIALIZED).is_err() {
+                    return -EFAULT;
+                }
+                process.rseq = None;
+                0
+            }
+            None => -EINVAL,
+        };
+    }
+    if flags != 0 {
+        return -EINVAL;
+    }
+
+    match process.rseq {
+        Some((registered, registered_len, registered_sig)) if registered == address && registered_len == len => {
+            if registered_sig != sig { -EPERM } else { -EBUSY }
+        }
+        Some(_) => -EINVAL,
+        None if (len as u64) < RSEQ_MIN_SIZE || !address.is_multiple_of(RSEQ_MIN_SIZE) => -EINVAL,
+        None => {
+            if cpu.memory.write_uint(address, 4, 0).is_err() || cpu.memory.write_uint(address + 4, 4, 0).is_err() {
+                return -EFAULT;
+            }
+            process.rseq = Some((address, len, sig));
+            0
+        }
+    }
+}
+
+/// Stores the action of a signal. The action is reported back to the program, but a signal
+/// caused by the program still terminates it (see [`Process::terminate`]).
+fn rt_sigaction(cpu: &mut Cpu, process: &mut Process, sig: u64, act: u64, oact: u64, sigsetsize: u64) -> i64 {
+    if sigsetsize != SIGSET_SIZE || sig == 0 || sig > NSIG {
+        return -EINVAL;
+    }
+    if act != 0 && (sig == SIGKILL || sig == SIGSTOP) {
+        return -EINVAL;
+    }
+
+    let mut action = [0u8; SIGACTION_SIZE];
+    if act != 0 && cpu.memory.read(act, &mut action).is_err() {
+        return -EFAULT;
+    }
+    let old_action = process.signal_actions.get(&sig).copied().unwrap_or_default();
+    if oact != 0 && cpu.memory.write(oact, &old_action).is_err() {
+        return -EFAULT;
+    }
+    if act != 0 {
+        process.signal_actions.insert(sig, action);
+    }
+    0
+}
+
+fn rt_sigprocmask(cpu: &mut Cpu, process: &mut Process, how: u64, set: u64, oset: u64, sigsetsize: u64) -> i64 {
+    if sigsetsize != SIGSET_SIZE {
+        return -EINVAL;
+    }
+
+    let new_mask = if set != 0 {
+        let Ok(set) = cpu.memory.read_uint(set, 8) else {
+            return -EFAULT;
+        };
+        let mask = match how {
+            SIG_BLOCK => process.signal_mask | set,
+            SIG_UNBLOCK => process.signal_mask & !set,
+            SIG_SETMASK => set,
+            _ => return -EINVAL,
+        };
+        // SIGKILL and SIGSTOP cannot be blocked
+        Some(mask & !(1 << (SIGKILL - 1) | 1 << (SIGSTOP - 1)))
+    } else {
+        None
+    };
+
+    if oset != 0 && cpu.memory.write_uint(oset, 8, process.signal_mask).is_err() {
+        return -EFAULT;
+    }
+    if let Some(mask) = new_mask {
+        process.signal_mask = mask;
+    }
+    0
+}
+
+fn lseek(process: &mut Process, fd: u64, offset: i64, whence: u64) -> i64 {
+    let Some(open_file) = process.files.get_mut(&fd) else {
+        return -EBADF;
+    };
+    let base = match whence {
+        SEEK_SET => 0,
+        SEEK_CUR => open_file.position,
+        SEEK_END => match open_file.file.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return -EINVAL,
+        },
+        _ => return -EINVAL,
+    };
+    match base.checked_add_signed(offset) {
+        Some(position) => {
+            open_file.position = position;
+            position as i64
+        }
+        None => -EINVAL,
+    }
+}
+
+fn openat(cpu: &mut Cpu, process: &mut Process, dirfd: i64, pathname: u64, flags: u64) -> i64 {
+    let path = match guest_path(cpu, process, dirfd, pathname) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    // the sysroot is only exposed read-only
+    if flags & O_ACCMODE != 0 || flags & (O_CREAT | O_TRUNC) != 0 {
+        return -EROFS;
+    }
+
+    match process.sysroot_path(&path).and_then(File::open) {
+        Ok(file) => {
+            let fd = process.next_fd();
+            process.files.insert(fd, OpenFile { path, file, position: 0 });
+            fd as i64
+        }
+        Err(e) => -(e.raw_os_error().unwrap_or(ENOENT as i32) as i64),
+    }
+}
+
+fn access(cpu: &mut Cpu, process: &mut Process, dirfd: i64, pathname: u64, mode: u64) -> i64 {
+    let path = match guest_path(cpu, process, dirfd, pathname) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    match process.sysroot_path(&path).and_then(std::fs::metadata) {
+        Ok(_) if mode & W_OK != 0 => -EROFS,
+        Ok(_) => 0,
+        Err(e) => -(e.raw_os_error().unwrap_or(ENOENT as i32) as i64),
+    }
+}
+
+fn readlinkat(cpu: &mut Cpu, process: &mut Process, dirfd: i64, pathname: u64, buf: u64, bufsiz: u64) -> i64 {
+    if bufsiz as i32 <= 0 {
+        return -EINVAL;
+    }
+    let path = match guest_path(cpu, process, dirfd, pathname) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    // the target is returned as is, absolute targets are guest paths as they are resolved inside the sysroot
+    let target = match process.sysroot_path_nofollow(&path).and_then(std::fs::read_link) {
+        Ok(target) => target,
+        Err(e) => return -(e.raw_os_error().unwrap_or(ENOENT as i32) as i64),
+    };
+    let bytes = target.as_os_str().as_bytes();
+    let len = bytes.len().min(bufsiz as i32 as usize);
+    match cpu.memory.write(buf, &bytes[..len]) {
+        Ok(_) => len as i64,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Implements stat, and lstat when not following a symbolic link in the last path component
+fn stat_path(cpu: &mut Cpu, process: &mut Process, dirfd: i64, pathname: u64, statbuf: u64, follow: bool) -> i64 {
+    let path = match guest_path(cpu, process, dirfd, pathname) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    let host_path = if follow { process.sysroot_path(&path) } else { process.sysroot_path_nofollow(&path) };
+    match host_path.and_then(std::fs::symlink_metadata) {
+        Ok(metadata) => write_stat(cpu, statbuf, &stat_from_metadata(&metadata)),
+        Err(e) => -(e.raw_os_error().unwrap_or(ENOENT as i32) as i64),
+    }
+}
+
+fn fstat(cpu: &mut Cpu, process: &mut Process, fd: u64, statbuf: u64) -> i64 {
+    let stat = match process.files.get(&fd) {
+        Some(open_file) => match open_file.file.metadata() {
+            Ok(metadata) => stat_from_metadata(&metadata),
+            Err(e) => return -(e.raw_os_error().unwrap_or(EBADF as i32) as i64),
+        },
+        // standard streams are terminals
+        None if fd <= 2 => Stat {
+            mode: S_IFCHR | 0o620,
+            nlink: 1,
+            blksize: 1024,
+            rdev: 0x8800,
+            ..Default::default()
+        },
+        None => return -EBADF,
+    };
+    write_stat(cpu, statbuf, &stat)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Stat {
+    dev: u64,
+    ino: u64,
+    nlink: u64,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    rdev: u64,
+    size: i64,
+    blksize: i64,
+    blocks: i64,
+    atime: (i64, i64),
+    mtime: (i64, i64),
+    ctime: (i64, i64),
+}
+
+fn stat_from_metadata(metadata: &Metadata) -> Stat {
+    Stat {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+        nlink: metadata.nlink(),
+        mode: metadata.mode(),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        rdev: metadata.rdev(),
+        size: metadata.size() as i64,
+        blksize: metadata.blksize() as i64,
+        blocks: metadata.blocks() as i64,
+        atime: (metadata.atime(), metadata.atime_nsec()),
+        mtime: (metadata.mtime(), metadata.mtime_nsec()),
+        ctime: (metadata.ctime(), metadata.ctime_nsec()),
+    }
+}
+
+/// Writes a `struct stat` in the x86-64 Linux layout
+fn write_stat(cpu: &mut Cpu, statbuf: u64, stat: &Stat) -> i64 {
+    let mut buf = [0u8; STAT_SIZE];
+    buf[0..8].copy_from_slice(&stat.dev.to_le_bytes());
+    buf[8..16].copy_from_slice(&stat.ino.to_le_bytes());
+    buf[16..24].copy_from_slice(&stat.nlink.to_le_bytes());
+    buf[24..28].copy_from_slice(&stat.mode.to_le_bytes());
+    buf[28..32].copy_from_slice(&stat.uid.to_le_bytes());
+    buf[32..36].copy_from_slice(&stat.gid.to_le_bytes());
+    buf[40..48].copy_from_slice(&stat.rdev.to_le_bytes());
+    buf[48..56].copy_from_slice(&stat.size.to_le_bytes());
+    buf[56..64].copy_from_slice(&stat.blksize.to_le_bytes());
+    buf[64..72].copy_from_slice(&stat.blocks.to_le_bytes());
+    for (offset, (sec, nsec)) in [(72, stat.atime), (88, stat.mtime), (104, stat.ctime)] {
+        buf[offset..offset + 8].copy_from_slice(&sec.to_le_bytes());
+        buf[offset + 8..offset + 16].copy_from_slice(&nsec.to_le_bytes());
+    }
+    match cpu.memory.write(statbuf, &buf) {
+        Ok(_) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Reads a NUL-terminated string from guest memory
+fn read_c_string(cpu: &Cpu, address: u64) -> Result<String, i64> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = cpu.memory.read_uint(address.wrapping_add(bytes.len() as u64), 1).map_err(|_| EFAULT)? as u8;
+        if byte == 0 {
+            break;
+        }
+        if bytes.len() >= PATH_MAX {
+            return Err(ENAMETOOLONG);
+        }
+        bytes.push(byte);
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Resolves a guest path argument relative to `dirfd` into an absolute, normalized guest path
+fn guest_path(cpu: &Cpu, process: &Process, dirfd: i64, pathname: u64) -> Result<PathBuf, i64> {
+    let path = read_c_string(cpu, pathname)?;
+    if path.is_empty() {
+        return Err(ENOENT);
+    }
+
+    let base = if path.starts_with('/') || dirfd == AT_FDCWD {
+        // the working directory is the root directory
+        PathBuf::from("/")
+    } else {
+        match process.files.get(&(dirfd as u64)) {
+            Some(open_file) => open_file.path.clone(),
+            None => return Err(EBADF),
+        }
+    };
+
+    Ok(normalize(&base.join(path)))
+}
+
+/// Removes `.` and `..` components without leaving the root directory
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    normalized
+}
@@ -0,0 +1,127 @@
+use super::error::Error;
+
+use crate::cpu::Cpu;
+use crate::mem::{Access, Protection, PAGE_SIZE};
+
+use goblin::elf::program_header::ProgramHeader;
+
+/// Space reserved for the thread control block at and above the thread pointer.
+/// glibc's `struct pthread` starts with the TCB header and is about 2.3 KiB.
+const TCB_SIZE: u64 = PAGE_SIZE;
+
+// offsets in the TCB header (tcbhead_t) on x86-64
+const TCB_TCB: u64 = 0x0;
+const TCB_DTV: u64 = 0x8;
+const TCB_SELF: u64 = 0x10;
+const TCB_STACK_GUARD: u64 = 0x28;
+const TCB_POINTER_GUARD: u64 = 0x30;
+
+/// Size of a dynamic thread vector entry (dtv_t), a counter or a block pointer and the pointer to free
+const DTV_ENTRY_SIZE: u64 = 16;
+
+/// Thread-local storage module in the static TLS block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlsModule {
+    /// module ID, as used by `__tls_get_addr`
+    pub id: u64,
+    /// offset of the module's TLS block below the thread pointer (TLS variant II)
+    pub offset: u64,
+    /// address of the initialization image (`.tdata`)
+    pub image: u64,
+    /// size of the initialization image
+    pub filesz: u64,
+    /// size of the module's TLS block, the rest after the image is zero-filled (`.tbss`)
+    pub memsz: u64,
+}
+
+/// Layout of the static TLS block, which lies directly below the thread pointer
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TlsLayout {
+    pub modules: Vec<TlsModule>,
+    /// size of the static TLS block
+    size: u64,
+    /// largest alignment of the modules, the thread pointer is aligned to it
+    alignment: u64,
+}
+
+impl TlsLayout {
+    /// Adds the `PT_TLS` segment of an image loaded with `bias`, below the modules added before
+    pub fn add(&mut self, phdr: &ProgramHeader, bias: u64) -> Result<TlsModule, Error> {
+        if phdr.p_filesz > phdr.p_memsz {
+            return Err(Error::ElfTlsSegmentInvalid);
+        }
+        let alignment = phdr.p_align.max(1);
+        self.size = self.size.checked_add(phdr.p_memsz)
+            .and_then(|size| size.checked_next_multiple_of(alignment))
+            .ok_or(Error::InvalidElf)?;
+        self.alignment = self.alignment.max(alignment);
+
+        let module = TlsModule {
+            id: self.modules.len() as u64 + 1,
+            offset: self.size,
+            image: bias.wrapping_add(phdr.p_vaddr),
+            filesz: phdr.p_filesz,
+            memsz: phdr.p_memsz,
+        };
+        self.modules.push(module);
+        Ok(module)
+    }
+}
+
+/// Allocates and initializes the static TLS block and thread control block of the
+/// initial thread below `top`, returning the thread pointer (the fs base).
+pub fn init(cpu: &mut Cpu, top: u64, layout: &TlsLayout, random: [u8; 16]) -> Result<u64, Error> {
+    let thread_pointer = allocate(cpu, top, layout, random)?;
+    copy_images(cpu, thread_pointer, layout)?;
+    Ok(thread_pointer)
+}
+
+/// Maps the dynamic thread vector, static TLS block and thread control block and sets up the TCB header:
+/// the self pointers, the DTV and the stack and pointer guards derived from the AT_RANDOM bytes, like glibc does
+pub fn allocate(cpu: &mut Cpu, top: u64, layout: &TlsLayout, random: [u8; 16]) -> Result<u64, Error> {
+    // the DTV starts with its length, followed by the generation and an entry per module
+    let dtv_size = (layout.modules.len() as u64 + 2) * DTV_ENTRY_SIZE;
+    let alignment = layout.alignment.max(16);
+    let size = dtv_size.checked_add(layout.size)
+        .and_then(|size| size.checked_add(alignment))
+        .and_then(|size| size.checked_add(TCB_SIZE))
+        .and_then(|size| size.checked_next_multiple_of(PAGE_SIZE))
+        .ok_or(Error::InvalidElf)?;
+    let start = cpu.memory.find_free(size, top).ok_or(Error::OutOfMemory)?;
+    cpu.memory.map(start, size, Protection::READ_WRITE)?;
+
+    let thread_pointer = (start + dtv_size + layout.size).checked_next_multiple_of(alignment).ok_or(Error::InvalidElf)?;
+
+    // the TCB points at the generation, all modules are in the static TLS block from the start
+    let dtv = start + DTV_ENTRY_SIZE;
+    cpu.memory.write_uint(start, 8, layout.modules.len() as u64)?;
+    cpu.memory.write_uint(dtv, 8, 0)?;
+    for module in &layout.modules {
+        cpu.memory.write_uint(dtv + module.id * DTV_ENTRY_SIZE, 8, thread_pointer - module.offset)?;
+    }
+
+    // the lowest byte of the stack guard is zero, to stop string functions from reading it
+    let stack_guard = u64::from_le_bytes(random[0..8].try_into().unwrap_or_default()) & !0xff;
+    let pointer_guard = u64::from_le_bytes(random[8..16].try_into().unwrap_or_default());
+
+    cpu.memory.write_uint(thread_pointer + TCB_TCB, 8, thread_pointer)?;
+    cpu.memory.write_uint(thread_pointer + TCB_DTV, 8, dtv)?;
+    cpu.memory.write_uint(thread_pointer + TCB_SELF, 8, thread_pointer)?;
+    cpu.memory.write_uint(thread_pointer + TCB_STACK_GUARD, 8, stack_guard)?;
+    cpu.memory.write_uint(thread_pointer + TCB_POINTER_GUARD, 8, pointer_guard)?;
+
+    Ok(thread_pointer)
+}
+
+/// Copies the initialization images of the TLS modules into the static TLS block. The rest of each module's
+/// block (`.tbss`) is left as freshly mapped, which reads as zero.
+pub fn copy_images(cpu: &mut Cpu, thread_pointer: u64, layout: &TlsLayout) -> Result<(), Error> {
+    for module in &layout.modules {
+        // the image must be mapped before its size is trusted for a host allocation
+        cpu.memory.check_access(module.image, module.filesz as usize, Access::Read)?;
+        let mut data = vec![0; module.filesz as usize];
+        cpu.memory.read(module.image, &mut data)?;
+        cpu.memory.write(thread_pointer - module.offset, &data)?;
+    }
+    Ok(())
+}